@@ -9,4 +9,13 @@ pub struct OrangeCliArgs {
     pub username: Option<String>,
     #[arg(short, long, value_name = "FILE")]
     pub orange_directory: Option<PathBuf>,
+    /// Record every packet of a session into the recordings folder
+    #[arg(long)]
+    pub record: bool,
+    /// Offer to play back a recorded session from the main menu
+    #[arg(long, value_name = "FILE")]
+    pub replay: Option<PathBuf>,
+    /// Playback speed of a replay, 1 is real time and 0 or less hands out one recorded batch per tick
+    #[arg(long)]
+    pub replay_speed: Option<f32>,
 }
@@ -0,0 +1,63 @@
+use orange_rs::network::auth::{SessionAuth, SessionCredentials, DEFAULT_JOIN_ENDPOINT};
+use serde_derive::{Deserialize, Serialize};
+
+/// An account that has logged in, with the session id handed out for it
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct Account {
+    pub username: String,
+    pub session_id: String,
+}
+
+/** The accounts known to the client and where to authenticate them, stored with the options
+ *  The session endpoint can be pointed at a local stand-in or a replacement session server
+ */
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct CredentialsStore {
+    #[serde(default = "default_session_endpoint")]
+    session_endpoint: String,
+    #[serde(default)]
+    selected_account: Option<String>,
+    #[serde(default)]
+    accounts: Vec<Account>,
+}
+
+fn default_session_endpoint() -> String {
+    DEFAULT_JOIN_ENDPOINT.to_string()
+}
+
+impl Default for CredentialsStore {
+    fn default() -> Self {
+        Self { session_endpoint: default_session_endpoint(), selected_account: None, accounts: vec![] }
+    }
+}
+
+impl CredentialsStore {
+    pub fn session_endpoint(&self) -> &str {
+        &self.session_endpoint
+    }
+
+    pub fn account(&self, username: &str) -> Option<&Account> {
+        self.accounts.iter().find(|account| account.username == username)
+    }
+
+    pub fn selected_account(&self) -> Option<&Account> {
+        self.selected_account.as_deref().and_then(|username| self.account(username))
+    }
+
+    /// Add an account or replace its session id, and select it
+    pub fn add_account(&mut self, account: Account) {
+        self.selected_account = Some(account.username.clone());
+        match self.accounts.iter_mut().find(|known| known.username == account.username) {
+            Some(known) => *known = account,
+            None => self.accounts.push(account),
+        }
+    }
+
+    /// How to authenticate as `username`, without credentials if there is no account for it
+    pub fn session_auth(&self, username: &str) -> SessionAuth {
+        SessionAuth {
+            join_endpoint: self.session_endpoint.clone(),
+            credentials: self.account(username).map(|account| SessionCredentials { username: account.username.clone(), session_id: account.session_id.clone() }),
+        }
+    }
+}
@@ -1,8 +1,9 @@
 mod test_world;
 mod orange_options;
 mod cli_options;
+mod credentials;
 
-use std::{collections::VecDeque, sync::{Arc, RwLock}, fs::File, io::{Write, Read}, net::{SocketAddr, Ipv4Addr}, str::FromStr, fmt::Display};
+use std::{collections::VecDeque, sync::{Arc, RwLock}, fs::File, io::{Write, Read}, net::{SocketAddr, Ipv4Addr}, str::FromStr, path::PathBuf};
 use clap::Parser;
 use env_logger::Builder;
 use log::{LevelFilter, warn};
@@ -32,47 +33,61 @@ use orange_rs::{
 };
 
 use orange_networking::network_interface::NetworkThread;
+use orange_rs::network::PacketConnection;
+use orange_rs::network::auth::SessionAuth;
+use orange_rs::network::state::{ConnectionStateMachine, ConnectionTimeouts, DisconnectReason};
+use orange_rs::network::recording::{PacketRecorder, ReplayConnection, ReplaySpeed, RECORDING_EXTENSION};
+use orange_rs::resource_manager::{ResourceManager, ResourceType, DEFAULT_ASSETS_DIR};
+use orange_rs::server::config::{ServerConfig, SERVER_PROPERTIES_FILE};
+use orange_rs::server::generator::FlatGenerator;
+use orange_rs::server::integrated::IntegratedServer;
 use rine::RineApplication;
 use ultraviolet::{DVec3, IVec3, Vec3};
 use winit::event::{DeviceEvent, VirtualKeyCode};
 use winit_input_helper::WinitInputHelper;
-use orange_rs::minecraft::mc_resource_handler::{CAMERA_BIND_GROUP_NAME, LIGHTMAP_TEXTURE_NAME, TERRAIN_OPAQUE_PIPELINE, TERRAIN_TRANSPARENT_PIPELINE};
+use orange_rs::minecraft::mc_resource_handler::{CAMERA_BIND_GROUP_NAME, LIGHTMAP_TEXTURE_NAME, TERRAIN_ATLAS_TEXTURE_NAME, TERRAIN_OPAQUE_PIPELINE, TERRAIN_TRANSPARENT_PIPELINE};
 use orange_rs::minecraft::registry::Registry;
+use orange_rs::client::textures::animation::AnimationTicker;
 use orange_rs::util::frustrum::Frustrum;
 use orange_rs::util::pos::NewChunkPosition;
 use orange_rs::world::ChunkStorageTrait;
 use crate::{test_world::TestWorld, orange_options::OrangeOptions};
 
-#[derive(Debug)]
-enum ServerConnectError {
-    InvalidAddress,
-    UnexpectedPacket(String),
-    Kick(String),
-}
-
-impl std::error::Error for ServerConnectError {}
-
-impl Display for ServerConnectError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "")
-    }
-}
-
-fn join_server(username: String, protocol_id: i32, address: String, port: u32, world: &mut TestWorld) -> Result<NetworkThread<Packet>, ServerConnectError> {
-    let network_thread = match NetworkThread::connect_to_server(address, port) {
-        Ok(nt) => { nt },
-        Err(_) => { return Err(ServerConnectError::InvalidAddress); }
-    };
-
+/** Run the login sequence, authenticating with the session server through `auth` if the server is in online mode
+ *  Leaves the world playing on success
+ */
+fn join_server<C: PacketConnection + ?Sized>(username: String, protocol_id: i32, auth: &SessionAuth, network_thread: &C, world: &mut TestWorld) -> Result<(), DisconnectReason> {
+    world.connection_state.connected();
     network_thread.send_packet(Packet::Handshake { handshake_data: username.clone() });
     let mut player_id: i32 = 0;
     world.player = Some(world.entities.push((EntityTransform { position: EntityPos::zero(), rotation: Vec3::zero() }, EntityMotion { velocity: Vec3::zero() }, EntityController { on_ground: true, stance: 1.6 }, EntityCamera { } )));
     
-    let mut do_login = true;
-    while do_login {
-        for packet in network_thread.get_packets() {
+    while !world.connection_state.is_playing() {
+        if network_thread.is_closed() {
+            world.connection_state.disconnect(DisconnectReason::ConnectionLost);
+        }
+        world.connection_state.check_timeouts();
+        if let Some(reason) = world.connection_state.disconnect_reason() {
+            return Err(reason.clone());
+        }
+
+        let mut packets = network_thread.get_packets().into_iter();
+        if packets.len() == 0 {
+            std::thread::sleep(LOGIN_POLL_INTERVAL);
+            continue;
+        }
+        for packet in packets.by_ref() {
+            // Kicks and anything out of order end the connection here
+            if !world.connection_state.handle_packet(&packet) {
+                break;
+            }
             match packet {
                 Packet::Handshake { handshake_data } => {
+                    // The server hash is only present when the server is in online mode
+                    if let Err(e) = auth.join(&handshake_data) {
+                        world.connection_state.disconnect(DisconnectReason::AuthenticationFailed(e.to_string()));
+                        break;
+                    }
                     let login_packet = Packet::Login{ protocol: protocol_id, username: username.clone(), seed: 0, dimension: 0 };
                     network_thread.send_packet(login_packet); 
                     warn!("Handshake Packet Received! {handshake_data}, sending login request as {username}.");
@@ -81,19 +96,49 @@ fn join_server(username: String, protocol_id: i32, address: String, port: u32, w
                     player_id = protocol;
                     world.set_dimension_id(dimension);
                     world.set_seed(seed);
-                    do_login = false;
                     break;
                 },
-                Packet::DisconnectKick { reason } => { return Err(ServerConnectError::Kick(reason)); }
-                _ => { return Err(ServerConnectError::UnexpectedPacket(format!("{:?}", packet))); }
+                _ => {}
             }
         }
+        if let Some(reason) = world.connection_state.disconnect_reason() {
+            return Err(reason.clone());
+        }
+        // Whatever arrived together with the login belongs to the world
+        for packet in packets {
+            world.handle_packet(packet, network_thread);
+        }
     }
     warn!("Logged in, leaving the login sequence.");
-    Ok(network_thread)
+    Ok(())
+}
+
+/// The base game's assets with the selected packs from `directory` on top, packs that fail to open are left out
+fn load_resource_packs(directory: &std::path::Path, packs: &[String]) -> ResourceManager {
+    let mut resources = ResourceManager::new();
+    let sources = std::iter::once(ResourceType::Dir(PathBuf::from(DEFAULT_ASSETS_DIR)))
+        .chain(packs.iter().map(|pack| ResourceType::from_path(directory.join(pack))));
+    for source in sources {
+        let path = source.path().display().to_string();
+        if let Err(e) = resources.push_pack(source) {
+            log::error!("Failed to open resource pack {path}: {e}");
+        }
+    }
+    resources
+}
+
+/// The directory of a world in `saves`, None for names that would lead anywhere else, like `..` or a path
+fn world_directory(saves: &std::path::Path, name: &str) -> Option<PathBuf> {
+    let mut components = std::path::Path::new(name).components();
+    match (components.next(), components.next()) {
+        (Some(std::path::Component::Normal(_)), None) if !name.contains(['/', '\\']) => Some(saves.join(name)),
+        _ => None,
+    }
 }
 
 const CHUNK_HEIGHT: usize = 8;
+/// How long to wait between checks for packets while logging in
+const LOGIN_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(5);
 
 enum GameState {
     MainMenu,
@@ -151,18 +196,156 @@ struct OrangeClient {
     server_ip: String,
     server_port: String,
     debug: bool,
+
+    /// Where to record sessions to, if recording
+    recording_directory: Option<PathBuf>,
+    connection_timeouts: ConnectionTimeouts,
+    /// How to authenticate with online mode servers
+    session_auth: SessionAuth,
+    /// Why the last connection ended, shown on the main menu
+    disconnect_reason: Option<DisconnectReason>,
+    /// A recording to offer playing back from the main menu
+    replay: Option<PathBuf>,
+    replay_speed: ReplaySpeed,
+    /// Where singleplayer worlds are kept, one directory each
+    saves_directory: PathBuf,
+    /// The singleplayer world to play, created if there is none by that name
+    world_name: String,
+    /// The server of the singleplayer world being played
+    integrated_server: Option<IntegratedServer>,
+    /// The resource pack stack models and textures were loaded from
+    resources: ResourceManager,
+    /// Animated textures of the terrain atlas
+    animations: AnimationTicker,
+    /// When the animations last ticked
+    animation_time: instant::Instant,
 }
 
 impl OrangeClient {
+    /// Find the animated textures of the terrain atlas in the resource packs
+    fn load_animations(client: &Client, registry: &Registry, resources: &ResourceManager) -> AnimationTicker {
+        let atlas_size = client.get_texture(TERRAIN_ATLAS_TEXTURE_NAME).get_size();
+        AnimationTicker::load(resources, registry.get_texture_register(), (atlas_size.x, atlas_size.y))
+    }
+
+    /// Move animated textures on by the game ticks that passed since they last did
+    fn tick_animations(&mut self, queue: &wgpu::Queue) {
+        const TICK: std::time::Duration = std::time::Duration::from_millis(50);
+        // Catch up a little after a stall, but never spend a frame on a long backlog
+        const MAX_TICKS_PER_FRAME: u32 = 10;
+        let mut ticks = 0;
+        while self.animation_time.elapsed() >= TICK {
+            self.animation_time += TICK;
+            ticks += 1;
+            if ticks > MAX_TICKS_PER_FRAME {
+                self.animation_time = instant::Instant::now();
+                break;
+            }
+            let updates = self.animations.tick();
+            mc_resource_handler::upload_atlas_tiles(&self.client, queue, &updates);
+        }
+    }
+
+    /** Read the resource packs again and rebuild every model and the terrain atlas from them
+     *  The connection and world are kept, every loaded section is just tessellated again
+     */
+    fn reload_resources(&mut self, window_client: &rine::RineWindowClient) {
+        if let Err(e) = self.registry.write().unwrap().reload(&orange_rs::game_version::GameVersion::B173, &mut self.resources) {
+            log::error!("Failed to reload resources: {e}");
+            return;
+        }
+        if let Err(e) = mc_resource_handler::load_terrain_atlas(&mut self.client, window_client.device(), window_client.queue(), &self.resources) {
+            log::warn!("Keeping the terrain atlas in use: {e}");
+        }
+        self.animations = Self::load_animations(&self.client, &self.registry.read().unwrap(), &self.resources);
+        if let GameState::InGame { test_world, .. } = &self.game_state {
+            let sections = test_world.read().unwrap().chunk_storage.mark_all_dirty();
+            log::info!("Tessellating {sections} sections again");
+        }
+    }
+
     pub fn join_server_connect(&mut self) -> Result<(), Box<dyn std::error::Error>> {
         match &self.game_state {
             GameState::MainMenu => {
-                self.game_state.to_joining_server();
                 let ip_v4 = Ipv4Addr::from_str(&self.server_ip)?;
                 let ip_port = self.server_port.parse::<u16>()?;
                 let ip = SocketAddr::new(std::net::IpAddr::V4(ip_v4), ip_port);
+                self.disconnect_reason = None;
+                let network_thread: NetworkThread<Packet> = match NetworkThread::connect_to_server(ip.ip().to_string(), ip.port() as u32) {
+                    Ok(nt) => { nt },
+                    Err(e) => {
+                        let reason = DisconnectReason::ConnectionFailed(e.to_string());
+                        self.disconnect_reason = Some(reason.clone());
+                        return Err(Box::new(reason));
+                    }
+                };
+                let connection: Box<dyn PacketConnection + Send> = match &self.recording_directory {
+                    Some(directory) => {
+                        let timestamp = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map(|t| t.as_secs()).unwrap_or(0);
+                        let path = directory.join(format!("{timestamp}.{RECORDING_EXTENSION}"));
+                        log::warn!("Recording packets to {}", path.display());
+                        Box::new(PacketRecorder::create(network_thread, path)?)
+                    },
+                    None => Box::new(network_thread),
+                };
+                self.start_session(connection)
+            },
+            GameState::InGame { test_world, server_thread } => {
+                return Ok(());
+            },
+            GameState::JoiningServer { server_thread, test_world } => {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Play a recorded session back as if connected to a server
+    pub fn join_replay(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let path = match (&self.game_state, &self.replay) {
+            (GameState::MainMenu, Some(path)) => path.clone(),
+            _ => { return Ok(()); }
+        };
+        let connection = ReplayConnection::open(path, self.replay_speed)?;
+        self.start_session(Box::new(connection))
+    }
+
+    /** Start an integrated server on the selected world and join it like any other server
+     *  A world that does not exist yet is generated, the server keeps its seed in the level so it stays the same
+     */
+    pub fn join_singleplayer(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        if !matches!(self.game_state, GameState::MainMenu) {
+            return Ok(());
+        }
+        self.disconnect_reason = None;
+        let Some(directory) = world_directory(&self.saves_directory, &self.world_name) else {
+            return Err(format!("Invalid world name {:?}", self.world_name).into());
+        };
+        log::warn!("{} world {}", if directory.exists() { "Loading" } else { "Generating" }, directory.display());
+        std::fs::create_dir_all(&directory)?;
+        let config = ServerConfig::load(&directory.join(SERVER_PROPERTIES_FILE))?;
+        let server = IntegratedServer::open(&config, &directory, Box::new(FlatGenerator::default()))?;
+        let connection = server.connect();
+        self.integrated_server = Some(server);
+        let result = self.start_session(Box::new(connection));
+        if result.is_err() {
+            self.integrated_server = None;
+        }
+        result
+    }
+
+    /// Log in through a connection and start ticking the world with it
+    fn start_session(&mut self, mut network_thread: Box<dyn PacketConnection + Send>) -> Result<(), Box<dyn std::error::Error>> {
+        match &self.game_state {
+            GameState::MainMenu => {
+                self.game_state.to_joining_server();
                 let mut test_world = TestWorld::new(CHUNK_HEIGHT, &self.registry.read().unwrap());
-                let mut network_thread = join_server(self.username.clone(), 14, ip.ip().to_string(), ip.port() as u32, &mut test_world)?;
+                test_world.connection_state = ConnectionStateMachine::new(self.connection_timeouts);
+                if let Err(reason) = join_server(self.username.clone(), orange_rs::packets::prot14::PROTOCOL_VERSION, &self.session_auth, network_thread.as_ref(), &mut test_world) {
+                    network_thread.stop();
+                    self.disconnect_reason = Some(reason.clone());
+                    self.game_state.to_main_menu();
+                    return Err(Box::new(reason));
+                }
                 let test_world_o = Arc::new(RwLock::new(test_world));
 
 
@@ -183,7 +366,7 @@ impl OrangeClient {
                     }
 
                     if let Ok(mut test_world) = test_world_copy.write() {
-                        test_world.tick(&network_thread);
+                        test_world.tick(network_thread.as_ref());
                     }
                     tick_time = tick_time_now;
                 });
@@ -227,6 +410,7 @@ impl OrangeClient {
             },
             GameState::JoiningServer { test_world, server_thread } => {
                 self.game_state.to_main_menu();
+                self.integrated_server = None;
             }
         }
     }
@@ -238,6 +422,7 @@ impl OrangeClient {
             },
             GameState::InGame { test_world, server_thread } => {
                 self.game_state.to_main_menu();
+                self.integrated_server = None;
             },
             GameState::JoiningServer { test_world, server_thread } => {
                 return;
@@ -245,6 +430,20 @@ impl OrangeClient {
         }
     }
 
+    /// Return to the main menu once the world's connection ended, keeping the reason to show there
+    fn check_disconnected(&mut self) {
+        let reason = match &self.game_state {
+            GameState::InGame { test_world, server_thread } => test_world.read().ok().and_then(|world| world.get_disconnect_reason().cloned()),
+            _ => None,
+        };
+        if let Some(reason) = reason {
+            log::warn!("Disconnected from the server: {reason}");
+            self.disconnect_reason = Some(reason);
+            self.game_state.to_main_menu();
+            self.integrated_server = None;
+        }
+    }
+
     pub fn update(&mut self) {
         // match self.game_state {
         //     GameState::MainMenu => {
@@ -277,6 +476,7 @@ impl RineApplication for OrangeClient {
 
         let orange_options_path = home_path.join("options.toml");
         let orange_assets_path = home_path.join("assets");
+        let saves_directory = home_path.join("saves");
 
         // Get or default the options
         let orange_options: OrangeOptions = orange_options_path.exists()
@@ -307,14 +507,35 @@ impl RineApplication for OrangeClient {
             mc_resource_handler::create_resources(&mut client, device, queue, config);
             mc_resource_handler::load_binary_resources(&mut client, device, queue);
         }
-        let registry = Arc::new(RwLock::new(Registry::load_from(orange_rs::game_version::GameVersion::B173)));
+        let resources = load_resource_packs(&home_path.join("resourcepacks"), orange_options.resource_packs());
+        let registry = Arc::new(RwLock::new(Registry::load_with_resources(orange_rs::game_version::GameVersion::B173, &resources)));
+        if let Err(e) = mc_resource_handler::load_terrain_atlas(&mut client, window_client.device(), window_client.queue(), &resources) {
+            log::warn!("Using the terrain atlas of the binary resources: {e}");
+        }
+        let animations = Self::load_animations(&client, &registry.read().unwrap(), &resources);
 
         // The tessellator to be used to mesh the chunks, intended for multithreaded usage (TODO)
         let shared_tessellator = Arc::new(RwLock::new(TerrainTessellator::new()));
 
         let tessellate_queue = VecDeque::<IVec3>::new();
 
-        let username = cli.username.or_else(|| Some(orange_options.offline_username().to_string()) ).unwrap_or_else(||String::from("Dev"));
+        let recording_directory = cli.record.then(|| home_path.join("recordings"));
+        if let Some(directory) = &recording_directory {
+            if let Err(e) = std::fs::create_dir_all(directory) {
+                log::error!("Could not create recordings folder! {e}");
+            }
+        }
+        let replay_speed = match cli.replay_speed {
+            Some(speed) if speed <= 0.0 => ReplaySpeed::Stepped,
+            Some(speed) if speed != 1.0 => ReplaySpeed::Accelerated(speed),
+            _ => ReplaySpeed::RealTime,
+        };
+
+        let username = cli.username
+            .or_else(|| orange_options.credentials().selected_account().map(|account| account.username.clone()))
+            .or_else(|| Some(orange_options.offline_username().to_string()))
+            .unwrap_or_else(||String::from("Dev"));
+        let session_auth = orange_options.credentials().session_auth(&username);
         Self {
             username,
             game_state: GameState::MainMenu,
@@ -327,11 +548,25 @@ impl RineApplication for OrangeClient {
             tessellate_queue,
             server_ip: param_ip,
             server_port: param_port.to_string(),
-            debug: false
+            debug: false,
+            recording_directory,
+            connection_timeouts: orange_options.connection_timeouts(),
+            session_auth,
+            disconnect_reason: None,
+            replay: cli.replay,
+            replay_speed,
+            saves_directory,
+            world_name: String::from("world"),
+            integrated_server: None,
+            resources,
+            animations,
+            animation_time: instant::Instant::now(),
         }
     }
 
     fn draw(&mut self, window_client: &rine::RineWindowClient, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView) {
+        self.check_disconnected();
+        self.tick_animations(window_client.queue());
         let device = window_client.device();
         let client = &self.client;
 
@@ -380,7 +615,7 @@ impl RineApplication for OrangeClient {
 
                     render_pass.set_pipeline(client.get_pipeline(TERRAIN_OPAQUE_PIPELINE).unwrap());
                     render_pass.set_bind_group(0, client.get_bind_group(CAMERA_BIND_GROUP_NAME).unwrap(), &[]);
-                    render_pass.set_bind_group(1, client.get_texture("terrain.png").bind_group(), &[]);
+                    render_pass.set_bind_group(1, client.get_texture(TERRAIN_ATLAS_TEXTURE_NAME).bind_group(), &[]);
                     render_pass.set_bind_group(2, client.get_texture(LIGHTMAP_TEXTURE_NAME).bind_group(), &[]);
 
                     let directions = client.camera.vectors();
@@ -484,6 +719,7 @@ impl RineApplication for OrangeClient {
     }
 
     fn handle_event<T>(&mut self, event: &winit::event::Event<T>, control_flow: &mut winit::event_loop::ControlFlow, window_client: &mut rine::RineWindowClient) {
+        let mut reload_resources = false;
         if let GameState::InGame { test_world, server_thread } = &self.game_state {
 
             let event_helper = &mut self.winit_input_helper;
@@ -534,6 +770,10 @@ impl RineApplication for OrangeClient {
                 if event_helper.key_pressed(Key::F3) {
                     self.debug = !self.debug;
                 }
+                // F3 + T, like vanilla
+                if event_helper.key_held(Key::F3) && event_helper.key_pressed(Key::T) {
+                    reload_resources = true;
+                }
                 if event_helper.key_held(Key::Space) {
                     client
                         .camera_controller
@@ -559,6 +799,9 @@ impl RineApplication for OrangeClient {
                 client.update(self.render_time.elapsed_time() as f32, window_client.queue());
             }
         }
+        if reload_resources {
+            self.reload_resources(window_client);
+        }
     }
 
     #[cfg(feature = "egui-int")]
@@ -605,12 +848,29 @@ impl RineApplication for OrangeClient {
             GameState::MainMenu => {
                 egui::Window::new("Orange Window").auto_sized().show(ctx, |ui| {
                     ui.label(&self.username);
+                    if let Some(reason) = &self.disconnect_reason {
+                        ui.label(format!("{reason}"));
+                    }
                     ui.label("Server Ip:");
                     ui.text_edit_singleline(&mut self.server_ip);
                     egui::text_edit::TextEdit::singleline(&mut self.server_port).char_limit(5).show(ui).response.changed();
                     if ui.button("Join Server").clicked() {
                         self.join_server_connect();
                     } 
+                    ui.separator();
+                    ui.label("World:");
+                    ui.text_edit_singleline(&mut self.world_name);
+                    if ui.button("Singleplayer").clicked() {
+                        if let Err(e) = self.join_singleplayer() {
+                            log::error!("Failed to start singleplayer: {e}");
+                        }
+                    }
+                    if self.replay.is_some() && ui.button("Play Recording").clicked() {
+                        if let Err(e) = self.join_replay() {
+                            log::error!("Failed to play recording: {e}");
+                            self.game_state.to_main_menu();
+                        }
+                    }
                 });
             },
         }
@@ -625,8 +885,6 @@ impl RineApplication for OrangeClient {
  * - Finish models for block, 
  * - Tints for leaves and grass (implement the entire biome system...),
  * - Fix bugs in lighting and ao (artifacts at ll=0/1, ao having sharp corners)
- * - Fix ao with model rotation
- * - Fix rotation of elements/faces on x axis
  * - Implement gamma curve (brightness)
  * - Implement GUIs
  * - Implement Huds
@@ -652,3 +910,19 @@ fn main() {
 
     rine::start_rine_application::<OrangeClient>();
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::world_directory;
+
+    #[test]
+    fn worlds_stay_in_the_saves_directory() {
+        let saves = Path::new("saves");
+        assert_eq!(world_directory(saves, "New World"), Some(saves.join("New World")));
+        for name in ["", ".", "..", "../world", "a/b", "a\\b", "/world"] {
+            assert_eq!(world_directory(saves, name), None, "{name}");
+        }
+    }
+}
@@ -1,14 +1,36 @@
+use std::time::Duration;
+
+use orange_rs::network::state::ConnectionTimeouts;
 use serde_derive::{Deserialize, Serialize};
 
+use crate::credentials::CredentialsStore;
+
 #[derive(Deserialize, Serialize)]
 pub struct OrangeOptions {
     server_ip: String,
     offline_username: String,
+    #[serde(default = "default_handshake_timeout")]
+    handshake_timeout_secs: u64,
+    #[serde(default = "default_read_timeout")]
+    read_timeout_secs: u64,
+    #[serde(default)]
+    credentials: CredentialsStore,
+    /// Zips or directories in the resourcepacks folder, each one above the ones before it
+    #[serde(default)]
+    resource_packs: Vec<String>,
+}
+
+fn default_handshake_timeout() -> u64 {
+    ConnectionTimeouts::default().handshake.as_secs()
+}
+
+fn default_read_timeout() -> u64 {
+    ConnectionTimeouts::default().read.as_secs()
 }
 
 impl OrangeOptions {
     pub fn new() -> Self {
-        Self { server_ip: String::new(), offline_username: "".into() }
+        Self { server_ip: String::new(), offline_username: "".into(), handshake_timeout_secs: default_handshake_timeout(), read_timeout_secs: default_read_timeout(), credentials: CredentialsStore::default(), resource_packs: vec![] }
     }
     pub fn server_ip(&self) -> &str {
         &self.server_ip
@@ -16,4 +38,13 @@ impl OrangeOptions {
     pub fn offline_username(&self) -> &str {
         &self.offline_username
     }
+    pub fn credentials(&self) -> &CredentialsStore {
+        &self.credentials
+    }
+    pub fn resource_packs(&self) -> &[String] {
+        &self.resource_packs
+    }
+    pub fn connection_timeouts(&self) -> ConnectionTimeouts {
+        ConnectionTimeouts { handshake: Duration::from_secs(self.handshake_timeout_secs), read: Duration::from_secs(self.read_timeout_secs) }
+    }
 }
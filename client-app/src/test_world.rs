@@ -1,14 +1,14 @@
-use std::io::Read;
 use legion::EntityStore;
-use orange_networking::network_interface::NetworkThread;
 use orange_rs::minecraft::prot14::generate_block_to_state_map;
+use orange_rs::minecraft::prot14::map_chunk::{decode_map_chunk, MapChunkRegion};
 use orange_rs::minecraft::registry::Registry;
-use orange_rs::util::nibble;
-use ultraviolet::{IVec2, IVec3, Vec3};
+use orange_rs::network::PacketConnection;
+use orange_rs::network::state::{ConnectionStateMachine, ConnectionTimeouts, DisconnectReason};
+use ultraviolet::{IVec3, Vec3};
 use orange_rs::entities::{EntityController, EntityTransform};
 use orange_rs::packets::prot14::{MultiBlockChangeData, Packet};
-use orange_rs::util::pos::{BlockPos, EntityPos, NewChunkPosition};
-use orange_rs::world::chunk::{Chunk, CHUNK_SECTION_AXIS_SIZE, TBlockData};
+use orange_rs::util::pos::{BlockPos, EntityPos};
+use orange_rs::world::chunk::{Chunk, TBlockData};
 use orange_rs::world::{ChunkStorage, ChunkStoragePlanar, ChunkStorageTrait};
 use rustc_hash::FxHashMap as HashMap;
 
@@ -25,6 +25,8 @@ pub struct TestWorld {
     pub player: Option<legion::Entity>,
 
     block_to_state_map: HashMap<u16, usize>,
+    chat_messages: Vec<String>,
+    pub connection_state: ConnectionStateMachine,
 }
 
 impl TestWorld {
@@ -43,6 +45,8 @@ impl TestWorld {
             entities: entity_world,
             player: None,
             block_to_state_map,
+            chat_messages: vec![],
+            connection_state: ConnectionStateMachine::new(ConnectionTimeouts::default()),
         }
     }
 
@@ -86,7 +90,19 @@ impl TestWorld {
         self.has_weather
     }
 
-    pub fn tick(&mut self, network_thread: &NetworkThread<Packet>) {
+    pub fn get_chat_messages(&self) -> &[String] {
+        &self.chat_messages
+    }
+
+    /// Why the connection to the server ended, if it did
+    pub fn get_disconnect_reason(&self) -> Option<&DisconnectReason> {
+        self.connection_state.disconnect_reason()
+    }
+
+    pub fn tick<C: PacketConnection + ?Sized>(&mut self, network_thread: &C) {
+        if self.connection_state.is_disconnected() {
+            return;
+        }
         let (stance, on_ground) = if let Some(controller) = self.get_player_controller() {
             (controller.stance, controller.on_ground)
         } else { (-1.6, false) };
@@ -106,154 +122,168 @@ impl TestWorld {
         }
         network_thread.send_packet(Packet::KeepAlive);
         for packet in network_thread.get_packets() {
-            match packet {
-                Packet::KeepAlive => { network_thread.send_packet(Packet::KeepAlive {}); },
-                Packet::Handshake { handshake_data } => { log::warn!("Unexpectedly received a handshake packet! This is not supposed to happen after login!"); },
-                Packet::Login { protocol, username, seed, dimension } => { log::warn!("Unexpectedly received a login packet! This is not supposed to happen after login!"); },
-                Packet::Chat { chat_data } => { log::warn!("[Chat]{chat_data}"); },
-                Packet::TimeUpdate { time } => { self.set_time(time); },
-                Packet::EntityChangeEquipment { entity_id, equipment_slot, item_id, item_damage } => {
-                    // warn!("Entity Change Equipment");
-                },
-                Packet::SpawnPosition { x, y, z } => { self.set_spawn_point(BlockPos::new(x, y, z)); },
-                Packet::InteractWithEntity { user, entity, is_left_click } => {
-                    // warn!("Interact with entity");
-                },
-                Packet::UpdateHealth { health } => { if health == 0 { network_thread.send_packet(Packet::Respawn { world: self.get_dimension_id() }); } },
-                Packet::Respawn { world } => { self.set_dimension_id(world); }, // leave the respawn
-                Packet::PlayerOnGround { on_ground } => { self.set_player_on_ground(on_ground); },
-                Packet::PlayerPosition { x, y, stance, z, on_ground } => {
-                    // warn!("Player Position packet");
-                },
-                Packet::PlayerLook { yaw, pitch, on_ground } => {
-                    // warn!("Player Look packet");
-                },
-                Packet::PlayerPositionAndLook { x, y_c_stance_s, stance_c_y_s, z, yaw, pitch, on_ground } => {
-                    // warn!("Received Stance: {stance_c_y_s}, received y: {y_c_stance_s}");
-                    self.set_player_position(EntityPos::new(x as f32, y_c_stance_s as f32, z as f32));
-                    self.set_player_look(Vec3::new(yaw, pitch, 0.0));
-                    self.set_player_on_ground(on_ground);
-                    self.set_player_stance(y_c_stance_s - stance_c_y_s);
-                    network_thread.send_packet(Packet::PlayerPositionAndLook { x, y_c_stance_s: stance_c_y_s, stance_c_y_s: y_c_stance_s, z, yaw, pitch, on_ground });
-                },
-                Packet::PlayerDigging { status, x, y, z, face } => {
-                    // warn!("Player Digging: {status}");
-                },
-                Packet::PlayerUse { x, y, z, direction, item_data } => {
-                    // warn!("Player Use");
-                },
-                Packet::PlayerChangeSlot { slot } => {
-                    // warn!("Player Change Slot");
-                },
-                Packet::PlayerUseBed { entity, in_bed, x, y, z } => {
-                    // warn!("Player Use Bed");
-                },
-                Packet::Animation { entity, animat } => {
-                    // warn!("Animation");
-                },
-                Packet::EntityAction { entity, action } => {
-                    // warn!("Entity Action");
-                },
-                Packet::NamedEntitySpawn { entity, name, x, y, z, rotation, pitch, held_item } => {
-                    // warn!("{name} spawned");
-                },
-                Packet::PickupSpawn { entity, item, count, damage_meta, x, y, z, rotation, pitch, roll } => {
-                    // warn!("Pickup Spawned");
-                },
-                Packet::CollectItem { item_entity, collector_entity } => {
-                    // warn!("Collect Item");
-                },
-                Packet::CreateNonMobEntity { entity, entity_type, x, y, z, unknown } => {
-                    // warn!("Create NonMob Entity");
-                },
-                Packet::SpawnMob { entity, entity_type, x, y, z, yaw, pitch, meta } => {
-                    // warn!("Spawn Mob");
-                },
-                Packet::EntityPaintings { entity, title, x, y, z, direction } => {
-                    // warn!("Entity Painting {title}");
-                },
-                Packet::UpdatePosition { strafe, forward, pitch, yaw, unk, is_jumping } => {
-                    // warn!("UpdatePosition");
-                },
-                Packet::EntityVelocity { entity, vel_x, vel_y, vel_z } => {
-                    // warn!("Entity Velocity");
-                },
-                Packet::DestroyEntity { entity } => {
-                    // warn!("Destroy Entity");
-                },
-                Packet::Entity { entity } => {
-                    // warn!("Spawn {entity}");
-                },
-                Packet::EntityMoveRelative { entity, dx, dy, dz } => {
-                    // warn!("Entity Move Rel");
-                },
-                Packet::EntityLook { entity, yaw, pitch } => {
-                    // warn!("Entitiy Look");
-                },
-                Packet::EntityLookMoveRelative { entity, dx, dy, dz, yaw, pitch } => {
-                    // warn!("Entity Move Look");
-                },
-                Packet::EntityTeleport { entity, x, y, z, yaw, pitch } => {
-                    // warn!("Entity Teleport");
-                },
-                Packet::EntityStatus { entity, status } => {
-                    // warn!("Entity Status");
-                },
-                Packet::AttachEntity { entity, vehicle_entity } => {
-                    // warn!("Attach Entity");
-                },
-                Packet::EntityMeta { entity, meta } => {
-                    // warn!("Entity Meta");
-                },
-                Packet::PreChunk { x, z, mode } => {
-                },
-                Packet::MapChunk { x, y, z, size_x, size_y, size_z, compressed_data } => {
-                    self.handle_map_chunk(x, y as i32, z, size_x, size_y, size_z, compressed_data);
-                },
-                Packet::MultiBlockChange { chunk_x, chunk_z, coords_type_metadata_array } => {
-                    // warn!("Multi Block Change");
-                    self.set_blocks(chunk_x, chunk_z, coords_type_metadata_array);
-                },
-                Packet::BlockChange { x, y, z, block_type, metadata } => {
-                    // warn!("Block Change");
-                    self.set_block(x, y as i32, z, block_type as u8, metadata as u8);
-                },
-                Packet::BlockAction { x, y, z, instrument_or_state, pitch_or_direction } => {
-                    // warn!("Block Action");
-                },
-                Packet::Explosion { x, y, z, radius, explosion_data } => {
-                    // warn!("Explosion");
-                },
-                Packet::SoundEffect { effect_id, x, y, z, data } => {
-                    // warn!("Sound effect");
-                },
-                Packet::BedWeatherState { state_reason } => {
-                    // warn!("Weather State or Bed");
-                },
-                Packet::ThunderBolt { entity, unk_flag, x, y, z } => {
-                    // warn!("Thunder Bolt");
-                },
-                Packet::OpenContainerWindow { window_id, inventory_type, title, slot_count } => {  },
-                Packet::CloseContainerWindow { window_id } => {  },
-                Packet::ClickContainerWindow { window_id, slot, right_click, action, shift, item_id, item_count, item_uses } => {  },
-                Packet::SetContainerSlot { window_id, slot, item_data } => {
-                    // warn!("Set Slot Item");
-                },
-                Packet::SetWindowItems { window_id, window_data } => {
-                    // warn!("Set Window Item");
-                },
-                Packet::UpdateProgressBar { window_id, progress_bar, value } => {  },
-                Packet::Transaction { window_id, action_id, accepted } => {  },
-                Packet::UpdateSign { x, y, z, line_1, line_2, line_3, line_4 } => {  },
-                Packet::ItemData { item_type, item_id, item_data } => {
-                    // warn!("Item Data");
-                },
-                Packet::IncrementStatistic { statistic_id, amount } => {
-                    // warn!("Updating Statistic");
-                },
-                Packet::DisconnectKick { reason } => {
-                    // warn!("Disconnected: {reason}, stopping connection.");
-                }
+            self.handle_packet(packet, network_thread);
+        }
+        if network_thread.is_closed() {
+            self.connection_state.disconnect(DisconnectReason::ConnectionLost);
+        }
+        self.connection_state.check_timeouts();
+    }
+
+    pub fn handle_packet<C: PacketConnection + ?Sized>(&mut self, packet: Packet, network_thread: &C) {
+        if !self.connection_state.handle_packet(&packet) {
+            return;
+        }
+        match packet {
+            Packet::KeepAlive => { network_thread.send_packet(Packet::KeepAlive {}); },
+            Packet::Handshake { handshake_data } => { log::warn!("Unexpectedly received a handshake packet! This is not supposed to happen after login!"); },
+            Packet::Login { protocol, username, seed, dimension } => { log::warn!("Unexpectedly received a login packet! This is not supposed to happen after login!"); },
+            Packet::Chat { chat_data } => { log::warn!("[Chat]{chat_data}"); self.chat_messages.push(chat_data); },
+            Packet::TimeUpdate { time } => { self.set_time(time); },
+            Packet::EntityChangeEquipment { entity_id, equipment_slot, item_id, item_damage } => {
+                // warn!("Entity Change Equipment");
+            },
+            Packet::SpawnPosition { x, y, z } => { self.set_spawn_point(BlockPos::new(x, y, z)); },
+            Packet::InteractWithEntity { user, entity, is_left_click } => {
+                // warn!("Interact with entity");
+            },
+            Packet::UpdateHealth { health } => { if health == 0 { network_thread.send_packet(Packet::Respawn { world: self.get_dimension_id() }); } },
+            Packet::Respawn { world } => { self.set_dimension_id(world); }, // leave the respawn
+            Packet::PlayerOnGround { on_ground } => { self.set_player_on_ground(on_ground); },
+            Packet::PlayerPosition { x, y, stance, z, on_ground } => {
+                // warn!("Player Position packet");
+            },
+            Packet::PlayerLook { yaw, pitch, on_ground } => {
+                // warn!("Player Look packet");
+            },
+            Packet::PlayerPositionAndLook { x, y_c_stance_s, stance_c_y_s, z, yaw, pitch, on_ground } => {
+                // warn!("Received Stance: {stance_c_y_s}, received y: {y_c_stance_s}");
+                self.set_player_position(EntityPos::new(x as f32, y_c_stance_s as f32, z as f32));
+                self.set_player_look(Vec3::new(yaw, pitch, 0.0));
+                self.set_player_on_ground(on_ground);
+                self.set_player_stance(y_c_stance_s - stance_c_y_s);
+                network_thread.send_packet(Packet::PlayerPositionAndLook { x, y_c_stance_s: stance_c_y_s, stance_c_y_s: y_c_stance_s, z, yaw, pitch, on_ground });
+            },
+            Packet::PlayerDigging { status, x, y, z, face } => {
+                // warn!("Player Digging: {status}");
+            },
+            Packet::PlayerUse { x, y, z, direction, item_data } => {
+                // warn!("Player Use");
+            },
+            Packet::PlayerChangeSlot { slot } => {
+                // warn!("Player Change Slot");
+            },
+            Packet::PlayerUseBed { entity, in_bed, x, y, z } => {
+                // warn!("Player Use Bed");
+            },
+            Packet::Animation { entity, animat } => {
+                // warn!("Animation");
+            },
+            Packet::EntityAction { entity, action } => {
+                // warn!("Entity Action");
+            },
+            Packet::NamedEntitySpawn { entity, name, x, y, z, rotation, pitch, held_item } => {
+                // warn!("{name} spawned");
+            },
+            Packet::PickupSpawn { entity, item, count, damage_meta, x, y, z, rotation, pitch, roll } => {
+                // warn!("Pickup Spawned");
+            },
+            Packet::CollectItem { item_entity, collector_entity } => {
+                // warn!("Collect Item");
+            },
+            Packet::CreateNonMobEntity { entity, entity_type, x, y, z, unknown } => {
+                // warn!("Create NonMob Entity");
+            },
+            Packet::SpawnMob { entity, entity_type, x, y, z, yaw, pitch, meta } => {
+                // warn!("Spawn Mob");
+            },
+            Packet::EntityPaintings { entity, title, x, y, z, direction } => {
+                // warn!("Entity Painting {title}");
+            },
+            Packet::UpdatePosition { strafe, forward, pitch, yaw, unk, is_jumping } => {
+                // warn!("UpdatePosition");
+            },
+            Packet::EntityVelocity { entity, vel_x, vel_y, vel_z } => {
+                // warn!("Entity Velocity");
+            },
+            Packet::DestroyEntity { entity } => {
+                // warn!("Destroy Entity");
+            },
+            Packet::Entity { entity } => {
+                // warn!("Spawn {entity}");
+            },
+            Packet::EntityMoveRelative { entity, dx, dy, dz } => {
+                // warn!("Entity Move Rel");
+            },
+            Packet::EntityLook { entity, yaw, pitch } => {
+                // warn!("Entitiy Look");
+            },
+            Packet::EntityLookMoveRelative { entity, dx, dy, dz, yaw, pitch } => {
+                // warn!("Entity Move Look");
+            },
+            Packet::EntityTeleport { entity, x, y, z, yaw, pitch } => {
+                // warn!("Entity Teleport");
+            },
+            Packet::EntityStatus { entity, status } => {
+                // warn!("Entity Status");
+            },
+            Packet::AttachEntity { entity, vehicle_entity } => {
+                // warn!("Attach Entity");
+            },
+            Packet::EntityMeta { entity, meta } => {
+                // warn!("Entity Meta");
+            },
+            Packet::PreChunk { x, z, mode } => {
+            },
+            Packet::MapChunk { x, y, z, size_x, size_y, size_z, compressed_data } => {
+                self.handle_map_chunk(MapChunkRegion::from_packet(x, y, z, size_x, size_y, size_z), compressed_data);
+            },
+            Packet::MultiBlockChange { chunk_x, chunk_z, coords_type_metadata_array } => {
+                // warn!("Multi Block Change");
+                self.set_blocks(chunk_x, chunk_z, coords_type_metadata_array);
+            },
+            Packet::BlockChange { x, y, z, block_type, metadata } => {
+                // warn!("Block Change");
+                self.set_block(x, y as i32, z, block_type as u8, metadata as u8);
+            },
+            Packet::BlockAction { x, y, z, instrument_or_state, pitch_or_direction } => {
+                // warn!("Block Action");
+            },
+            Packet::Explosion { x, y, z, radius, explosion_data } => {
+                // warn!("Explosion");
+            },
+            Packet::SoundEffect { effect_id, x, y, z, data } => {
+                // warn!("Sound effect");
+            },
+            Packet::BedWeatherState { state_reason } => {
+                // warn!("Weather State or Bed");
+            },
+            Packet::ThunderBolt { entity, unk_flag, x, y, z } => {
+                // warn!("Thunder Bolt");
+            },
+            Packet::OpenContainerWindow { window_id, inventory_type, title, slot_count } => {  },
+            Packet::CloseContainerWindow { window_id } => {  },
+            Packet::ClickContainerWindow { window_id, slot, right_click, action, shift, item_id, item_count, item_uses } => {  },
+            Packet::SetContainerSlot { window_id, slot, item_data } => {
+                // warn!("Set Slot Item");
+            },
+            Packet::SetWindowItems { window_id, window_data } => {
+                // warn!("Set Window Item");
+            },
+            Packet::UpdateProgressBar { window_id, progress_bar, value } => {  },
+            Packet::Transaction { window_id, action_id, accepted } => {  },
+            Packet::UpdateSign { x, y, z, line_1, line_2, line_3, line_4 } => {  },
+            Packet::ItemData { item_type, item_id, item_data } => {
+                // warn!("Item Data");
+            },
+            Packet::IncrementStatistic { statistic_id, amount } => {
+                // warn!("Updating Statistic");
+            },
+            Packet::ServerListPing => {
+                log::warn!("Unexpectedly received a server list ping! Servers should not send this.");
+            },
+            Packet::DisconnectKick { reason } => {
+                // Kicks end the connection in the connection state before reaching here
             }
         }
     }
@@ -341,6 +371,11 @@ impl TestWorld {
         self.height
     }
 
+    pub fn get_block(&self, x: i32, y: i32, z: i32) -> Option<TBlockData> {
+        let cpos = (x >> 4, y >> 4, z >> 4);
+        self.chunk_storage.get_chunk(cpos.into()).ok().map(|chunk| chunk.get_block_at_pos((x & 15) as u32, (y & 15) as u32, (z & 15) as u32))
+    }
+
     pub fn set_block(&mut self, x: i32, y: i32, z: i32, block: u8, meta: u8) {
         let cpos = (x >> 4, y >> 4, z >> 4);
         match self.chunk_storage.get_chunk_mut(cpos.into()) {
@@ -384,85 +419,36 @@ impl TestWorld {
         }
     }
 
-    pub fn handle_map_chunk(&mut self, block_x: i32, block_y: i32, block_z: i32, size_x: i8, size_y: i8, size_z: i8, compressed_data: Vec<u8>) {
-        let size_x = size_x as usize + 1;
-        let size_y = size_y as usize + 1;
-        let size_z = size_z as usize + 1;
-
-        // The chunk's position id
-        let chunk_x = block_x >> 4;
-        let chunk_z = block_z >> 4;
-
-        // The chunk's position as a block position
-        let chunk_x_real = chunk_x << 4;
-        let chunk_z_real = chunk_z << 4;
-
-        // Starting position as inner chunk coords
-        let chunk_x_start = (block_x - chunk_x_real) as u32;
-        let chunk_y_start = block_y as u32; // Y should be an offset by nature of minecraft, in the range 0 -> 128
-        let chunk_z_start = (block_z - chunk_z_real) as u32;
-
-        let region_size = size_x * size_y * size_z;
-        let mut inflater = flate2::read::ZlibDecoder::new(compressed_data.as_slice());
-        let expected_size = (region_size * 5) >> 1;
-        let meta_start = region_size;
-        let block_light_start = (region_size * 3) >> 1;
-        let sky_light_start = region_size * 2;
-        let mut raw_data = vec![0; expected_size];
-        let num_bytes = inflater.read(&mut raw_data).unwrap();
-
-        let block_bytes = &raw_data[0..meta_start];
-        let meta_bytes = &raw_data[meta_start..block_light_start];
-        let block_light_bytes = &raw_data[block_light_start..sky_light_start];
-        let sky_light_bytes = &raw_data[sky_light_start..];
-
-        for y in 0..size_y {
-
-            let actual_y = chunk_y_start + y as u32;
-            let chunk_index = (actual_y / CHUNK_SECTION_AXIS_SIZE as u32) as i32;
-            let local_y = actual_y % CHUNK_SECTION_AXIS_SIZE as u32;
+    pub fn handle_map_chunk(&mut self, region: MapChunkRegion, compressed_data: Vec<u8>) {
+        let updates = match decode_map_chunk(region, &compressed_data) {
+            Ok(updates) => updates,
+            Err(e) => { log::error!("Dropping MapChunk at {}, {}, {}: {e}", region.x, region.y, region.z); return; }
+        };
 
-            let chunk_pos = NewChunkPosition::new(chunk_x, chunk_index, chunk_z);
-            let chunk = match self.chunk_storage.get_or_create_chunk(chunk_pos.vec, || { Chunk::create_empty() }) {
+        for update in &updates {
+            let chunk = match self.chunk_storage.get_or_create_chunk(update.section_position, || { Chunk::create_empty() }) {
                 Ok(chunk) => chunk,
                 _ => continue,
             };
 
-            for x in 0..size_x {
-                for z in 0..size_z {
-
-                    let block_index = y + (z * size_y) + (x * size_y * size_z);
-                    let data: u16 = block_bytes[block_index].into();
-
-                    let meta = nibble::nibble_get(meta_bytes, block_index);
-                    let block_light = nibble::nibble_get(block_light_bytes, block_index);
-                    let sky_light = nibble::nibble_get(sky_light_bytes, block_index);
-                    let data = data | ((meta as u16) << 8);
-
-                    let data = match self.block_to_state_map.get(&data) {
-                        Some(state) => *state,
-                        _ => { log::error!("Failed to find id: {}|{}", data & 0b11111111, data >> 8); *self.block_to_state_map.get(&19).unwrap() }
-                    };
-
-                    let x = chunk_x_start + x as u32;
-                    let y = local_y as u32;
-                    let z = chunk_z_start + z as u32;
-                    chunk.set_block_at_pos(x, y, z, data as TBlockData);
-                    chunk.set_blocklight_at_pos(x, y, z, block_light);
-                    chunk.set_skylight_at_pos(x, y, z, sky_light);
-                } // for z
-            } // for x
+            for block in &update.blocks {
+                let data = match self.block_to_state_map.get(&block.data) {
+                    Some(state) => *state,
+                    _ => { log::error!("Failed to find id: {}|{}", block.data & 0b11111111, block.data >> 8); *self.block_to_state_map.get(&19).unwrap() }
+                };
+
+                chunk.set_block_at_pos(block.x, block.y, block.z, data as TBlockData);
+                chunk.set_blocklight_at_pos(block.x, block.y, block.z, block.block_light);
+                chunk.set_skylight_at_pos(block.x, block.y, block.z, block.sky_light);
+            }
             chunk.set_dirty(true);
-        } // for y
+        }
 
         // Dirty Neighbors
-        let updated_nearby_chunk_position = [IVec2::new(chunk_x + 1, chunk_z), IVec2::new(chunk_x - 1, chunk_z), IVec2::new(chunk_x, chunk_z + 1), IVec2::new(chunk_x, chunk_z - 1)];
-        let a = chunk_y_start as i32 / CHUNK_SECTION_AXIS_SIZE as i32;
-        let b = (chunk_y_start as i32 + size_y as i32) / CHUNK_SECTION_AXIS_SIZE as i32;
-        for y in a .. b {
-            for pos in updated_nearby_chunk_position {
-                let pos = IVec3::new(pos.x, y, pos.y);
-                let _ = self.chunk_storage.get_chunk_mut(pos).and_then(|chunk| {
+        for update in &updates {
+            let pos = update.section_position;
+            for neighbor in [pos + IVec3::unit_x(), pos - IVec3::unit_x(), pos + IVec3::unit_z(), pos - IVec3::unit_z()] {
+                let _ = self.chunk_storage.get_chunk_mut(neighbor).and_then(|chunk| {
                     chunk.set_dirty(true);
                     Ok(())
                 });
@@ -470,3 +456,112 @@ impl TestWorld {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use orange_rs::game_version::GameVersion;
+    use orange_rs::minecraft::registry::Registry;
+    use orange_rs::network::auth::{SessionAuth, SessionCredentials};
+    use orange_rs::network::connection::TcpConnection;
+    use orange_rs::network::mock_server::{flat_chunk, MockLogin, MockServer, MockServerAction};
+    use orange_rs::network::mock_session::MockSessionServer;
+    use orange_rs::network::state::{ConnectionStateMachine, ConnectionTimeouts, DisconnectReason};
+    use orange_rs::packets::prot14::{Packet, PROTOCOL_VERSION};
+    use orange_rs::world::chunk::TBlockData;
+
+    use super::TestWorld;
+
+    /// No account, joining an online mode server would fail
+    fn offline_auth() -> SessionAuth {
+        SessionAuth { join_endpoint: String::from("http://127.0.0.1:1/unused"), credentials: None }
+    }
+
+    fn online_auth(server: &MockSessionServer) -> SessionAuth {
+        SessionAuth { join_endpoint: server.url("/game/joinserver.jsp"), credentials: Some(SessionCredentials { username: String::from("Dev"), session_id: String::from("12345") }) }
+    }
+
+    fn state_of(world: &TestWorld, block: u8, meta: u8) -> Option<TBlockData> {
+        world.block_to_state_map.get(&(block as u16 | ((meta as u16) << 8))).map(|state| *state as TBlockData)
+    }
+
+    #[test]
+    fn plays_scripted_session() {
+        let server = MockServer::start(MockLogin::default(), vec![
+            MockServerAction::SendAll(flat_chunk(0, 0, 4, 1)),
+            MockServerAction::Send(Packet::BlockChange { x: 1, y: 4, z: 1, block_type: 3, metadata: 0 }),
+            MockServerAction::Send(Packet::Chat { chat_data: String::from("Hello") }),
+            MockServerAction::Send(Packet::PlayerPositionAndLook { x: 8.5, y_c_stance_s: 5.62, stance_c_y_s: 4.0, z: 8.5, yaw: 90.0, pitch: 0.0, on_ground: true }),
+            MockServerAction::Expect { description: "position echo", matches: |packet| matches!(packet, Packet::PlayerPositionAndLook { y_c_stance_s, .. } if *y_c_stance_s == 4.0), timeout: Duration::from_secs(5) },
+            MockServerAction::Kick(String::from("Server closed")),
+        ]).unwrap();
+
+        let registry = Registry::load_custom(|registry| GameVersion::B173.load_blocks(registry));
+        let mut world = TestWorld::new(8, &registry);
+        let connection = TcpConnection::connect(server.address()).unwrap();
+        crate::join_server(String::from("Dev"), PROTOCOL_VERSION, &offline_auth(), &connection, &mut world).unwrap();
+
+        let start = Instant::now();
+        while world.get_disconnect_reason().is_none() && start.elapsed() < Duration::from_secs(5) {
+            world.tick(&connection);
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        let received = server.join().unwrap();
+
+        assert_eq!(world.get_disconnect_reason(), Some(&DisconnectReason::Kicked(String::from("Server closed"))));
+        assert_eq!(world.get_chat_messages(), ["Hello"]);
+        assert_eq!(world.get_time(), 6000);
+        assert_eq!(world.get_block(0, 0, 0), state_of(&world, 1, 0));
+        assert_eq!(world.get_block(15, 3, 15), state_of(&world, 1, 0));
+        assert_eq!(world.get_block(1, 4, 1), state_of(&world, 3, 0));
+        assert_eq!(world.get_block(2, 4, 2), state_of(&world, 0, 0));
+        assert_eq!(world.get_block(16, 0, 0), None);
+
+        assert!(matches!(received[0], Packet::Handshake { .. }));
+        assert!(matches!(received[1], Packet::Login { protocol: PROTOCOL_VERSION, .. }));
+        assert!(received.iter().any(|packet| matches!(packet, Packet::PlayerPositionAndLook { x, y_c_stance_s, stance_c_y_s, .. } if *x == 8.5 && *y_c_stance_s == 4.0 && *stance_c_y_s == 5.62)));
+    }
+
+    #[test]
+    fn login_times_out_on_silent_server() {
+        // Accepts the connection but never answers the handshake
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let connection = TcpConnection::connect(listener.local_addr().unwrap()).unwrap();
+
+        let registry = Registry::load_custom(|registry| GameVersion::B173.load_blocks(registry));
+        let mut world = TestWorld::new(8, &registry);
+        world.connection_state = ConnectionStateMachine::new(ConnectionTimeouts { handshake: Duration::from_millis(100), read: Duration::from_secs(5) });
+        let result = crate::join_server(String::from("Dev"), PROTOCOL_VERSION, &offline_auth(), &connection, &mut world);
+        assert_eq!(result, Err(DisconnectReason::TimedOut("handshaking")));
+    }
+
+    #[test]
+    fn authenticates_with_online_server() {
+        let session = MockSessionServer::start(|path| String::from(if path.contains("serverId=7c0ffee") { "OK" } else { "Bad login" }));
+        let server = MockServer::start(MockLogin { server_hash: String::from("7c0ffee"), ..Default::default() }, vec![]).unwrap();
+
+        let registry = Registry::load_custom(|registry| GameVersion::B173.load_blocks(registry));
+        let mut world = TestWorld::new(8, &registry);
+        let connection = TcpConnection::connect(server.address()).unwrap();
+        crate::join_server(String::from("Dev"), PROTOCOL_VERSION, &online_auth(&session), &connection, &mut world).unwrap();
+        assert!(world.connection_state.is_playing());
+        assert_eq!(session.requests(), ["/game/joinserver.jsp?user=Dev&sessionId=12345&serverId=7c0ffee"]);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn rejected_session_stops_login() {
+        let session = MockSessionServer::start(|_| String::from("Bad login"));
+        let server = MockServer::start(MockLogin { server_hash: String::from("7c0ffee"), ..Default::default() }, vec![]).unwrap();
+
+        let registry = Registry::load_custom(|registry| GameVersion::B173.load_blocks(registry));
+        let mut world = TestWorld::new(8, &registry);
+        let connection = TcpConnection::connect(server.address()).unwrap();
+        let result = crate::join_server(String::from("Dev"), PROTOCOL_VERSION, &online_auth(&session), &connection, &mut world);
+        assert_eq!(result, Err(DisconnectReason::AuthenticationFailed(String::from("Failed to login: Bad login"))));
+        // The login is never sent
+        drop(connection);
+        assert!(server.join().is_err());
+    }
+}
@@ -0,0 +1,61 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+use env_logger::Builder;
+use log::LevelFilter;
+use orange_rs::pack_converter::ClassicPackConverter;
+use orange_rs::resource_manager::{ResourceManager, ResourcePack, ResourceType, DEFAULT_ASSETS_DIR};
+
+/// Convert a texture pack made for the b1.7.3 client into an Orange resource pack
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+struct ConverterCliArgs {
+    /// The classic texture pack, a zip or a directory
+    input: PathBuf,
+    /// Where to write the converted pack, a zip if it ends in .zip and a directory otherwise
+    output: PathBuf,
+    /// Assets with the atlases that describe the cells of terrain.png and items.png
+    #[arg(long, default_value = DEFAULT_ASSETS_DIR)]
+    assets: PathBuf,
+}
+
+fn main() {
+    Builder::new().filter_level(LevelFilter::Info).init();
+    let cli = ConverterCliArgs::parse();
+
+    let atlases = match ResourceManager::from_sources(vec![ResourceType::Dir(cli.assets.clone())]) {
+        Ok(atlases) => atlases,
+        Err(e) => {
+            log::error!("Failed to open the assets at {}: {e}", cli.assets.display());
+            std::process::exit(1);
+        },
+    };
+    let classic = match ResourcePack::open(ResourceType::from_path(cli.input.clone())) {
+        Ok(classic) => classic,
+        Err(e) => {
+            log::error!("Failed to open {}: {e}", cli.input.display());
+            std::process::exit(1);
+        },
+    };
+
+    let (pack, report) = match ClassicPackConverter::new(&atlases).convert(&classic) {
+        Ok(converted) => converted,
+        Err(e) => {
+            log::error!("Failed to convert {}: {e}", cli.input.display());
+            std::process::exit(1);
+        },
+    };
+    for sheet in &report.skipped_sheets {
+        log::warn!("Skipped {sheet}");
+    }
+
+    let written = match ResourceType::from_path(cli.output.clone()) {
+        ResourceType::Zip(path) => pack.write_to_zip(&path),
+        ResourceType::Dir(path) => pack.write_to_directory(&path).map_err(Into::into),
+    };
+    if let Err(e) = written {
+        log::error!("Failed to write {}: {e}", cli.output.display());
+        std::process::exit(1);
+    }
+    log::info!("Wrote {} textures to {}", report.textures.len(), cli.output.display());
+}
@@ -0,0 +1,103 @@
+use std::io::BufRead;
+use std::net::TcpListener;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use clap::Parser;
+use env_logger::Builder;
+use log::LevelFilter;
+use orange_rs::network::auth::{HttpSessionVerifier, DEFAULT_CHECK_ENDPOINT};
+use orange_rs::server::config::{ServerConfig, SERVER_PROPERTIES_FILE};
+use orange_rs::server::game_server::GameServer;
+use orange_rs::server::generator::FlatGenerator;
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+struct ServerCliArgs {
+    /// Address to listen on, overrides server-ip
+    #[arg(long)]
+    bind: Option<String>,
+    /// Overrides server-port
+    #[arg(short, long)]
+    port: Option<u16>,
+    /// Verify players with the session server, overrides online-mode
+    #[arg(long, num_args = 0..=1, default_missing_value = "true")]
+    online_mode: Option<bool>,
+    /// The checkserver.jsp style endpoint used in online mode
+    #[arg(long, default_value = DEFAULT_CHECK_ENDPOINT)]
+    session_endpoint: String,
+    /// Overrides level-seed
+    #[arg(long)]
+    seed: Option<String>,
+    /// Columns sent around players in every direction, overrides view-distance
+    #[arg(long)]
+    view_distance: Option<i32>,
+}
+
+fn main() {
+    Builder::new().filter_level(LevelFilter::Info).init();
+    let cli = ServerCliArgs::parse();
+
+    let directory = Path::new(".");
+    let mut config = match ServerConfig::load(&directory.join(SERVER_PROPERTIES_FILE)) {
+        Ok(config) => config,
+        Err(e) => {
+            log::error!("Failed to load {SERVER_PROPERTIES_FILE}: {e}");
+            std::process::exit(1);
+        },
+    };
+    // Flags only apply to this run, they are not written back to server.properties
+    config.server_ip = cli.bind.unwrap_or(config.server_ip);
+    config.port = cli.port.unwrap_or(config.port);
+    config.online_mode = cli.online_mode.unwrap_or(config.online_mode);
+    config.level_seed = cli.seed.unwrap_or(config.level_seed);
+    config.view_distance = cli.view_distance.unwrap_or(config.view_distance);
+
+    let verifier = Box::new(HttpSessionVerifier::new(cli.session_endpoint));
+    let mut server = match GameServer::from_config(&config, directory, Box::new(FlatGenerator::default()), verifier) {
+        Ok(server) => server,
+        Err(e) => {
+            log::error!("Failed to set up the server: {e}");
+            std::process::exit(1);
+        },
+    };
+
+    let bind = if config.server_ip.is_empty() { "0.0.0.0" } else { config.server_ip.as_str() };
+    let listener = match TcpListener::bind((bind, config.port)) {
+        Ok(listener) => listener,
+        Err(e) => {
+            log::error!("Failed to bind to {bind}:{}: {e}", config.port);
+            std::process::exit(1);
+        },
+    };
+    log::info!("Listening on {bind}:{}{}", config.port, if config.online_mode { " in online mode" } else { "" });
+    server.listen(listener);
+
+    // Console lines are commands, run on the tick thread
+    let console = server.console_sender();
+    std::thread::spawn(move || {
+        for line in std::io::stdin().lock().lines().map_while(Result::ok) {
+            if !line.trim().is_empty() && console.send(line).is_err() {
+                return;
+            }
+        }
+    });
+
+    // Ctrl-C stops the server the same way the stop command does, saving the world first
+    let running = Arc::new(AtomicBool::new(true));
+    let interrupted = running.clone();
+    if let Err(e) = ctrlc::set_handler(move || interrupted.store(false, Ordering::Release)) {
+        log::warn!("Failed to handle Ctrl-C, use the stop command to save and stop: {e}");
+    }
+
+    server.run(&running);
+    log::info!("Stopping the server");
+    match server.shutdown() {
+        Ok(saved) => { log::info!("Saved {saved} sections"); },
+        Err(e) => {
+            log::error!("Failed to save the world: {e}");
+            std::process::exit(1);
+        },
+    }
+}
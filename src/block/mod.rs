@@ -9,7 +9,7 @@ use rustc_hash::FxHashMap as HashMap;
 use crate::minecraft::identifier::Identifier;
 use crate::client::models::model::BakedModel;
 use crate::direction::Direction;
-use crate::minecraft::registry::{Registerable, Registry};
+use crate::minecraft::registry::{Register, Registerable, Registry};
 
 use self::block_factory::BlockSettings;
 use self::properties::{PropertyValueType, PropertyDefinition};
@@ -260,6 +260,14 @@ impl BlockState {
         &self.property_map
     }
 
+    /// The name of every property and of its value, the way blockstate files refer to them
+    pub fn get_property_names(&self, properties: &Register<PropertyDefinition>) -> HashMap<String, String> {
+        self.property_map.iter().filter_map(|(name, (value, definition))| {
+            let definition = properties.get_element_from_identifier(definition)?;
+            definition.value_to_name(*value).ok().map(|value| (name.clone(), value.clone()))
+        }).collect()
+    }
+
     pub fn get_block(&self) -> Rc<Block> {
         self.block.clone()
     }
@@ -1,24 +1,39 @@
+// Models and textures are plain data shared with the server, everything else needs the client feature
+#[cfg(feature = "client")]
 pub mod camera;
+#[cfg(feature = "client")]
 pub mod minecraft_client;
+#[cfg(feature = "client")]
 pub mod client_chunk;
+#[cfg(feature = "client")]
 pub mod rendering;
+#[cfg(feature = "client")]
 pub mod gui;
 pub mod models;
 pub mod resource_manager;
 pub mod textures;
 
+#[cfg(feature = "client")]
 use std::collections::HashMap;
 
+#[cfg(feature = "client")]
 use camera::{Camera, CameraController, Projection};
+#[cfg(feature = "client")]
 use crate::math_helper::angle;
+#[cfg(feature = "client")]
 use rendering::
     textures::{DepthTextureWrapper, DiffuseTextureWrapper}
 ;
+#[cfg(feature = "client")]
 use ultraviolet::Mat4;
+#[cfg(feature = "client")]
 use wgpu::BindGroupLayout;
+#[cfg(feature = "client")]
 use winit::{window::CursorGrabMode, dpi::PhysicalSize};
+#[cfg(feature = "client")]
 use crate::minecraft::mc_resource_handler::CAMERA_BUFFER_NAME;
 
+#[cfg(feature = "client")]
 pub struct Client {
     pub camera: Camera,
 
@@ -38,6 +53,7 @@ pub struct Client {
     bind_groups: HashMap<String, wgpu::BindGroup>,
 }
 
+#[cfg(feature = "client")]
 impl Client {
     pub fn new(
         device: &wgpu::Device,
@@ -1,2 +1,3 @@
 pub mod model;
-pub mod model_builder;
\ No newline at end of file
+pub mod model_builder;
+pub mod report;
@@ -1,11 +1,13 @@
-use std::ops::Rem;
-use ultraviolet::{Mat4, Vec2, Vec3};
+use ultraviolet::{IVec3, Mat3, Vec2, Vec3};
 use rustc_hash::FxHashMap as HashMap;
 
 use crate::{direction::{Direction, DIRECTIONS}, client::textures::TextureObject, minecraft::identifier::Identifier};
 
 const ONE_SIXTEENTH: f32 = 1.0 / 16.0;
+/// Variants turn models around the center of the block
+const VARIANT_ROTATION_ORIGIN: Vec3 = Vec3::new(8.0, 8.0, 8.0);
 
+#[derive(Clone)]
 pub struct ModelPoly<const SIZE:  usize> {
     pub pos: [Vec3; SIZE],
     pub uvs: [Vec2; SIZE],
@@ -24,10 +26,22 @@ impl<const SIZE: usize> ModelPoly<SIZE> {
 pub type ModelQuad = ModelPoly<4>;
 pub type ModelTriangle = ModelPoly<3>;
 
+#[derive(Clone)]
 pub struct BakedModel {
     quads: Vec<ModelQuad>,
     textures: HashMap<String, String>,
     ambient_occlusion: bool,
+    /// Models to pick from by weight for each block, the model itself is the first of them
+    alternatives: Vec<(u32, BakedModel)>,
+}
+
+/** The seed vanilla picks the random model of a block with, the same for a position every time
+ *  Like vanilla only x is multiplied as an i32, overflowing before it is widened
+ */
+pub fn position_seed(position: IVec3) -> i64 {
+    let x = position.x.wrapping_mul(3129871) as i64;
+    let seed = x ^ (position.z as i64).wrapping_mul(116129781) ^ position.y as i64;
+    seed.wrapping_mul(seed).wrapping_mul(42317861).wrapping_add(seed.wrapping_mul(11)) >> 16
 }
 
 impl BakedModel {
@@ -36,9 +50,38 @@ impl BakedModel {
             quads: vec![],
             textures: HashMap::default(),
             ambient_occlusion: true,
+            alternatives: vec![],
         }
     }
 
+    /** A model picked from `models` by their weights for every block, like the rotated grass tops of vanilla
+     *  Until one is selected it shows the first of them
+     */
+    pub fn weighted(mut models: Vec<(u32, BakedModel)>) -> Self {
+        if models.len() <= 1 {
+            return models.pop().map(|(_, model)| model).unwrap_or_else(Self::new);
+        }
+        let mut model = models[0].1.clone();
+        model.alternatives = models;
+        model
+    }
+
+    /// The model to show for the block with this seed, see `position_seed`
+    pub fn select(&self, seed: i64) -> &BakedModel {
+        let total_weight: u64 = self.alternatives.iter().map(|(weight, _)| *weight as u64).sum();
+        if total_weight == 0 {
+            return self;
+        }
+        let mut pick = seed.unsigned_abs() % total_weight;
+        for (weight, model) in &self.alternatives {
+            if pick < *weight as u64 {
+                return model;
+            }
+            pick -= *weight as u64;
+        }
+        self
+    }
+
     pub fn shapes(&self) -> &Vec<ModelQuad> {
         &self.quads
     }
@@ -46,6 +89,34 @@ impl BakedModel {
         &self.textures
     }
     pub fn ambient_occlusion(&self) -> bool { self.ambient_occlusion }
+
+    /** Add the quads of `other` to this model, for blockstates made of several parts
+     *  Quads keep the uvs they were baked with, so parts may give texture variables different textures
+     *  When either picks from weighted models, every pairing of them becomes one to pick from
+     */
+    pub fn merge(&mut self, other: BakedModel) {
+        if self.alternatives.is_empty() && other.alternatives.is_empty() {
+            self.merge_quads(other);
+            return;
+        }
+        let choices = |model: BakedModel| if model.alternatives.is_empty() { vec![(1, model)] } else { model.alternatives };
+        let mut pairs = vec![];
+        for (weight, model) in choices(std::mem::replace(self, Self::new())) {
+            for (other_weight, other_model) in choices(other.clone()) {
+                let mut paired = model.clone();
+                paired.merge_quads(other_model);
+                pairs.push((weight.saturating_mul(other_weight), paired));
+            }
+        }
+        *self = Self::weighted(pairs);
+    }
+
+    fn merge_quads(&mut self, other: BakedModel) {
+        self.quads.extend(other.quads);
+        for (variable, texture) in other.textures {
+            self.textures.entry(variable).or_insert(texture);
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -96,6 +167,36 @@ fn get_face_vertices_on_cuboid(face: Direction, points: &[Vec3; 8]) -> [Vec3; 4]
     }
 }
 
+/// The corners of a cuboid, in the order get_face_vertices_on_cuboid picks faces from
+fn cuboid_points(min_pos: Vec3, max_pos: Vec3) -> [Vec3; 8] {
+    [
+        Vec3::new(min_pos.x, max_pos.y, min_pos.z), // 0
+        Vec3::new(min_pos.x, max_pos.y, max_pos.z), // 1
+        Vec3::new(min_pos.x, min_pos.y, min_pos.z), // 2
+        Vec3::new(min_pos.x, min_pos.y, max_pos.z), // 3
+        Vec3::new(max_pos.x, max_pos.y, max_pos.z), // 4
+        Vec3::new(max_pos.x, max_pos.y, min_pos.z), // 5
+        Vec3::new(max_pos.x, min_pos.y, max_pos.z), // 6
+        Vec3::new(max_pos.x, min_pos.y, min_pos.z), // 7
+    ]
+}
+
+/** The uv, in sixteenths of the texture, a position gets on a face pointing towards `face`
+ *  These are the uvs vanilla gives faces that do not set their own, a face across a whole block runs from 0, 0 to 16, 16
+ *  with its corners in the order of get_face_vertices_on_cuboid
+ */
+fn get_face_uv_at(face: Direction, pos: Vec3) -> Vec2 {
+    let pos = pos * 16.0;
+    match face {
+        Direction::North => Vec2::new(pos.z, 16.0 - pos.y),
+        Direction::South => Vec2::new(16.0 - pos.z, 16.0 - pos.y),
+        Direction::East => Vec2::new(16.0 - pos.x, 16.0 - pos.y),
+        Direction::West => Vec2::new(pos.x, 16.0 - pos.y),
+        Direction::Up => Vec2::new(pos.x, pos.z),
+        Direction::Down => Vec2::new(16.0 - pos.x, pos.z),
+    }
+}
+
 impl VoxelModel {
     pub fn new() -> Self {
         Self {
@@ -136,59 +237,91 @@ impl VoxelModel {
         self.ambient_occlusion = ambient_occlusion;
     }
 
-    fn rotate_points(points: &mut [Vec3; 8], rescale: bool, angle: f32, axis: u8, origin: Vec3) {
-        let angle = angle.to_radians();
-        let axis = match axis {
+    fn axis_vector(axis: u8) -> Vec3 {
+        match axis {
             0 => Vec3::unit_x(),
             1 => Vec3::unit_y(),
             2 => Vec3::unit_z(),
             _ => Vec3::zero(),
-        };
-        // warn!("Rotation on axis {axis:?} by {angle} radians");
-        let rotation = Mat4::from_rotation_around(axis.xyzw(), angle).extract_rotation().normalized();
-        // the function provided by minecraft's wiki under the sapling example, but doesn't work: https://minecraft.fandom.com/wiki/Tutorials/Models#Block_models
-        // let scale = if *rescale { 1.0 + 1.0 / (angle.cos() - 1.0) } else { 1.0 };
-        let scale = if rescale { let t = 0.5 - 0.5*(4.0*angle).sin(); (1.0 - t) + t * 2.0_f32.sqrt() } else { 1.0 };
+        }
+    }
 
-        let center = origin * ONE_SIXTEENTH;
-        let scale = ((Vec3::one() - axis) * scale) + axis;
-        for i in 0..8 {
-            points[i] = points[i] - center;
+    /// Turn a vector `angle` degrees around an axis through the origin
+    fn rotate_vector(vector: Vec3, angle: f32, axis: u8) -> Vec3 {
+        Mat3::from_rotation_around(Self::axis_vector(axis), angle.to_radians()) * vector
+    }
 
-            points[i].x *= scale.x;
-            points[i].y *= scale.y;
-            points[i].z *= scale.z;
-        }
-        rotation.rotate_vecs(points);
-        for i in 0..8 {
-            points[i] += center;
+    /** Turn the corners of an element `angle` degrees around an axis through `origin`, in sixteenths of a block
+     *  With `rescale` the element is stretched across the other two axes by 1 / cos(angle), as vanilla does,
+     *  so a face spanning the block still does once turned 22.5 or 45 degrees
+     */
+    fn rotate_points(points: &mut [Vec3; 8], rescale: bool, angle: f32, axis: u8, origin: Vec3) {
+        let axis_vector = Self::axis_vector(axis);
+        let scale = if rescale { 1.0 / angle.to_radians().cos() } else { 1.0 };
+        let scale = ((Vec3::one() - axis_vector) * scale) + axis_vector;
+
+        let center = origin * ONE_SIXTEENTH;
+        for point in points.iter_mut() {
+            *point = Self::rotate_vector(*point - center, angle, axis) * scale + center;
         }
     }
 
-    fn rotate_direction(dir: Option<Direction>, angle: u8) -> Option<Direction> {
-        dir.map(|dir| {
-            match angle {
-                1 => { dir.ccw() },
-                2 => { dir.reverse_horizontal() },
-                3 => { dir.cw() },
-                _ => { dir },
+    /// Where a direction of the model faces once turned by a variant, the same way its corners are turned
+    fn rotate_direction(dir: Option<Direction>, rotation: VariantRotation) -> Option<Direction> {
+        dir.map(|dir| Direction::from_float_vector(rotation.rotate_vector(dir.get_float_vector())))
+    }
+
+    /** Put the corners of an axis aligned quad in the order get_face_vertices_on_cuboid gives them for the way it faces,
+     *  the order ambient occlusion is sampled in, keeping every uv with its corner
+     *  A turned face keeps the order of the face it was, which would shade the wrong corners
+     */
+    fn order_corners(facing: Direction, pos: [Vec3; 4], uvs: [Vec2; 4]) -> ([Vec3; 4], [Vec2; 4]) {
+        let corner_uvs = pos.map(|pos| get_face_uv_at(facing, pos));
+        let center = corner_uvs.iter().fold(Vec2::zero(), |sum, uv| sum + *uv) / 4.0;
+        let (mut ordered_pos, mut ordered_uvs) = (pos, uvs);
+        let mut filled = [false; 4];
+        for (index, corner_uv) in corner_uvs.iter().enumerate() {
+            let slot = (corner_uv.x > center.x) as usize + 2 * (corner_uv.y > center.y) as usize;
+            // A face with no area has no order to put it in
+            if filled[slot] {
+                return (pos, uvs);
             }
-        })
+            filled[slot] = true;
+            ordered_pos[slot] = pos[index];
+            ordered_uvs[slot] = uvs[index];
+        }
+        (ordered_pos, ordered_uvs)
     }
 
+    /// Quarter turns of an angle in degrees, negative angles turning the other way
     pub fn flatten_angle_to_index(angle: f32) -> u8 {
-        ((angle as u32) / 90u32).rem(4).try_into().unwrap_or(0)
+        ((angle / 90.0).round() as i32).rem_euclid(4) as u8
     }
 
-    fn find_texture_in_map(texture_strings: &HashMap<String, String>, mut tex_to_find: String) -> String {
-        let default_texture = String::from("missing");
+    fn find_texture_in_map(texture_strings: &HashMap<String, String>, tex_to_find: String) -> String {
+        Self::resolve_texture_in_map(texture_strings, &tex_to_find).unwrap_or_else(|| String::from("missing"))
+    }
 
-        while tex_to_find.starts_with("#") {
-            let a = &texture_strings.get(&tex_to_find[1..]);
-            tex_to_find = a.unwrap_or(&default_texture).clone();
+    /// Follow #variables to the texture they name, None when one is not set or they lead back to themselves
+    fn resolve_texture_in_map<'a>(texture_strings: &'a HashMap<String, String>, mut tex_to_find: &'a str) -> Option<String> {
+        // A chain longer than there are variables has gone around in a loop
+        for _ in 0..=texture_strings.len() {
+            match tex_to_find.strip_prefix('#') {
+                Some(variable) => tex_to_find = texture_strings.get(variable)?,
+                None => return Some(tex_to_find.to_string()),
+            }
         }
+        None
+    }
 
-        return tex_to_find;
+    /// The texture a face names, through the texture variables of this model
+    pub fn resolve_texture(&self, texture: &str) -> Option<String> {
+        Self::resolve_texture_in_map(&self.textures, texture)
+    }
+
+    /// The texture, or texture variable, of every face of every element
+    pub fn face_textures(&self) -> impl Iterator<Item = &str> {
+        self.elements.iter().flat_map(|element| element.faces.iter().flatten().map(|face| face.texture_variable.as_str()))
     }
 
     pub fn clear_elements(&mut self) {
@@ -196,47 +329,46 @@ impl VoxelModel {
     }
 
     pub fn bake(self, textures: &HashMap<Identifier, TextureObject>) -> BakedModel {
-        self.bake_with_rotate(None, textures)
+        self.bake_with_rotate(VariantRotation::default(), textures)
     }
 
-    pub fn bake_with_rotate(self, variant_rotation: Option<VoxelRotation>, texture_register: &HashMap<Identifier, TextureObject>) -> BakedModel {
+    pub fn bake_with_rotate(self, variant_rotation: VariantRotation, texture_register: &HashMap<Identifier, TextureObject>) -> BakedModel {
         let mut quads = vec![];
         let textures = self.textures;
         for element in &self.elements {
             let min_pos = element.from * ONE_SIXTEENTH;
             let max_pos = element.to * ONE_SIXTEENTH;
 
-            let points = &mut [
-                Vec3::new(min_pos.x, max_pos.y, min_pos.z), // 0
-                Vec3::new(min_pos.x, max_pos.y, max_pos.z), // 1
-                Vec3::new(min_pos.x, min_pos.y, min_pos.z), // 2
-                Vec3::new(min_pos.x, min_pos.y, max_pos.z), // 3
-                Vec3::new(max_pos.x, max_pos.y, max_pos.z), // 4
-                Vec3::new(max_pos.x, max_pos.y, min_pos.z), // 5
-                Vec3::new(max_pos.x, min_pos.y, max_pos.z), // 6
-                Vec3::new(max_pos.x, min_pos.y, min_pos.z), // 7
-            ];
+            let points = &mut cuboid_points(min_pos, max_pos);
+            // Faces without uvs of their own take them from where the element is before it is turned
+            let element_points = *points;
 
             if let Some(VoxelRotation{rescale , angle, axis, origin }) = &element.rotation {
                 Self::rotate_points(points, *rescale, *angle, *axis, *origin);
             }
-            let variant_rotation_angle =
-            if let Some(VoxelRotation{rescale , angle, axis, origin }) = variant_rotation {
-                Self::rotate_points(points, rescale, angle, axis, origin);
-                Self::flatten_angle_to_index(angle)
-            } else {
-                0u8
-            };
+            variant_rotation.rotate_points(points);
 
             for (index, face) in element.faces.iter().enumerate() {
                 if let Some(face) = face {
                     let face_direction = DIRECTIONS[index];
                     let pos = get_face_vertices_on_cuboid(face_direction, points);
-                    let (uv_min, uv_max) = if let Some(uv) = face.uv {
-                        (uv[0], uv[1])
+                    let facing = Self::rotate_direction(Some(face_direction), variant_rotation).unwrap_or(face_direction);
+                    let face_uvs = if let Some([uv_min, uv_max]) = face.uv {
+                        [uv_min, (uv_max.x, uv_min.y).into(), (uv_min.x, uv_max.y).into(), uv_max]
                     } else {
-                        // TODO: default uv's to the size of the face
-                        ((0.0, 0.0).into(), (16.0, 16.0).into())
+                        get_face_vertices_on_cuboid(face_direction, &element_points).map(|pos| get_face_uv_at(face_direction, pos))
+                    };
+                    // uvlocked faces turn their uvs back against the variant, so textures line up as if nothing was turned
+                    let face_uvs = if variant_rotation.uvlock {
+                        variant_rotation.lock_uvs(face_direction, facing, face_uvs)
+                    } else {
+                        face_uvs
+                    };
+                    let face_uvs = match face.rotation {
+                        1 => { [ face_uvs[2], face_uvs[0], face_uvs[3], face_uvs[1] ] },
+                        2 => { [ face_uvs[3], face_uvs[2], face_uvs[1], face_uvs[0] ] },
+                        3 => { [ face_uvs[1], face_uvs[3], face_uvs[0], face_uvs[2] ] },
+                        _ => { face_uvs },
                     };
                     let uvs = {
                         let texture = Self::find_texture_in_map(&textures, face.texture_variable.clone());
@@ -248,29 +380,29 @@ impl VoxelModel {
                             (Vec2::new(0.0, 0.0), Vec2::new(1.0, 1.0)) 
                         };
                         let uv_range = texture_extent_max - texture_extent_min;
-                        let (quad_uv_min, quad_uv_max) = (uv_min * ONE_SIXTEENTH, uv_max * ONE_SIXTEENTH);
-
-                        // let (uv_min, uv_max) = (uv_min + uv_range * quad_uv_min, uv_min + uv_range * quad_uv_max);
-                        let (uv_min, uv_max) = (texture_extent_min + uv_range * quad_uv_min, texture_extent_min + uv_range * quad_uv_max);
-
-                        [uv_min, (uv_max.x, uv_min.y).into(), (uv_min.x, uv_max.y).into(), uv_max]
-                    };
-                    let uvs = match face.rotation {
-                        1 => { [ uvs[2], uvs[0], uvs[3], uvs[1] ] },
-                        2 => { [ uvs[3], uvs[2], uvs[1], uvs[0] ] },
-                        3 => { [ uvs[1], uvs[3], uvs[0], uvs[2] ] },
-                        _ => { uvs },
+                        face_uvs.map(|uv| texture_extent_min + uv_range * (uv * ONE_SIXTEENTH))
                     };
                     let color = (1.0, 1.0, 1.0).into();
-                    let cullface = Self::rotate_direction(face.cullface, variant_rotation_angle);
-                    let ao_face = if element.rotation.is_some() { None } else { cullface.clone() };
-                    let normal = face_direction.get_float_vector();
+                    let cullface = Self::rotate_direction(face.cullface, variant_rotation);
+                    // Faces of turned elements are not along the grid, so they are neither shaded by their neighbours nor reordered
+                    // Only faces on the edge of the block, the ones culled by a neighbour, take their shade from it
+                    let (pos, uvs, ao_face) = if element.rotation.is_some() {
+                        (pos, uvs, None)
+                    } else {
+                        let (pos, uvs) = Self::order_corners(facing, pos, uvs);
+                        (pos, uvs, cullface)
+                    };
+                    let normal = match &element.rotation {
+                        Some(VoxelRotation { angle, axis, .. }) => Self::rotate_vector(face_direction.get_float_vector(), *angle, *axis),
+                        None => face_direction.get_float_vector(),
+                    };
+                    let normal = variant_rotation.rotate_vector(normal).normalized();
                     let tint_index = face.tint_index;
                     quads.push( ModelQuad { pos, uvs, texture: face.texture_variable.clone(), color, cullface, ao_face, normal, tint_index });
                 }
             }
         }
-        BakedModel { quads, textures, ambient_occlusion: self.ambient_occlusion }
+        BakedModel { quads, textures, ambient_occlusion: self.ambient_occlusion, alternatives: vec![] }
     }
 }
 
@@ -403,3 +535,343 @@ impl VoxelRotation {
         }
     }
 }
+
+/** How a blockstate variant turns its model, in quarter turns around the center of the block
+ *  Turned around the x axis first, then y, then z
+ */
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct VariantRotation {
+    x: u8,
+    y: u8,
+    z: u8,
+    uvlock: bool,
+}
+
+impl VariantRotation {
+    pub fn new(x: f32, y: f32) -> Self {
+        Self {
+            x: VoxelModel::flatten_angle_to_index(x),
+            y: VoxelModel::flatten_angle_to_index(y),
+            z: 0,
+            uvlock: false,
+        }
+    }
+    pub fn with_z(mut self, z: f32) -> Self {
+        self.z = VoxelModel::flatten_angle_to_index(z);
+        self
+    }
+    pub fn with_uvlock(mut self, uvlock: bool) -> Self {
+        self.uvlock = uvlock;
+        self
+    }
+
+    fn turns(&self) -> [(u8, u8); 3] {
+        [(0, self.x), (1, self.y), (2, self.z)]
+    }
+
+    fn rotate_points(&self, points: &mut [Vec3; 8]) {
+        for (axis, turns) in self.turns() {
+            if turns != 0 {
+                VoxelModel::rotate_points(points, false, turns as f32 * 90.0, axis, VARIANT_ROTATION_ORIGIN);
+            }
+        }
+    }
+
+    /** The uvs a face pointing towards `face` needs once turned to `facing` to show the part of the texture it did before,
+     *  worked out from where the corners of a face across the whole block, running from 0, 0 to 16, 16, end up
+     */
+    fn lock_uvs(&self, face: Direction, facing: Direction, uvs: [Vec2; 4]) -> [Vec2; 4] {
+        let mut block = cuboid_points(Vec3::zero(), Vec3::one());
+        self.rotate_points(&mut block);
+        let [origin, along_u, along_v, _] = get_face_vertices_on_cuboid(face, &block).map(|pos| get_face_uv_at(facing, pos));
+        uvs.map(|uv| origin + (along_u - origin) * (uv.x * ONE_SIXTEENTH) + (along_v - origin) * (uv.y * ONE_SIXTEENTH))
+    }
+
+    fn rotate_vector(&self, mut vector: Vec3) -> Vec3 {
+        for (axis, turns) in self.turns() {
+            if turns != 0 {
+                vector = VoxelModel::rotate_vector(vector, turns as f32 * 90.0, axis);
+            }
+        }
+        vector
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rustc_hash::FxHashMap as HashMap;
+    use ultraviolet::{IVec3, Vec2, Vec3};
+
+    use super::{get_face_uv_at, position_seed, BakedModel, VariantRotation, VoxelElement, VoxelFace, VoxelModel, VoxelRotation};
+    use crate::client::textures::TextureObject;
+    use crate::direction::{Direction, DIRECTIONS};
+    use crate::minecraft::identifier::Identifier;
+    use crate::minecraft::template_models;
+
+    fn textures() -> HashMap<Identifier, TextureObject> {
+        let mut textures = HashMap::default();
+        textures.insert(Identifier::from("block/stone"), TextureObject::AtlasTexture { internal_uv: [Vec2::new(0.0, 0.0), Vec2::new(1.0, 1.0)] });
+        textures
+    }
+
+    /// A full block with every face culled by the block it faces
+    fn cube() -> VoxelModel {
+        let element = DIRECTIONS.iter().fold(VoxelElement::new([0.0, 0.0, 0.0], [16.0, 16.0, 16.0]), |element, direction| {
+            element.with_face(VoxelFace::new("#all").with_cullface(*direction), *direction)
+        });
+        VoxelModel::new().with_texture("all", "minecraft:block/stone").with_element(element)
+    }
+
+    fn nearest_direction(vector: Vec3) -> Direction {
+        *DIRECTIONS.iter().max_by(|a, b| a.get_float_vector().dot(vector).total_cmp(&b.get_float_vector().dot(vector))).unwrap()
+    }
+
+    #[test]
+    fn rotated_cullfaces_follow_their_faces() {
+        for x in [0.0, 90.0, 180.0, 270.0] {
+            for y in [0.0, 90.0, 180.0, 270.0] {
+                let model = cube().bake_with_rotate(VariantRotation::new(x, y), &textures());
+                for quad in model.shapes() {
+                    let center = quad.pos.iter().fold(Vec3::zero(), |sum, pos| sum + *pos) / 4.0 - Vec3::broadcast(0.5);
+                    assert_eq!(quad.cullface, Some(nearest_direction(center)), "x {x} y {y}");
+                }
+            }
+        }
+    }
+
+    /// The uv of the face of `model` facing `direction` at each of its corners, by where the corner is
+    fn uvs_by_position(model: &BakedModel, direction: Direction) -> Vec<([i32; 3], [i32; 2])> {
+        let quad = model.shapes().iter().find(|quad| quad.cullface == Some(direction)).unwrap();
+        let mut uvs: Vec<_> = quad.pos.iter().zip(quad.uvs.iter())
+            .map(|(pos, uv)| ([pos.x, pos.y, pos.z].map(|c| (c * 16.0).round() as i32), [uv.x, uv.y].map(|c| (c * 16.0).round() as i32)))
+            .collect();
+        uvs.sort();
+        uvs
+    }
+
+    #[test]
+    fn uvlock_keeps_textures_in_place() {
+        let unrotated = cube().bake(&textures());
+        for angle in [90.0, 180.0, 270.0] {
+            let turned_y = cube().bake_with_rotate(VariantRotation::new(0.0, angle).with_uvlock(true), &textures());
+            let turned_x = cube().bake_with_rotate(VariantRotation::new(angle, 0.0).with_uvlock(true), &textures());
+            for (model, directions) in [(turned_y, [Direction::Up, Direction::Down]), (turned_x, [Direction::North, Direction::South])] {
+                for direction in directions {
+                    assert_eq!(uvs_by_position(&model, direction), uvs_by_position(&unrotated, direction), "{angle} {direction:?}");
+                }
+            }
+        }
+        let unlocked = cube().bake_with_rotate(VariantRotation::new(0.0, 90.0), &textures());
+        assert_ne!(uvs_by_position(&unlocked, Direction::Up), uvs_by_position(&unrotated, Direction::Up));
+    }
+
+    #[test]
+    fn position_seeds_match_vanilla() {
+        assert_eq!(position_seed(IVec3::new(3, 64, -7)), -28127101761920);
+        // x * 3129871 overflows an i32 out here
+        assert_eq!(position_seed(IVec3::new(30000000, 64, -30000000)), -35216720112214);
+    }
+
+    #[test]
+    fn selects_weighted_models_by_seed() {
+        let single = |texture: &str| VoxelModel::new().with_texture("all", texture).bake(&textures());
+        let model = BakedModel::weighted(vec![(1, single("a")), (3, single("b"))]);
+        let picked = |seed: i64| model.select(seed).textures()["all"].clone();
+        assert_eq!(picked(0), "a");
+        assert_eq!(picked(1), "b");
+        assert_eq!(picked(3), "b");
+        assert_eq!(picked(4), "a");
+        assert_eq!(picked(-5), "b");
+        assert_eq!(picked(super::position_seed((3, 64, -7).into())), picked(super::position_seed((3, 64, -7).into())));
+
+        // Merging a weighted model pairs every one of its models with the other part
+        let mut merged = single("post");
+        merged.merge(model.clone());
+        assert_eq!(merged.select(0).textures()["all"], "post");
+        assert_eq!(merged.alternatives.len(), 2);
+        assert!(BakedModel::weighted(vec![(2, single("c"))]).alternatives.is_empty());
+    }
+
+    /// The uvs of every quad in sixteenths of the texture, in the order the quads were baked
+    fn sixteenths(model: &BakedModel) -> Vec<[[i32; 2]; 4]> {
+        model.shapes().iter().map(|quad| quad.uvs.map(|uv| [(uv.x * 16.0).round() as i32, (uv.y * 16.0).round() as i32])).collect()
+    }
+
+    #[test]
+    fn default_uvs_follow_the_element() {
+        let element = DIRECTIONS.iter().fold(VoxelElement::new([2.0, 4.0, 6.0], [10.0, 12.0, 14.0]), |element, direction| {
+            element.with_face(VoxelFace::new("#all"), *direction)
+        });
+        let model = VoxelModel::new().with_texture("all", "minecraft:block/stone").with_element(element).bake(&textures());
+        assert_eq!(sixteenths(&model), [
+            [[6, 4], [14, 4], [6, 12], [14, 12]], // North
+            [[2, 4], [10, 4], [2, 12], [10, 12]], // South
+            [[6, 4], [14, 4], [6, 12], [14, 12]], // East
+            [[2, 4], [10, 4], [2, 12], [10, 12]], // West
+            [[2, 6], [10, 6], [2, 14], [10, 14]], // Up
+            [[6, 6], [14, 6], [6, 14], [14, 14]], // Down
+        ]);
+
+        // Only within the texture the face is on
+        let mut textures = textures();
+        textures.insert(Identifier::from("block/dirt"), TextureObject::AtlasTexture { internal_uv: [Vec2::new(0.5, 0.25), Vec2::new(0.75, 0.5)] });
+        let slab = VoxelModel::new().with_texture("all", "minecraft:block/dirt")
+            .with_element(VoxelElement::new([0.0, 0.0, 0.0], [16.0, 8.0, 16.0]).with_face(VoxelFace::new("#all"), Direction::North))
+            .bake(&textures);
+        assert_eq!(slab.shapes()[0].uvs, [Vec2::new(0.5, 0.375), Vec2::new(0.75, 0.375), Vec2::new(0.5, 0.5), Vec2::new(0.75, 0.5)]);
+    }
+
+    #[test]
+    fn face_rotation_turns_textures_clockwise() {
+        let face = |rotation: f32| {
+            let element = VoxelElement::new([0.0, 0.0, 0.0], [16.0, 16.0, 16.0]).with_face(VoxelFace::new("#all").with_uv([0.0, 0.0], [8.0, 4.0]).with_rotation(rotation), Direction::Up);
+            sixteenths(&VoxelModel::new().with_texture("all", "minecraft:block/stone").with_element(element).bake(&textures()))[0]
+        };
+        assert_eq!(face(0.0), [[0, 0], [8, 0], [0, 4], [8, 4]]);
+        assert_eq!(face(90.0), [[0, 4], [0, 0], [8, 4], [8, 0]]);
+        assert_eq!(face(180.0), [[8, 4], [0, 4], [8, 0], [0, 0]]);
+        assert_eq!(face(270.0), [[8, 0], [8, 4], [0, 0], [0, 4]]);
+        assert_eq!(face(-90.0), face(270.0));
+    }
+
+    #[test]
+    fn uvlock_uses_the_turned_position() {
+        // The top of half a block shows the part of the texture a full block would where it is turned to
+        let half = VoxelModel::new().with_texture("all", "minecraft:block/stone")
+            .with_element(VoxelElement::new([0.0, 0.0, 0.0], [8.0, 16.0, 16.0]).with_face(VoxelFace::new("#all"), Direction::Up));
+        let turned = half.clone().bake_with_rotate(VariantRotation::new(0.0, 90.0), &textures());
+        // Without it the texture turns with the block, its first corner now at the second
+        assert_eq!(sixteenths(&turned), [[[8, 0], [8, 16], [0, 0], [0, 16]]]);
+        let locked = half.bake_with_rotate(VariantRotation::new(0.0, 90.0).with_uvlock(true), &textures());
+        let mut locked_uvs = sixteenths(&locked)[0];
+        locked_uvs.sort();
+        assert_eq!(locked_uvs, [[0, 8], [0, 16], [16, 8], [16, 16]]);
+    }
+
+    #[test]
+    fn uvlock_turns_the_uvs_a_face_sets() {
+        // The part of the texture a face sets is turned around the middle of the texture, and laid along the world like an unturned face
+        let partial = VoxelModel::new().with_texture("all", "minecraft:block/stone")
+            .with_element(VoxelElement::new([0.0, 0.0, 0.0], [16.0, 16.0, 16.0]).with_face(VoxelFace::new("#all").with_uv([7.0, 6.0], [9.0, 8.0]), Direction::Up));
+        for (angle, uv_min) in [(0.0, (7.0, 6.0)), (90.0, (6.0, 7.0)), (180.0, (7.0, 8.0)), (270.0, (8.0, 7.0))] {
+            let locked = partial.clone().bake_with_rotate(VariantRotation::new(0.0, angle).with_uvlock(true), &textures());
+            let quad = &locked.shapes()[0];
+            for (pos, uv) in quad.pos.iter().zip(quad.uvs) {
+                let expected = Vec2::from(uv_min) + Vec2::new(pos.x, pos.z) * 2.0;
+                assert!((uv * 16.0 - expected).mag() < 1e-4, "{angle} {pos:?} {uv:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn bakes_template_uvs() {
+        let bake = |template: &VoxelModel, variable: &str| sixteenths(&template.clone().with_texture(variable, "minecraft:block/stone").bake(&textures()));
+        let half_side = [[0, 0], [16, 0], [0, 8], [16, 8]];
+        let full = [[0, 0], [16, 0], [0, 16], [16, 16]];
+        assert_eq!(bake(template_models::slab_all(), "all"), [half_side, half_side, half_side, half_side, full, full]);
+        assert_eq!(bake(template_models::cube_all(), "all"), [full; 6]);
+        assert_eq!(bake(template_models::torch(), "torch"), [
+            [[7, 6], [9, 6], [7, 8], [9, 8]],
+            [[7, 14], [9, 14], [7, 16], [9, 16]],
+            full, full, full, full,
+        ]);
+        let plate_side = [[1, 15], [15, 15], [1, 16], [15, 16]];
+        let plate_top = [[1, 1], [15, 1], [1, 15], [15, 15]];
+        assert_eq!(bake(template_models::pressure_plate_up(), "all"), [plate_side, plate_side, plate_side, plate_side, plate_top, plate_top]);
+        let upper_half = [[0, 8], [16, 8], [0, 16], [16, 16]];
+        assert_eq!(bake(template_models::stair_all(), "all"), [
+            half_side, half_side, half_side, half_side, full, full,
+            upper_half, upper_half, [[0, 8], [8, 8], [0, 16], [8, 16]], [[8, 8], [16, 8], [8, 16], [16, 16]], upper_half,
+        ]);
+        assert_eq!(bake(template_models::door_bottom_left(), "door_face"), [
+            full,
+            [[16, 0], [0, 0], [16, 16], [0, 16]],
+            [[3, 0], [0, 0], [3, 16], [0, 16]],
+            [[0, 0], [3, 0], [0, 16], [3, 16]],
+            [[16, 16], [16, 13], [0, 16], [0, 13]],
+        ]);
+    }
+
+    #[test]
+    fn only_culled_faces_are_shaded_by_neighbours() {
+        for template in [template_models::torch(), template_models::pressure_plate_up(), template_models::slab_all()] {
+            let model = template.clone().bake_with_rotate(VariantRotation::new(0.0, 90.0), &textures());
+            for quad in model.shapes() {
+                assert_eq!(quad.ao_face, quad.cullface);
+            }
+        }
+        let plate = template_models::pressure_plate_up().clone().bake(&textures());
+        assert!(plate.shapes().iter().any(|quad| quad.ao_face.is_none()));
+    }
+
+    #[test]
+    fn variant_rotations_remap_faces_on_every_axis() {
+        let turned = |axis: usize, angle: f32| match axis {
+            0 => VariantRotation::new(angle, 0.0),
+            1 => VariantRotation::new(0.0, angle),
+            _ => VariantRotation::default().with_z(angle),
+        };
+        for axis in 0..3 {
+            for angle in [90.0, 180.0, 270.0, -90.0] {
+                let model = cube().bake_with_rotate(turned(axis, angle), &textures());
+                for quad in model.shapes() {
+                    let center = quad.pos.iter().fold(Vec3::zero(), |sum, pos| sum + *pos) / 4.0 - Vec3::broadcast(0.5);
+                    let facing = nearest_direction(center);
+                    assert_eq!(quad.cullface, Some(facing), "axis {axis} angle {angle}");
+                    assert_eq!(quad.ao_face, Some(facing), "axis {axis} angle {angle}");
+                    assert!((quad.normal - facing.get_float_vector()).mag() < 1e-4, "axis {axis} angle {angle}");
+                    // Corners are in the order ambient occlusion is sampled in for the way the face now points
+                    let corners = quad.pos.map(|pos| get_face_uv_at(facing, pos));
+                    assert!(corners[0].x < corners[1].x && corners[2].x < corners[3].x, "axis {axis} angle {angle}");
+                    assert!(corners[0].y < corners[2].y && corners[1].y < corners[3].y, "axis {axis} angle {angle}");
+                }
+            }
+        }
+        // A quarter turn one way is three the other
+        let once = cube().bake_with_rotate(VariantRotation::default().with_z(90.0), &textures());
+        let thrice = cube().bake_with_rotate(VariantRotation::default().with_z(-270.0), &textures());
+        assert!(once.shapes().iter().zip(thrice.shapes()).all(|(a, b)| a.cullface == b.cullface));
+    }
+
+    #[test]
+    fn element_rotations_rescale_on_every_axis() {
+        // A face through the middle of the block, across it along the axis after `axis`
+        let faces = [Direction::North, Direction::Up, Direction::East];
+        for axis in 0..3_usize {
+            let across = (axis + 1) % 3;
+            let through = (axis + 2) % 3;
+            let mut to = [16.0; 3];
+            to[through] = 8.0;
+            let mut from = [0.0; 3];
+            from[through] = 8.0;
+            for angle in [-45.0_f32, -22.5, 22.5, 45.0] {
+                for rescale in [false, true] {
+                    let element = VoxelElement::new(from, to)
+                        .with_rotation(VoxelRotation::new(angle, axis as u8, [8.0, 8.0, 8.0], rescale))
+                        .with_face(VoxelFace::new("#all").with_cullface(faces[through]), faces[through]);
+                    let model = VoxelModel::new().with_texture("all", "minecraft:block/stone").with_element(element).bake(&textures());
+                    let quad = &model.shapes()[0];
+                    let extent = |pos: &Vec3| [pos.x, pos.y, pos.z][across];
+                    let min = quad.pos.iter().map(extent).fold(f32::MAX, f32::min);
+                    let max = quad.pos.iter().map(extent).fold(f32::MIN, f32::max);
+                    let expected = if rescale { 0.5 } else { 0.5 * angle.to_radians().cos() };
+                    assert!((min - (0.5 - expected)).abs() < 1e-4 && (max - (0.5 + expected)).abs() < 1e-4, "axis {axis} angle {angle} rescale {rescale}: {min} {max}");
+                    // The face still culls by where it is in the block, but is shaded by its own normal only
+                    assert_eq!(quad.cullface, Some(faces[through]));
+                    assert_eq!(quad.ao_face, None);
+                    assert!((quad.normal.mag() - 1.0).abs() < 1e-4);
+                    assert!((quad.normal.dot(faces[through].get_float_vector()) - angle.to_radians().cos()).abs() < 1e-4, "axis {axis} angle {angle}");
+                }
+            }
+        }
+
+        // The crossed planes of plants reach the corners they are drawn to
+        let cross = template_models::cross().clone().with_texture("cross", "minecraft:block/stone").bake(&textures());
+        for quad in cross.shapes() {
+            for pos in quad.pos {
+                assert!([0.05, 0.95].iter().any(|corner| (pos.x - corner).abs() < 1e-4), "{pos:?}");
+                assert!([0.05, 0.95].iter().any(|corner| (pos.z - corner).abs() < 1e-4), "{pos:?}");
+            }
+        }
+    }
+}
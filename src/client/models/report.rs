@@ -0,0 +1,58 @@
+use std::fmt::Display;
+
+use crate::minecraft::identifier::Identifier;
+
+/** Everything that went wrong loading the models and blockstates of the resource packs
+ *  None of it stops loading, whatever is listed is shown with the missing model or texture instead
+ */
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ModelLoadReport {
+    /// Model files that could not be read, and why
+    pub invalid_models: Vec<(Identifier, String)>,
+    /// Blockstate files, or variants and parts of them, that could not be read, and why
+    pub invalid_blockstates: Vec<(Identifier, String)>,
+    /// Models with a parent there is no model file for, and that parent
+    pub missing_parents: Vec<(Identifier, Identifier)>,
+    /// Chains of parents leading back to the model they started from, which is both first and last
+    pub parent_cycles: Vec<Vec<Identifier>>,
+    /// Models blockstates use that could not be made, and the block using them
+    pub missing_models: Vec<(Identifier, Identifier)>,
+    /// Texture variables of models in use that never resolve to a texture, by model
+    pub unresolved_textures: Vec<(Identifier, String)>,
+    /// Textures models in use name that are not in the atlas, by model
+    pub unknown_textures: Vec<(Identifier, Identifier)>,
+    /// Blocks without a blockstate file
+    pub missing_blockstates: Vec<Identifier>,
+}
+
+impl ModelLoadReport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.invalid_models.is_empty()
+            && self.invalid_blockstates.is_empty()
+            && self.missing_parents.is_empty()
+            && self.parent_cycles.is_empty()
+            && self.missing_models.is_empty()
+            && self.unresolved_textures.is_empty()
+            && self.unknown_textures.is_empty()
+            && self.missing_blockstates.is_empty()
+    }
+
+    /// Add to one of the lists unless it is already there, the same problem is often met by every state of a block
+    pub fn push_unique<T: PartialEq>(list: &mut Vec<T>, problem: T) {
+        if !list.contains(&problem) {
+            list.push(problem);
+        }
+    }
+}
+
+impl Display for ModelLoadReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} invalid models, {} invalid blockstates, {} missing parents, {} parent cycles, {} missing models, {} unresolved texture variables, {} unknown textures, {} blocks without blockstates",
+            self.invalid_models.len(), self.invalid_blockstates.len(), self.missing_parents.len(), self.parent_cycles.len(),
+            self.missing_models.len(), self.unresolved_textures.len(), self.unknown_textures.len(), self.missing_blockstates.len())
+    }
+}
@@ -5,7 +5,7 @@ use ultraviolet::{IVec3, Vec2, Vec3};
 use wgpu::{Device, util::DeviceExt};
 
 use crate::{block::{Block, BlockState}, direction::DIRECTIONS, world::chunk::{Chunk, CHUNK_SECTION_AXIS_SIZE, TLightData}};
-use crate::client::models::model::BakedModel;
+use crate::client::models::model::{position_seed, BakedModel};
 use crate::client::textures::TextureObject;
 use crate::direction::{DirectionAll, DIRECTIONS_ALL};
 use crate::minecraft::identifier::Identifier;
@@ -524,7 +524,7 @@ impl TerrainTessellator {
                     let is_transparent = block.is_transparent();
 
                     let model = match models.get(state.get_state_identifier()) {
-                        Some(model) => model,
+                        Some(model) => model.select(position_seed(chunk_pos * CHUNK_SECTION_AXIS_SIZE as i32 + IVec3::new(x as i32, y as i32, z as i32))),
                         _ => continue,
                     };
 
@@ -0,0 +1,309 @@
+use image::imageops::{self, FilterType};
+use image::{Rgba, RgbaImage};
+use rustc_hash::FxHashMap as HashMap;
+use serde_json::Value;
+
+use crate::minecraft::identifier::Identifier;
+use crate::resource_manager::{ResourceError, ResourceKind, ResourceManager};
+
+use super::stitcher::AtlasRect;
+use super::TextureObject;
+
+/// One entry of the frames list, which frame of the image to show and for how many ticks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AnimationFrame {
+    pub index: usize,
+    pub time: u32,
+}
+
+/// The animation section of a .png.mcmeta
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnimationMetadata {
+    /// Ticks each frame is shown for, unless the frame says otherwise
+    pub frametime: u32,
+    /// Blend from each frame into the next over the time it is shown
+    pub interpolate: bool,
+    /// The order frames are shown in, every frame of the image in order if there is none
+    pub frames: Option<Vec<AnimationFrame>>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+}
+
+impl AnimationMetadata {
+    /// The animation in a .png.mcmeta, None if it has no animation section
+    pub fn parse(contents: &str) -> Result<Option<Self>, ResourceError> {
+        let value: Value = serde_json::from_str(contents)?;
+        let animation = match &value["animation"] {
+            Value::Object(animation) => animation,
+            _ => return Ok(None),
+        };
+        let number = |key: &str| animation.get(key).and_then(Value::as_u64).map(|number| number as u32);
+        let frametime = number("frametime").unwrap_or(1).max(1);
+        let frames = animation.get("frames").and_then(Value::as_array).map(|frames| {
+            frames.iter().filter_map(|frame| match frame {
+                Value::Number(index) => index.as_u64().map(|index| AnimationFrame { index: index as usize, time: frametime }),
+                Value::Object(frame) => frame.get("index").and_then(Value::as_u64).map(|index| AnimationFrame {
+                    index: index as usize,
+                    time: frame.get("time").and_then(Value::as_u64).map_or(frametime, |time| (time as u32).max(1)),
+                }),
+                _ => None,
+            }).collect()
+        });
+        Ok(Some(Self {
+            frametime,
+            interpolate: animation.get("interpolate").and_then(Value::as_bool).unwrap_or(false),
+            frames,
+            width: number("width"),
+            height: number("height"),
+        }))
+    }
+}
+
+/// Which frames are shown at a tick, `progress` of the way from `current` to `next`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrameSelection {
+    pub current: usize,
+    pub next: usize,
+    pub progress: f32,
+}
+
+/// An animated texture, the frames cut from its image and where in the atlas they are shown
+pub struct AnimatedTexture {
+    identifier: Identifier,
+    rect: AtlasRect,
+    frames: Vec<RgbaImage>,
+    sequence: Vec<AnimationFrame>,
+    interpolate: bool,
+    /// Sum of the time of every frame in the sequence
+    length: u64,
+}
+
+impl AnimatedTexture {
+    /** Cut the frames out of `image`, left to right and top to bottom
+     *  Frames are squares as large as the image is narrow unless the metadata gives their size, and
+     *  are scaled to `rect` when the image was made for another resolution than the atlas
+     */
+    pub fn new(identifier: Identifier, image: &RgbaImage, metadata: &AnimationMetadata, rect: AtlasRect) -> Option<Self> {
+        let side = image.width().min(image.height());
+        let frame_width = metadata.width.unwrap_or(side).min(image.width());
+        let frame_height = metadata.height.unwrap_or(frame_width).min(image.height());
+        if frame_width == 0 || frame_height == 0 || rect.width == 0 || rect.height == 0 {
+            return None;
+        }
+        let (across, down) = (image.width() / frame_width, image.height() / frame_height);
+        let mut frames = Vec::with_capacity((across * down) as usize);
+        for row in 0..down {
+            for column in 0..across {
+                let frame = imageops::crop_imm(image, column * frame_width, row * frame_height, frame_width, frame_height).to_image();
+                frames.push(match (frame.width(), frame.height()) == (rect.width, rect.height) {
+                    true => frame,
+                    false => imageops::resize(&frame, rect.width, rect.height, FilterType::Nearest),
+                });
+            }
+        }
+
+        let sequence: Vec<AnimationFrame> = match &metadata.frames {
+            Some(sequence) => sequence.iter().copied().filter(|frame| frame.index < frames.len()).collect(),
+            None => (0..frames.len()).map(|index| AnimationFrame { index, time: metadata.frametime }).collect(),
+        };
+        if sequence.is_empty() {
+            log::warn!("The animation of {identifier} has no frames");
+            return None;
+        }
+        let length = sequence.iter().map(|frame| frame.time as u64).sum();
+        Some(Self { identifier, rect, frames, sequence, interpolate: metadata.interpolate, length })
+    }
+
+    pub fn identifier(&self) -> &Identifier {
+        &self.identifier
+    }
+
+    /// Where in the atlas the frames are shown
+    pub fn rect(&self) -> AtlasRect {
+        self.rect
+    }
+
+    /// The frames shown at `tick`, the animation loops forever
+    pub fn select(&self, tick: u64) -> FrameSelection {
+        let mut remaining = tick % self.length;
+        for (position, frame) in self.sequence.iter().enumerate() {
+            if remaining < frame.time as u64 {
+                let next = self.sequence[(position + 1) % self.sequence.len()].index;
+                let progress = if self.interpolate { remaining as f32 / frame.time as f32 } else { 0.0 };
+                return FrameSelection { current: frame.index, next, progress };
+            }
+            remaining -= frame.time as u64;
+        }
+        unreachable!("The tick is always within the length of the sequence")
+    }
+
+    /// The image shown at `tick`, blended between two frames when interpolating
+    pub fn image_at(&self, tick: u64) -> RgbaImage {
+        let selection = self.select(tick);
+        let current = &self.frames[selection.current];
+        if selection.progress == 0.0 || selection.current == selection.next {
+            return current.clone();
+        }
+        let next = &self.frames[selection.next];
+        RgbaImage::from_fn(current.width(), current.height(), |x, y| {
+            let (from, to) = (current.get_pixel(x, y).0, next.get_pixel(x, y).0);
+            Rgba(std::array::from_fn(|channel| {
+                (from[channel] as f32 + (to[channel] as f32 - from[channel] as f32) * selection.progress).round() as u8
+            }))
+        })
+    }
+}
+
+/// A part of the atlas to replace with new pixels
+pub struct TileUpdate {
+    pub rect: AtlasRect,
+    pub image: RgbaImage,
+}
+
+/** Keeps every animated texture of an atlas going, one game tick at a time
+ *  Each tick hands out the tiles that look different from the tick before, for the client to upload
+ */
+#[derive(Default)]
+pub struct AnimationTicker {
+    animations: Vec<AnimatedTexture>,
+    /// The frame selection of each animation that was last handed out
+    shown: Vec<Option<FrameSelection>>,
+    tick: u64,
+}
+
+impl AnimationTicker {
+    pub fn new(animations: Vec<AnimatedTexture>) -> Self {
+        let shown = vec![None; animations.len()];
+        Self { animations, shown, tick: 0 }
+    }
+
+    /** Find every texture in `textures` that has an animation in its .png.mcmeta
+     *  `atlas_size` is the size in pixels of the atlas the textures' uvs are in
+     */
+    pub fn load(resources: &ResourceManager, textures: &HashMap<Identifier, TextureObject>, atlas_size: (u32, u32)) -> Self {
+        let mut animations = vec![];
+        for (identifier, texture) in textures {
+            let TextureObject::AtlasTexture { internal_uv } = texture else { continue; };
+            if !resources.contains(identifier, ResourceKind::TextureMetadata) {
+                continue;
+            }
+            let loaded = resources.read_to_string(identifier, ResourceKind::TextureMetadata)
+                .and_then(|contents| AnimationMetadata::parse(&contents))
+                .and_then(|metadata| match metadata {
+                    Some(metadata) => Ok(Some((metadata, resources.read_image(identifier, ResourceKind::Texture)?))),
+                    None => Ok(None),
+                });
+            match loaded {
+                Ok(Some((metadata, image))) => {
+                    let rect = AtlasRect::from_uv(internal_uv, atlas_size.0, atlas_size.1);
+                    animations.extend(AnimatedTexture::new(identifier.clone(), &image.to_rgba8(), &metadata, rect));
+                },
+                Ok(None) => {},
+                Err(e) => { log::warn!("Failed to load the animation of {identifier}: {e}"); },
+            }
+        }
+        // In a fixed order, so ticks always upload in the same order
+        animations.sort_by(|a, b| a.identifier.get_identifier().cmp(b.identifier.get_identifier()));
+        log::info!("Loaded {} animated textures", animations.len());
+        Self::new(animations)
+    }
+
+    pub fn animations(&self) -> &[AnimatedTexture] {
+        &self.animations
+    }
+
+    pub fn current_tick(&self) -> u64 {
+        self.tick
+    }
+
+    /// The tiles to upload for the current tick, then moves on to the next
+    pub fn tick(&mut self) -> Vec<TileUpdate> {
+        let mut updates = vec![];
+        for (animation, shown) in self.animations.iter().zip(self.shown.iter_mut()) {
+            let selection = animation.select(self.tick);
+            if *shown != Some(selection) {
+                updates.push(TileUpdate { rect: animation.rect, image: animation.image_at(self.tick) });
+                *shown = Some(selection);
+            }
+        }
+        self.tick += 1;
+        updates
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{Rgba, RgbaImage};
+    use rustc_hash::FxHashMap as HashMap;
+    use ultraviolet::Vec2;
+
+    use super::{AnimatedTexture, AnimationFrame, AnimationMetadata, AnimationTicker, FrameSelection};
+    use crate::client::textures::stitcher::AtlasRect;
+    use crate::client::textures::TextureObject;
+    use crate::minecraft::identifier::Identifier;
+    use crate::resource_manager::{ResourceManager, ResourceType};
+    use crate::util::test_directory::TestDirectory;
+
+    /// A vertical strip of 2x2 frames, each a single grey level of 0, 100, 200 and so on
+    fn strip(frames: u32) -> RgbaImage {
+        RgbaImage::from_fn(2, 2 * frames, |_, y| Rgba([(y / 2 * 100) as u8, 0, 0, 255]))
+    }
+
+    #[test]
+    fn parses_animation_sections() {
+        let metadata = AnimationMetadata::parse(r#"{"animation": {"frametime": 3, "interpolate": true, "frames": [1, {"index": 0, "time": 7}, {"time": 2}]}}"#)
+            .unwrap().unwrap();
+        assert_eq!(metadata.frametime, 3);
+        assert!(metadata.interpolate);
+        assert_eq!(metadata.frames, Some(vec![AnimationFrame { index: 1, time: 3 }, AnimationFrame { index: 0, time: 7 }]));
+
+        let metadata = AnimationMetadata::parse(r#"{"animation": {}}"#).unwrap().unwrap();
+        assert_eq!((metadata.frametime, metadata.interpolate, metadata.frames), (1, false, None));
+        assert!(AnimationMetadata::parse(r#"{"texture": {"blur": true}}"#).unwrap().is_none());
+    }
+
+    #[test]
+    fn selects_and_blends_frames() {
+        let rect = AtlasRect { x: 4, y: 6, width: 2, height: 2 };
+        let metadata = AnimationMetadata::parse(r#"{"animation": {"frametime": 2, "frames": [0, 2, {"index": 1, "time": 4}, 9]}}"#).unwrap().unwrap();
+        let animation = AnimatedTexture::new(Identifier::from("block/lava"), &strip(3), &metadata, rect).unwrap();
+        // Frame 9 does not exist and is left out, the sequence is 0 for 2 ticks, 2 for 2 and 1 for 4
+        let shown: Vec<usize> = (0..10).map(|tick| animation.select(tick).current).collect();
+        assert_eq!(shown, [0, 0, 2, 2, 1, 1, 1, 1, 0, 0]);
+        assert_eq!(animation.image_at(5).get_pixel(1, 1).0, [100, 0, 0, 255]);
+
+        let metadata = AnimationMetadata::parse(r#"{"animation": {"frametime": 4, "interpolate": true}}"#).unwrap().unwrap();
+        let animation = AnimatedTexture::new(Identifier::from("block/water"), &strip(2), &metadata, rect).unwrap();
+        assert_eq!(animation.select(1), FrameSelection { current: 0, next: 1, progress: 0.25 });
+        assert_eq!(animation.select(6), FrameSelection { current: 1, next: 0, progress: 0.5 });
+        assert_eq!(animation.image_at(1).get_pixel(0, 0).0, [25, 0, 0, 255]);
+        assert_eq!(animation.image_at(6).get_pixel(0, 0).0, [50, 0, 0, 255]);
+
+        // A 16 pixel pack in a 2 pixel atlas is scaled down to fit
+        let large = RgbaImage::from_pixel(16, 32, Rgba([9, 9, 9, 255]));
+        let animation = AnimatedTexture::new(Identifier::from("block/fire"), &large, &metadata, rect).unwrap();
+        assert_eq!(animation.image_at(0).dimensions(), (2, 2));
+    }
+
+    #[test]
+    fn ticker_uploads_only_changed_tiles() {
+        let directory = TestDirectory::new("animation");
+        let textures_directory = directory.join("assets/minecraft/textures/block");
+        std::fs::create_dir_all(&textures_directory).unwrap();
+        strip(2).save(textures_directory.join("portal.png")).unwrap();
+        std::fs::write(textures_directory.join("portal.png.mcmeta"), r#"{"animation": {"frametime": 3}}"#).unwrap();
+        strip(1).save(textures_directory.join("stone.png")).unwrap();
+        let resources = ResourceManager::from_sources(vec![ResourceType::Dir(directory.to_path_buf())]).unwrap();
+
+        let mut textures = HashMap::default();
+        textures.insert(Identifier::from("block/portal"), TextureObject::AtlasTexture { internal_uv: [Vec2::new(0.5, 0.25), Vec2::new(0.75, 0.5)] });
+        textures.insert(Identifier::from("block/stone"), TextureObject::AtlasTexture { internal_uv: [Vec2::new(0.0, 0.0), Vec2::new(0.25, 0.25)] });
+        let mut ticker = AnimationTicker::load(&resources, &textures, (8, 8));
+        assert_eq!(ticker.animations().len(), 1);
+        assert_eq!(ticker.animations()[0].rect(), AtlasRect { x: 4, y: 2, width: 2, height: 2 });
+
+        let uploads: Vec<usize> = (0..7).map(|_| ticker.tick().len()).collect();
+        assert_eq!(uploads, [1, 0, 0, 1, 0, 0, 1]);
+        assert_eq!(ticker.current_tick(), 7);
+    }
+}
@@ -1,3 +1,6 @@
+pub mod animation;
+pub mod stitcher;
+
 use ultraviolet::Vec2;
 use crate::minecraft::identifier::Identifier;
 use crate::minecraft::registry::Registerable;
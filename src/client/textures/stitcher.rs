@@ -0,0 +1,376 @@
+use image::{Rgba, RgbaImage};
+use rustc_hash::FxHashMap as HashMap;
+use ultraviolet::Vec2;
+
+use crate::minecraft::identifier::Identifier;
+use crate::resource_manager::{ResourceError, ResourceKind, ResourceManager};
+
+use super::TextureObject;
+
+/// The largest atlas side most GPUs support
+pub const DEFAULT_MAX_ATLAS_SIZE: u32 = 8192;
+
+#[derive(Debug)]
+pub enum StitchError {
+    /// The textures need an atlas larger than the maximum size on a side
+    TooLarge(u32),
+    /// There was nothing to stitch
+    Empty,
+}
+
+impl std::error::Error for StitchError {}
+
+impl std::fmt::Display for StitchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::TooLarge(max_size) => write!(f, "The textures do not fit in a {max_size}x{max_size} atlas"),
+            Self::Empty => write!(f, "There are no textures to stitch"),
+        }
+    }
+}
+
+/// Where a texture was put in an atlas, in pixels of the full size atlas
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AtlasRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl AtlasRect {
+    /// The pixels covered by uvs from 0 to 1, in an atlas of the given size
+    pub fn from_uv(internal_uv: &[Vec2; 2], atlas_width: u32, atlas_height: u32) -> Self {
+        let to_pixel = |uv: f32, size: u32| (uv * size as f32).round().clamp(0.0, size as f32) as u32;
+        let (x, y) = (to_pixel(internal_uv[0].x, atlas_width), to_pixel(internal_uv[0].y, atlas_height));
+        let (x_max, y_max) = (to_pixel(internal_uv[1].x, atlas_width), to_pixel(internal_uv[1].y, atlas_height));
+        Self { x: x.min(x_max), y: y.min(y_max), width: x.abs_diff(x_max), height: y.abs_diff(y_max) }
+    }
+}
+
+/** Packs separate images of any size into one atlas, entirely on the CPU
+ *  Each texture sits in a slot of its own, with its edge pixels repeated into a border around it, so
+ *  sampling at the edge of a texture never picks up its neighbours
+ *  Slots are aligned to the size of the smallest mip level, making each mip level of a slot
+ *  come from nothing but that slot
+ */
+pub struct AtlasStitcher {
+    padding: u32,
+    max_size: u32,
+    mip_levels: u32,
+    textures: Vec<(Identifier, RgbaImage)>,
+}
+
+impl Default for AtlasStitcher {
+    fn default() -> Self {
+        Self { padding: 1, max_size: DEFAULT_MAX_ATLAS_SIZE, mip_levels: 0, textures: vec![] }
+    }
+}
+
+impl AtlasStitcher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Pixels of repeated edge around every texture
+    pub fn with_padding(mut self, padding: u32) -> Self {
+        self.padding = padding;
+        self
+    }
+
+    /// The largest the atlas may be on a side
+    pub fn with_max_size(mut self, max_size: u32) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    /// How many levels to generate below the full size atlas, limited by the smallest texture
+    pub fn with_mip_levels(mut self, mip_levels: u32) -> Self {
+        self.mip_levels = mip_levels;
+        self
+    }
+
+    /// Add a texture, replacing one that was added before with the same identifier
+    pub fn add(&mut self, identifier: Identifier, image: RgbaImage) {
+        if image.width() == 0 || image.height() == 0 {
+            log::warn!("Not adding the empty texture {identifier} to the atlas");
+            return;
+        }
+        match self.textures.iter_mut().find(|(existing, _)| *existing == identifier) {
+            Some((_, existing)) => *existing = image,
+            None => self.textures.push((identifier, image)),
+        }
+    }
+
+    /// Add every texture of `namespace` whose name starts with `prefix`, as the topmost pack has it
+    pub fn add_resources(&mut self, resources: &ResourceManager, namespace: &str, prefix: &str) -> Result<usize, ResourceError> {
+        let identifiers = resources.list(namespace, ResourceKind::Texture, prefix);
+        for identifier in &identifiers {
+            let image = resources.read_image(identifier, ResourceKind::Texture)?;
+            self.add(identifier.clone(), image.to_rgba8());
+        }
+        Ok(identifiers.len())
+    }
+
+    pub fn len(&self) -> usize {
+        self.textures.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.textures.is_empty()
+    }
+
+    /// Pack every texture into the smallest power of two atlas they fit in
+    pub fn stitch(self) -> Result<StitchedAtlas, StitchError> {
+        if self.textures.is_empty() {
+            return Err(StitchError::Empty);
+        }
+        // More levels than the smallest texture has would blend it away entirely
+        let smallest = self.textures.iter().map(|(_, image)| image.width().min(image.height())).min().unwrap_or(1).max(1);
+        let mip_levels = self.mip_levels.min(smallest.ilog2());
+        let alignment = 1 << mip_levels;
+        let slot_size = |size: u32| (size + 2 * self.padding).div_ceil(alignment) * alignment;
+
+        // Tallest first, then widest, and by identifier so the same textures always give the same atlas
+        let mut order: Vec<usize> = (0..self.textures.len()).collect();
+        order.sort_by(|&a, &b| {
+            let (a_id, a_image) = &self.textures[a];
+            let (b_id, b_image) = &self.textures[b];
+            b_image.height().cmp(&a_image.height())
+                .then(b_image.width().cmp(&a_image.width()))
+                .then_with(|| a_id.get_identifier().cmp(b_id.get_identifier()))
+        });
+        let slots: Vec<(u32, u32)> = order.iter().map(|&index| {
+            let image = &self.textures[index].1;
+            (slot_size(image.width()), slot_size(image.height()))
+        }).collect();
+
+        let area: u64 = slots.iter().map(|&(width, height)| width as u64 * height as u64).sum();
+        let mut width = slots.iter().map(|&(width, _)| width).max().unwrap_or(1).next_power_of_two();
+        let mut height = slots.iter().map(|&(_, height)| height).max().unwrap_or(1).next_power_of_two();
+        while (width as u64 * height as u64) < area {
+            if width <= height { width *= 2; } else { height *= 2; }
+        }
+        let positions = loop {
+            if width > self.max_size || height > self.max_size {
+                return Err(StitchError::TooLarge(self.max_size));
+            }
+            if let Some(positions) = Self::pack_shelves(&slots, width, height) {
+                break positions;
+            }
+            if width <= height { width *= 2; } else { height *= 2; }
+        };
+
+        let mut image = RgbaImage::new(width, height);
+        let mut rects = HashMap::default();
+        for ((&index, &(slot_width, slot_height)), (slot_x, slot_y)) in order.iter().zip(&slots).zip(positions) {
+            let (identifier, texture) = &self.textures[index];
+            // Fill the whole slot, anything outside of the texture repeats its nearest edge pixel
+            for y in 0..slot_height {
+                for x in 0..slot_width {
+                    let source_x = x.saturating_sub(self.padding).min(texture.width() - 1);
+                    let source_y = y.saturating_sub(self.padding).min(texture.height() - 1);
+                    image.put_pixel(slot_x + x, slot_y + y, *texture.get_pixel(source_x, source_y));
+                }
+            }
+            let rect = AtlasRect { x: slot_x + self.padding, y: slot_y + self.padding, width: texture.width(), height: texture.height() };
+            rects.insert(identifier.clone(), rect);
+        }
+
+        let mut mipmaps = vec![image];
+        for _ in 0..mip_levels {
+            let next = downsample(mipmaps.last().expect("There is always the full size level"));
+            mipmaps.push(next);
+        }
+        Ok(StitchedAtlas { mipmaps, rects })
+    }
+
+    /** Put slots in rows as tall as their first slot, starting a new row when one is full
+     *  Slots are sorted tallest first, so little height is lost, None if they do not all fit
+     */
+    fn pack_shelves(slots: &[(u32, u32)], width: u32, height: u32) -> Option<Vec<(u32, u32)>> {
+        let mut positions = Vec::with_capacity(slots.len());
+        let (mut x, mut y, mut shelf_height) = (0, 0, 0);
+        for &(slot_width, slot_height) in slots {
+            if x + slot_width > width {
+                (x, y, shelf_height) = (0, y + shelf_height, 0);
+            }
+            if x + slot_width > width || y + slot_height > height {
+                return None;
+            }
+            positions.push((x, y));
+            x += slot_width;
+            shelf_height = shelf_height.max(slot_height);
+        }
+        Some(positions)
+    }
+}
+
+/// Average every 2x2 block of pixels into one, an atlas always has even sides down to its last level
+fn downsample(image: &RgbaImage) -> RgbaImage {
+    let (width, height) = ((image.width() / 2).max(1), (image.height() / 2).max(1));
+    RgbaImage::from_fn(width, height, |x, y| {
+        let mut sum = [0u32; 4];
+        for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+            let pixel = image.get_pixel((x * 2 + dx).min(image.width() - 1), (y * 2 + dy).min(image.height() - 1));
+            for (total, channel) in sum.iter_mut().zip(pixel.0) {
+                *total += channel as u32;
+            }
+        }
+        Rgba(sum.map(|total| ((total + 2) / 4) as u8))
+    })
+}
+
+/// The result of stitching, the atlas image with all its mip levels and where each texture ended up
+pub struct StitchedAtlas {
+    /// The full size atlas first, each next level half the size of the one before
+    mipmaps: Vec<RgbaImage>,
+    rects: HashMap<Identifier, AtlasRect>,
+}
+
+impl StitchedAtlas {
+    /// The full size atlas
+    pub fn image(&self) -> &RgbaImage {
+        &self.mipmaps[0]
+    }
+
+    pub fn mipmaps(&self) -> &[RgbaImage] {
+        &self.mipmaps
+    }
+
+    pub fn width(&self) -> u32 {
+        self.image().width()
+    }
+
+    pub fn height(&self) -> u32 {
+        self.image().height()
+    }
+
+    pub fn rect(&self, identifier: &Identifier) -> Option<AtlasRect> {
+        self.rects.get(identifier).copied()
+    }
+
+    pub fn rects(&self) -> &HashMap<Identifier, AtlasRect> {
+        &self.rects
+    }
+
+    /// Where a texture is in the atlas, as uvs from 0 to 1
+    pub fn texture(&self, identifier: &Identifier) -> Option<TextureObject> {
+        let rect = self.rect(identifier)?;
+        let (width, height) = (self.width() as f32, self.height() as f32);
+        let internal_uv = [
+            Vec2::new(rect.x as f32 / width, rect.y as f32 / height),
+            Vec2::new((rect.x + rect.width) as f32 / width, (rect.y + rect.height) as f32 / height),
+        ];
+        Some(TextureObject::AtlasTexture { internal_uv })
+    }
+
+    /// Put the atlas itself under `atlas`, and every texture in it under its own identifier
+    pub fn register(&self, atlas: Identifier, textures: &mut HashMap<Identifier, TextureObject>) {
+        textures.insert(atlas, TextureObject::TextureAtlas {});
+        for identifier in self.rects.keys() {
+            if let Some(texture) = self.texture(identifier) {
+                textures.insert(identifier.clone(), texture);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{Rgba, RgbaImage};
+    use rustc_hash::FxHashMap as HashMap;
+
+    use super::{AtlasStitcher, StitchError};
+    use crate::client::textures::TextureObject;
+    use crate::minecraft::identifier::Identifier;
+
+    /// Every pixel tells which texture it is from and where in it
+    fn texture(id: u8, width: u32, height: u32) -> RgbaImage {
+        RgbaImage::from_fn(width, height, |x, y| Rgba([id, x as u8, y as u8, 255]))
+    }
+
+    #[test]
+    fn packs_textures_of_any_size() {
+        let mut stitcher = AtlasStitcher::new().with_padding(2);
+        let sizes = [(16, 16), (32, 16), (8, 8), (16, 64), (4, 12)];
+        for (id, (width, height)) in sizes.iter().enumerate() {
+            stitcher.add(Identifier::from(format!("block/{id}")), texture(id as u8, *width, *height));
+        }
+        let atlas = stitcher.stitch().unwrap();
+        assert!(atlas.width().is_power_of_two() && atlas.height().is_power_of_two());
+        assert_eq!(atlas.mipmaps().len(), 1);
+
+        let rects: Vec<_> = (0..sizes.len()).map(|id| atlas.rect(&Identifier::from(format!("block/{id}"))).unwrap()).collect();
+        for (id, rect) in rects.iter().enumerate() {
+            assert_eq!((rect.width, rect.height), sizes[id]);
+            assert!(rect.x >= 2 && rect.y >= 2 && rect.x + rect.width + 2 <= atlas.width() && rect.y + rect.height + 2 <= atlas.height());
+            for y in 0..rect.height {
+                for x in 0..rect.width {
+                    assert_eq!(atlas.image().get_pixel(rect.x + x, rect.y + y).0, [id as u8, x as u8, y as u8, 255]);
+                }
+            }
+            // The padding repeats the edges
+            assert_eq!(atlas.image().get_pixel(rect.x - 2, rect.y - 1).0, [id as u8, 0, 0, 255]);
+            assert_eq!(atlas.image().get_pixel(rect.x + rect.width + 1, rect.y + rect.height).0, [id as u8, rect.width as u8 - 1, rect.height as u8 - 1, 255]);
+            // Padded slots never overlap
+            for other in &rects[id + 1..] {
+                let apart = rect.x + rect.width + 4 <= other.x || other.x + other.width + 4 <= rect.x
+                    || rect.y + rect.height + 4 <= other.y || other.y + other.height + 4 <= rect.y;
+                assert!(apart, "{rect:?} overlaps {other:?}");
+            }
+        }
+
+        let mut textures = HashMap::default();
+        atlas.register(Identifier::from("block/atlas"), &mut textures);
+        assert!(matches!(textures[&Identifier::from("block/atlas")], TextureObject::TextureAtlas {}));
+        match textures[&Identifier::from("block/3")] {
+            TextureObject::AtlasTexture { internal_uv } => {
+                assert_eq!(internal_uv[0].x * atlas.width() as f32, rects[3].x as f32);
+                assert_eq!(internal_uv[1].y * atlas.height() as f32, (rects[3].y + 64) as f32);
+            },
+            _ => panic!("Textures should be in the atlas"),
+        }
+    }
+
+    #[test]
+    fn mipmaps_do_not_bleed() {
+        let colours = [[255, 0, 0, 255], [0, 255, 0, 255], [0, 0, 255, 255], [255, 255, 255, 0]];
+        let mut stitcher = AtlasStitcher::new().with_padding(1).with_mip_levels(8);
+        for (id, colour) in colours.iter().enumerate() {
+            stitcher.add(Identifier::from(format!("block/{id}")), RgbaImage::from_pixel(16, 16, Rgba(*colour)));
+        }
+        stitcher.add(Identifier::from("block/small"), RgbaImage::from_pixel(4, 8, Rgba([9, 9, 9, 255])));
+        let atlas = stitcher.stitch().unwrap();
+        // Limited by the 4 pixel wide texture
+        assert_eq!(atlas.mipmaps().len(), 3);
+        for (level, mipmap) in atlas.mipmaps().iter().enumerate() {
+            assert_eq!(mipmap.width(), atlas.width() >> level);
+            for (id, colour) in colours.iter().enumerate() {
+                let rect = atlas.rect(&Identifier::from(format!("block/{id}"))).unwrap();
+                for y in rect.y >> level..(rect.y + rect.height) >> level {
+                    for x in rect.x >> level..(rect.x + rect.width) >> level {
+                        assert_eq!(mipmap.get_pixel(x, y).0, *colour, "Level {level} of {id} at {x} {y}");
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn refuses_atlases_over_the_maximum() {
+        let mut stitcher = AtlasStitcher::new().with_max_size(32).with_padding(0);
+        for id in 0..4 {
+            stitcher.add(Identifier::from(format!("block/{id}")), texture(id, 16, 16));
+        }
+        let atlas = stitcher.stitch().unwrap();
+        assert_eq!((atlas.width(), atlas.height()), (32, 32));
+
+        let mut stitcher = AtlasStitcher::new().with_max_size(32).with_padding(1);
+        for id in 0..4 {
+            stitcher.add(Identifier::from(format!("block/{id}")), texture(id, 16, 16));
+        }
+        assert!(matches!(stitcher.stitch(), Err(StitchError::TooLarge(32))));
+        assert!(matches!(AtlasStitcher::new().stitch(), Err(StitchError::Empty)));
+    }
+}
@@ -43,6 +43,11 @@ impl Direction {
         }
     }
 
+    /// The direction closest to a vector
+    pub fn from_float_vector(vector: Vec3) -> Direction {
+        *DIRECTIONS.iter().max_by(|a, b| a.get_float_vector().dot(vector).total_cmp(&b.get_float_vector().dot(vector))).unwrap()
+    }
+
     pub fn ordinal(&self) -> usize {
         match self {
             Direction::North => 0,
@@ -24,3 +24,10 @@ pub struct EntityController {
     pub on_ground: bool,
     pub stance: f64,
 }
+
+// Represents an entity that clients are told about, by the id they know it as
+#[derive(Clone, Debug, PartialEq)]
+pub struct EntityNetworked {
+    pub entity_id: i32,
+    pub name: String,
+}
@@ -1,19 +1,20 @@
-use std::fs::{self, DirEntry};
-use std::path::PathBuf;
-
 use rustc_hash::FxHashMap as HashMap;
 use serde_json::{Value, Number};
 use ultraviolet::Vec2;
 use crate::block::Block;
 use crate::block::block_factory::BlockFactory;
 use crate::block::properties::PropertyDefinition;
-use crate::client::models::model::{BakedModel, VoxelModel, VoxelRotation};
+use crate::client::models::model::{BakedModel, VariantRotation, VoxelModel};
+use crate::client::models::report::ModelLoadReport;
 use crate::client::textures::TextureObject;
 use crate::client::textures::TextureObject::AtlasTexture;
 use crate::direction::Direction;
+use crate::minecraft::blockstates::{BlockstateModel, MultipartCondition, VariantKey};
 use crate::minecraft::filetypes::{MCAtlasTextureFile, UniformAtlasTextureType, MCModelFile, MCBlockstateType};
 use crate::minecraft::identifier::Identifier;
 use crate::minecraft::registry::Registry;
+use crate::minecraft::template_models;
+use crate::resource_manager::{ResourceError, ResourceKind, ResourceManager};
 
 pub enum GameVersion {
     B173,
@@ -21,9 +22,31 @@ pub enum GameVersion {
 }
 
 impl GameVersion {
-    pub fn load_registry(&self, registry: &mut Registry) {
+    /// Register everything the version has, with models, blockstates and atlases read from `resources`
+    pub fn load_registry(&self, registry: &mut Registry, resources: &ResourceManager) {
+        match self {
+            Self::B173 => load_b173(registry, resources),
+            _ => {},
+        }
+    }
+
+    /** Read the textures and models of the already registered blockstates from `resources`, and bake them
+     *  Replaces any textures and models there were, blocks and their states are left as they are
+     */
+    pub fn load_models(&self, registry: &mut Registry, resources: &ResourceManager) -> Result<(), ResourceError> {
         match self {
-            Self::B173 => load_b173(registry),
+            Self::B173 => load_b173_models(registry, resources),
+            _ => Ok(()),
+        }
+    }
+
+    /// Register only the blocks and their states, without reading any assets or baking models
+    pub fn load_blocks(&self, registry: &mut Registry) {
+        match self {
+            Self::B173 => {
+                register_properties(registry);
+                register_blocks(registry);
+            },
             _ => {},
         }
     }
@@ -49,26 +72,6 @@ fn non_full_cull(_: Direction) -> bool {
 }
 
 
-/** Apply a function to all files in dir and subdirs   
-Will crash if depth is greater than number of allowed open files per program 
-*/
-fn iter_files_recursive<F: FnMut(&DirEntry)>(path: PathBuf, file_funct: &mut F) {
-    if !path.is_dir() {
-        log::error!("Not a dir: {}", path.display());
-        return;
-    }
-
-    for f in fs::read_dir(path).unwrap() {
-        let entry = f.unwrap();
-        let entry_path = entry.path();
-        if entry_path.is_dir() {
-            iter_files_recursive(entry_path, file_funct);
-        } else {
-            file_funct(&entry);
-        }
-    }
-}
-
 fn register_properties(registry: &mut Registry) {
 
     let properties = registry.get_property_register_mut();
@@ -564,112 +567,160 @@ fn register_blocks(registry: &mut Registry) {
 
 }
 
-// TODO: Check for infinite recursion through already visited models
-fn make_model(registry: &Registry, identifier: &Identifier, model_files: &HashMap<Identifier, MCModelFile>, voxel_models: &mut HashMap<Identifier, VoxelModel>) -> Option<VoxelModel> {
-    let already_visited = false;
-    if already_visited { return None; }
-
-    if !model_files.contains_key(identifier) {
-        log::warn!("No model file for {}", identifier);
-        return None;
+/// Blockstates may leave out the block/ of the models they use
+fn block_model_identifier(model: &str) -> Identifier {
+    let identifier = Identifier::from_str(model);
+    if identifier.get_name().starts_with("block/") {
+        return identifier;
     }
-    model_files.get(identifier).and_then(|model_file| {
-        let mut model = match model_file.get_parent() {
-            Some(parent) => {
-                // Apply models ontop of parent
-                let parent_id: Identifier = parent.into();
-                let parent_model = if voxel_models.contains_key(&parent_id) {
-                    voxel_models.get(&parent_id)
-                } else {
-                    make_model(registry, &parent_id, model_files, voxel_models).and_then(|model| {
-                        voxel_models.insert(parent_id.clone(), model);
-                        voxel_models.get(&parent_id)
-                    })
-                };
-                parent_model.and_then(|parent| {
-                    let mut model = VoxelModel::from_template(parent);
-                    if model_file.elements().len() > 0 {
-                        model.clear_elements(); // override parent elements
-                    }
-                    
-                    Some(model)
-                })
-            }, // end has a parent
+    Identifier::new(identifier.get_namespace().clone(), format!("block/{}", identifier.get_name()))
+}
+
+/// Bake the models of a variant or a part, picked from by weight when there are several
+fn bake_blockstate_models(models: &[BlockstateModel], voxel_models: &HashMap<Identifier, VoxelModel>, missing_model: &VoxelModel, textures: &HashMap<Identifier, TextureObject>, block_id: &Identifier, state_identifier: &str, report: &mut ModelLoadReport) -> BakedModel {
+    let baked = models.iter().map(|blockstate_model| {
+        let model_id = block_model_identifier(&blockstate_model.model);
+        let model = match voxel_models.get(&model_id) {
+            Some(model) => {
+                log::info!("Using model {} for blockstate {}", model_id, state_identifier);
+                check_model_textures(&model_id, model, textures, report);
+                model
+            },
             None => {
-                // Create model file as-is
-                let mut model = VoxelModel::new();
-                model.with_ambient_occlusion_nc(model_file.get_ambient_occlusion());
-                Some(model)
-            } // end has no parent
-        }; // end match parent
-
-        if let Some(model) = &mut model {
-            for (texture_var, texture_id) in model_file.textures() {
-                model.with_texture_nc(texture_var, texture_id);
-            }
-            for element in model_file.elements() {
-                let voxel_element = element.to_voxel_element();
-                model.with_element_nc(voxel_element);
-            }
-        }
+                log::error!("Invalid model {} for blockstate {}!", model_id, state_identifier);
+                ModelLoadReport::push_unique(&mut report.missing_models, (model_id, block_id.clone()));
+                missing_model
+            },
+        };
+        let rotation = VariantRotation::new(blockstate_model.x, blockstate_model.y).with_z(blockstate_model.z).with_uvlock(blockstate_model.uvlock);
+        (blockstate_model.weight, model.clone().bake_with_rotate(rotation, textures))
+    }).collect();
+    BakedModel::weighted(baked)
+}
 
-        model
-    })
+/// Report the faces of a model in use whose textures are not set, or not in the atlas
+fn check_model_textures(model_id: &Identifier, model: &VoxelModel, textures: &HashMap<Identifier, TextureObject>, report: &mut ModelLoadReport) {
+    for face_texture in model.face_textures() {
+        match model.resolve_texture(face_texture) {
+            Some(texture) => {
+                let texture = Identifier::from(texture);
+                if !textures.contains_key(&texture) {
+                    ModelLoadReport::push_unique(&mut report.unknown_textures, (model_id.clone(), texture));
+                }
+            },
+            None => ModelLoadReport::push_unique(&mut report.unresolved_textures, (model_id.clone(), face_texture.to_string())),
+        }
+    }
 }
 
-fn load_b173(registry: &mut Registry) {
+/** Make the model of a model file on top of its parents, keeping every parent made on the way in `voxel_models`
+ *  `children` are the models waiting on this one as their parent, a parent among them means the chain loops
+ */
+fn make_model(identifier: &Identifier, model_files: &HashMap<Identifier, MCModelFile>, voxel_models: &mut HashMap<Identifier, VoxelModel>, children: &mut Vec<Identifier>, report: &mut ModelLoadReport) -> Option<VoxelModel> {
+    if let Some(model) = voxel_models.get(identifier) {
+        return Some(model.clone());
+    }
+    let model_file = match model_files.get(identifier) {
+        Some(model_file) => model_file,
+        None => {
+            log::warn!("No model file for {}", identifier);
+            return None;
+        },
+    };
+    let mut model = match model_file.get_parent() {
+        Some(parent) => {
+            // Apply models ontop of parent
+            let parent_id: Identifier = parent.into();
+            children.push(identifier.clone());
+            if let Some(start) = children.iter().position(|child| *child == parent_id) {
+                let mut cycle = children[start..].to_vec();
+                cycle.push(parent_id);
+                children.pop();
+                log::error!("The parents of {} lead back to it", identifier);
+                // Any model of the loop finds it again, it is only reported once
+                if !report.parent_cycles.iter().any(|reported| reported.contains(identifier)) {
+                    report.parent_cycles.push(cycle);
+                }
+                return None;
+            }
+            if !model_files.contains_key(&parent_id) {
+                children.pop();
+                log::error!("Missing parent {} of model {}", parent_id, identifier);
+                ModelLoadReport::push_unique(&mut report.missing_parents, (identifier.clone(), parent_id));
+                return None;
+            }
+            let parent_model = make_model(&parent_id, model_files, voxel_models, children, report);
+            children.pop();
+            let parent_model = parent_model?;
+            let mut model = VoxelModel::from_template(&parent_model);
+            voxel_models.insert(parent_id, parent_model);
+            if !model_file.elements().is_empty() {
+                model.clear_elements(); // override parent elements
+            }
+            model
+        }, // end has a parent
+        None => {
+            // Create model file as-is
+            let mut model = VoxelModel::new();
+            model.with_ambient_occlusion_nc(model_file.get_ambient_occlusion());
+            model
+        } // end has no parent
+    }; // end match parent
+
+    for (texture_var, texture_id) in model_file.textures() {
+        model.with_texture_nc(texture_var, texture_id);
+    }
+    for element in model_file.elements() {
+        let voxel_element = element.to_voxel_element();
+        model.with_element_nc(voxel_element);
+    }
+    Some(model)
+}
 
+fn load_b173(registry: &mut Registry, resources: &ResourceManager) {
     register_properties(registry);
     register_blocks(registry);
+    if let Err(e) = load_b173_models(registry, resources) {
+        log::error!("Failed to load the models, blocks will not be shown: {}", e);
+    }
+}
 
+fn load_b173_models(registry: &mut Registry, resources: &ResourceManager) -> Result<(), ResourceError> {
+    // Whatever the last load reported no longer applies, even if this one stops early
+    *registry.get_model_load_report_mut() = ModelLoadReport::new();
+    // Read the atlas before anything is replaced, so a pack without one leaves the loaded models in place
+    let atlas_textures: MCAtlasTextureFile = resources.read_json(&Identifier::from("block/terrain"), ResourceKind::Atlas)?;
 
+    let mut report = ModelLoadReport::new();
     let mut model_files = HashMap::default();
     let mut voxel_models = HashMap::default();
     let mut blockstate_files = HashMap::default();
 
-    let namespace = "minecraft";
-    let context = "models";
-    let assets_dir = "../orange-mc-assets";
-
-    let read_dir = format!("{}/assets/{}/{}/", assets_dir, namespace, context);
-    iter_files_recursive(read_dir.clone().into(), &mut |entry| {
-        if entry.file_name().to_str().unwrap().to_owned().ends_with(".json") {
-            match serde_json::from_str::<MCModelFile>(fs::read_to_string(entry.path()).unwrap().as_str()) {
-                Ok(model_file) => {
-                    let resource_extension = entry.path().extension().map(|ext| format!(".{}", ext.to_string_lossy().to_string())).unwrap_or("".to_string());
-                    let resource_path = entry.path().to_string_lossy().replace(&read_dir, "").replace(&resource_extension, "").replace("\\", "/");
-                    let resource_id = Identifier::new(namespace.to_string(), resource_path.clone());
-                    model_files.insert(resource_id, model_file);
-                },
-                Err(e) => { log::error!("Error processing {}: {}", entry.file_name().to_string_lossy(), e) }
+    // Every pack may add models and blockstates in any namespace, the topmost pack's file is used
+    for namespace in resources.namespaces() {
+        for resource_id in resources.list(&namespace, ResourceKind::Model, "") {
+            match resources.read_json::<MCModelFile>(&resource_id, ResourceKind::Model) {
+                Ok(model_file) => { model_files.insert(resource_id, model_file); },
+                Err(e) => {
+                    log::error!("Error processing model {}: {}", resource_id, e);
+                    report.invalid_models.push((resource_id, e.to_string()));
+                }
             };
-        } else {
-            log::error!("Unknown File: {}", entry.path().display());
         }
-    });
-
-    let context = "blockstates";
-    let read_dir = format!("{}/assets/{}/{}/", assets_dir, namespace, context);
-    iter_files_recursive(read_dir.clone().into(), &mut |entry| {
-        if entry.file_name().to_string_lossy().ends_with(".json") {
-            match serde_json::from_str::<MCBlockstateType>(fs::read_to_string(entry.path()).unwrap().as_str()) {
-                Ok(blockstate_file) => {
-                    let resource_extension = entry.path().extension().map(|ext| format!(".{}", ext.to_string_lossy().to_string())).unwrap_or("".to_string());
-                    let resource_path = entry.path().to_string_lossy().replace(&read_dir, "").replace(&resource_extension, "").replace("\\", "/");
-                    let resource_id = Identifier::new(namespace.to_string(), resource_path);
-                    blockstate_files.insert(resource_id, blockstate_file);
-                },
-                Err(e) => { log::error!("Error processing {}: {}", entry.file_name().to_string_lossy(), e) }
+        for resource_id in resources.list(&namespace, ResourceKind::Blockstate, "") {
+            match resources.read_json::<MCBlockstateType>(&resource_id, ResourceKind::Blockstate) {
+                Ok(blockstate_file) => { blockstate_files.insert(resource_id, blockstate_file); },
+                Err(e) => {
+                    log::error!("Error processing blockstate {}: {}", resource_id, e);
+                    report.invalid_blockstates.push((resource_id, e.to_string()));
+                }
             };
         }
-    });
-
-    let atlas_texture_json_str = fs::read_to_string([assets_dir, "assets/minecraft/textures/block/terrain.mcatlas"].join("/"))
-        .expect("Should have been able to read the file");
-    let atlas_textures: MCAtlasTextureFile = serde_json::from_str(atlas_texture_json_str.as_str()).unwrap();
+    }
 
     {
         let textures = registry.get_texture_register_mut();
+        textures.clear();
         for UniformAtlasTextureType { identifier, cell } in atlas_textures.atlas.get_uniform_textures() {
             let tex = make_atlas_tex(cell as usize);
             textures.insert(Identifier::from_str(identifier.as_str()), tex);
@@ -677,7 +728,8 @@ fn load_b173(registry: &mut Registry) {
     }
 
     for model_file_id in model_files.keys() {
-        let voxel_model = make_model(registry, &model_file_id, &model_files, &mut voxel_models);
+        if voxel_models.contains_key(model_file_id) { continue; }
+        let voxel_model = make_model(model_file_id, &model_files, &mut voxel_models, &mut vec![], &mut report);
         if let Some(voxel_model) = voxel_model { voxel_models.insert(model_file_id.clone(), voxel_model); } else { log::warn!("Couln't make voxel model for {}", model_file_id); }
     }
     
@@ -685,7 +737,10 @@ fn load_b173(registry: &mut Registry) {
     // Blocks & Items
     
 
-    let missing_model_file = voxel_models.get(&Identifier::from_str("minecraft:block/missing")).expect("Did not have a missing model");
+    let missing_model_file = voxel_models.get(&Identifier::from_str("minecraft:block/missing")).unwrap_or_else(|| {
+        log::error!("There is no minecraft:block/missing model, using the built in one");
+        template_models::missing()
+    });
 
     let mut mapped_models: Vec<(Identifier, BakedModel)> = vec![];
 
@@ -694,84 +749,211 @@ fn load_b173(registry: &mut Registry) {
         let identifier = state.get_state_identifier().get_identifier();
         let block_id = state.get_block_identifier();
 
-        let blockstate_file = blockstate_files.get(block_id).expect(format!("Missing blockstate file for {}", block_id).as_str());
+        let blockstate_file = match blockstate_files.get(block_id) {
+            Some(blockstate_file) => blockstate_file,
+            None => {
+                log::error!("Missing blockstate file for {}", block_id);
+                ModelLoadReport::push_unique(&mut report.missing_blockstates, block_id.clone());
+                mapped_models.push((state.get_state_identifier().clone(), missing_model_file.clone().bake(textures)));
+                continue;
+            },
+        };
         let blockstate_model = match &blockstate_file {
             MCBlockstateType::variants(variants) => {
-                let mut t_variant_model = Identifier::from_str(variants.get("").map(|v| v["model"].as_str().unwrap()).unwrap_or("minecraft:missing"));
-                let mut rotation_axis_angle = None;
-                for (property_list, variant_model) in variants {
-                    let mut valid_variant = true;
-                    for variant_property in property_list.split(",") {
-                        valid_variant &= identifier.contains(variant_property);
-                    }
-                    if valid_variant {
-                        t_variant_model = Identifier::from(variant_model["model"].as_str().unwrap());
-                        if variant_model["x"].is_number() {
-                            rotation_axis_angle = Some((0, variant_model["x"].as_f64()));
-                        } else if variant_model["y"].is_number() {
-                            rotation_axis_angle = Some((1, variant_model["y"].as_f64()));
-                        } else if variant_model["z"].is_number() {
-                            rotation_axis_angle = Some((2, variant_model["z"].as_f64()));
-                        }
+                /* The variant naming the most properties of those the state has is used
+                 * Vanilla has no order between keys that match the same state and ends up with whichever it read last,
+                 * this deliberately picks the most specific one so overlapping keys always give the same model
+                 */
+                let property_names = state.get_property_names(registry.get_property_register());
+                let mut matched: Option<(usize, &Value)> = None;
+                for (key, variant) in variants {
+                    let key = match VariantKey::parse(key) {
+                        Ok(key) => key,
+                        Err(e) => {
+                            log::error!("Skipping a variant of the blockstate {}: {}", block_id, e);
+                            ModelLoadReport::push_unique(&mut report.invalid_blockstates, (block_id.clone(), e.to_string()));
+                            continue;
+                        },
+                    };
+                    if key.matches(&property_names) && matched.map_or(true, |(len, _)| key.len() > len) {
+                        matched = Some((key.len(), variant));
                     }
                 }
-
-                if !t_variant_model.get_name().starts_with("block/") {
-                    t_variant_model = Identifier::new(t_variant_model.get_namespace().clone(), format!("block/{}", t_variant_model.get_name()));
-                }
-
-                if t_variant_model.get_identifier() == "minecraft:missing" {
-                    log::warn!("Using missing model for {}", identifier);
-                }
-
-                let model = match voxel_models.get(&t_variant_model) {
-                    Some(model_file) => {
-                        log::info!("Using model {} for blockstate {}", t_variant_model, identifier);
-                        model_file
+                match matched.map(|(_, variant)| BlockstateModel::parse_list(variant)) {
+                    Some(Ok(models)) => bake_blockstate_models(&models, &voxel_models, missing_model_file, textures, block_id, identifier, &mut report),
+                    Some(Err(e)) => {
+                        log::error!("Using missing model for {}: {}", identifier, e);
+                        ModelLoadReport::push_unique(&mut report.invalid_blockstates, (block_id.clone(), e.to_string()));
+                        missing_model_file.clone().bake(textures)
                     },
                     None => {
-                        log::error!("Invalid model {} for blockstate {}!", t_variant_model, identifier);
-                        missing_model_file
-                    }
-                };
-                let rotation = rotation_axis_angle.map(|(axis, angle)| { VoxelRotation::new(angle.unwrap_or(0.) as f32, axis, [8., 8., 8.], false) });
-                model.clone().bake_with_rotate(rotation, &textures)
+                        log::warn!("Using missing model for {}", identifier);
+                        missing_model_file.clone().bake(textures)
+                    },
+                }
             },
             MCBlockstateType::multipart(multiparts) => {
-                let mut applied_models: Vec<(Identifier, Option<VoxelRotation>)> = vec![];
+                // Every part whose condition the state meets is baked with its own rotation, then merged into one model
+                let property_names = state.get_property_names(registry.get_property_register());
+                let mut merged: Option<BakedModel> = None;
                 for part in multiparts {
-                    let conditions_passed = match &part["when"] {
-                        Value::Object(values) => {
-                            true
+                    let condition = match MultipartCondition::parse(&part["when"]) {
+                        Ok(condition) => condition,
+                        Err(e) => {
+                            log::error!("Skipping a part of the blockstate {}: {}", block_id, e);
+                            ModelLoadReport::push_unique(&mut report.invalid_blockstates, (block_id.clone(), e.to_string()));
+                            continue;
                         },
-                        _ => false,
                     };
-                    if !conditions_passed { continue; }
-                    let applied = &part["apply"];
-                    let model = Identifier::from(applied["model"].as_str().unwrap());
-                    let rotation_axis_angle = if let Value::Number(x) = &applied["x"] {
-                        Some((0, x))
-                    } else if let Value::Number(y) = &applied["y"] {
-                        Some((1, y))
-                    } else if let Value::Number(z) = &applied["z"] {
-                        Some((2, z))
-                    } else {
-                        None
+                    if !condition.test(&property_names) { continue; }
+                    let models = match BlockstateModel::parse_list(&part["apply"]) {
+                        Ok(models) => models,
+                        Err(e) => {
+                            log::error!("Skipping a part of the blockstate {}: {}", block_id, e);
+                            ModelLoadReport::push_unique(&mut report.invalid_blockstates, (block_id.clone(), e.to_string()));
+                            continue;
+                        },
                     };
-                    let rotation = rotation_axis_angle.map(|(axis, angle)| { VoxelRotation::new(angle.as_f64().unwrap_or(0.) as f32, axis, [8., 8., 8.], false) });
-                    applied_models.push((model, rotation));
+                    let baked = bake_blockstate_models(&models, &voxel_models, missing_model_file, textures, block_id, identifier, &mut report);
+                    match &mut merged {
+                        Some(merged) => merged.merge(baked),
+                        None => merged = Some(baked),
+                    }
                 }
-
-                // let model = VoxelModel::new();
-                // model.bake(textures)
-                missing_model_file.clone().bake(textures)
-
+                // No part applies, the state shows nothing
+                merged.unwrap_or_else(BakedModel::new)
             }
         };
         mapped_models.push((state.get_state_identifier().clone(), blockstate_model));
     }
 
+    let models = registry.get_model_register_mut();
+    models.clear();
     for mapped_model in mapped_models {
-        registry.get_model_register_mut().insert(mapped_model.0, mapped_model.1);
+        models.insert(mapped_model.0, mapped_model.1);
+    }
+    if !report.is_empty() {
+        log::warn!("Loaded models with {}", report);
+    }
+    *registry.get_model_load_report_mut() = report;
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::path::Path;
+
+    use super::GameVersion;
+    use crate::minecraft::identifier::Identifier;
+    use crate::minecraft::registry::{Registerable, Registry};
+    use crate::resource_manager::{ResourceKind, ResourceManager, ResourceType};
+    use crate::util::test_directory::TestDirectory;
+
+    /** Write a pack with every b1.7.3 block using the missing model, a single face cube with stone on it
+     *  `files` are written last, replacing any of those, by their path and contents
+     */
+    pub(crate) fn write_pack(root: &Path, files: &[(String, String)]) {
+        let mut all_files = vec![
+            (ResourceKind::Atlas.path(&Identifier::from("block/terrain")),
+                String::from(r#"{"atlas": {"Uniform": {"across": 16, "textures": [{"cell": 1, "identifier": "minecraft:block/stone"}]}}}"#)),
+            (ResourceKind::Model.path(&Identifier::from("block/missing")),
+                String::from(r##"{"textures": {"all": "minecraft:block/stone"}, "elements": [{"from": [0, 0, 0], "to": [16, 16, 16], "faces": {"up": {"texture": "#all"}}}]}"##)),
+        ];
+        let mut blocks = Registry::new();
+        GameVersion::B173.load_blocks(&mut blocks);
+        for block in blocks.get_block_register().get_elements() {
+            all_files.push((ResourceKind::Blockstate.path(block.get_identifier()), String::from(r#"{"variants": {"": {"model": "minecraft:block/missing"}}}"#)));
+        }
+        all_files.extend(files.iter().cloned());
+        for (path, contents) in all_files {
+            let path = root.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, contents).unwrap();
+        }
+    }
+
+    fn load_pack(name: &str, files: &[(String, String)]) -> Registry {
+        let directory = TestDirectory::new(name);
+        write_pack(&directory, files);
+        let resources = ResourceManager::from_sources(vec![ResourceType::Dir(directory.to_path_buf())]).unwrap();
+        Registry::load_with_resources(GameVersion::B173, &resources)
+    }
+
+    #[test]
+    fn merges_applied_multipart_parts() {
+        let model = |name: &str, face: &str| (ResourceKind::Model.path(&Identifier::from(name)),
+            format!(r##"{{"textures": {{"texture": "minecraft:block/stone"}}, "elements": [{{"from": [6, 0, 6], "to": [10, 16, 10], "faces": {{"{face}": {{"texture": "#texture"}}}}}}]}}"##));
+        let registry = load_pack("multipart", &[
+            model("block/fence_post", "up"),
+            model("block/fence_side", "north"),
+            (ResourceKind::Blockstate.path(&Identifier::from("fence")), String::from(r#"{"multipart": [
+                {"apply": {"model": "minecraft:block/fence_post"}},
+                {"when": {"north": "true"}, "apply": {"model": "minecraft:fence_side"}},
+                {"when": {"east": "true"}, "apply": {"model": "minecraft:fence_side", "y": 90}},
+                {"when": {"OR": [{"south": "true"}, {"west": "true"}]}, "apply": [{"model": "minecraft:fence_side", "y": 180}]}
+            ]}"#)),
+        ]);
+        let quads = |state: &str| registry.get_model_register()[&Identifier::from(state)].shapes().len();
+        assert_eq!(quads("fence#east=false,north=false,south=false,west=false"), 1);
+        assert_eq!(quads("fence#east=false,north=true,south=false,west=false"), 2);
+        assert_eq!(quads("fence#east=true,north=true,south=false,west=false"), 3);
+        assert_eq!(quads("fence#east=false,north=false,south=true,west=true"), 2);
+        assert_eq!(quads("fence#east=true,north=true,south=true,west=true"), 4);
+
+        // Each part keeps its own rotation, the side turned 90 degrees faces east
+        let model = &registry.get_model_register()[&Identifier::from("fence#east=true,north=false,south=false,west=false")];
+        let normals: Vec<_> = model.shapes().iter().map(|quad| quad.normal).collect();
+        assert_eq!(normals.len(), 2);
+        assert!(normals[1] != normals[0]);
+    }
+
+    #[test]
+    fn reports_problems_and_falls_back_to_missing_model() {
+        let model = |name: &str, contents: &str| (ResourceKind::Model.path(&Identifier::from(name)), contents.to_string());
+        let blockstate = |block: &str, model: &str| (ResourceKind::Blockstate.path(&Identifier::from(block)), format!(r#"{{"variants": {{"": {{"model": "{model}"}}}}}}"#));
+        let directory = TestDirectory::new("report");
+        write_pack(&directory, &[
+            model("block/loop_a", r#"{"parent": "minecraft:block/loop_b"}"#),
+            model("block/loop_b", r#"{"parent": "minecraft:block/loop_a"}"#),
+            model("block/orphan", r#"{"parent": "minecraft:block/nowhere"}"#),
+            model("block/untextured", r##"{"textures": {"a": "#b", "b": "#a"}, "elements": [{"from": [0, 0, 0], "to": [16, 16, 16], "faces": {
+                "up": {"texture": "#side"}, "down": {"texture": "#a"}, "north": {"texture": "minecraft:block/unknown"}}}]}"##),
+            blockstate("stone", "minecraft:block/loop_a"),
+            blockstate("dirt", "minecraft:block/untextured"),
+            blockstate("cobblestone", "minecraft:block/orphan"),
+            (ResourceKind::Blockstate.path(&Identifier::from("sand")), String::from(r#"{"variants": {"": {"y": 90}}}"#)),
+        ]);
+        std::fs::remove_file(directory.join(ResourceKind::Blockstate.path(&Identifier::from("glass")))).unwrap();
+        let resources = ResourceManager::from_sources(vec![ResourceType::Dir(directory.to_path_buf())]).unwrap();
+        let registry = Registry::load_with_resources(GameVersion::B173, &resources);
+
+        let report = registry.get_model_load_report();
+        assert_eq!(report.parent_cycles.len(), 1);
+        assert_eq!(report.parent_cycles[0].len(), 3);
+        assert_eq!(report.parent_cycles[0].first(), report.parent_cycles[0].last());
+        assert_eq!(report.missing_parents, [(Identifier::from("block/orphan"), Identifier::from("block/nowhere"))]);
+        let mut missing_models = report.missing_models.iter().map(|(model, block)| (model.to_string(), block.to_string())).collect::<Vec<_>>();
+        missing_models.sort();
+        assert_eq!(missing_models, [
+            (String::from("minecraft:block/loop_a"), String::from("minecraft:stone")),
+            (String::from("minecraft:block/orphan"), String::from("minecraft:cobblestone")),
+        ]);
+        let mut unresolved = report.unresolved_textures.iter().map(|(_, variable)| variable.as_str()).collect::<Vec<_>>();
+        unresolved.sort();
+        assert_eq!(unresolved, ["#a", "#side"]);
+        assert_eq!(report.unknown_textures, [(Identifier::from("block/untextured"), Identifier::from("block/unknown"))]);
+        assert_eq!(report.missing_blockstates, [Identifier::from("glass")]);
+        assert_eq!(report.invalid_blockstates.len(), 1);
+        assert_eq!(report.invalid_blockstates[0].0, Identifier::from("sand"));
+
+        // Every one of them is still shown, with the missing model
+        let quads = |block: &str| {
+            let state = registry.get_block_register().get_element_from_identifier(&Identifier::from(block)).unwrap().get_default_state();
+            registry.get_model_register()[state.get_state_identifier()].shapes().len()
+        };
+        for block in ["stone", "cobblestone", "glass", "sand"] {
+            assert_eq!(quads(block), 1, "{block}");
+        }
+        assert_eq!(quads("dirt"), 3);
     }
 }
@@ -6,10 +6,11 @@ pub mod math_helper;
 pub mod util;
 pub mod server;
 pub mod packets;
+pub mod network;
 pub mod entities;
 pub mod resource_manager;
+pub mod pack_converter;
 
-#[cfg(feature = "client")]
 pub mod client;
 pub mod minecraft;
 
@@ -0,0 +1,251 @@
+use rustc_hash::FxHashMap as HashMap;
+use serde_json::Value;
+
+#[derive(Debug)]
+pub enum BlockstateError {
+    /// A `when` that is neither an object of properties nor an OR or AND of them
+    InvalidCondition(String),
+    /// A variant key that is not a list of property=value
+    InvalidVariant(String),
+    /// A variant or applied part without a model, or with an invalid rotation or weight
+    InvalidModel(String),
+}
+
+impl std::error::Error for BlockstateError {}
+
+impl std::fmt::Display for BlockstateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidCondition(when) => write!(f, "Invalid multipart condition {when}"),
+            Self::InvalidVariant(key) => write!(f, "Invalid variant {key}"),
+            Self::InvalidModel(model) => write!(f, "Invalid blockstate model {model}"),
+        }
+    }
+}
+
+/** The `when` of a part of a multipart blockstate, deciding which states the part is applied to
+ *  Values may list alternatives as `a|b`, and a leading `!` applies the part to every other value
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub enum MultipartCondition {
+    /// The part has no `when`, it is applied to every state
+    Always,
+    /// Every property must have one of its values
+    Properties(Vec<PropertyCondition>),
+    Or(Vec<MultipartCondition>),
+    And(Vec<MultipartCondition>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PropertyCondition {
+    pub property: String,
+    pub values: Vec<String>,
+    /// Match every value except those listed
+    pub negated: bool,
+}
+
+impl PropertyCondition {
+    fn test(&self, properties: &HashMap<String, String>) -> bool {
+        match properties.get(&self.property) {
+            Some(value) => self.values.contains(value) != self.negated,
+            None => false,
+        }
+    }
+}
+
+impl MultipartCondition {
+    /// Parse the `when` of a part, a missing one is Null and always applies
+    pub fn parse(when: &Value) -> Result<Self, BlockstateError> {
+        let object = match when {
+            Value::Null => return Ok(Self::Always),
+            Value::Object(object) => object,
+            other => return Err(BlockstateError::InvalidCondition(other.to_string())),
+        };
+        let nested = |conditions: &Value| -> Result<Vec<Self>, BlockstateError> {
+            match conditions {
+                Value::Array(conditions) => conditions.iter().map(Self::parse).collect(),
+                other => Err(BlockstateError::InvalidCondition(other.to_string())),
+            }
+        };
+        if object.len() == 1 {
+            if let Some(conditions) = object.get("OR") {
+                return Ok(Self::Or(nested(conditions)?));
+            }
+            if let Some(conditions) = object.get("AND") {
+                return Ok(Self::And(nested(conditions)?));
+            }
+        }
+        let conditions = object.iter().map(|(property, value)| {
+            let value = match value {
+                Value::String(value) => value.clone(),
+                Value::Bool(_) | Value::Number(_) => value.to_string(),
+                other => return Err(BlockstateError::InvalidCondition(format!("{property}: {other}"))),
+            };
+            let (negated, value) = match value.strip_prefix('!') {
+                Some(value) => (true, value.to_string()),
+                None => (false, value),
+            };
+            Ok(PropertyCondition { property: property.clone(), values: value.split('|').map(str::to_string).collect(), negated })
+        }).collect::<Result<Vec<_>, _>>()?;
+        Ok(Self::Properties(conditions))
+    }
+
+    /// Whether a state with these property values gets the part
+    pub fn test(&self, properties: &HashMap<String, String>) -> bool {
+        match self {
+            Self::Always => true,
+            Self::Properties(conditions) => conditions.iter().all(|condition| condition.test(properties)),
+            Self::Or(conditions) => conditions.iter().any(|condition| condition.test(properties)),
+            Self::And(conditions) => conditions.iter().all(|condition| condition.test(properties)),
+        }
+    }
+}
+
+/** The key of a variant, the property values a state must have to use it
+ *  An empty key is met by every state
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct VariantKey {
+    properties: Vec<(String, String)>,
+}
+
+impl VariantKey {
+    pub fn parse(key: &str) -> Result<Self, BlockstateError> {
+        let properties = key.split(',').filter(|property| !property.is_empty()).map(|property| {
+            match property.split_once('=') {
+                Some((name, value)) if !name.is_empty() => Ok((name.to_string(), value.to_string())),
+                _ => Err(BlockstateError::InvalidVariant(key.to_string())),
+            }
+        }).collect::<Result<Vec<_>, _>>()?;
+        Ok(Self { properties })
+    }
+
+    /// Whether every property of the key has exactly its value in the state
+    pub fn matches(&self, properties: &HashMap<String, String>) -> bool {
+        self.properties.iter().all(|(name, value)| properties.get(name) == Some(value))
+    }
+
+    /// How many properties the key names, a state matching several variants uses the one naming the most
+    pub fn len(&self) -> usize {
+        self.properties.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.properties.is_empty()
+    }
+}
+
+/// A model used by a variant or a part of a multipart blockstate, and how it is turned
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockstateModel {
+    pub model: String,
+    /// Rotation around the x axis in degrees, applied before `y` and then `z`
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    /// Keep textures facing the way they would unrotated
+    pub uvlock: bool,
+    /// How likely the model is picked from a list of them
+    pub weight: u32,
+}
+
+impl BlockstateModel {
+    pub fn parse(value: &Value) -> Result<Self, BlockstateError> {
+        let invalid = || BlockstateError::InvalidModel(value.to_string());
+        let model = value["model"].as_str().ok_or_else(invalid)?.to_string();
+        let angle = |name: &str| match &value[name] {
+            Value::Null => Ok(0.0),
+            angle => angle.as_f64().map(|angle| angle as f32).ok_or_else(invalid),
+        };
+        let uvlock = match &value["uvlock"] {
+            Value::Null => false,
+            uvlock => uvlock.as_bool().ok_or_else(invalid)?,
+        };
+        let weight = match &value["weight"] {
+            Value::Null => 1,
+            weight => weight.as_u64().and_then(|weight| u32::try_from(weight).ok()).filter(|weight| *weight > 0).ok_or_else(invalid)?,
+        };
+        Ok(Self { model, x: angle("x")?, y: angle("y")?, z: angle("z")?, uvlock, weight })
+    }
+
+    /// A variant or the apply of a part, one model or a list of them to pick from by weight
+    pub fn parse_list(value: &Value) -> Result<Vec<Self>, BlockstateError> {
+        match value {
+            Value::Array(models) if !models.is_empty() => models.iter().map(Self::parse).collect(),
+            Value::Array(_) => Err(BlockstateError::InvalidModel(value.to_string())),
+            model => Ok(vec![Self::parse(model)?]),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rustc_hash::FxHashMap as HashMap;
+    use serde_json::json;
+
+    use super::{BlockstateModel, MultipartCondition, VariantKey};
+
+    fn state(properties: &[(&str, &str)]) -> HashMap<String, String> {
+        properties.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect()
+    }
+
+    #[test]
+    fn evaluates_conditions() {
+        let always = MultipartCondition::parse(&serde_json::Value::Null).unwrap();
+        assert!(always.test(&state(&[])));
+
+        let single = MultipartCondition::parse(&json!({"north": "true", "up": false})).unwrap();
+        assert!(single.test(&state(&[("north", "true"), ("up", "false")])));
+        assert!(!single.test(&state(&[("north", "true"), ("up", "true")])));
+        assert!(!single.test(&state(&[("north", "true")])));
+
+        let alternatives = MultipartCondition::parse(&json!({"facing": "north|south", "age": "!0|1"})).unwrap();
+        assert!(alternatives.test(&state(&[("facing", "south"), ("age", "2")])));
+        assert!(!alternatives.test(&state(&[("facing", "east"), ("age", "2")])));
+        assert!(!alternatives.test(&state(&[("facing", "north"), ("age", "1")])));
+
+        let or = MultipartCondition::parse(&json!({"OR": [{"north": "side|up"}, {"east": "side", "west": "none"}]})).unwrap();
+        assert!(or.test(&state(&[("north", "up"), ("east", "none"), ("west", "side")])));
+        assert!(or.test(&state(&[("north", "none"), ("east", "side"), ("west", "none")])));
+        assert!(!or.test(&state(&[("north", "none"), ("east", "side"), ("west", "side")])));
+
+        let and = MultipartCondition::parse(&json!({"AND": [{"north": "true"}, {"OR": [{"south": "true"}, {"up": "true"}]}]})).unwrap();
+        assert!(and.test(&state(&[("north", "true"), ("south", "false"), ("up", "true")])));
+        assert!(!and.test(&state(&[("north", "false"), ("south", "true"), ("up", "true")])));
+
+        assert!(MultipartCondition::parse(&json!("north")).is_err());
+        assert!(MultipartCondition::parse(&json!({"OR": {"north": "true"}})).is_err());
+    }
+
+    #[test]
+    fn matches_variant_keys_exactly() {
+        let age = VariantKey::parse("age=1").unwrap();
+        assert!(age.matches(&state(&[("age", "1")])));
+        assert!(!age.matches(&state(&[("age", "10")])));
+        assert!(!age.matches(&state(&[("age", "11")])));
+
+        let facing = VariantKey::parse("facing=north,lit=true").unwrap();
+        assert_eq!(facing.len(), 2);
+        assert!(facing.matches(&state(&[("lit", "true"), ("facing", "north")])));
+        assert!(!facing.matches(&state(&[("lit", "false"), ("facing", "north")])));
+
+        assert!(VariantKey::parse("").unwrap().matches(&state(&[("age", "3")])));
+        assert!(VariantKey::parse("age").is_err());
+    }
+
+    #[test]
+    fn parses_weighted_models() {
+        let models = BlockstateModel::parse_list(&json!([
+            {"model": "block/stone", "x": 90, "y": 270, "z": 180, "uvlock": true},
+            {"model": "block/stone_mirrored", "weight": 3}
+        ])).unwrap();
+        assert_eq!(models, [
+            BlockstateModel { model: String::from("block/stone"), x: 90.0, y: 270.0, z: 180.0, uvlock: true, weight: 1 },
+            BlockstateModel { model: String::from("block/stone_mirrored"), x: 0.0, y: 0.0, z: 0.0, uvlock: false, weight: 3 },
+        ]);
+        assert_eq!(BlockstateModel::parse_list(&json!({"model": "block/dirt"})).unwrap().len(), 1);
+        assert!(BlockstateModel::parse_list(&json!([])).is_err());
+        assert!(BlockstateModel::parse_list(&json!({"model": "block/dirt", "weight": 0})).is_err());
+        assert!(BlockstateModel::parse_list(&json!({"y": 90})).is_err());
+    }
+}
@@ -184,6 +184,9 @@ impl MCModelRotation {
             "z" => { 2 },
             _ => { 0 },
         };
+        if ![-45.0, -22.5, 0.0, 22.5, 45.0].contains(&self.angle) {
+            log::warn!("Elements are turned by -45, -22.5, 0, 22.5 or 45 degrees, not {}", self.angle);
+        }
         VoxelRotation::new(self.angle, axis, self.origin, self.rescale.unwrap_or(false))
     }
 }
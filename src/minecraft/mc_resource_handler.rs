@@ -9,6 +9,9 @@ use wgpu::util::DeviceExt;
 
 use crate::client::{rendering::textures::DiffuseTextureWrapper, Client};
 use crate::client::rendering::verticies::TerrainVertex;
+use crate::client::textures::animation::TileUpdate;
+use crate::minecraft::identifier::Identifier;
+use crate::resource_manager::{ResourceError, ResourceKind, ResourceManager};
 
 pub type TexMapType = HashMap<String, DiffuseTextureWrapper>;
 
@@ -24,6 +27,8 @@ pub static TERRAIN_OPAQUE_PIPELINE: &str = "opaque_terrain_shader";
 pub static TERRAIN_TRANSPARENT_PIPELINE: &str = "transparent_terrain_shader";
 
 pub static ATLAS_TEXTURE_NAME: &str = "minecraft:atlas";
+/// The terrain atlas chunks are drawn with, under the name of the binary resource it first comes from
+pub static TERRAIN_ATLAS_TEXTURE_NAME: &str = "terrain.png";
 pub static LIGHTMAP_TEXTURE_NAME: &str = "minecraft:lightmap";
 
 pub fn create_resources(client: &mut Client, device: &wgpu::Device, queue: &wgpu::Queue, config: &wgpu::SurfaceConfiguration) {
@@ -342,61 +347,97 @@ pub fn load_binary_resources(client: &mut Client, device: &wgpu::Device, queue:
             .expect("Cursor io never fails");
 
         let image = reader.decode().unwrap();
-        let dims = image.dimensions();
-        let width = dims.0;
-        let height = dims.1;
-
-        let tex_dims = wgpu::Extent3d {
-            width,
-            height,
-            depth_or_array_layers: 1,
-        };
-        let tex_format = wgpu::TextureFormat::Rgba8UnormSrgb;
-
-        let diffuse_texture = device.create_texture(&wgpu::TextureDescriptor {
-            size: tex_dims,
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: tex_format,
-            view_formats: &[tex_format],
-            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
-            label: Some("diffuse_texture"),
-        });
+        let texture = create_diffuse_texture(client, device, queue, &image);
+        client.insert_texture(path, texture);
+    }
+}
+
+/** Build the terrain atlas from the topmost pack with a block/terrain.png, replacing the one in use
+ *  Leaves the current atlas alone if no pack has one, like the binary resources of a classic install
+ */
+pub fn load_terrain_atlas(client: &mut Client, device: &wgpu::Device, queue: &wgpu::Queue, resources: &ResourceManager) -> Result<(), ResourceError> {
+    let image = resources.read_image(&Identifier::from("block/terrain"), ResourceKind::Texture)?;
+    let texture = create_diffuse_texture(client, device, queue, &image);
+    client.insert_texture(TERRAIN_ATLAS_TEXTURE_NAME, texture);
+    Ok(())
+}
+
+/// Write the tiles of animated textures over their place in the terrain atlas
+pub fn upload_atlas_tiles(client: &Client, queue: &wgpu::Queue, updates: &[TileUpdate]) {
+    let atlas = client.get_texture(TERRAIN_ATLAS_TEXTURE_NAME);
+    for TileUpdate { rect, image } in updates {
         queue.write_texture(
-            diffuse_texture.as_image_copy(),
-            image.to_rgba8().as_bytes(),
+            wgpu::ImageCopyTexture {
+                texture: atlas.get_texture(),
+                mip_level: 0,
+                origin: wgpu::Origin3d { x: rect.x, y: rect.y, z: 0 },
+                aspect: wgpu::TextureAspect::All,
+            },
+            image.as_bytes(),
             wgpu::ImageDataLayout {
                 offset: 0,
-                bytes_per_row: Some(4 * width),
-                rows_per_image: Some(height),
+                bytes_per_row: Some(4 * image.width()),
+                rows_per_image: Some(image.height()),
             },
-            tex_dims,
+            wgpu::Extent3d { width: image.width(), height: image.height(), depth_or_array_layers: 1 },
         );
-        let diffuse_texture_view = diffuse_texture.create_view(&wgpu::TextureViewDescriptor::default());
-
-        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-            address_mode_u: wgpu::AddressMode::ClampToEdge,
-            address_mode_v: wgpu::AddressMode::ClampToEdge,
-            address_mode_w: wgpu::AddressMode::ClampToEdge,
-            mag_filter: wgpu::FilterMode::Nearest,
-            min_filter: wgpu::FilterMode::Nearest,
-            mipmap_filter: wgpu::FilterMode::Nearest,
-            ..Default::default()
-        });
-
-        let texture = DiffuseTextureWrapper::new(
-            diffuse_texture,
-            dims.into(),
-            diffuse_texture_view,
-            sampler,
-            device,
-            client.get_layout(ATLAS_LAYOUT_NAME).unwrap(),
-        );
-        client.insert_texture(path, texture);
     }
 }
 
+fn create_diffuse_texture(client: &Client, device: &wgpu::Device, queue: &wgpu::Queue, image: &DynamicImage) -> DiffuseTextureWrapper {
+    let dims = image.dimensions();
+    let width = dims.0;
+    let height = dims.1;
+
+    let tex_dims = wgpu::Extent3d {
+        width,
+        height,
+        depth_or_array_layers: 1,
+    };
+    let tex_format = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+    let diffuse_texture = device.create_texture(&wgpu::TextureDescriptor {
+        size: tex_dims,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: tex_format,
+        view_formats: &[tex_format],
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        label: Some("diffuse_texture"),
+    });
+    queue.write_texture(
+        diffuse_texture.as_image_copy(),
+        image.to_rgba8().as_bytes(),
+        wgpu::ImageDataLayout {
+            offset: 0,
+            bytes_per_row: Some(4 * width),
+            rows_per_image: Some(height),
+        },
+        tex_dims,
+    );
+    let diffuse_texture_view = diffuse_texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+    let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+        address_mode_u: wgpu::AddressMode::ClampToEdge,
+        address_mode_v: wgpu::AddressMode::ClampToEdge,
+        address_mode_w: wgpu::AddressMode::ClampToEdge,
+        mag_filter: wgpu::FilterMode::Nearest,
+        min_filter: wgpu::FilterMode::Nearest,
+        mipmap_filter: wgpu::FilterMode::Nearest,
+        ..Default::default()
+    });
+
+    DiffuseTextureWrapper::new(
+        diffuse_texture,
+        dims.into(),
+        diffuse_texture_view,
+        sampler,
+        device,
+        client.get_layout(ATLAS_LAYOUT_NAME).unwrap(),
+    )
+}
+
 fn create_missing_tex(_a: image::ImageError) -> DynamicImage {
     let mut rgb_tex = Rgb32FImage::new(2, 2);
     let pink_pixel = image::Rgb::<f32>([1.0, 1.0, 1.0]);
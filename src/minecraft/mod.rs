@@ -1,7 +1,9 @@
 mod blocks;
+#[cfg(feature = "client")]
 pub mod mc_resource_handler;
 pub mod identifier;
 pub mod template_models;
 pub mod registry;
 pub mod prot14;
 pub mod filetypes;
+pub mod blockstates;
@@ -0,0 +1,289 @@
+use std::fmt::Display;
+use std::io::{Read, Write};
+
+use rustc_hash::FxHashMap as HashMap;
+use ultraviolet::IVec3;
+
+use crate::util::nibble;
+use crate::world::chunk::CHUNK_SECTION_AXIS_SIZE;
+
+/// Height of a b1.7.3 world in blocks, MapChunk regions may not extend past it
+pub const WORLD_HEIGHT: i32 = 128;
+
+#[derive(Debug)]
+pub enum MapChunkError {
+    /// The region does not fit in the world vertically
+    RegionOutOfBounds { y: i32, size_y: usize },
+    /// The compressed data could not be inflated
+    Decompression(std::io::Error),
+    /// The block data could not be deflated
+    Compression(std::io::Error),
+    /// The inflated data does not match the size of the region
+    DataLengthMismatch { expected: usize, actual: usize },
+}
+
+impl std::error::Error for MapChunkError {}
+
+impl Display for MapChunkError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::RegionOutOfBounds { y, size_y } => write!(f, "Region from y {y} with height {size_y} is outside of the world"),
+            Self::Decompression(e) => write!(f, "Failed to inflate chunk data: {e}"),
+            Self::Compression(e) => write!(f, "Failed to deflate chunk data: {e}"),
+            Self::DataLengthMismatch { expected, actual } => write!(f, "Expected {expected} bytes of chunk data, got {actual}"),
+        }
+    }
+}
+
+/// A single block of a MapChunk region, positioned inside of its section
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MapChunkBlock {
+    pub x: u32,
+    pub y: u32,
+    pub z: u32,
+    /// The block id in the lower byte and the metadata in the upper byte, as used by the block to state map
+    pub data: u16,
+    pub block_light: u8,
+    pub sky_light: u8,
+}
+
+/// All blocks of a MapChunk region that fall in one section
+#[derive(Debug, Clone, PartialEq)]
+pub struct SectionUpdate {
+    /// Position of the section in chunk coordinates, y being the section index
+    pub section_position: IVec3,
+    pub blocks: Vec<MapChunkBlock>,
+}
+
+/// The origin and size of a MapChunk region, unlike the packet the sizes are the actual sizes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MapChunkRegion {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+    pub size_x: usize,
+    pub size_y: usize,
+    pub size_z: usize,
+}
+
+impl MapChunkRegion {
+    /// Build a region from the fields of a MapChunk packet
+    pub fn from_packet(x: i32, y: i16, z: i32, size_x: i8, size_y: i8, size_z: i8) -> Self {
+        // The sizes are unsigned bytes on the wire, a size of 256 is sent as 255
+        Self {
+            x, y: y as i32, z,
+            size_x: size_x as u8 as usize + 1,
+            size_y: size_y as u8 as usize + 1,
+            size_z: size_z as u8 as usize + 1,
+        }
+    }
+
+    /// The sizes in the form sent by a MapChunk packet
+    pub fn packet_sizes(&self) -> (i8, i8, i8) {
+        ((self.size_x - 1) as u8 as i8, (self.size_y - 1) as u8 as i8, (self.size_z - 1) as u8 as i8)
+    }
+
+    pub fn volume(&self) -> usize {
+        self.size_x * self.size_y * self.size_z
+    }
+
+    /// Number of bytes the region inflates to: block ids, then metadata, block light and sky light nibbles
+    pub fn data_length(&self) -> usize {
+        self.volume() + 3 * self.volume().div_ceil(2)
+    }
+
+    fn validate(&self) -> Result<(), MapChunkError> {
+        if self.y < 0 || self.y + self.size_y as i32 > WORLD_HEIGHT {
+            return Err(MapChunkError::RegionOutOfBounds { y: self.y, size_y: self.size_y });
+        }
+        Ok(())
+    }
+
+    /// Index of a block inside of the region's arrays, the data is ordered x, z, y with y changing fastest
+    fn index(&self, x: usize, y: usize, z: usize) -> usize {
+        y + (z * self.size_y) + (x * self.size_y * self.size_z)
+    }
+}
+
+/** Inflate the data of a MapChunk packet and split it into the sections it touches
+ *  Regions may start anywhere and span any number of sections, the updates are returned in the order
+ *  their sections are first touched
+ */
+pub fn decode_map_chunk(region: MapChunkRegion, compressed_data: &[u8]) -> Result<Vec<SectionUpdate>, MapChunkError> {
+    region.validate()?;
+
+    let expected = region.data_length();
+    let mut raw_data = Vec::with_capacity(expected);
+    // Read one byte more than needed so oversized data is noticed without inflating all of it
+    flate2::read::ZlibDecoder::new(compressed_data)
+        .take(expected as u64 + 1)
+        .read_to_end(&mut raw_data)
+        .map_err(MapChunkError::Decompression)?;
+    if raw_data.len() != expected {
+        return Err(MapChunkError::DataLengthMismatch { expected, actual: raw_data.len() });
+    }
+
+    let volume = region.volume();
+    let meta_start = volume;
+    let block_light_start = meta_start + volume.div_ceil(2);
+    let sky_light_start = block_light_start + volume.div_ceil(2);
+    let block_bytes = &raw_data[0..meta_start];
+    let meta_bytes = &raw_data[meta_start..block_light_start];
+    let block_light_bytes = &raw_data[block_light_start..sky_light_start];
+    let sky_light_bytes = &raw_data[sky_light_start..];
+
+    let section_size = CHUNK_SECTION_AXIS_SIZE as i32;
+    let mut updates: Vec<SectionUpdate> = vec![];
+    let mut section_indices: HashMap<(i32, i32, i32), usize> = HashMap::default();
+    for x in 0..region.size_x {
+        for z in 0..region.size_z {
+            for y in 0..region.size_y {
+                let index = region.index(x, y, z);
+                let world_pos = IVec3::new(region.x + x as i32, region.y + y as i32, region.z + z as i32);
+                let section = (world_pos.x.div_euclid(section_size), world_pos.y.div_euclid(section_size), world_pos.z.div_euclid(section_size));
+
+                let update_index = *section_indices.entry(section).or_insert_with(|| {
+                    updates.push(SectionUpdate { section_position: IVec3::new(section.0, section.1, section.2), blocks: vec![] });
+                    updates.len() - 1
+                });
+
+                let meta = nibble::nibble_get(meta_bytes, index);
+                updates[update_index].blocks.push(MapChunkBlock {
+                    x: world_pos.x.rem_euclid(section_size) as u32,
+                    y: world_pos.y.rem_euclid(section_size) as u32,
+                    z: world_pos.z.rem_euclid(section_size) as u32,
+                    data: block_bytes[index] as u16 | ((meta as u16) << 8),
+                    block_light: nibble::nibble_get(block_light_bytes, index),
+                    sky_light: nibble::nibble_get(sky_light_bytes, index),
+                });
+            }
+        }
+    }
+
+    Ok(updates)
+}
+
+/** Build the compressed data of a MapChunk packet for a region
+ *  `block_at` is given world coordinates and returns the block data (id | meta << 8), block light and sky light
+ */
+pub fn encode_map_chunk<F: FnMut(IVec3) -> (u16, u8, u8)>(region: MapChunkRegion, mut block_at: F) -> Result<Vec<u8>, MapChunkError> {
+    region.validate()?;
+
+    let volume = region.volume();
+    let nibble_length = volume.div_ceil(2);
+    let mut block_bytes = vec![0u8; volume];
+    let mut meta_bytes = vec![0u8; nibble_length];
+    let mut block_light_bytes = vec![0u8; nibble_length];
+    let mut sky_light_bytes = vec![0u8; nibble_length];
+    for x in 0..region.size_x {
+        for z in 0..region.size_z {
+            for y in 0..region.size_y {
+                let index = region.index(x, y, z);
+                let (data, block_light, sky_light) = block_at(IVec3::new(region.x + x as i32, region.y + y as i32, region.z + z as i32));
+                block_bytes[index] = data as u8;
+                nibble::nibble_set(&mut meta_bytes, index, (data >> 8) as u8);
+                nibble::nibble_set(&mut block_light_bytes, index, block_light);
+                nibble::nibble_set(&mut sky_light_bytes, index, sky_light);
+            }
+        }
+    }
+
+    let mut encoder = flate2::write::ZlibEncoder::new(vec![], flate2::Compression::default());
+    for bytes in [block_bytes, meta_bytes, block_light_bytes, sky_light_bytes] {
+        encoder.write_all(&bytes).map_err(MapChunkError::Compression)?;
+    }
+    encoder.finish().map_err(MapChunkError::Compression)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use ultraviolet::IVec3;
+
+    use super::{decode_map_chunk, encode_map_chunk, MapChunkError, MapChunkRegion};
+    use crate::world::chunk::CHUNK_SECTION_AXIS_SIZE;
+
+    fn compress(bytes: &[u8]) -> Vec<u8> {
+        let mut encoder = flate2::write::ZlibEncoder::new(vec![], flate2::Compression::default());
+        encoder.write_all(bytes).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    /// A 1x2x1 column at (3, 10, 5): stone over grass, with meta 1 on the grass and distinct light values
+    const COLUMN_FIXTURE: [u8; 5] = [
+        1, 2, // block ids, y = 10 then y = 11
+        0x10, // meta nibbles, lower nibble first
+        0x2F, // block light
+        0x0E, // sky light
+    ];
+
+    #[test]
+    fn decodes_fixture_column() {
+        let region = MapChunkRegion::from_packet(3, 10, 5, 0, 1, 0);
+        let updates = decode_map_chunk(region, &compress(&COLUMN_FIXTURE)).unwrap();
+        assert_eq!(updates.len(), 1);
+        assert_eq!(updates[0].section_position, IVec3::new(0, 0, 0));
+        let blocks = &updates[0].blocks;
+        assert_eq!((blocks[0].x, blocks[0].y, blocks[0].z), (3, 10, 5));
+        assert_eq!((blocks[0].data, blocks[0].block_light, blocks[0].sky_light), (1, 15, 14));
+        assert_eq!((blocks[1].x, blocks[1].y, blocks[1].z), (3, 11, 5));
+        assert_eq!((blocks[1].data, blocks[1].block_light, blocks[1].sky_light), (2 | (1 << 8), 2, 0));
+    }
+
+    #[test]
+    fn splits_region_across_sections() {
+        let size = CHUNK_SECTION_AXIS_SIZE as i32;
+        // Two blocks on each side of the section border on every axis, starting in negative chunk coordinates
+        let region = MapChunkRegion { x: -2, y: size - 2, z: size - 2, size_x: 4, size_y: 4, size_z: 4 };
+        let data = encode_map_chunk(region, |pos| ((pos.y as u16) & 0xFF, 0, 15)).unwrap();
+        let updates = decode_map_chunk(region, &data).unwrap();
+
+        assert_eq!(updates.len(), 8);
+        assert!(updates.iter().all(|update| update.blocks.len() == 8));
+        let below = updates.iter().find(|update| update.section_position == IVec3::new(-1, 0, 0)).unwrap();
+        assert!(below.blocks.iter().all(|block| block.x >= size as u32 - 2 && block.data == (block.y as u16)));
+        let above = updates.iter().find(|update| update.section_position == IVec3::new(0, 1, 1)).unwrap();
+        assert!(above.blocks.iter().all(|block| block.y < 2 && block.data == (block.y as u16 + size as u16)));
+    }
+
+    #[test]
+    fn round_trips_full_column() {
+        let region = MapChunkRegion::from_packet(16, 0, -32, 15, 127, 15);
+        let data = encode_map_chunk(region, |pos| ((pos.x + pos.y + pos.z) as u16 & 0xFF | 3 << 8, (pos.y % 16) as u8, 15)).unwrap();
+        let updates = decode_map_chunk(region, &data).unwrap();
+        assert_eq!(updates.len(), 128 / CHUNK_SECTION_AXIS_SIZE);
+        assert_eq!(updates.iter().map(|update| update.blocks.len()).sum::<usize>(), 16 * 128 * 16);
+        assert!(updates.iter().flat_map(|update| update.blocks.iter()).all(|block| block.data >> 8 == 3 && block.sky_light == 15));
+    }
+
+    #[test]
+    fn rejects_short_data() {
+        let region = MapChunkRegion::from_packet(0, 0, 0, 1, 1, 1);
+        let result = decode_map_chunk(region, &compress(&COLUMN_FIXTURE));
+        assert!(matches!(result, Err(MapChunkError::DataLengthMismatch { expected: 20, actual: 5 })));
+    }
+
+    #[test]
+    fn rejects_oversized_data() {
+        let region = MapChunkRegion::from_packet(0, 0, 0, 0, 0, 0);
+        let result = decode_map_chunk(region, &compress(&COLUMN_FIXTURE));
+        assert!(matches!(result, Err(MapChunkError::DataLengthMismatch { expected: 4, actual: 5 })));
+    }
+
+    #[test]
+    fn rejects_region_outside_world() {
+        let region = MapChunkRegion::from_packet(0, 120, 0, 0, 15, 0);
+        let result = decode_map_chunk(region, &compress(&[0; 40]));
+        assert!(matches!(result, Err(MapChunkError::RegionOutOfBounds { y: 120, size_y: 16 })));
+        let region = MapChunkRegion::from_packet(0, -1, 0, 0, 0, 0);
+        assert!(matches!(decode_map_chunk(region, &[]), Err(MapChunkError::RegionOutOfBounds { .. })));
+    }
+
+    #[test]
+    fn rejects_corrupt_data() {
+        let region = MapChunkRegion::from_packet(0, 0, 0, 0, 0, 0);
+        let result = decode_map_chunk(region, &[0x78, 0x9C, 0xFF, 0xFF, 0xFF]);
+        assert!(matches!(result, Err(MapChunkError::Decompression(_))));
+    }
+}
@@ -2,6 +2,22 @@ use rustc_hash::FxHashMap as HashMap;
 
 use super::{registry::Registry, identifier::Identifier};
 
+pub mod map_chunk;
+
+/// Entity positions are sent as fixed point numbers with 5 fractional bits
+pub fn to_fixed_point(value: f64) -> i32 {
+    (value * 32.0).floor() as i32
+}
+
+pub fn from_fixed_point(value: i32) -> f64 {
+    value as f64 / 32.0
+}
+
+/// Entity angles are sent as a byte, a full turn being 256
+pub fn to_angle_byte(degrees: f32) -> i8 {
+    (degrees * 256.0 / 360.0) as i32 as i8
+}
+
 pub fn generate_block_to_state_map(registry: &Registry) -> HashMap<u16, usize> {
     let blocks = registry.get_block_register();
     let mut map = HashMap::default();
@@ -3,8 +3,10 @@ use std::rc::Rc;
 use crate::block::BlockState;
 use crate::block::properties::PropertyDefinition;
 use crate::client::models::model::BakedModel;
+use crate::client::models::report::ModelLoadReport;
 use crate::{block::Block, minecraft::identifier::Identifier, game_version::GameVersion};
 use crate::client::textures::TextureObject;
+use crate::resource_manager::{ResourceError, ResourceManager};
 
 use rustc_hash::FxHashMap as HashMap;
 
@@ -19,6 +21,7 @@ pub struct Registry {
     properties: Register<PropertyDefinition>,
     blockstates: Register<BlockState>,
     models: HashMap<Identifier, BakedModel>,
+    model_report: ModelLoadReport,
     // dimension: Vec<Dimension>,
 }
 
@@ -33,17 +36,41 @@ impl Registry {
         let properties = Register::<PropertyDefinition>::new(256);
         let blockstates = Register::<BlockState>::new(256);
         let models = HashMap::default();
-        Self { blocks, textures, properties, blockstates, models }
+        let model_report = ModelLoadReport::new();
+        Self { blocks, textures, properties, blockstates, models, model_report }
     }
 
+    /// Load a version with the assets of the base game
     pub fn load_from(version: GameVersion) -> Self {
+        let resources = ResourceManager::with_default_assets().unwrap_or_else(|e| {
+            log::error!("Failed to open the default assets: {e}");
+            ResourceManager::new()
+        });
+        Self::load_with_resources(version, &resources)
+    }
+
+    /// Load a version with models, blockstates and atlases from a resource pack stack
+    pub fn load_with_resources(version: GameVersion, resources: &ResourceManager) -> Self {
         let mut registry = Self::new();
-        
-        version.load_registry(&mut registry);
+
+        version.load_registry(&mut registry, resources);
 
         registry
     }
 
+    /** Re-read the resource pack stack and rebake every model and texture of `version` from it
+     *  Blocks and blockstates keep their indices, so loaded chunks and anything else refering to them stay valid
+     *  On an error the packs and models that were loaded before are kept
+     */
+    pub fn reload(&mut self, version: &GameVersion, resources: &mut ResourceManager) -> Result<(), ResourceError> {
+        // Only swap the packs in once models could be loaded from them
+        let reopened = resources.reopen()?;
+        version.load_models(self, &reopened)?;
+        *resources = reopened;
+        log::info!("Reloaded {} textures and {} models from {} packs", self.textures.len(), self.models.len(), resources.packs().len());
+        Ok(())
+    }
+
     pub fn load_custom<F: FnOnce(&mut Registry)>(funct: F) -> Self {
         let mut registry = Self::new();
         funct(&mut registry);
@@ -90,6 +117,15 @@ impl Registry {
         &mut self.models
     }
 
+    /// What went wrong the last time models were loaded
+    pub fn get_model_load_report(&self) -> &ModelLoadReport {
+        &self.model_report
+    }
+
+    pub fn get_model_load_report_mut(&mut self) -> &mut ModelLoadReport {
+        &mut self.model_report
+    }
+
     pub fn reset(&mut self) {
         self.blocks.clear();
     }
@@ -182,3 +218,64 @@ impl<T: Registerable> Register<T> {
         self.current_id
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::Registry;
+    use crate::client::textures::TextureObject;
+    use crate::game_version::GameVersion;
+    use crate::minecraft::identifier::Identifier;
+    use crate::resource_manager::{ResourceKind, ResourceManager, ResourceType};
+    use crate::util::test_directory::TestDirectory;
+
+    fn write_pack(root: &Path, atlas_cell: u32, faces: &str) {
+        crate::game_version::tests::write_pack(root, &[
+            (ResourceKind::Atlas.path(&Identifier::from("block/terrain")),
+                format!(r#"{{"atlas": {{"Uniform": {{"across": 16, "textures": [{{"cell": {atlas_cell}, "identifier": "minecraft:block/stone"}}]}}}}}}"#)),
+            (ResourceKind::Model.path(&Identifier::from("block/missing")),
+                format!(r##"{{"textures": {{"all": "minecraft:block/stone"}}, "elements": [{{"from": [0, 0, 0], "to": [16, 16, 16], "faces": {{{faces}}}}}]}}"##)),
+        ]);
+    }
+
+    fn stone_uv(registry: &Registry) -> [f32; 2] {
+        match registry.get_texture_register().get(&Identifier::from("block/stone")) {
+            Some(TextureObject::AtlasTexture { internal_uv }) => [internal_uv[0].x, internal_uv[0].y],
+            _ => panic!("Stone should be in the atlas"),
+        }
+    }
+
+    #[test]
+    fn reload_rebakes_models_and_keeps_blocks() {
+        let directory = TestDirectory::new("reload");
+        write_pack(&directory, 1, r##""up": {"texture": "#all"}, "down": {"texture": "#all"}"##);
+
+        let mut resources = ResourceManager::from_sources(vec![ResourceType::Dir(directory.to_path_buf())]).unwrap();
+        let mut registry = Registry::load_with_resources(GameVersion::B173, &resources);
+        let stone = Identifier::from("stone");
+        let block_count = registry.get_block_register().get_elements().len();
+        let state_count = registry.get_blockstate_register().get_elements().len();
+        let stone_index = registry.get_block_register().get_index_from_identifier(&stone);
+        assert_eq!(registry.get_model_register().len(), state_count);
+        assert_eq!(stone_uv(&registry), [1. / 16., 0.]);
+        assert!(registry.get_model_register().values().all(|model| model.shapes().len() == 2));
+
+        // Change the pack on disk as someone editing it while playing would
+        write_pack(&directory, 17, r##""north": {"texture": "#all"}"##);
+        registry.reload(&GameVersion::B173, &mut resources).unwrap();
+        assert_eq!(stone_uv(&registry), [1. / 16., 1. / 16.]);
+        assert!(registry.get_model_register().values().all(|model| model.shapes().len() == 1));
+        assert_eq!(registry.get_model_register().len(), state_count);
+        assert_eq!(registry.get_block_register().get_elements().len(), block_count);
+        assert_eq!(registry.get_blockstate_register().get_elements().len(), state_count);
+        assert_eq!(registry.get_block_register().get_index_from_identifier(&stone), stone_index);
+
+        // A pack that breaks leaves what was loaded in place
+        std::fs::remove_file(directory.join(ResourceKind::Atlas.path(&Identifier::from("block/terrain")))).unwrap();
+        assert!(registry.reload(&GameVersion::B173, &mut resources).is_err());
+        assert!(resources.contains(&Identifier::from("block/terrain"), ResourceKind::Atlas));
+        assert_eq!(stone_uv(&registry), [1. / 16., 1. / 16.]);
+        assert_eq!(registry.get_model_register().len(), state_count);
+    }
+}
@@ -0,0 +1,174 @@
+use std::collections::hash_map::RandomState;
+use std::fmt::Display;
+use std::hash::{BuildHasher, Hasher};
+use std::time::{Duration, SystemTime};
+
+use super::http::{self, HttpError};
+
+/// The legacy session endpoint the b1.7.3 client joins servers through
+pub const DEFAULT_JOIN_ENDPOINT: &str = "http://session.minecraft.net/game/joinserver.jsp";
+/// The legacy session endpoint b1.7.3 servers check joining players against
+pub const DEFAULT_CHECK_ENDPOINT: &str = "http://session.minecraft.net/game/checkserver.jsp";
+/// Sent by servers in the handshake instead of a server hash when they are in offline mode
+pub const OFFLINE_SERVER_HASH: &str = "-";
+/// How long the session server may take to answer
+const SESSION_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug)]
+pub enum AuthError {
+    /// The server is in online mode but there is no account to join it with
+    NotLoggedIn,
+    Http(HttpError),
+    /// The session server refused the join, with its response
+    Rejected(String),
+}
+
+impl std::error::Error for AuthError {}
+
+impl Display for AuthError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotLoggedIn => write!(f, "Not logged in to an account"),
+            Self::Http(e) => write!(f, "Could not reach the session server: {e}"),
+            Self::Rejected(response) => write!(f, "Failed to login: {response}"),
+        }
+    }
+}
+
+impl From<HttpError> for AuthError {
+    fn from(e: HttpError) -> Self {
+        Self::Http(e)
+    }
+}
+
+/// The account a client joins online mode servers as
+#[derive(Debug, Clone, PartialEq)]
+pub struct SessionCredentials {
+    pub username: String,
+    pub session_id: String,
+}
+
+/// Where and as whom to authenticate when a server asks for it
+#[derive(Debug, Clone, PartialEq)]
+pub struct SessionAuth {
+    pub join_endpoint: String,
+    pub credentials: Option<SessionCredentials>,
+}
+
+impl SessionAuth {
+    /** Tell the session server we are joining the server that sent `server_hash` in its handshake
+     *  Offline servers need no authentication and always succeed
+     */
+    pub fn join(&self, server_hash: &str) -> Result<(), AuthError> {
+        if !requires_authentication(server_hash) {
+            return Ok(());
+        }
+        let credentials = self.credentials.as_ref().ok_or(AuthError::NotLoggedIn)?;
+        join_session(&self.join_endpoint, credentials, server_hash)
+    }
+}
+
+pub fn requires_authentication(server_hash: &str) -> bool {
+    server_hash != OFFLINE_SERVER_HASH
+}
+
+/// Register a join with a joinserver.jsp style endpoint, which answers OK when the session is valid
+pub fn join_session(endpoint: &str, credentials: &SessionCredentials, server_hash: &str) -> Result<(), AuthError> {
+    let url = http::with_query(endpoint, &[("user", &credentials.username), ("sessionId", &credentials.session_id), ("serverId", server_hash)]);
+    let response = http::get(&url, SESSION_TIMEOUT)?;
+    let body = response.body.trim();
+    if response.status == 200 && body.eq_ignore_ascii_case("ok") {
+        Ok(())
+    } else if body.is_empty() {
+        Err(AuthError::Rejected(format!("Session server answered with status {}", response.status)))
+    } else {
+        Err(AuthError::Rejected(body.to_string()))
+    }
+}
+
+/// A fresh server id for an online mode server to send in its handshake, a random hex number like b1.7.3 servers use
+pub fn generate_server_id() -> String {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u128(SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_nanos());
+    format!("{:x}", hasher.finish())
+}
+
+/// Checks with a session service that a player joining an online mode server is who they claim to be
+pub trait SessionVerifier {
+    /// Whether `username` told the session service they are joining the server with `server_id`
+    fn verify(&self, username: &str, server_id: &str) -> Result<bool, AuthError>;
+}
+
+/// Verifies players through a checkserver.jsp style endpoint, which answers YES for a valid join
+#[derive(Debug, Clone, PartialEq)]
+pub struct HttpSessionVerifier {
+    pub check_endpoint: String,
+}
+
+impl HttpSessionVerifier {
+    pub fn new(check_endpoint: String) -> Self {
+        Self { check_endpoint }
+    }
+}
+
+impl Default for HttpSessionVerifier {
+    fn default() -> Self {
+        Self::new(DEFAULT_CHECK_ENDPOINT.to_string())
+    }
+}
+
+impl SessionVerifier for HttpSessionVerifier {
+    fn verify(&self, username: &str, server_id: &str) -> Result<bool, AuthError> {
+        let url = http::with_query(&self.check_endpoint, &[("user", username), ("serverId", server_id)]);
+        let response = http::get(&url, SESSION_TIMEOUT)?;
+        Ok(response.status == 200 && response.body.trim().eq_ignore_ascii_case("yes"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{generate_server_id, AuthError, HttpSessionVerifier, SessionAuth, SessionCredentials, SessionVerifier};
+    use crate::network::mock_session::MockSessionServer;
+
+    fn auth(endpoint: String) -> SessionAuth {
+        SessionAuth { join_endpoint: endpoint, credentials: Some(SessionCredentials { username: String::from("Dev"), session_id: String::from("token:1 2") }) }
+    }
+
+    #[test]
+    fn joins_through_session_server() {
+        let server = MockSessionServer::start(|_| String::from("OK"));
+        auth(server.url("/game/joinserver.jsp")).join("4f2a").unwrap();
+        assert_eq!(server.requests(), ["/game/joinserver.jsp?user=Dev&sessionId=token%3A1%202&serverId=4f2a"]);
+    }
+
+    #[test]
+    fn reports_rejections() {
+        let server = MockSessionServer::start(|_| String::from("Bad login"));
+        match auth(server.url("/game/joinserver.jsp")).join("4f2a") {
+            Err(AuthError::Rejected(response)) => assert_eq!(response, "Bad login"),
+            result => panic!("Expected a rejection, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn skips_offline_servers() {
+        let server = MockSessionServer::start(|_| String::from("Bad login"));
+        let mut auth = auth(server.url("/game/joinserver.jsp"));
+        auth.join("-").unwrap();
+        assert!(server.requests().is_empty());
+
+        auth.credentials = None;
+        assert!(matches!(auth.join("4f2a"), Err(AuthError::NotLoggedIn)));
+    }
+
+    #[test]
+    fn verifies_through_session_server() {
+        let server = MockSessionServer::start(|path| String::from(if path.contains("user=Dev&") { "YES" } else { "NO" }));
+        let verifier = HttpSessionVerifier::new(server.url("/game/checkserver.jsp"));
+        let server_id = generate_server_id();
+        assert!(verifier.verify("Dev", &server_id).unwrap());
+        assert!(!verifier.verify("Notch", &server_id).unwrap());
+        assert_eq!(server.requests()[0], format!("/game/checkserver.jsp?user=Dev&serverId={server_id}"));
+        assert_ne!(server_id, generate_server_id());
+    }
+}
@@ -0,0 +1,107 @@
+use std::io::{Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use orange_networking::packet::{PacketEnumHolder, PacketParseError};
+
+use super::PacketConnection;
+use crate::packets::prot14::Packet;
+
+/** A packet connection over a plain tcp stream, usable from either end of the socket
+ *  Packets are parsed on a reader thread and collected by get_packets, sending writes directly to the stream
+ */
+pub struct TcpConnection {
+    stream: Mutex<TcpStream>,
+    received: Mutex<Receiver<Packet>>,
+    closed: Arc<AtomicBool>,
+    reader: Option<JoinHandle<()>>,
+}
+
+impl TcpConnection {
+    pub fn connect<A: ToSocketAddrs>(address: A) -> std::io::Result<Self> {
+        Self::from_stream(TcpStream::connect(address)?)
+    }
+
+    /// Wrap an already connected stream, such as one accepted by a listener
+    pub fn from_stream(stream: TcpStream) -> std::io::Result<Self> {
+        stream.set_nodelay(true)?;
+        let read_stream = stream.try_clone()?;
+        let (sender, received) = mpsc::channel();
+        let closed = Arc::new(AtomicBool::new(false));
+        let reader_closed = closed.clone();
+        let reader = thread::spawn(move || {
+            Self::read_packets(read_stream, sender);
+            reader_closed.store(true, Ordering::Release);
+        });
+        Ok(Self { stream: Mutex::new(stream), received: Mutex::new(received), closed, reader: Some(reader) })
+    }
+
+    fn read_packets(mut stream: TcpStream, sender: Sender<Packet>) {
+        let mut buffer = vec![];
+        let mut read_buffer = [0u8; 4096];
+        loop {
+            let read = match stream.read(&mut read_buffer) {
+                Ok(0) | Err(_) => { return; },
+                Ok(read) => read,
+            };
+            buffer.extend_from_slice(&read_buffer[..read]);
+
+            let mut consumed = 0;
+            loop {
+                match Packet::bytes_to_packet(&buffer[consumed..]) {
+                    Ok((packet, size)) => {
+                        consumed += size;
+                        if sender.send(packet).is_err() { return; }
+                    },
+                    Err(PacketParseError::NotEnoughData) => { break; },
+                    Err(_) => {
+                        log::error!("Received an unparseable packet with id {:#04X}, closing the connection", buffer[consumed]);
+                        let _ = stream.shutdown(Shutdown::Both);
+                        return;
+                    },
+                }
+            }
+            buffer.drain(..consumed);
+        }
+    }
+
+    pub fn peer_address(&self) -> std::io::Result<SocketAddr> {
+        self.stream.lock().unwrap().peer_addr()
+    }
+}
+
+impl PacketConnection for TcpConnection {
+    fn send_packet(&self, packet: Packet) {
+        let bytes = Packet::packet_to_bytes(packet);
+        if let Err(e) = self.stream.lock().unwrap().write_all(&bytes) {
+            log::warn!("Failed to send packet: {e}");
+            self.closed.store(true, Ordering::Release);
+        }
+    }
+
+    fn get_packets(&self) -> Vec<Packet> {
+        self.received.lock().unwrap().try_iter().collect()
+    }
+
+    /// Whether the other end closed the connection or sent something unreadable
+    fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Acquire)
+    }
+
+    fn stop(&mut self) {
+        let _ = self.stream.lock().unwrap().shutdown(Shutdown::Both);
+        if let Some(reader) = self.reader.take() {
+            let _ = reader.join();
+        }
+        self.closed.store(true, Ordering::Release);
+    }
+}
+
+impl Drop for TcpConnection {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
@@ -0,0 +1,131 @@
+use std::fmt::Display;
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+// Just enough of an HTTP/1.0 client to talk to the legacy session endpoints
+// Only plain http urls are supported, the endpoints are configurable so a local stand-in or proxy can be used
+
+#[derive(Debug)]
+pub enum HttpError {
+    InvalidUrl(String),
+    Io(std::io::Error),
+    MalformedResponse,
+}
+
+impl std::error::Error for HttpError {}
+
+impl Display for HttpError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidUrl(url) => write!(f, "Invalid url: {url}"),
+            Self::Io(e) => write!(f, "Http io error: {e}"),
+            Self::MalformedResponse => write!(f, "Malformed http response"),
+        }
+    }
+}
+
+impl From<std::io::Error> for HttpError {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub body: String,
+}
+
+/// The parts of an http url needed to make a request
+struct HttpUrl<'a> {
+    host: &'a str,
+    port: u16,
+    path: &'a str,
+}
+
+impl<'a> HttpUrl<'a> {
+    fn parse(url: &'a str) -> Result<Self, HttpError> {
+        let rest = url.strip_prefix("http://").ok_or_else(|| HttpError::InvalidUrl(url.to_string()))?;
+        let (authority, path) = match rest.find('/') {
+            Some(index) => (&rest[..index], &rest[index..]),
+            None => (rest, "/"),
+        };
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => (host, port.parse().map_err(|_| HttpError::InvalidUrl(url.to_string()))?),
+            None => (authority, 80),
+        };
+        if host.is_empty() {
+            return Err(HttpError::InvalidUrl(url.to_string()));
+        }
+        Ok(Self { host, port, path })
+    }
+}
+
+/// Percent encode a query parameter
+pub fn encode_query_value(value: &str) -> String {
+    value.bytes().map(|byte| match byte {
+        b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (byte as char).to_string(),
+        _ => format!("%{:02X}", byte),
+    }).collect()
+}
+
+/// Build a url from a base and query parameters, encoding the values
+pub fn with_query(base: &str, parameters: &[(&str, &str)]) -> String {
+    let query: Vec<String> = parameters.iter().map(|(key, value)| format!("{key}={}", encode_query_value(value))).collect();
+    let separator = if base.contains('?') { '&' } else { '?' };
+    format!("{base}{separator}{}", query.join("&"))
+}
+
+/// Make a GET request, waiting at most `timeout` for each step
+pub fn get(url: &str, timeout: Duration) -> Result<HttpResponse, HttpError> {
+    let parsed = HttpUrl::parse(url)?;
+    let address = (parsed.host, parsed.port).to_socket_addrs()?.next().ok_or_else(|| HttpError::InvalidUrl(url.to_string()))?;
+    let mut stream = TcpStream::connect_timeout(&address, timeout)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+
+    write!(stream, "GET {} HTTP/1.0\r\nHost: {}\r\nUser-Agent: orange-rs\r\nConnection: close\r\n\r\n", parsed.path, parsed.host)?;
+    let mut response = vec![];
+    stream.read_to_end(&mut response)?;
+    parse_response(&response)
+}
+
+fn parse_response(response: &[u8]) -> Result<HttpResponse, HttpError> {
+    let header_end = response.windows(4).position(|window| window == b"\r\n\r\n").ok_or(HttpError::MalformedResponse)?;
+    let head = std::str::from_utf8(&response[..header_end]).map_err(|_| HttpError::MalformedResponse)?;
+    let status = head.lines().next()
+        .and_then(|status_line| status_line.split_whitespace().nth(1))
+        .and_then(|status| status.parse().ok())
+        .ok_or(HttpError::MalformedResponse)?;
+    let body = String::from_utf8_lossy(&response[header_end + 4..]).to_string();
+    Ok(HttpResponse { status, body })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{encode_query_value, parse_response, with_query, HttpUrl};
+
+    #[test]
+    fn parses_urls() {
+        let url = HttpUrl::parse("http://127.0.0.1:8080/game/joinserver.jsp?user=a").unwrap();
+        assert_eq!((url.host, url.port, url.path), ("127.0.0.1", 8080, "/game/joinserver.jsp?user=a"));
+        let url = HttpUrl::parse("http://session.minecraft.net").unwrap();
+        assert_eq!((url.host, url.port, url.path), ("session.minecraft.net", 80, "/"));
+        assert!(HttpUrl::parse("https://session.minecraft.net/").is_err());
+    }
+
+    #[test]
+    fn encodes_queries() {
+        assert_eq!(encode_query_value("a b&c=d"), "a%20b%26c%3Dd");
+        assert_eq!(with_query("http://host/join", &[("user", "Dev"), ("serverId", "-12ab")]), "http://host/join?user=Dev&serverId=-12ab");
+    }
+
+    #[test]
+    fn parses_responses() {
+        let response = parse_response(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nOK").unwrap();
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body, "OK");
+        assert!(parse_response(b"garbage").is_err());
+    }
+}
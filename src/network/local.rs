@@ -0,0 +1,84 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+
+use super::PacketConnection;
+use crate::packets::prot14::Packet;
+
+/** One end of a connection within a single process, packets are handed to the other end as they are
+ *  without being written out as bytes, so a client and an integrated server can talk without a socket
+ *  Stopping or dropping either end closes the connection for both
+ */
+pub struct LocalConnection {
+    sender: Mutex<Option<Sender<Packet>>>,
+    received: Mutex<Receiver<Packet>>,
+    closed: Arc<AtomicBool>,
+}
+
+impl LocalConnection {
+    /// Both ends of a new connection
+    pub fn pair() -> (Self, Self) {
+        let (first_sender, second_received) = mpsc::channel();
+        let (second_sender, first_received) = mpsc::channel();
+        let closed = Arc::new(AtomicBool::new(false));
+        (
+            Self { sender: Mutex::new(Some(first_sender)), received: Mutex::new(first_received), closed: closed.clone() },
+            Self { sender: Mutex::new(Some(second_sender)), received: Mutex::new(second_received), closed },
+        )
+    }
+}
+
+impl PacketConnection for LocalConnection {
+    fn send_packet(&self, packet: Packet) {
+        if self.is_closed() {
+            return;
+        }
+        let sent = self.sender.lock().unwrap().as_ref().is_some_and(|sender| sender.send(packet).is_ok());
+        if !sent {
+            self.closed.store(true, Ordering::Release);
+        }
+    }
+
+    /// Packets sent before the other end closed are still handed out
+    fn get_packets(&self) -> Vec<Packet> {
+        self.received.lock().unwrap().try_iter().collect()
+    }
+
+    fn stop(&mut self) {
+        self.closed.store(true, Ordering::Release);
+        self.sender.lock().unwrap().take();
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Acquire)
+    }
+}
+
+impl Drop for LocalConnection {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LocalConnection;
+    use crate::network::PacketConnection;
+    use crate::packets::prot14::Packet;
+
+    #[test]
+    fn exchanges_packets_until_closed() {
+        let (client, server) = LocalConnection::pair();
+        client.send_packet(Packet::Handshake { handshake_data: String::from("Dev") });
+        server.send_packet(Packet::KeepAlive);
+        assert!(matches!(server.get_packets().as_slice(), [Packet::Handshake { handshake_data }] if handshake_data == "Dev"));
+        assert!(matches!(client.get_packets().as_slice(), [Packet::KeepAlive]));
+        assert!(server.get_packets().is_empty());
+
+        server.send_packet(Packet::Chat { chat_data: String::from("Bye") });
+        drop(server);
+        assert!(client.is_closed());
+        assert!(matches!(client.get_packets().as_slice(), [Packet::Chat { .. }]));
+        client.send_packet(Packet::KeepAlive);
+    }
+}
@@ -0,0 +1,282 @@
+use std::fmt::Display;
+use std::net::{SocketAddr, TcpListener};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use super::connection::TcpConnection;
+use super::PacketConnection;
+use crate::minecraft::prot14::map_chunk::{encode_map_chunk, MapChunkRegion, WORLD_HEIGHT};
+use crate::packets::prot14::{Packet, PROTOCOL_VERSION};
+
+/// How long the mock server waits for the client during the login sequence
+const LOGIN_TIMEOUT: Duration = Duration::from_secs(5);
+/// How often the mock server checks for packets while waiting
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+#[derive(Debug)]
+pub enum MockServerError {
+    Io(std::io::Error),
+    /// Gave up waiting for the described packet
+    Timeout(String),
+    UnexpectedPacket(String),
+    ClientDisconnected,
+}
+
+impl std::error::Error for MockServerError {}
+
+impl Display for MockServerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "Mock server io error: {e}"),
+            Self::Timeout(expected) => write!(f, "Timed out waiting for {expected}"),
+            Self::UnexpectedPacket(packet) => write!(f, "Unexpected packet: {packet}"),
+            Self::ClientDisconnected => write!(f, "Client disconnected"),
+        }
+    }
+}
+
+impl From<std::io::Error> for MockServerError {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+/// What the mock server answers the client's login with
+#[derive(Debug, Clone)]
+pub struct MockLogin {
+    pub entity_id: i32,
+    pub seed: i64,
+    pub dimension: i8,
+    pub spawn: (i32, i32, i32),
+    pub time: u64,
+    /// Sent in the handshake reply, a dash for an offline mode server
+    pub server_hash: String,
+}
+
+impl Default for MockLogin {
+    fn default() -> Self {
+        Self { entity_id: 1, seed: 0, dimension: 0, spawn: (8, 64, 8), time: 6000, server_hash: String::from("-") }
+    }
+}
+
+/// A step of a mock server's script, run in order once the client is logged in
+pub enum MockServerAction {
+    Send(Packet),
+    /// Send several packets at once, such as the output of flat_chunk
+    SendAll(Vec<Packet>),
+    Wait(Duration),
+    /// Wait until the client sends a packet matching `matches`, failing the script after `timeout`
+    Expect { description: &'static str, matches: fn(&Packet) -> bool, timeout: Duration },
+    /// Kick the client with a reason and end the script
+    Kick(String),
+}
+
+/** A scripted stand-in for a b1.7.3 server on localhost, for testing clients without a real server
+ *  Accepts a single client, answers the handshake and login, then runs its script
+ *  Every packet the client sends is kept and can be inspected while or after the script runs
+ */
+pub struct MockServer {
+    address: SocketAddr,
+    received: Arc<Mutex<Vec<Packet>>>,
+    handle: Option<JoinHandle<Result<(), MockServerError>>>,
+}
+
+impl MockServer {
+    pub fn start(login: MockLogin, script: Vec<MockServerAction>) -> std::io::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let address = listener.local_addr()?;
+        let received = Arc::new(Mutex::new(vec![]));
+        let thread_received = received.clone();
+        let handle = thread::spawn(move || {
+            let (stream, _) = listener.accept()?;
+            let mut connection = TcpConnection::from_stream(stream)?;
+            let result = Self::run(&connection, &thread_received, login, script);
+            // Let the client read everything before the socket closes
+            thread::sleep(POLL_INTERVAL * 10);
+            connection.stop();
+            result
+        });
+        Ok(Self { address, received, handle: Some(handle) })
+    }
+
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// A copy of every packet the client has sent so far
+    pub fn received_packets(&self) -> Vec<Packet> {
+        self.received.lock().unwrap().clone()
+    }
+
+    /// Wait for the script to finish, returning every packet the client sent
+    pub fn join(mut self) -> Result<Vec<Packet>, MockServerError> {
+        if let Some(handle) = self.handle.take() {
+            handle.join().map_err(|_| MockServerError::ClientDisconnected)??;
+        }
+        Ok(self.received_packets())
+    }
+
+    fn run(connection: &TcpConnection, received: &Mutex<Vec<Packet>>, login: MockLogin, script: Vec<MockServerAction>) -> Result<(), MockServerError> {
+        let username = match Self::expect(connection, received, "handshake", LOGIN_TIMEOUT, |packet| matches!(packet, Packet::Handshake { .. }))? {
+            Packet::Handshake { handshake_data } => handshake_data,
+            _ => unreachable!(),
+        };
+        connection.send_packet(Packet::Handshake { handshake_data: login.server_hash.clone() });
+
+        match Self::expect(connection, received, "login", LOGIN_TIMEOUT, |packet| matches!(packet, Packet::Login { .. }))? {
+            Packet::Login { protocol, username: login_name, .. } if protocol == PROTOCOL_VERSION && login_name == username => {},
+            packet => {
+                connection.send_packet(Packet::DisconnectKick { reason: String::from("Outdated client!") });
+                return Err(MockServerError::UnexpectedPacket(format!("{:?}", packet)));
+            },
+        }
+        connection.send_packet(Packet::Login { protocol: login.entity_id, username: String::new(), seed: login.seed, dimension: login.dimension });
+        let (x, y, z) = login.spawn;
+        connection.send_packet(Packet::SpawnPosition { x, y, z });
+        connection.send_packet(Packet::TimeUpdate { time: login.time });
+
+        for action in script {
+            match action {
+                MockServerAction::Send(packet) => { connection.send_packet(packet); },
+                MockServerAction::SendAll(packets) => { packets.into_iter().for_each(|packet| connection.send_packet(packet)); },
+                MockServerAction::Wait(duration) => {
+                    thread::sleep(duration);
+                    Self::collect(connection, received);
+                },
+                MockServerAction::Expect { description, matches, timeout } => { Self::expect(connection, received, description, timeout, matches)?; },
+                MockServerAction::Kick(reason) => {
+                    connection.send_packet(Packet::DisconnectKick { reason });
+                    break;
+                },
+            }
+        }
+        Self::collect(connection, received);
+        Ok(())
+    }
+
+    fn collect(connection: &TcpConnection, received: &Mutex<Vec<Packet>>) -> Vec<Packet> {
+        let packets = connection.get_packets();
+        received.lock().unwrap().extend(packets.iter().cloned());
+        packets
+    }
+
+    fn expect<F: Fn(&Packet) -> bool>(connection: &TcpConnection, received: &Mutex<Vec<Packet>>, description: &str, timeout: Duration, matches: F) -> Result<Packet, MockServerError> {
+        let start = Instant::now();
+        loop {
+            if let Some(packet) = Self::collect(connection, received).into_iter().find(&matches) {
+                return Ok(packet);
+            }
+            if connection.is_closed() {
+                return Err(MockServerError::ClientDisconnected);
+            }
+            if start.elapsed() > timeout {
+                return Err(MockServerError::Timeout(description.to_string()));
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
+
+/** The PreChunk and MapChunk packets of a full chunk column filled with `block` up to `height`
+ *  Sky light is full above the filled blocks and empty inside of them
+ */
+pub fn flat_chunk(chunk_x: i32, chunk_z: i32, height: i32, block: u8) -> Vec<Packet> {
+    let region = MapChunkRegion { x: chunk_x << 4, y: 0, z: chunk_z << 4, size_x: 16, size_y: WORLD_HEIGHT as usize, size_z: 16 };
+    let compressed_data = encode_map_chunk(region, |pos| {
+        if pos.y < height { (block as u16, 0, 0) } else { (0, 0, 15) }
+    }).expect("A full chunk column is always inside of the world");
+    let (size_x, size_y, size_z) = region.packet_sizes();
+    vec![
+        Packet::PreChunk { x: chunk_x, z: chunk_z, mode: true },
+        Packet::MapChunk { x: region.x, y: region.y as i16, z: region.z, size_x, size_y, size_z, compressed_data },
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{flat_chunk, MockLogin, MockServer, MockServerAction, MockServerError};
+    use crate::minecraft::prot14::map_chunk::{decode_map_chunk, MapChunkRegion};
+    use crate::network::connection::TcpConnection;
+    use crate::network::PacketConnection;
+    use crate::packets::prot14::{Packet, PROTOCOL_VERSION};
+
+    /// Poll the connection until `count` packets arrived
+    fn receive(connection: &TcpConnection, count: usize) -> Vec<Packet> {
+        let start = Instant::now();
+        let mut packets = vec![];
+        while packets.len() < count && start.elapsed() < Duration::from_secs(5) {
+            packets.extend(connection.get_packets());
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        packets
+    }
+
+    /// Log in and receive `count` packets past the handshake, the first three being the login, spawn and time
+    fn login(connection: &TcpConnection, count: usize) -> Vec<Packet> {
+        connection.send_packet(Packet::Handshake { handshake_data: String::from("Dev") });
+        assert!(matches!(receive(connection, 1).as_slice(), [Packet::Handshake { handshake_data }] if handshake_data == "-"));
+        connection.send_packet(Packet::Login { protocol: PROTOCOL_VERSION, username: String::from("Dev"), seed: 0, dimension: 0 });
+        receive(connection, count)
+    }
+
+    #[test]
+    fn logs_in_and_streams_chunks() {
+        let server = MockServer::start(MockLogin { entity_id: 42, ..Default::default() }, vec![
+            MockServerAction::SendAll(flat_chunk(-1, 2, 4, 1)),
+            MockServerAction::Send(Packet::Chat { chat_data: String::from("Welcome") }),
+            MockServerAction::Kick(String::from("Goodbye")),
+        ]).unwrap();
+        let connection = TcpConnection::connect(server.address()).unwrap();
+
+        let packets = login(&connection, 7);
+        assert!(matches!(packets[0], Packet::Login { protocol: 42, .. }));
+        assert!(matches!(packets[1], Packet::SpawnPosition { x: 8, y: 64, z: 8 }));
+
+        let packets = &packets[3..];
+        assert!(matches!(packets[0], Packet::PreChunk { x: -1, z: 2, mode: true }));
+        match &packets[1] {
+            Packet::MapChunk { x, y, z, size_x, size_y, size_z, compressed_data } => {
+                let updates = decode_map_chunk(MapChunkRegion::from_packet(*x, *y, *z, *size_x, *size_y, *size_z), compressed_data).unwrap();
+                let stone = updates.iter().flat_map(|update| update.blocks.iter()).filter(|block| block.data == 1).count();
+                assert_eq!(stone, 16 * 16 * 4);
+            },
+            packet => panic!("Expected a MapChunk, got {:?}", packet),
+        }
+        assert!(matches!(&packets[2], Packet::Chat { chat_data } if chat_data == "Welcome"));
+        assert!(matches!(&packets[3], Packet::DisconnectKick { reason } if reason == "Goodbye"));
+
+        let received = server.join().unwrap();
+        assert_eq!(received.len(), 2);
+    }
+
+    #[test]
+    fn waits_for_expected_packets() {
+        let server = MockServer::start(MockLogin::default(), vec![
+            MockServerAction::Send(Packet::PlayerPositionAndLook { x: 8.5, y_c_stance_s: 65.62, stance_c_y_s: 64.0, z: 8.5, yaw: 0.0, pitch: 0.0, on_ground: true }),
+            MockServerAction::Expect { description: "position echo", matches: |packet| matches!(packet, Packet::PlayerPositionAndLook { .. }), timeout: Duration::from_secs(5) },
+        ]).unwrap();
+        let connection = TcpConnection::connect(server.address()).unwrap();
+        match &login(&connection, 4)[3..] {
+            [Packet::PlayerPositionAndLook { x, y_c_stance_s, stance_c_y_s, z, yaw, pitch, on_ground }] => {
+                connection.send_packet(Packet::PlayerPositionAndLook { x: *x, y_c_stance_s: *stance_c_y_s, stance_c_y_s: *y_c_stance_s, z: *z, yaw: *yaw, pitch: *pitch, on_ground: *on_ground });
+            },
+            packets => panic!("Expected a position, got {:?}", packets),
+        }
+
+        let received = server.join().unwrap();
+        assert!(matches!(received.last(), Some(Packet::PlayerPositionAndLook { y_c_stance_s, .. }) if *y_c_stance_s == 64.0));
+    }
+
+    #[test]
+    fn times_out_on_missing_packets() {
+        let server = MockServer::start(MockLogin::default(), vec![
+            MockServerAction::Expect { description: "chat", matches: |packet| matches!(packet, Packet::Chat { .. }), timeout: Duration::from_millis(100) },
+        ]).unwrap();
+        let connection = TcpConnection::connect(server.address()).unwrap();
+        login(&connection, 3);
+        assert!(matches!(server.join(), Err(MockServerError::Timeout(_))));
+    }
+}
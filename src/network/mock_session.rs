@@ -0,0 +1,82 @@
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+/** A local stand-in for the http session server, for testing authentication without reaching the real one
+ *  Answers every GET with the body its responder gives for the requested path, and keeps the paths it was asked for
+ */
+pub struct MockSessionServer {
+    address: SocketAddr,
+    requests: Arc<Mutex<Vec<String>>>,
+    stopping: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl MockSessionServer {
+    /// Start serving on localhost, `respond` is given the path and query of each request
+    pub fn start<F: Fn(&str) -> String + Send + 'static>(respond: F) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind the mock session server");
+        let address = listener.local_addr().unwrap();
+        let requests = Arc::new(Mutex::new(vec![]));
+        let stopping = Arc::new(AtomicBool::new(false));
+        let thread_requests = requests.clone();
+        let thread_stopping = stopping.clone();
+        let handle = thread::spawn(move || {
+            for stream in listener.incoming() {
+                if thread_stopping.load(Ordering::Acquire) {
+                    return;
+                }
+                let Ok(stream) = stream else { continue; };
+                if let Err(e) = Self::answer(stream, &respond, &thread_requests) {
+                    log::warn!("Mock session server failed to answer: {e}");
+                }
+            }
+        });
+        Self { address, requests, stopping, handle: Some(handle) }
+    }
+
+    fn answer<F: Fn(&str) -> String>(stream: TcpStream, respond: &F, requests: &Mutex<Vec<String>>) -> std::io::Result<()> {
+        let mut reader = BufReader::new(stream);
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line)?;
+        // Skip the headers, requests have no body
+        let mut header = String::new();
+        while reader.read_line(&mut header)? > 2 {
+            header.clear();
+        }
+
+        let path = request_line.split_whitespace().nth(1).unwrap_or("/").to_string();
+        let body = respond(&path);
+        requests.lock().unwrap().push(path);
+        let mut stream = reader.into_inner();
+        write!(stream, "HTTP/1.0 200 OK\r\nContent-Type: text/plain\r\nContent-Length: {}\r\n\r\n{}", body.len(), body)?;
+        stream.flush()
+    }
+
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// The full url of `path` on this server, to configure as an endpoint
+    pub fn url(&self, path: &str) -> String {
+        format!("http://{}{}", self.address, path)
+    }
+
+    /// The path and query of every request answered so far
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+impl Drop for MockSessionServer {
+    fn drop(&mut self) {
+        self.stopping.store(true, Ordering::Release);
+        // Wake the accept loop so it sees it should stop
+        let _ = TcpStream::connect(self.address);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
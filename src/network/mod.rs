@@ -0,0 +1,41 @@
+use orange_networking::network_interface::NetworkThread;
+
+use crate::packets::prot14::Packet;
+
+pub mod auth;
+pub mod connection;
+pub mod http;
+pub mod local;
+pub mod mock_server;
+pub mod mock_session;
+pub mod ping;
+pub mod recording;
+pub mod state;
+
+/** Anything packets can be exchanged through, a live server connection or a stand-in for one
+ *  Lets the world tick the same way whether the packets come from a server, a recording or a test
+ */
+pub trait PacketConnection {
+    fn send_packet(&self, packet: Packet);
+    /// All packets received since the last call
+    fn get_packets(&self) -> Vec<Packet>;
+    fn stop(&mut self);
+    /// Whether the other end is known to be gone, connections that can't tell always report false
+    fn is_closed(&self) -> bool {
+        false
+    }
+}
+
+impl PacketConnection for NetworkThread<Packet> {
+    fn send_packet(&self, packet: Packet) {
+        NetworkThread::send_packet(self, packet);
+    }
+
+    fn get_packets(&self) -> Vec<Packet> {
+        NetworkThread::get_packets(self).into_iter().collect()
+    }
+
+    fn stop(&mut self) {
+        NetworkThread::stop(self);
+    }
+}
@@ -0,0 +1,168 @@
+use std::fmt::Display;
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::{Duration, Instant};
+
+use orange_networking::packet::{PacketEnumHolder, PacketParseError};
+
+use crate::packets::prot14::Packet;
+
+/// The section sign used by the server list to separate the fields of the ping response
+pub const PING_SEPARATOR: char = '§';
+
+#[derive(Debug)]
+pub enum PingError {
+    InvalidAddress,
+    Io(std::io::Error),
+    /// The server closed the connection before a full response arrived
+    ConnectionClosed,
+    UnexpectedPacket(String),
+    MalformedResponse(String),
+}
+
+impl std::error::Error for PingError {}
+
+impl Display for PingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidAddress => write!(f, "Invalid server address"),
+            Self::Io(e) => write!(f, "Connection error: {e}"),
+            Self::ConnectionClosed => write!(f, "Server closed the connection"),
+            Self::UnexpectedPacket(packet) => write!(f, "Unexpected packet: {packet}"),
+            Self::MalformedResponse(response) => write!(f, "Malformed ping response: {response}"),
+        }
+    }
+}
+
+impl From<std::io::Error> for PingError {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+/// What a server reports about itself through the server list ping
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerStatus {
+    pub motd: String,
+    pub online_players: u32,
+    pub max_players: u32,
+    /// Time between sending the ping and receiving the full response
+    pub latency: Duration,
+}
+
+impl ServerStatus {
+    /** Parse the reason of the kick sent in response to a ping, in the form motd§online§max
+     *  The motd itself may contain section signs, so the counts are taken from the end
+     */
+    pub fn from_kick_reason(reason: &str, latency: Duration) -> Result<Self, PingError> {
+        let mut fields = reason.rsplitn(3, PING_SEPARATOR);
+        let max_players = fields.next();
+        let online_players = fields.next();
+        let motd = fields.next();
+        match (motd, online_players.map(str::parse::<u32>), max_players.map(str::parse::<u32>)) {
+            (Some(motd), Some(Ok(online_players)), Some(Ok(max_players))) => {
+                Ok(Self { motd: motd.to_string(), online_players, max_players, latency })
+            },
+            _ => Err(PingError::MalformedResponse(reason.to_string())),
+        }
+    }
+}
+
+/** Query a server for its motd and player counts without joining it
+ *  Sends the 0xFE server list ping and waits up to `timeout` for each step of the exchange
+ */
+pub fn ping_server<A: ToSocketAddrs>(address: A, timeout: Duration) -> Result<ServerStatus, PingError> {
+    let address = address.to_socket_addrs()?.next().ok_or(PingError::InvalidAddress)?;
+    let mut stream = TcpStream::connect_timeout(&address, timeout)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+
+    let ping_time = Instant::now();
+    stream.write_all(&Packet::packet_to_bytes(Packet::ServerListPing))?;
+
+    let mut buffer = vec![];
+    let mut read_buffer = [0u8; 256];
+    loop {
+        let read = stream.read(&mut read_buffer)?;
+        if read == 0 {
+            return Err(PingError::ConnectionClosed);
+        }
+        buffer.extend_from_slice(&read_buffer[..read]);
+
+        match Packet::bytes_to_packet(&buffer) {
+            Ok((Packet::DisconnectKick { reason }, _)) => {
+                return ServerStatus::from_kick_reason(&reason, ping_time.elapsed());
+            },
+            Ok((packet, _)) => { return Err(PingError::UnexpectedPacket(format!("{:?}", packet))); },
+            Err(PacketParseError::NotEnoughData) => { continue; },
+            Err(_) => { return Err(PingError::MalformedResponse(format!("{:02X?}", buffer))); },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+    use std::time::Duration;
+
+    use orange_networking::packet::PacketEnumHolder;
+
+    use super::{ping_server, PingError, ServerStatus};
+    use crate::packets::prot14::Packet;
+
+    /// Accept a single connection, check for the ping byte and answer with `reason`
+    fn spawn_mock_server(reason: &'static str) -> (std::net::SocketAddr, thread::JoinHandle<()>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut ping = [0u8; 1];
+            stream.read_exact(&mut ping).unwrap();
+            assert_eq!(ping[0], 0xFE);
+            stream.write_all(&Packet::packet_to_bytes(Packet::DisconnectKick { reason: reason.to_string() })).unwrap();
+        });
+        (address, handle)
+    }
+
+    #[test]
+    fn ping_reads_status() {
+        let (address, handle) = spawn_mock_server("A Minecraft Server§3§20");
+        let status = ping_server(address, Duration::from_secs(5)).unwrap();
+        handle.join().unwrap();
+        assert_eq!(status.motd, "A Minecraft Server");
+        assert_eq!(status.online_players, 3);
+        assert_eq!(status.max_players, 20);
+    }
+
+    #[test]
+    fn ping_rejects_malformed_status() {
+        let (address, handle) = spawn_mock_server("You are banned");
+        let status = ping_server(address, Duration::from_secs(5));
+        handle.join().unwrap();
+        assert!(matches!(status, Err(PingError::MalformedResponse(_))));
+    }
+
+    #[test]
+    fn ping_times_out() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let handle = thread::spawn(move || {
+            // Hold the connection open without ever answering
+            let (_stream, _) = listener.accept().unwrap();
+            thread::sleep(Duration::from_millis(500));
+        });
+        let status = ping_server(address, Duration::from_millis(100));
+        handle.join().unwrap();
+        assert!(matches!(status, Err(PingError::Io(_))));
+    }
+
+    #[test]
+    fn motd_keeps_section_signs() {
+        let status = ServerStatus::from_kick_reason("§cRed §lServer§0§8", Duration::ZERO).unwrap();
+        assert_eq!(status.motd, "§cRed §lServer");
+        assert_eq!(status.online_players, 0);
+        assert_eq!(status.max_players, 8);
+    }
+}
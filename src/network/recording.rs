@@ -0,0 +1,418 @@
+use std::collections::VecDeque;
+use std::fmt::Display;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use orange_networking::packet::PacketEnumHolder;
+
+use super::PacketConnection;
+use crate::packets::prot14::Packet;
+
+/// Identifies a packet recording, followed by the format version
+const RECORDING_MAGIC: &[u8; 4] = b"OPKT";
+const RECORDING_VERSION: u8 = 1;
+
+/// Extension used for recordings written by the client
+pub const RECORDING_EXTENSION: &str = "opkt";
+
+/// The time between two game ticks, used when driving a replay
+pub const TICK_DURATION: Duration = Duration::from_millis(50);
+
+#[derive(Debug)]
+pub enum RecordingError {
+    Io(std::io::Error),
+    /// The file does not start with the recording magic
+    InvalidHeader,
+    UnsupportedVersion(u8),
+    InvalidDirection(u8),
+    /// A packet could not be parsed, carries the time it was recorded at
+    MalformedPacket(Duration),
+}
+
+impl std::error::Error for RecordingError {}
+
+impl Display for RecordingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "Recording io error: {e}"),
+            Self::InvalidHeader => write!(f, "Not a packet recording"),
+            Self::UnsupportedVersion(version) => write!(f, "Unsupported recording version {version}"),
+            Self::InvalidDirection(direction) => write!(f, "Invalid packet direction {direction}"),
+            Self::MalformedPacket(time) => write!(f, "Malformed packet recorded at {}ms", time.as_millis()),
+        }
+    }
+}
+
+impl From<std::io::Error> for RecordingError {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PacketDirection {
+    /// Sent by the server to the client
+    Inbound,
+    /// Sent by the client to the server
+    Outbound,
+}
+
+impl PacketDirection {
+    fn to_byte(self) -> u8 {
+        match self {
+            Self::Inbound => 0,
+            Self::Outbound => 1,
+        }
+    }
+
+    fn from_byte(byte: u8) -> Result<Self, RecordingError> {
+        match byte {
+            0 => Ok(Self::Inbound),
+            1 => Ok(Self::Outbound),
+            _ => Err(RecordingError::InvalidDirection(byte)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RecordedPacket {
+    /// Time since the start of the recording
+    pub time: Duration,
+    pub direction: PacketDirection,
+    pub packet: Packet,
+}
+
+/** Writes packets to a recording
+ *  Each entry is the time in microseconds, the direction, and the length prefixed packet bytes
+ */
+pub struct PacketWriter<W: Write> {
+    writer: W,
+    start: Instant,
+}
+
+impl<W: Write> PacketWriter<W> {
+    pub fn new(mut writer: W) -> Result<Self, RecordingError> {
+        writer.write_all(RECORDING_MAGIC)?;
+        writer.write_u8(RECORDING_VERSION)?;
+        Ok(Self { writer, start: Instant::now() })
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    pub fn write_packet(&mut self, time: Duration, direction: PacketDirection, packet: Packet) -> Result<(), RecordingError> {
+        let bytes = Packet::packet_to_bytes(packet);
+        self.writer.write_u64::<BigEndian>(time.as_micros() as u64)?;
+        self.writer.write_u8(direction.to_byte())?;
+        self.writer.write_u32::<BigEndian>(bytes.len() as u32)?;
+        self.writer.write_all(&bytes)?;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<(), RecordingError> {
+        Ok(self.writer.flush()?)
+    }
+}
+
+/// Read every packet of a recording
+pub fn read_recording<R: Read>(mut reader: R) -> Result<Vec<RecordedPacket>, RecordingError> {
+    let mut magic = [0u8; 4];
+    reader.read_exact(&mut magic).map_err(|_| RecordingError::InvalidHeader)?;
+    if &magic != RECORDING_MAGIC {
+        return Err(RecordingError::InvalidHeader);
+    }
+    let version = reader.read_u8()?;
+    if version != RECORDING_VERSION {
+        return Err(RecordingError::UnsupportedVersion(version));
+    }
+
+    let mut packets = vec![];
+    loop {
+        // A recording ends after the last whole entry, a partial time is a truncated header
+        let mut time = Vec::with_capacity(8);
+        match reader.by_ref().take(8).read_to_end(&mut time)? {
+            0 => { break; },
+            8 => {},
+            _ => { return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into()); },
+        }
+        let time = Duration::from_micros(u64::from_be_bytes(time.try_into().unwrap()));
+        let direction = PacketDirection::from_byte(reader.read_u8()?)?;
+        let length = reader.read_u32::<BigEndian>()? as usize;
+        let mut bytes = vec![0u8; length];
+        reader.read_exact(&mut bytes)?;
+        let packet = match Packet::bytes_to_packet(&bytes) {
+            Ok((packet, read)) if read == length => packet,
+            _ => { return Err(RecordingError::MalformedPacket(time)); },
+        };
+        packets.push(RecordedPacket { time, direction, packet });
+    }
+    Ok(packets)
+}
+
+pub fn read_recording_file<P: AsRef<Path>>(path: P) -> Result<Vec<RecordedPacket>, RecordingError> {
+    read_recording(BufReader::new(File::open(path)?))
+}
+
+/** Wraps a connection and records every packet passing through it
+ *  Packets received by a single call to get_packets share a timestamp, so a replay can hand them out in the same batches
+ */
+pub struct PacketRecorder<C: PacketConnection, W: Write> {
+    connection: C,
+    writer: Mutex<PacketWriter<W>>,
+}
+
+impl<C: PacketConnection> PacketRecorder<C, BufWriter<File>> {
+    /// Record into a new file at `path`
+    pub fn create<P: AsRef<Path>>(connection: C, path: P) -> Result<Self, RecordingError> {
+        Self::new(connection, BufWriter::new(File::create(path)?))
+    }
+}
+
+impl<C: PacketConnection, W: Write> PacketRecorder<C, W> {
+    pub fn new(connection: C, writer: W) -> Result<Self, RecordingError> {
+        Ok(Self { connection, writer: Mutex::new(PacketWriter::new(writer)?) })
+    }
+
+    fn record(writer: &mut PacketWriter<W>, time: Duration, direction: PacketDirection, packet: Packet) {
+        if let Err(e) = writer.write_packet(time, direction, packet) {
+            log::error!("Failed to record packet: {e}");
+        }
+    }
+
+    /// Stop recording, returning the wrapped connection and writer
+    pub fn into_inner(self) -> (C, W) {
+        let writer = self.writer.into_inner().unwrap();
+        (self.connection, writer.writer)
+    }
+}
+
+impl<C: PacketConnection, W: Write> PacketConnection for PacketRecorder<C, W> {
+    fn send_packet(&self, packet: Packet) {
+        if let Ok(mut writer) = self.writer.lock() {
+            let time = writer.elapsed();
+            Self::record(&mut writer, time, PacketDirection::Outbound, packet.clone());
+        }
+        self.connection.send_packet(packet);
+    }
+
+    fn get_packets(&self) -> Vec<Packet> {
+        let packets = self.connection.get_packets();
+        if let Ok(mut writer) = self.writer.lock() {
+            let time = writer.elapsed();
+            for packet in &packets {
+                Self::record(&mut writer, time, PacketDirection::Inbound, packet.clone());
+            }
+        }
+        packets
+    }
+
+    fn stop(&mut self) {
+        if let Ok(mut writer) = self.writer.lock() {
+            if let Err(e) = writer.flush() {
+                log::error!("Failed to flush packet recording: {e}");
+            }
+        }
+        self.connection.stop();
+    }
+
+    fn is_closed(&self) -> bool {
+        self.connection.is_closed()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReplaySpeed {
+    /// Packets arrive with the same timing as when they were recorded
+    RealTime,
+    /// Time passes faster by the given factor
+    Accelerated(f32),
+    /// Each call to get_packets hands out the next recorded batch, regardless of time
+    Stepped,
+}
+
+/** A connection that plays back the inbound packets of a recording
+ *  Packets sent to it are kept so they can be compared against the recording
+ */
+pub struct ReplayConnection {
+    inbound: Mutex<VecDeque<RecordedPacket>>,
+    sent: Mutex<Vec<Packet>>,
+    speed: ReplaySpeed,
+    /// Set when the first packets are requested, so loading the replay does not eat into its timing
+    start: Mutex<Option<Instant>>,
+}
+
+impl ReplayConnection {
+    pub fn new(recording: Vec<RecordedPacket>, speed: ReplaySpeed) -> Self {
+        let inbound = recording.into_iter().filter(|packet| packet.direction == PacketDirection::Inbound).collect();
+        Self { inbound: Mutex::new(inbound), sent: Mutex::new(vec![]), speed, start: Mutex::new(None) }
+    }
+
+    pub fn open<P: AsRef<Path>>(path: P, speed: ReplaySpeed) -> Result<Self, RecordingError> {
+        Ok(Self::new(read_recording_file(path)?, speed))
+    }
+
+    /// Whether every recorded packet has been handed out
+    pub fn is_finished(&self) -> bool {
+        self.inbound.lock().unwrap().is_empty()
+    }
+
+    /// Take the packets sent to the replay since the last call
+    pub fn take_sent_packets(&self) -> Vec<Packet> {
+        std::mem::take(&mut *self.sent.lock().unwrap())
+    }
+
+    /// How far into the recording the replay is
+    fn replay_time(&self) -> Duration {
+        let elapsed = self.start.lock().unwrap().get_or_insert_with(Instant::now).elapsed();
+        match self.speed {
+            ReplaySpeed::RealTime | ReplaySpeed::Stepped => elapsed,
+            ReplaySpeed::Accelerated(factor) => elapsed.mul_f32(factor.max(0.0)),
+        }
+    }
+
+    /** Drive a replay to its end without a window or server, calling `tick` every game tick
+     *  Waits between ticks unless stepping, in which case every tick receives the next batch
+     */
+    pub fn run<F: FnMut(&Self)>(&self, mut tick: F) {
+        let tick_duration = match self.speed {
+            ReplaySpeed::RealTime => Some(TICK_DURATION),
+            ReplaySpeed::Accelerated(factor) => Some(TICK_DURATION.div_f32(factor.max(f32::EPSILON))),
+            ReplaySpeed::Stepped => None,
+        };
+        while !self.is_finished() {
+            let tick_start = Instant::now();
+            tick(self);
+            if let Some(remaining) = tick_duration.and_then(|duration| duration.checked_sub(tick_start.elapsed())) {
+                std::thread::sleep(remaining);
+            }
+        }
+    }
+}
+
+impl PacketConnection for ReplayConnection {
+    fn send_packet(&self, packet: Packet) {
+        self.sent.lock().unwrap().push(packet);
+    }
+
+    fn get_packets(&self) -> Vec<Packet> {
+        let mut inbound = self.inbound.lock().unwrap();
+        let until = match self.speed {
+            ReplaySpeed::Stepped => match inbound.front() {
+                Some(packet) => packet.time,
+                None => { return vec![]; },
+            },
+            _ => self.replay_time(),
+        };
+
+        let mut packets = vec![];
+        while inbound.front().map_or(false, |packet| packet.time <= until) {
+            packets.push(inbound.pop_front().unwrap().packet);
+        }
+        packets
+    }
+
+    fn stop(&mut self) {
+        self.inbound.lock().unwrap().clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    use super::{read_recording, PacketDirection, PacketRecorder, RecordingError, ReplayConnection, ReplaySpeed};
+    use crate::network::PacketConnection;
+    use crate::packets::prot14::Packet;
+
+    /// Hands out one batch of scripted packets per call and keeps what was sent
+    struct ScriptedConnection {
+        batches: Mutex<Vec<Vec<Packet>>>,
+        sent: Mutex<Vec<Packet>>,
+    }
+
+    impl PacketConnection for ScriptedConnection {
+        fn send_packet(&self, packet: Packet) {
+            self.sent.lock().unwrap().push(packet);
+        }
+
+        fn get_packets(&self) -> Vec<Packet> {
+            let mut batches = self.batches.lock().unwrap();
+            if batches.is_empty() { vec![] } else { batches.remove(0) }
+        }
+
+        fn stop(&mut self) {}
+    }
+
+    fn record_session() -> Vec<u8> {
+        let connection = ScriptedConnection {
+            batches: Mutex::new(vec![
+                vec![Packet::TimeUpdate { time: 6000 }, Packet::Chat { chat_data: "Hello".to_string() }],
+                vec![Packet::KeepAlive],
+            ]),
+            sent: Mutex::new(vec![]),
+        };
+        let mut recorder = PacketRecorder::new(connection, vec![]).unwrap();
+        recorder.send_packet(Packet::Handshake { handshake_data: "Dev".to_string() });
+        assert_eq!(recorder.get_packets().len(), 2);
+        std::thread::sleep(Duration::from_millis(5));
+        assert_eq!(recorder.get_packets().len(), 1);
+        recorder.stop();
+
+        let (connection, bytes) = recorder.into_inner();
+        assert_eq!(connection.sent.lock().unwrap().len(), 1);
+        bytes
+    }
+
+    #[test]
+    fn recording_round_trips() {
+        let recording = read_recording(record_session().as_slice()).unwrap();
+        let directions: Vec<PacketDirection> = recording.iter().map(|packet| packet.direction).collect();
+        assert_eq!(directions, [PacketDirection::Outbound, PacketDirection::Inbound, PacketDirection::Inbound, PacketDirection::Inbound]);
+        assert!(matches!(recording[0].packet, Packet::Handshake { ref handshake_data } if handshake_data == "Dev"));
+        assert!(matches!(recording[2].packet, Packet::Chat { ref chat_data } if chat_data == "Hello"));
+        assert_eq!(recording[1].time, recording[2].time);
+        assert!(recording[3].time > recording[2].time);
+    }
+
+    #[test]
+    fn stepped_replay_keeps_batches() {
+        let recording = read_recording(record_session().as_slice()).unwrap();
+        let replay = ReplayConnection::new(recording, ReplaySpeed::Stepped);
+        assert_eq!(replay.get_packets().len(), 2);
+        replay.send_packet(Packet::KeepAlive);
+        assert_eq!(replay.get_packets().len(), 1);
+        assert!(replay.is_finished());
+        assert!(replay.get_packets().is_empty());
+        assert_eq!(replay.take_sent_packets().len(), 1);
+    }
+
+    #[test]
+    fn accelerated_replay_runs_to_end() {
+        let recording = read_recording(record_session().as_slice()).unwrap();
+        let replay = ReplayConnection::new(recording, ReplaySpeed::Accelerated(100.0));
+        let mut received = 0;
+        replay.run(|connection| received += connection.get_packets().len());
+        assert_eq!(received, 3);
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        assert!(matches!(read_recording(&b"PNG\0\x01"[..]), Err(RecordingError::InvalidHeader)));
+        let mut truncated = record_session();
+        truncated.truncate(truncated.len() - 1);
+        assert!(matches!(read_recording(truncated.as_slice()), Err(RecordingError::Io(_))));
+    }
+
+    #[test]
+    fn rejects_truncated_headers() {
+        let mut recording = record_session();
+        recording.extend_from_slice(&[0, 0, 0]);
+        assert!(matches!(read_recording(recording.as_slice()), Err(RecordingError::Io(_))));
+    }
+}
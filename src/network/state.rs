@@ -0,0 +1,243 @@
+use std::fmt::Display;
+use std::time::{Duration, Instant};
+
+use crate::packets::prot14::Packet;
+
+/// Why a connection ended, in a form that can be shown to the player
+#[derive(Debug, Clone, PartialEq)]
+pub enum DisconnectReason {
+    /// The server kicked the player with a message
+    Kicked(String),
+    /// The connection could not be opened
+    ConnectionFailed(String),
+    /// Nothing was heard from the server for too long while in the given state
+    TimedOut(&'static str),
+    UnexpectedPacket(String),
+    /// The session server would not let the player join an online mode server
+    AuthenticationFailed(String),
+    /// The connection was closed by the server or lost
+    ConnectionLost,
+    /// The player left on their own
+    Quit,
+}
+
+impl std::error::Error for DisconnectReason {}
+
+impl Display for DisconnectReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Kicked(reason) => write!(f, "Kicked: {reason}"),
+            Self::ConnectionFailed(e) => write!(f, "Failed to connect: {e}"),
+            Self::TimedOut(state) => write!(f, "Timed out while {state}"),
+            Self::UnexpectedPacket(packet) => write!(f, "Unexpected packet: {packet}"),
+            Self::AuthenticationFailed(e) => write!(f, "Authentication failed: {e}"),
+            Self::ConnectionLost => write!(f, "Connection lost"),
+            Self::Quit => write!(f, "Disconnected"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConnectionState {
+    Connecting,
+    Handshaking,
+    LoggingIn,
+    Playing,
+    Disconnected(DisconnectReason),
+}
+
+impl ConnectionState {
+    /// What the connection is doing, as used in timeout messages
+    fn describe(&self) -> &'static str {
+        match self {
+            Self::Connecting => "connecting",
+            Self::Handshaking => "handshaking",
+            Self::LoggingIn => "logging in",
+            Self::Playing => "playing",
+            Self::Disconnected(_) => "disconnected",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ConnectionTimeouts {
+    /// How long connecting, handshaking and logging in may take together
+    pub handshake: Duration,
+    /// How long the server may stay silent while playing, b1.7.3 servers send a keep alive every second
+    pub read: Duration,
+}
+
+impl Default for ConnectionTimeouts {
+    fn default() -> Self {
+        Self { handshake: Duration::from_secs(10), read: Duration::from_secs(30) }
+    }
+}
+
+/** Tracks the state of a connection to a server through the login sequence and play
+ *  Fed every packet from the server, and checked regularly to catch a server that went quiet
+ */
+#[derive(Debug, Clone)]
+pub struct ConnectionStateMachine {
+    state: ConnectionState,
+    timeouts: ConnectionTimeouts,
+    login_start: Instant,
+    last_packet: Instant,
+}
+
+impl ConnectionStateMachine {
+    pub fn new(timeouts: ConnectionTimeouts) -> Self {
+        let now = Instant::now();
+        Self { state: ConnectionState::Connecting, timeouts, login_start: now, last_packet: now }
+    }
+
+    pub fn state(&self) -> &ConnectionState {
+        &self.state
+    }
+
+    pub fn timeouts(&self) -> ConnectionTimeouts {
+        self.timeouts
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.state == ConnectionState::Playing
+    }
+
+    pub fn is_disconnected(&self) -> bool {
+        matches!(self.state, ConnectionState::Disconnected(_))
+    }
+
+    pub fn disconnect_reason(&self) -> Option<&DisconnectReason> {
+        match &self.state {
+            ConnectionState::Disconnected(reason) => Some(reason),
+            _ => None,
+        }
+    }
+
+    /// The socket is open, the client is about to send its handshake
+    pub fn connected(&mut self) {
+        if self.state == ConnectionState::Connecting {
+            self.state = ConnectionState::Handshaking;
+            self.last_packet = Instant::now();
+        }
+    }
+
+    /// End the connection, keeping the first reason given
+    pub fn disconnect(&mut self, reason: DisconnectReason) {
+        if !self.is_disconnected() {
+            self.state = ConnectionState::Disconnected(reason);
+        }
+    }
+
+    /** Advance the state with a packet from the server
+     *  Returns whether the packet should be handled further, which it should not once disconnected
+     */
+    pub fn handle_packet(&mut self, packet: &Packet) -> bool {
+        if self.is_disconnected() {
+            return false;
+        }
+        self.last_packet = Instant::now();
+
+        match (&self.state, packet) {
+            (_, Packet::DisconnectKick { reason }) => {
+                self.disconnect(DisconnectReason::Kicked(reason.clone()));
+                return false;
+            },
+            (ConnectionState::Handshaking, Packet::Handshake { .. }) => { self.state = ConnectionState::LoggingIn; },
+            (ConnectionState::LoggingIn, Packet::Login { .. }) => { self.state = ConnectionState::Playing; },
+            (ConnectionState::Playing, _) => {},
+            (_, packet) => {
+                self.disconnect(DisconnectReason::UnexpectedPacket(format!("{:?}", packet)));
+                return false;
+            },
+        }
+        true
+    }
+
+    /// Disconnect if the login is taking too long or the server stopped sending packets, returns whether it did
+    pub fn check_timeouts(&mut self) -> bool {
+        let timed_out = match self.state {
+            ConnectionState::Connecting | ConnectionState::Handshaking | ConnectionState::LoggingIn => self.login_start.elapsed() > self.timeouts.handshake,
+            ConnectionState::Playing => self.last_packet.elapsed() > self.timeouts.read,
+            ConnectionState::Disconnected(_) => false,
+        };
+        if timed_out {
+            let state = self.state.describe();
+            self.disconnect(DisconnectReason::TimedOut(state));
+        }
+        timed_out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{ConnectionState, ConnectionStateMachine, ConnectionTimeouts, DisconnectReason};
+    use crate::packets::prot14::Packet;
+
+    fn short_timeouts() -> ConnectionTimeouts {
+        ConnectionTimeouts { handshake: Duration::from_millis(20), read: Duration::from_millis(60) }
+    }
+
+    fn logged_in(timeouts: ConnectionTimeouts) -> ConnectionStateMachine {
+        let mut state = ConnectionStateMachine::new(timeouts);
+        state.connected();
+        assert!(state.handle_packet(&Packet::Handshake { handshake_data: String::from("-") }));
+        assert!(state.handle_packet(&Packet::Login { protocol: 1, username: String::new(), seed: 0, dimension: 0 }));
+        state
+    }
+
+    #[test]
+    fn follows_login_sequence() {
+        let mut state = ConnectionStateMachine::new(ConnectionTimeouts::default());
+        assert_eq!(state.state(), &ConnectionState::Connecting);
+        state.connected();
+        assert_eq!(state.state(), &ConnectionState::Handshaking);
+        state.handle_packet(&Packet::Handshake { handshake_data: String::from("-") });
+        assert_eq!(state.state(), &ConnectionState::LoggingIn);
+        state.handle_packet(&Packet::Login { protocol: 1, username: String::new(), seed: 0, dimension: 0 });
+        assert!(state.is_playing());
+        assert!(state.handle_packet(&Packet::TimeUpdate { time: 0 }));
+    }
+
+    #[test]
+    fn kick_ends_connection() {
+        let mut state = logged_in(ConnectionTimeouts::default());
+        assert!(!state.handle_packet(&Packet::DisconnectKick { reason: String::from("Server closed") }));
+        assert_eq!(state.disconnect_reason(), Some(&DisconnectReason::Kicked(String::from("Server closed"))));
+        // Nothing after the kick is handled, and the reason stays the first one
+        assert!(!state.handle_packet(&Packet::KeepAlive));
+        state.disconnect(DisconnectReason::Quit);
+        assert_eq!(state.disconnect_reason(), Some(&DisconnectReason::Kicked(String::from("Server closed"))));
+    }
+
+    #[test]
+    fn rejects_packets_before_login() {
+        let mut state = ConnectionStateMachine::new(ConnectionTimeouts::default());
+        state.connected();
+        assert!(!state.handle_packet(&Packet::TimeUpdate { time: 0 }));
+        assert!(matches!(state.disconnect_reason(), Some(DisconnectReason::UnexpectedPacket(_))));
+    }
+
+    #[test]
+    fn times_out_during_handshake() {
+        let mut state = ConnectionStateMachine::new(short_timeouts());
+        state.connected();
+        assert!(!state.check_timeouts());
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(state.check_timeouts());
+        assert_eq!(state.disconnect_reason(), Some(&DisconnectReason::TimedOut("handshaking")));
+    }
+
+    #[test]
+    fn keep_alive_watchdog() {
+        let mut state = logged_in(short_timeouts());
+        std::thread::sleep(Duration::from_millis(40));
+        state.handle_packet(&Packet::KeepAlive);
+        std::thread::sleep(Duration::from_millis(40));
+        assert!(!state.check_timeouts());
+        std::thread::sleep(Duration::from_millis(40));
+        assert!(state.check_timeouts());
+        assert_eq!(state.disconnect_reason(), Some(&DisconnectReason::TimedOut("playing")));
+    }
+}
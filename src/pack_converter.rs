@@ -0,0 +1,231 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Cursor, Write};
+use std::path::Path;
+
+use image::{DynamicImage, GenericImageView, ImageOutputFormat};
+
+use crate::minecraft::filetypes::{AtlasTextureFileType, MCAtlasTextureFile};
+use crate::minecraft::identifier::Identifier;
+use crate::resource_manager::{PackMetadata, ResourceError, ResourceKind, ResourceManager, ResourcePack, PACK_METADATA_FILE};
+
+/// The pack_format written to the pack.mcmeta of converted packs
+pub const ORANGE_PACK_FORMAT: u32 = 1;
+
+/// The sheets of a classic texture pack that are cut into textures, and the atlas describing their cells
+pub const CLASSIC_SHEETS: [(&str, &str); 2] = [
+    ("terrain.png", "block/terrain"),
+    ("items.png", "item/items"),
+];
+
+/// What a conversion did, and what it could not do
+#[derive(Debug, Default)]
+pub struct ConversionReport {
+    /// Every texture cut from a sheet
+    pub textures: Vec<Identifier>,
+    /// Sheets the classic pack does not have, or that there is no atlas for
+    pub skipped_sheets: Vec<String>,
+}
+
+/** A pack converted in memory, files by their path from the root of the pack
+ *  Written out as a directory or a zip, either of which the resource manager can load
+ */
+#[derive(Debug, Default)]
+pub struct ConvertedPack {
+    pub files: BTreeMap<String, Vec<u8>>,
+}
+
+impl ConvertedPack {
+    pub fn write_to_directory(&self, directory: &Path) -> io::Result<()> {
+        for (path, contents) in &self.files {
+            let path = directory.join(path);
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(path, contents)?;
+        }
+        Ok(())
+    }
+
+    pub fn write_to_zip(&self, path: &Path) -> Result<(), ResourceError> {
+        let mut zip = zip::ZipWriter::new(fs::File::create(path)?);
+        let options = zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+        for (name, contents) in &self.files {
+            zip.start_file(name.as_str(), options)?;
+            zip.write_all(contents)?;
+        }
+        zip.finish()?;
+        Ok(())
+    }
+}
+
+/** Turns texture packs made for the classic client, a terrain.png and other loose images,
+ *  into packs with the assets/namespace/textures layout models are loaded from
+ *  Sheets are cut by the cells of the atlases in `atlases`, usually the default assets
+ */
+pub struct ClassicPackConverter<'a> {
+    atlases: &'a ResourceManager,
+}
+
+impl<'a> ClassicPackConverter<'a> {
+    pub fn new(atlases: &'a ResourceManager) -> Self {
+        Self { atlases }
+    }
+
+    /** Convert the classic pack, which may be a zip or a directory
+     *  Besides a png for every cell, each sheet is kept whole under its atlas name so the
+     *  atlas in use keeps working with the pack
+     */
+    pub fn convert(&self, classic: &ResourcePack) -> Result<(ConvertedPack, ConversionReport), ResourceError> {
+        let mut pack = ConvertedPack::default();
+        let mut report = ConversionReport::default();
+        let root = Self::classic_root(classic);
+
+        for (sheet, atlas_name) in CLASSIC_SHEETS {
+            let sheet_path = format!("{root}{sheet}");
+            if !classic.contains(&sheet_path) {
+                report.skipped_sheets.push(sheet.to_string());
+                continue;
+            }
+            let atlas_id = Identifier::from(atlas_name);
+            let atlas: MCAtlasTextureFile = match self.atlases.read_json(&atlas_id, ResourceKind::Atlas) {
+                Ok(atlas) => atlas,
+                Err(e) => {
+                    log::warn!("Not converting {sheet}, there is no atlas for it: {e}");
+                    report.skipped_sheets.push(sheet.to_string());
+                    continue;
+                },
+            };
+            let image = image::load_from_memory(&classic.read(&sheet_path)?)?;
+            for (identifier, texture) in slice_sheet(&image, &atlas.atlas) {
+                pack.files.insert(ResourceKind::Texture.path(&identifier), encode_png(&texture)?);
+                report.textures.push(identifier);
+            }
+            pack.files.insert(ResourceKind::Texture.path(&atlas_id), encode_png(&image)?);
+        }
+
+        let metadata = PackMetadata { pack_format: ORANGE_PACK_FORMAT, description: Self::description(classic, &root) };
+        pack.files.insert(PACK_METADATA_FILE.to_string(), metadata.to_json().into_bytes());
+        if let Ok(icon) = classic.read(&format!("{root}pack.png")) {
+            pack.files.insert(String::from("pack.png"), icon);
+        }
+        Ok((pack, report))
+    }
+
+    /// Packs are often zipped with their files in a folder, the shallowest terrain.png marks where they start
+    fn classic_root(classic: &ResourcePack) -> String {
+        classic.files_with_prefix("")
+            .filter(|path| *path == "terrain.png" || path.ends_with("/terrain.png"))
+            .min_by_key(|path| path.matches('/').count())
+            .map(|path| path[..path.len() - "terrain.png".len()].to_string())
+            .unwrap_or_default()
+    }
+
+    /// The pack.txt of a classic pack holds its description, one line for each line shown in the menu
+    fn description(classic: &ResourcePack, root: &str) -> String {
+        classic.read(&format!("{root}pack.txt"))
+            .map(|bytes| String::from_utf8_lossy(&bytes).lines().map(str::trim_end).collect::<Vec<_>>().join("\n"))
+            .unwrap_or_else(|_| classic.name())
+    }
+}
+
+/** Cut a sheet into its textures by the cells of an atlas
+ *  The size of a cell follows from the size of the sheet, so packs with larger textures are cut the same way
+ */
+pub fn slice_sheet(sheet: &DynamicImage, atlas: &AtlasTextureFileType) -> Vec<(Identifier, DynamicImage)> {
+    let (width, height) = sheet.dimensions();
+    let mut textures = vec![];
+    match atlas {
+        AtlasTextureFileType::Uniform { across, down, textures: cells } => {
+            let (across, down) = (*across.max(&1), down.unwrap_or(*across).max(1));
+            let (cell_width, cell_height) = (width / across, height / down);
+            for cell in cells {
+                let (column, row) = (cell.cell % across, cell.cell / across);
+                if row >= down || cell_width == 0 || cell_height == 0 {
+                    log::warn!("Cell {} of {} is outside of the sheet", cell.cell, cell.identifier);
+                    continue;
+                }
+                textures.push((Identifier::from_str(&cell.identifier), sheet.crop_imm(column * cell_width, row * cell_height, cell_width, cell_height)));
+            }
+        },
+        AtlasTextureFileType::NonUniform { textures: rects } => {
+            for rect in rects {
+                let [u_min, v_min, u_max, v_max] = rect.uv;
+                let (x, y) = ((u_min * width as f32).round() as u32, (v_min * height as f32).round() as u32);
+                let (x_max, y_max) = ((u_max * width as f32).round() as u32, (v_max * height as f32).round() as u32);
+                if x_max <= x || y_max <= y || x_max > width || y_max > height {
+                    log::warn!("The area of {} is outside of the sheet", rect.identifier);
+                    continue;
+                }
+                textures.push((Identifier::from_str(&rect.identifier), sheet.crop_imm(x, y, x_max - x, y_max - y)));
+            }
+        },
+    }
+    textures
+}
+
+fn encode_png(image: &DynamicImage) -> Result<Vec<u8>, ResourceError> {
+    let mut bytes = Cursor::new(vec![]);
+    image.write_to(&mut bytes, ImageOutputFormat::Png)?;
+    Ok(bytes.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use image::{DynamicImage, GenericImageView, Rgba, RgbaImage};
+
+    use super::{ClassicPackConverter, ORANGE_PACK_FORMAT};
+    use crate::minecraft::identifier::Identifier;
+    use crate::resource_manager::{PackMetadata, ResourceKind, ResourceManager, ResourcePack, ResourceType};
+    use crate::util::test_directory::TestDirectory;
+
+    /// A sheet of 16 by 16 cells of 2 pixels, every cell a colour of its own
+    fn classic_terrain() -> Vec<u8> {
+        let sheet = RgbaImage::from_fn(32, 32, |x, y| Rgba([(x / 2) as u8, (y / 2) as u8, 7, 255]));
+        let mut bytes = std::io::Cursor::new(vec![]);
+        DynamicImage::ImageRgba8(sheet).write_to(&mut bytes, image::ImageOutputFormat::Png).unwrap();
+        bytes.into_inner()
+    }
+
+    #[test]
+    fn cuts_terrain_into_textures() {
+        let directory = TestDirectory::new("convert");
+        let atlas_path = directory.join("assets").join(ResourceKind::Atlas.path(&Identifier::from("block/terrain")));
+        std::fs::create_dir_all(atlas_path.parent().unwrap()).unwrap();
+        std::fs::write(&atlas_path, r#"{"atlas": {"Uniform": {"across": 16, "textures": [
+            {"cell": 0, "identifier": "minecraft:block/grass_top"},
+            {"cell": 17, "identifier": "minecraft:block/cobblestone"}
+        ]}}}"#).unwrap();
+        let atlases = ResourceManager::from_sources(vec![ResourceType::Dir(directory.join("assets"))]).unwrap();
+
+        // Zipped inside a folder, as classic packs often are
+        let classic_path = directory.join("Classic.zip");
+        let mut zip = zip::ZipWriter::new(std::fs::File::create(&classic_path).unwrap());
+        for (name, contents) in [("Classic/terrain.png", classic_terrain()), ("Classic/pack.txt", b"Classic pack\nby someone\n".to_vec())] {
+            zip.start_file(name, zip::write::FileOptions::default()).unwrap();
+            zip.write_all(&contents).unwrap();
+        }
+        zip.finish().unwrap();
+
+        let classic = ResourcePack::open(ResourceType::Zip(classic_path)).unwrap();
+        let (pack, report) = ClassicPackConverter::new(&atlases).convert(&classic).unwrap();
+        assert_eq!(report.textures, [Identifier::from("block/grass_top"), Identifier::from("block/cobblestone")]);
+        assert_eq!(report.skipped_sheets, ["items.png"]);
+
+        let metadata = PackMetadata::parse(std::str::from_utf8(&pack.files["pack.mcmeta"]).unwrap()).unwrap();
+        assert_eq!(metadata, PackMetadata { pack_format: ORANGE_PACK_FORMAT, description: String::from("Classic pack\nby someone") });
+
+        // The converted pack loads like any other
+        let output = directory.join("converted");
+        pack.write_to_directory(&output).unwrap();
+        let resources = ResourceManager::from_sources(vec![ResourceType::Dir(output)]).unwrap();
+        let cobblestone = resources.read_image(&Identifier::from("block/cobblestone"), ResourceKind::Texture).unwrap();
+        assert_eq!(cobblestone.dimensions(), (2, 2));
+        assert!(cobblestone.to_rgba8().pixels().all(|pixel| *pixel == Rgba([1, 1, 7, 255])));
+        let grass = resources.read_image(&Identifier::from("block/grass_top"), ResourceKind::Texture).unwrap();
+        assert_eq!(grass.get_pixel(1, 1), Rgba([0, 0, 7, 255]));
+        assert_eq!(resources.read_image(&Identifier::from("block/terrain"), ResourceKind::Texture).unwrap().dimensions(), (32, 32));
+    }
+}
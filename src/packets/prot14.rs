@@ -2,6 +2,9 @@ use bytemuck::{Pod, Zeroable};
 use log::warn;
 use orange_networking::{orange_networking_derive::PacketEnumHolder, packet::{PacketEnumHolder, PacketParseable, PacketParseError}, ByteArray};
 
+/// The protocol version spoken by b1.7.3, sent in the Login packet
+pub const PROTOCOL_VERSION: i32 = 14;
+
 #[repr(u8)]
 #[derive(Debug, Clone, PacketEnumHolder)]
 pub enum Packet {
@@ -73,6 +76,8 @@ pub enum Packet {
     // Variable data, ascii text is an array of bytes
     ItemData { item_type: i16, item_id: i16, item_data: ItemAsciiData } = 0x83,
     IncrementStatistic { statistic_id: i32, amount: i8 } = 0xC8,
+    // Sent alone by the server list, answered with a DisconnectKick of the form motd§online§max
+    ServerListPing = 0xFE,
     DisconnectKick { reason: String } = 0xFF,
 }
 
@@ -251,9 +256,9 @@ impl PacketParseable for NonMobUnknownData {
 
 #[derive(Debug, Clone)]
 pub struct ItemPacketData {
-    id: i16, 
-    amount: i8,
-    damage: i16,
+    pub id: i16,
+    pub amount: i8,
+    pub damage: i16,
 }
 
 impl PacketParseable for ItemPacketData {
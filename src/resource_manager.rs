@@ -1,75 +1,428 @@
-use std::{path::PathBuf, marker::PhantomData};
-use rustc_hash::FxHashMap as HashMap;
+use std::collections::BTreeSet;
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
 
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use zip::ZipArchive;
+
+use crate::minecraft::identifier::Identifier;
+
+/// Where the assets of the base game are kept, the bottom pack of the default stack
+pub const DEFAULT_ASSETS_DIR: &str = "../orange-mc-assets";
+pub const PACK_METADATA_FILE: &str = "pack.mcmeta";
+
+#[derive(Debug, Clone)]
 pub enum ResourceType {
     Zip(PathBuf),
     Dir(PathBuf)
 }
 
-struct JsonFileFormat;
-struct TomlFileFormat;
-struct PngFileFormat;
+impl ResourceType {
+    /// A zip for files ending in .zip, a directory for anything else
+    pub fn from_path(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        match path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("zip")) {
+            true => Self::Zip(path),
+            false => Self::Dir(path),
+        }
+    }
 
+    pub fn path(&self) -> &Path {
+        match self {
+            Self::Zip(path) | Self::Dir(path) => path,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ResourceError {
+    Io(io::Error),
+    Zip(zip::result::ZipError),
+    Json(serde_json::Error),
+    Image(image::ImageError),
+    /// No pack in the stack has the file at this path
+    NotFound(String),
+}
 
-struct FileFormatReader<T> { _data: PhantomData<T> }
-trait FileFormatReaderTrait {
-    fn load_file();
+impl std::error::Error for ResourceError {}
+
+impl std::fmt::Display for ResourceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "{e}"),
+            Self::Zip(e) => write!(f, "{e}"),
+            Self::Json(e) => write!(f, "{e}"),
+            Self::Image(e) => write!(f, "{e}"),
+            Self::NotFound(path) => write!(f, "No resource pack has {path}"),
+        }
+    }
 }
 
-impl FileFormatReaderTrait for FileFormatReader<JsonFileFormat> {
-    fn load_file() {
-       log::warn!("Json Reading!"); 
+impl From<io::Error> for ResourceError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
     }
 }
 
-impl FileFormatReaderTrait for FileFormatReader<TomlFileFormat> {
-    fn load_file() {
-        log::warn!("Toml Reading!");
+impl From<zip::result::ZipError> for ResourceError {
+    fn from(e: zip::result::ZipError) -> Self {
+        Self::Zip(e)
     }
 }
 
-impl FileFormatReaderTrait for FileFormatReader<PngFileFormat> {
-    fn load_file() {
-        log::warn!("Png Reading!");
+impl From<serde_json::Error> for ResourceError {
+    fn from(e: serde_json::Error) -> Self {
+        Self::Json(e)
     }
 }
 
-pub enum ResourceFileFormat {
-    JSON,
-    TOML,
-    PNG,
+impl From<image::ImageError> for ResourceError {
+    fn from(e: image::ImageError) -> Self {
+        Self::Image(e)
+    }
+}
+
+/// What a resource is, which decides the directory and extension it is looked up by
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ResourceKind {
+    Model,
+    Blockstate,
+    Texture,
+    /// The .png.mcmeta next to a texture
+    TextureMetadata,
+    /// A texture atlas described by cells of an image, such as terrain.mcatlas
+    Atlas,
+    Shader,
+}
+
+impl ResourceKind {
+    pub fn directory(&self) -> &'static str {
+        match self {
+            Self::Model => "models",
+            Self::Blockstate => "blockstates",
+            Self::Texture | Self::TextureMetadata | Self::Atlas => "textures",
+            Self::Shader => "shaders",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Model | Self::Blockstate => ".json",
+            Self::Texture => ".png",
+            Self::TextureMetadata => ".png.mcmeta",
+            Self::Atlas => ".mcatlas",
+            Self::Shader => ".wgsl",
+        }
+    }
+
+    /// Where the resource with `identifier` is in a pack, assets/namespace/directory/name.extension
+    pub fn path(&self, identifier: &Identifier) -> String {
+        format!("assets/{}/{}/{}{}", identifier.get_namespace(), self.directory(), identifier.get_name(), self.extension())
+    }
+}
+
+/// The pack section of a pack.mcmeta
+#[derive(Debug, Clone, PartialEq)]
+pub struct PackMetadata {
+    pub pack_format: u32,
+    pub description: String,
+}
+
+impl PackMetadata {
+    /// Parse a pack.mcmeta, the description may be plain text or a text component
+    pub fn parse(contents: &str) -> Result<Self, ResourceError> {
+        let value: Value = serde_json::from_str(contents)?;
+        let pack = &value["pack"];
+        let pack_format = pack["pack_format"].as_u64().unwrap_or(0) as u32;
+        let description = match &pack["description"] {
+            Value::String(text) => text.clone(),
+            Value::Null => String::new(),
+            Value::Object(component) => component.get("text").and_then(Value::as_str).unwrap_or_default().to_string(),
+            other => other.to_string(),
+        };
+        Ok(Self { pack_format, description })
+    }
+
+    /// The contents of a pack.mcmeta with this as its pack section
+    pub fn to_json(&self) -> String {
+        let value = serde_json::json!({ "pack": { "pack_format": self.pack_format, "description": self.description } });
+        serde_json::to_string_pretty(&value).expect("A json value always serializes")
+    }
+}
+
+/** A single resource pack, a directory or a zip with an assets directory at its root
+ *  The files in it are listed once when it is opened, reading them goes back to the source
+ */
+pub struct ResourcePack {
+    source: ResourceType,
+    metadata: Option<PackMetadata>,
+    /// Every file in the pack, by its path from the root of the pack with forward slashes
+    files: BTreeSet<String>,
+    archive: Option<Mutex<ZipArchive<BufReader<File>>>>,
+}
+
+impl ResourcePack {
+    pub fn open(source: ResourceType) -> Result<Self, ResourceError> {
+        let mut pack = Self { source, metadata: None, files: BTreeSet::new(), archive: None };
+        match &pack.source {
+            ResourceType::Dir(path) => {
+                if !path.is_dir() {
+                    return Err(ResourceError::Io(io::Error::new(io::ErrorKind::NotFound, format!("{} is not a directory", path.display()))));
+                }
+                Self::list_directory(path, path, &mut pack.files)?;
+            },
+            ResourceType::Zip(path) => {
+                let archive = ZipArchive::new(BufReader::new(File::open(path)?))?;
+                pack.files = archive.file_names().filter(|name| !name.ends_with('/')).map(str::to_string).collect();
+                pack.archive = Some(Mutex::new(archive));
+            },
+        }
+        if pack.contains(PACK_METADATA_FILE) {
+            match PackMetadata::parse(&String::from_utf8_lossy(&pack.read(PACK_METADATA_FILE)?)) {
+                Ok(metadata) => { pack.metadata = Some(metadata); },
+                Err(e) => { log::warn!("Invalid {PACK_METADATA_FILE} in {}: {e}", pack.name()); },
+            }
+        }
+        Ok(pack)
+    }
+
+    fn list_directory(root: &Path, directory: &Path, files: &mut BTreeSet<String>) -> io::Result<()> {
+        for entry in fs::read_dir(directory)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                Self::list_directory(root, &path, files)?;
+            } else if let Ok(relative) = path.strip_prefix(root) {
+                files.insert(relative.to_string_lossy().replace('\\', "/"));
+            }
+        }
+        Ok(())
+    }
+
+    /// The file or directory name of the pack
+    pub fn name(&self) -> String {
+        self.source.path().file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_else(|| self.source.path().display().to_string())
+    }
+
+    pub fn source(&self) -> &ResourceType {
+        &self.source
+    }
+
+    pub fn metadata(&self) -> Option<&PackMetadata> {
+        self.metadata.as_ref()
+    }
+
+    pub fn contains(&self, path: &str) -> bool {
+        self.files.contains(path)
+    }
+
+    /// Every file whose path starts with `prefix`
+    pub fn files_with_prefix<'a>(&'a self, prefix: &'a str) -> impl Iterator<Item = &'a str> {
+        self.files.range(prefix.to_string()..).map(String::as_str).take_while(move |path| path.starts_with(prefix))
+    }
+
+    pub fn read(&self, path: &str) -> Result<Vec<u8>, ResourceError> {
+        if !self.contains(path) {
+            return Err(ResourceError::NotFound(path.to_string()));
+        }
+        match (&self.source, &self.archive) {
+            (ResourceType::Zip(_), Some(archive)) => {
+                let mut archive = archive.lock().unwrap();
+                let mut file = archive.by_name(path)?;
+                let mut bytes = Vec::with_capacity(file.size() as usize);
+                file.read_to_end(&mut bytes)?;
+                Ok(bytes)
+            },
+            (source, _) => Ok(fs::read(source.path().join(path))?),
+        }
+    }
 }
 
+/** An ordered stack of resource packs, resources are read from the topmost pack that has them
+ *  so a pack only needs the files it changes, everything else comes from the packs below it
+ */
+#[derive(Default)]
 pub struct ResourceManager {
-    resource_sources: Vec<ResourceType>,
+    /// Bottom pack first
+    packs: Vec<ResourcePack>,
 }
 
 impl ResourceManager {
-    pub fn reload_resources<T>(&self, buffer: &mut HashMap<String, T>, format: ResourceFileFormat, supported_extensions: Vec<String>) {
-        match format {
-            ResourceFileFormat::JSON => { FileFormatReader::<JsonFileFormat>::load_file(); },
-            ResourceFileFormat::TOML => { FileFormatReader::<TomlFileFormat>::load_file(); },
-            ResourceFileFormat::PNG => { FileFormatReader::<PngFileFormat>::load_file(); }
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Open every source, the first is the bottom of the stack
+    pub fn from_sources(sources: Vec<ResourceType>) -> Result<Self, ResourceError> {
+        let mut manager = Self::new();
+        for source in sources {
+            manager.push_pack(source)?;
         }
+        Ok(manager)
+    }
+
+    /// Just the assets of the base game
+    pub fn with_default_assets() -> Result<Self, ResourceError> {
+        Self::from_sources(vec![ResourceType::Dir(PathBuf::from(DEFAULT_ASSETS_DIR))])
+    }
+
+    /// Put a pack on top of the stack, overriding what the packs below it have
+    pub fn push_pack(&mut self, source: ResourceType) -> Result<(), ResourceError> {
+        let pack = ResourcePack::open(source)?;
+        log::info!("Added resource pack {}", pack.name());
+        self.packs.push(pack);
+        Ok(())
+    }
+
+    /// The packs from the bottom of the stack to the top
+    pub fn packs(&self) -> &[ResourcePack] {
+        &self.packs
+    }
+
+    /** Open every pack again as a new stack, picking up files that were added, changed or removed since
+     *  This stack is left as it was, so it can stay in use until whatever is read from the new one is known to be fine
+     */
+    pub fn reopen(&self) -> Result<Self, ResourceError> {
+        let sources: Vec<ResourceType> = self.packs.iter().map(|pack| pack.source.clone()).collect();
+        Self::from_sources(sources)
+    }
+
+    /// The topmost pack that has the file at `path`
+    pub fn find_pack(&self, path: &str) -> Option<&ResourcePack> {
+        self.packs.iter().rev().find(|pack| pack.contains(path))
+    }
+
+    pub fn contains(&self, identifier: &Identifier, kind: ResourceKind) -> bool {
+        self.find_pack(&kind.path(identifier)).is_some()
+    }
+
+    /// Read a file by its path from the root of a pack
+    pub fn read_path(&self, path: &str) -> Result<Vec<u8>, ResourceError> {
+        self.find_pack(path).ok_or_else(|| ResourceError::NotFound(path.to_string()))?.read(path)
     }
 
-    /** Apply a function to all files in dir and subdirs   
-      Will crash if depth is greater than number of allowed open files per program 
-      */
-    fn iter_files_recursive<F: FnMut(&std::fs::DirEntry)>(path: PathBuf, file_funct: &mut F) {
-        if !path.is_dir() {
-            log::error!("Not a dir: {}", path.display());
-            return;
+    pub fn read(&self, identifier: &Identifier, kind: ResourceKind) -> Result<Vec<u8>, ResourceError> {
+        self.read_path(&kind.path(identifier))
+    }
+
+    pub fn read_to_string(&self, identifier: &Identifier, kind: ResourceKind) -> Result<String, ResourceError> {
+        let bytes = self.read(identifier, kind)?;
+        String::from_utf8(bytes).map_err(|e| ResourceError::Io(io::Error::new(io::ErrorKind::InvalidData, e)))
+    }
+
+    pub fn read_json<T: DeserializeOwned>(&self, identifier: &Identifier, kind: ResourceKind) -> Result<T, ResourceError> {
+        Ok(serde_json::from_slice(&self.read(identifier, kind)?)?)
+    }
+
+    /// Read and decode an image, of any format the image crate can guess from its contents
+    pub fn read_image(&self, identifier: &Identifier, kind: ResourceKind) -> Result<image::DynamicImage, ResourceError> {
+        Ok(image::load_from_memory(&self.read(identifier, kind)?)?)
+    }
+
+    /// Every namespace any pack has assets in
+    pub fn namespaces(&self) -> BTreeSet<String> {
+        self.packs.iter()
+            .flat_map(|pack| pack.files_with_prefix("assets/"))
+            .filter_map(|path| path["assets/".len()..].split_once('/').map(|(namespace, _)| namespace.to_string()))
+            .collect()
+    }
+
+    /** Every resource of a kind in `namespace` whose name starts with `prefix`, from all packs
+     *  A prefix of "block/" lists the block models for models, an empty one lists them all
+     */
+    pub fn list(&self, namespace: &str, kind: ResourceKind, prefix: &str) -> Vec<Identifier> {
+        let directory = format!("assets/{namespace}/{}/", kind.directory());
+        let search = format!("{directory}{prefix}");
+        let names: BTreeSet<&str> = self.packs.iter()
+            .flat_map(|pack| pack.files_with_prefix(&search).collect::<Vec<_>>())
+            .filter_map(|path| path[directory.len()..].strip_suffix(kind.extension()))
+            .collect();
+        names.into_iter().map(|name| Identifier::new(namespace.to_string(), name.to_string())).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::path::Path;
+
+    use super::{PackMetadata, ResourceKind, ResourceManager, ResourceType};
+    use crate::minecraft::identifier::Identifier;
+    use crate::util::test_directory::TestDirectory;
+
+    fn write_dir_pack(root: &Path, files: &[(&str, &str)]) {
+        for (path, contents) in files {
+            let path = root.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, contents).unwrap();
         }
+    }
 
-        for f in std::fs::read_dir(path).unwrap() {
-            let entry = f.unwrap();
-            let entry_path = entry.path();
-            if entry_path.is_dir() {
-                Self::iter_files_recursive(entry_path, file_funct);
-            } else {
-                file_funct(&entry);
-            }
+    fn write_zip_pack(path: &Path, files: &[(&str, &str)]) {
+        let mut zip = zip::ZipWriter::new(std::fs::File::create(path).unwrap());
+        for (name, contents) in files {
+            zip.start_file(*name, zip::write::FileOptions::default()).unwrap();
+            zip.write_all(contents.as_bytes()).unwrap();
         }
+        zip.finish().unwrap();
+    }
+
+    #[test]
+    fn topmost_pack_wins() {
+        let directory = TestDirectory::new("packs_stack");
+        let base = directory.join("base");
+        write_dir_pack(&base, &[
+            ("pack.mcmeta", r#"{"pack": {"pack_format": 1, "description": "Base"}}"#),
+            ("assets/minecraft/models/block/stone.json", "base stone"),
+            ("assets/minecraft/models/block/dirt.json", "base dirt"),
+            ("assets/minecraft/blockstates/stone.json", "{}"),
+        ]);
+        let zip = directory.join("Override.zip");
+        write_zip_pack(&zip, &[
+            ("pack.mcmeta", r#"{"pack": {"pack_format": 1, "description": {"text": "Zipped"}}}"#),
+            ("assets/minecraft/models/block/stone.json", "zip stone"),
+            ("assets/minecraft/models/item/stick.json", "zip stick"),
+            ("assets/orange/textures/block/glow.png", "png"),
+            ("assets/orange/textures/block/glow.png.mcmeta", "{}"),
+        ]);
+        let resources = ResourceManager::from_sources(vec![ResourceType::from_path(&base), ResourceType::from_path(&zip)]).unwrap();
+
+        let stone = Identifier::from("block/stone");
+        assert_eq!(resources.read_to_string(&stone, ResourceKind::Model).unwrap(), "zip stone");
+        assert_eq!(resources.read_to_string(&Identifier::from("block/dirt"), ResourceKind::Model).unwrap(), "base dirt");
+        assert!(resources.contains(&Identifier::from("stone"), ResourceKind::Blockstate));
+        assert!(resources.read(&Identifier::from("block/gravel"), ResourceKind::Model).is_err());
+        assert_eq!(resources.find_pack(&ResourceKind::Model.path(&stone)).unwrap().name(), "Override.zip");
+
+        assert_eq!(resources.packs()[0].metadata(), Some(&PackMetadata { pack_format: 1, description: String::from("Base") }));
+        assert_eq!(resources.packs()[1].metadata().unwrap().description, "Zipped");
+        assert_eq!(resources.namespaces().into_iter().collect::<Vec<_>>(), ["minecraft", "orange"]);
+    }
+
+    #[test]
+    fn lists_by_namespace_and_prefix() {
+        let directory = TestDirectory::new("packs_list");
+        write_dir_pack(&directory.join("base"), &[
+            ("assets/minecraft/models/block/stone.json", ""),
+            ("assets/minecraft/models/block/cube_all.json", ""),
+            ("assets/minecraft/models/item/stick.json", ""),
+            ("assets/minecraft/textures/block/water.png", ""),
+            ("assets/minecraft/textures/block/water.png.mcmeta", ""),
+        ]);
+        write_dir_pack(&directory.join("top"), &[
+            ("assets/minecraft/models/block/stone.json", ""),
+            ("assets/minecraft/models/block/slab.json", ""),
+            ("assets/orange/models/block/stone.json", ""),
+        ]);
+        let resources = ResourceManager::from_sources(vec![ResourceType::Dir(directory.join("base")), ResourceType::Dir(directory.join("top"))]).unwrap();
+
+        let names = |identifiers: Vec<Identifier>| identifiers.iter().map(|identifier| identifier.to_string()).collect::<Vec<_>>();
+        assert_eq!(names(resources.list("minecraft", ResourceKind::Model, "block/")), ["minecraft:block/cube_all", "minecraft:block/slab", "minecraft:block/stone"]);
+        assert_eq!(names(resources.list("minecraft", ResourceKind::Model, "")).len(), 4);
+        assert_eq!(names(resources.list("orange", ResourceKind::Model, "block/")), ["orange:block/stone"]);
+        assert_eq!(names(resources.list("minecraft", ResourceKind::Texture, "block/")), ["minecraft:block/water"]);
+        assert_eq!(names(resources.list("minecraft", ResourceKind::TextureMetadata, "")), ["minecraft:block/water"]);
     }
 }
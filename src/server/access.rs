@@ -0,0 +1,283 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use super::config::{ServerConfig, SERVER_PROPERTIES_FILE};
+use super::properties::PropertiesError;
+
+pub const OPS_FILE: &str = "ops.txt";
+pub const WHITELIST_FILE: &str = "white-list.txt";
+pub const BANNED_PLAYERS_FILE: &str = "banned-players.txt";
+pub const BANNED_IPS_FILE: &str = "banned-ips.txt";
+
+/// Why a player may not join, sent to them as the kick reason
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AccessDenied {
+    BannedPlayer,
+    BannedIp,
+    NotWhitelisted,
+}
+
+impl std::fmt::Display for AccessDenied {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::BannedPlayer => write!(f, "You are banned from this server!"),
+            Self::BannedIp => write!(f, "Your IP address is banned from this server!"),
+            Self::NotWhitelisted => write!(f, "You are not white-listed on this server!"),
+        }
+    }
+}
+
+impl std::error::Error for AccessDenied {}
+
+/** A set of player names or addresses, kept in a vanilla style text file with one lowercase entry per line
+ *  Every change is written straight back to the file, and the file can be reloaded when it was edited by hand
+ */
+#[derive(Debug, Clone, Default)]
+pub struct AccessList {
+    path: Option<PathBuf>,
+    entries: BTreeSet<String>,
+    /// Modification time and size of the file when it was last read or written
+    file_state: Option<(SystemTime, u64)>,
+}
+
+impl AccessList {
+    /// A list that is never saved
+    pub fn in_memory() -> Self {
+        Self::default()
+    }
+
+    /// Read a list from its file, creating an empty file if there is none yet
+    pub fn load(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let mut list = Self { path: Some(path.into()), entries: BTreeSet::new(), file_state: None };
+        match list.path.as_deref().map(Path::exists) {
+            Some(true) => list.reload()?,
+            _ => list.save()?,
+        }
+        Ok(list)
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    fn normalize(entry: &str) -> String {
+        entry.trim().to_lowercase()
+    }
+
+    pub fn contains(&self, entry: &str) -> bool {
+        self.entries.contains(&Self::normalize(entry))
+    }
+
+    /// The entries in sorted order
+    pub fn entries(&self) -> impl Iterator<Item = &str> {
+        self.entries.iter().map(String::as_str)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Add an entry and save, returns false if it was already there
+    pub fn add(&mut self, entry: &str) -> io::Result<bool> {
+        let entry = Self::normalize(entry);
+        if entry.is_empty() || !self.entries.insert(entry) {
+            return Ok(false);
+        }
+        self.save().map(|_| true)
+    }
+
+    /// Remove an entry and save, returns false if it was not there
+    pub fn remove(&mut self, entry: &str) -> io::Result<bool> {
+        if !self.entries.remove(&Self::normalize(entry)) {
+            return Ok(false);
+        }
+        self.save().map(|_| true)
+    }
+
+    pub fn save(&mut self) -> io::Result<()> {
+        let Some(path) = &self.path else { return Ok(()); };
+        let contents: String = self.entries.iter().map(|entry| format!("{entry}\n")).collect();
+        fs::write(path, contents)?;
+        self.file_state = Self::read_file_state(path);
+        Ok(())
+    }
+
+    /// Read the file again, replacing every entry
+    pub fn reload(&mut self) -> io::Result<()> {
+        let Some(path) = &self.path else { return Ok(()); };
+        let contents = fs::read_to_string(path)?;
+        self.entries = contents.lines().map(Self::normalize).filter(|entry| !entry.is_empty()).collect();
+        self.file_state = Self::read_file_state(path);
+        Ok(())
+    }
+
+    /// Reload if the file changed since it was last read or written, returns whether it did
+    pub fn reload_if_changed(&mut self) -> io::Result<bool> {
+        let Some(path) = &self.path else { return Ok(false); };
+        if Self::read_file_state(path) == self.file_state {
+            return Ok(false);
+        }
+        self.reload().map(|_| true)
+    }
+
+    fn read_file_state(path: &Path) -> Option<(SystemTime, u64)> {
+        let metadata = fs::metadata(path).ok()?;
+        Some((metadata.modified().ok()?, metadata.len()))
+    }
+}
+
+/** Who may join a server and who may run operator commands
+ *  The whitelist only applies while it is enabled, bans always do
+ */
+#[derive(Debug, Clone, Default)]
+pub struct AccessLists {
+    pub ops: AccessList,
+    pub whitelist: AccessList,
+    pub banned_players: AccessList,
+    pub banned_ips: AccessList,
+    whitelist_enabled: bool,
+    /// The server.properties turning the whitelist on or off is written to, None for lists in memory
+    properties_path: Option<PathBuf>,
+}
+
+impl AccessLists {
+    /// Lists that are never saved, for servers without a directory of their own
+    pub fn in_memory() -> Self {
+        Self::default()
+    }
+
+    /// Load every list from its file in `directory`
+    pub fn load(directory: &Path) -> io::Result<Self> {
+        Ok(Self {
+            ops: AccessList::load(directory.join(OPS_FILE))?,
+            whitelist: AccessList::load(directory.join(WHITELIST_FILE))?,
+            banned_players: AccessList::load(directory.join(BANNED_PLAYERS_FILE))?,
+            banned_ips: AccessList::load(directory.join(BANNED_IPS_FILE))?,
+            whitelist_enabled: false,
+            properties_path: Some(directory.join(SERVER_PROPERTIES_FILE)),
+        })
+    }
+
+    pub fn with_whitelist(mut self, enabled: bool) -> Self {
+        self.whitelist_enabled = enabled;
+        self
+    }
+
+    pub fn is_whitelist_enabled(&self) -> bool {
+        self.whitelist_enabled
+    }
+
+    /// Turn the whitelist on or off, like vanilla it is saved as white-list in server.properties so it lasts past a restart
+    pub fn set_whitelist_enabled(&mut self, enabled: bool) -> io::Result<()> {
+        self.whitelist_enabled = enabled;
+        let Some(path) = &self.properties_path else { return Ok(()); };
+        let mut config = ServerConfig::load(path).map_err(|e| match e {
+            PropertiesError::Io(e) => e,
+            e => io::Error::new(io::ErrorKind::InvalidData, e),
+        })?;
+        config.white_list = enabled;
+        config.save(path)
+    }
+
+    pub fn is_op(&self, username: &str) -> bool {
+        self.ops.contains(username)
+    }
+
+    /// Whether a player may join, `address` is None for players that did not connect over the network
+    pub fn check(&self, username: &str, address: Option<IpAddr>) -> Result<(), AccessDenied> {
+        if self.banned_players.contains(username) {
+            return Err(AccessDenied::BannedPlayer);
+        }
+        if address.is_some_and(|address| self.banned_ips.contains(&address.to_string())) {
+            return Err(AccessDenied::BannedIp);
+        }
+        if self.whitelist_enabled && !self.whitelist.contains(username) && !self.ops.contains(username) {
+            return Err(AccessDenied::NotWhitelisted);
+        }
+        Ok(())
+    }
+
+    /// Reload every list whose file changed, returns whether any did
+    pub fn reload_changed(&mut self) -> io::Result<bool> {
+        let mut changed = false;
+        for list in [&mut self.ops, &mut self.whitelist, &mut self.banned_players, &mut self.banned_ips] {
+            changed |= list.reload_if_changed()?;
+        }
+        Ok(changed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::IpAddr;
+
+    use super::{AccessDenied, AccessList, AccessLists, BANNED_PLAYERS_FILE, OPS_FILE};
+    use crate::server::config::{ServerConfig, SERVER_PROPERTIES_FILE};
+    use crate::util::test_directory::TestDirectory;
+
+    #[test]
+    fn checks_bans_and_whitelist() {
+        let mut lists = AccessLists::in_memory();
+        let address: IpAddr = "10.0.0.5".parse().unwrap();
+        assert_eq!(lists.check("Notch", Some(address)), Ok(()));
+
+        lists.banned_players.add("notch").unwrap();
+        assert_eq!(lists.check("NOTCH", None), Err(AccessDenied::BannedPlayer));
+        lists.banned_ips.add("10.0.0.5").unwrap();
+        assert_eq!(lists.check("Jeb", Some(address)), Err(AccessDenied::BannedIp));
+        assert_eq!(lists.check("Jeb", None), Ok(()));
+
+        lists.set_whitelist_enabled(true).unwrap();
+        assert_eq!(lists.check("Jeb", None), Err(AccessDenied::NotWhitelisted));
+        lists.whitelist.add("Jeb").unwrap();
+        lists.ops.add("Dinnerbone").unwrap();
+        assert_eq!(lists.check("jeb", None), Ok(()));
+        assert_eq!(lists.check("Dinnerbone", None), Ok(()));
+    }
+
+    #[test]
+    fn vanilla_files_round_trip() {
+        let directory = TestDirectory::new("access_files");
+        std::fs::write(directory.join(OPS_FILE), "Notch\n\n  jeb_ \n").unwrap();
+        let mut lists = AccessLists::load(&directory).unwrap();
+        assert_eq!(lists.ops.entries().collect::<Vec<_>>(), ["jeb_", "notch"]);
+        assert!(directory.join(BANNED_PLAYERS_FILE).exists());
+
+        assert!(lists.banned_players.add("Griefer").unwrap());
+        assert!(!lists.banned_players.add("griefer").unwrap());
+        assert_eq!(std::fs::read_to_string(directory.join(BANNED_PLAYERS_FILE)).unwrap(), "griefer\n");
+        assert!(lists.ops.remove("NOTCH").unwrap());
+        assert_eq!(AccessList::load(directory.join(OPS_FILE)).unwrap().entries().collect::<Vec<_>>(), ["jeb_"]);
+    }
+
+    #[test]
+    fn whitelist_setting_is_saved() {
+        let directory = TestDirectory::new("access_whitelist_setting");
+        std::fs::write(directory.join(SERVER_PROPERTIES_FILE), "motd=Hello\nwhite-list=false\n").unwrap();
+        let mut lists = AccessLists::load(&directory).unwrap();
+        lists.set_whitelist_enabled(true).unwrap();
+        let config = ServerConfig::load(&directory.join(SERVER_PROPERTIES_FILE)).unwrap();
+        assert!(config.white_list);
+        assert_eq!(config.motd, "Hello");
+    }
+
+    #[test]
+    fn reloads_edited_files() {
+        let directory = TestDirectory::new("access_reload");
+        let mut lists = AccessLists::load(&directory).unwrap();
+        assert!(!lists.reload_changed().unwrap());
+
+        std::fs::write(directory.join(OPS_FILE), "notch\ndinnerbone\n").unwrap();
+        assert!(lists.reload_changed().unwrap());
+        assert!(lists.is_op("Dinnerbone"));
+        assert!(!lists.reload_changed().unwrap());
+    }
+}
@@ -0,0 +1,269 @@
+use ultraviolet::{DVec3, IVec3};
+
+use crate::packets::prot14::{ItemPacketData, Packet};
+use crate::util::pos::BlockPos;
+use crate::world::chunk::TBlockData;
+
+use super::server_player::{ServerPlayer, PLAYER_EYE_HEIGHT};
+use super::world::ServerWorld;
+
+/// How far from a player's eyes the center of a block they dig or click may be
+pub const MAX_REACH: f64 = 6.0;
+
+/// PlayerDigging status sent when the player starts hitting a block
+pub const DIG_START: i8 = 0;
+/// PlayerDigging status sent when the block broke on the client
+pub const DIG_FINISH: i8 = 2;
+
+const PLAYER_HEIGHT: f64 = 1.8;
+const PLAYER_HALF_WIDTH: f64 = 0.3;
+const BEDROCK: TBlockData = 7;
+/// Blocks that are placed into rather than against, water, lava and fire
+const REPLACEABLE: [TBlockData; 5] = [8, 9, 10, 11, 51];
+
+/// Why a dig or placement was refused, the client is then sent the blocks as they really are
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BlockActionError {
+    OutOfReach,
+    OutsideWorld,
+    /// Finished breaking a block without having started on it
+    NotDigging,
+    Unbreakable,
+    /// The held item is not a block
+    NotPlaceable,
+    /// The player's selected slot does not hold the block they placed
+    NotHeld,
+    /// Placing into a block that is neither air nor replaceable
+    Occupied,
+    /// The placed block would overlap the player
+    InsidePlayer,
+    InvalidFace(i8),
+    /// Only operators may change blocks this close to spawn
+    SpawnProtected,
+}
+
+impl std::fmt::Display for BlockActionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::OutOfReach => write!(f, "Block is out of reach"),
+            Self::OutsideWorld => write!(f, "Block is outside of the world"),
+            Self::NotDigging => write!(f, "Finished digging a block that was never started"),
+            Self::Unbreakable => write!(f, "Block can't be broken"),
+            Self::NotPlaceable => write!(f, "Held item is not a block"),
+            Self::NotHeld => write!(f, "Placed block is not in the player's hand"),
+            Self::Occupied => write!(f, "There is already a block there"),
+            Self::InsidePlayer => write!(f, "Block would be inside of the player"),
+            Self::InvalidFace(face) => write!(f, "Invalid block face {face}"),
+            Self::SpawnProtected => write!(f, "Block is protected by spawn protection"),
+        }
+    }
+}
+
+impl std::error::Error for BlockActionError {}
+
+/// The offset to the neighbouring block on a face, as sent in PlayerDigging and PlayerUse
+pub fn face_offset(face: i8) -> Option<IVec3> {
+    match face {
+        0 => Some(IVec3::new(0, -1, 0)),
+        1 => Some(IVec3::new(0, 1, 0)),
+        2 => Some(IVec3::new(0, 0, -1)),
+        3 => Some(IVec3::new(0, 0, 1)),
+        4 => Some(IVec3::new(-1, 0, 0)),
+        5 => Some(IVec3::new(1, 0, 0)),
+        _ => None,
+    }
+}
+
+/// Whether the center of a block is within reach of the player's eyes
+pub fn is_in_reach(player: &ServerPlayer, position: BlockPos) -> bool {
+    let eyes = player.position + DVec3::new(0.0, PLAYER_EYE_HEIGHT, 0.0);
+    let center = DVec3::new(position.x as f64 + 0.5, position.y as f64 + 0.5, position.z as f64 + 0.5);
+    (center - eyes).mag() <= MAX_REACH
+}
+
+/// Whether a block is inside the square of `radius` blocks around spawn, a radius of 0 protects nothing
+pub fn is_spawn_protected(spawn: BlockPos, position: BlockPos, radius: u32) -> bool {
+    radius > 0 && (position.x - spawn.x).unsigned_abs().max((position.z - spawn.z).unsigned_abs()) <= radius
+}
+
+fn overlaps_player(player: &ServerPlayer, position: BlockPos) -> bool {
+    let (min, max) = (
+        player.position - DVec3::new(PLAYER_HALF_WIDTH, 0.0, PLAYER_HALF_WIDTH),
+        player.position + DVec3::new(PLAYER_HALF_WIDTH, PLAYER_HEIGHT, PLAYER_HALF_WIDTH),
+    );
+    let overlaps = |block: i32, min: f64, max: f64| (block as f64) < max && (block + 1) as f64 > min;
+    overlaps(position.x, min.x, max.x) && overlaps(position.y, min.y, max.y) && overlaps(position.z, min.z, max.z)
+}
+
+/** Handle a PlayerDigging, breaking the block once the client says it finished
+ *  Breaking takes no time on the server yet, so finishing only needs a start on the same block
+ */
+pub fn dig(world: &mut ServerWorld, player: &mut ServerPlayer, status: i8, position: BlockPos) -> Result<(), BlockActionError> {
+    match status {
+        DIG_START => {
+            player.digging = None;
+            if !is_in_reach(player, position) {
+                return Err(BlockActionError::OutOfReach);
+            }
+            world.get_block(position.x, position.y, position.z).ok_or(BlockActionError::OutsideWorld)?;
+            player.digging = Some(position);
+            Ok(())
+        },
+        DIG_FINISH => {
+            if player.digging.take() != Some(position) {
+                return Err(BlockActionError::NotDigging);
+            }
+            if !is_in_reach(player, position) {
+                return Err(BlockActionError::OutOfReach);
+            }
+            match world.get_block(position.x, position.y, position.z) {
+                None => Err(BlockActionError::OutsideWorld),
+                Some(block) if block & 0xFF == BEDROCK => Err(BlockActionError::Unbreakable),
+                Some(_) => {
+                    world.set_block(position.x, position.y, position.z, 0);
+                    Ok(())
+                },
+            }
+        },
+        _ => Ok(()),
+    }
+}
+
+/// The block a PlayerUse against `position` would place into, None if the face is not a real face
+pub fn placement_target(world: &mut ServerWorld, position: BlockPos, face: i8) -> Option<BlockPos> {
+    let offset = face_offset(face)?;
+    match world.get_block(position.x, position.y, position.z) {
+        Some(block) if REPLACEABLE.contains(&(block & 0xFF)) => Some(position),
+        _ => Some(position + offset),
+    }
+}
+
+/** Handle a PlayerUse, placing the held block against the clicked face
+ *  The block has to be in the selected slot of the player's inventory on the server, one of it is used up
+ */
+pub fn place(world: &mut ServerWorld, player: &mut ServerPlayer, position: BlockPos, face: i8, item: &ItemPacketData) -> Result<(), BlockActionError> {
+    // Using the held item without looking at a block
+    if face == -1 && position == IVec3::new(-1, -1, -1) {
+        return Ok(());
+    }
+    if !(1..256).contains(&item.id) {
+        return Err(BlockActionError::NotPlaceable);
+    }
+    if !player.inventory.held().is_some_and(|held| held.id == item.id && held.damage == item.damage) {
+        return Err(BlockActionError::NotHeld);
+    }
+    if !is_in_reach(player, position) {
+        return Err(BlockActionError::OutOfReach);
+    }
+    let target = placement_target(world, position, face).ok_or(BlockActionError::InvalidFace(face))?;
+    match world.get_block(target.x, target.y, target.z) {
+        None => Err(BlockActionError::OutsideWorld),
+        Some(block) if block != 0 && !REPLACEABLE.contains(&(block & 0xFF)) => Err(BlockActionError::Occupied),
+        Some(_) if overlaps_player(player, target) => Err(BlockActionError::InsidePlayer),
+        Some(_) => {
+            world.set_block(target.x, target.y, target.z, item.id as TBlockData | ((item.damage as TBlockData & 0xF) << 8));
+            player.inventory.take_held();
+            Ok(())
+        },
+    }
+}
+
+/// BlockChange packets that undo whatever the client predicted at `positions`
+pub fn corrections(world: &mut ServerWorld, positions: &[BlockPos]) -> Vec<Packet> {
+    positions.iter()
+        .filter_map(|&position| world.get_block(position.x, position.y, position.z).map(|block| ServerWorld::block_change_packet(position, block)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use ultraviolet::{DVec3, IVec3};
+
+    use super::{corrections, dig, place, BlockActionError, DIG_FINISH, DIG_START};
+    use crate::packets::prot14::{ItemPacketData, Packet};
+    use crate::server::generator::FlatGenerator;
+    use crate::server::inventory::PlayerInventory;
+    use crate::server::server_player::ServerPlayer;
+    use crate::server::world::ServerWorld;
+
+    fn setup() -> (ServerWorld, ServerPlayer) {
+        let world = ServerWorld::new(Box::new(FlatGenerator::default()), 0, IVec3::new(0, 7, 0));
+        let mut player = ServerPlayer::remote(String::from("Dev"), false);
+        player.position = DVec3::new(0.5, 7.0, 0.5);
+        (world, player)
+    }
+
+    fn item(id: i16, damage: i16) -> ItemPacketData {
+        ItemPacketData { id, amount: 1, damage }
+    }
+
+    /// Empty the player's inventory and put a stack into their selected slot
+    fn hold(player: &mut ServerPlayer, id: i16, amount: u32, damage: i16) {
+        player.inventory = PlayerInventory::new();
+        player.inventory.add_item(id, amount, damage);
+    }
+
+    #[test]
+    fn digging_needs_start_and_reach() {
+        let (mut world, mut player) = setup();
+        let grass = IVec3::new(2, 6, 0);
+        assert_eq!(dig(&mut world, &mut player, DIG_FINISH, grass), Err(BlockActionError::NotDigging));
+        assert_eq!(dig(&mut world, &mut player, DIG_START, grass), Ok(()));
+        assert_eq!(world.get_block(2, 6, 0), Some(2));
+        assert_eq!(dig(&mut world, &mut player, DIG_FINISH, grass), Ok(()));
+        assert_eq!(world.get_block(2, 6, 0), Some(0));
+
+        assert_eq!(dig(&mut world, &mut player, DIG_START, IVec3::new(7, 6, 0)), Err(BlockActionError::OutOfReach));
+        player.position = DVec3::new(0.5, 1.0, 0.5);
+        assert_eq!(dig(&mut world, &mut player, DIG_START, IVec3::new(0, 0, 1)), Ok(()));
+        assert_eq!(dig(&mut world, &mut player, DIG_FINISH, IVec3::new(0, 0, 1)), Err(BlockActionError::Unbreakable));
+        assert_eq!(world.get_block(0, 0, 1), Some(7));
+    }
+
+    #[test]
+    fn placing_against_faces() {
+        let (mut world, mut player) = setup();
+        // Wool with its colour in the metadata on top of the grass at (2, 6, 0)
+        hold(&mut player, 35, 1, 14);
+        assert_eq!(place(&mut world, &mut player, IVec3::new(2, 6, 0), 1, &item(35, 14)), Ok(()));
+        assert_eq!(world.get_block(2, 7, 0), Some(35 | (14 << 8)));
+        hold(&mut player, 3, 64, 0);
+        assert_eq!(place(&mut world, &mut player, IVec3::new(2, 6, 0), 4, &item(3, 0)), Err(BlockActionError::Occupied));
+        assert_eq!(place(&mut world, &mut player, IVec3::new(0, 6, 0), 1, &item(3, 0)), Err(BlockActionError::InsidePlayer));
+        assert_eq!(place(&mut world, &mut player, IVec3::new(2, 6, 0), 7, &item(3, 0)), Err(BlockActionError::InvalidFace(7)));
+        assert_eq!(place(&mut world, &mut player, IVec3::new(2, 6, 0), 1, &item(256, 0)), Err(BlockActionError::NotPlaceable));
+        assert_eq!(place(&mut world, &mut player, IVec3::new(20, 6, 0), 1, &item(3, 0)), Err(BlockActionError::OutOfReach));
+        assert_eq!(place(&mut world, &mut player, IVec3::new(-1, -1, -1), -1, &item(3, 0)), Ok(()));
+
+        world.set_block(1, 7, 2, 9);
+        hold(&mut player, 4, 1, 0);
+        assert_eq!(place(&mut world, &mut player, IVec3::new(1, 7, 2), 1, &item(4, 0)), Ok(()));
+        assert_eq!(world.get_block(1, 7, 2), Some(4));
+    }
+
+    #[test]
+    fn placing_uses_up_the_held_block() {
+        let (mut world, mut player) = setup();
+        hold(&mut player, 3, 1, 0);
+        assert_eq!(place(&mut world, &mut player, IVec3::new(2, 6, 0), 1, &item(4, 0)), Err(BlockActionError::NotHeld));
+        assert_eq!(place(&mut world, &mut player, IVec3::new(2, 6, 0), 1, &item(3, 1)), Err(BlockActionError::NotHeld));
+        assert_eq!(place(&mut world, &mut player, IVec3::new(2, 6, 0), 1, &item(3, 0)), Ok(()));
+        assert!(player.inventory.held().is_none());
+        assert_eq!(place(&mut world, &mut player, IVec3::new(2, 7, 0), 1, &item(3, 0)), Err(BlockActionError::NotHeld));
+        assert_eq!(world.get_block(2, 8, 0), Some(0));
+    }
+
+    #[test]
+    fn changes_are_recorded_and_corrected() {
+        let (mut world, mut player) = setup();
+        dig(&mut world, &mut player, DIG_START, IVec3::new(1, 6, 1)).unwrap();
+        dig(&mut world, &mut player, DIG_FINISH, IVec3::new(1, 6, 1)).unwrap();
+        let changes = world.take_changes();
+        assert_eq!(changes.len(), 1);
+        assert_eq!((changes[0].position, changes[0].old, changes[0].new), (IVec3::new(1, 6, 1), 2, 0));
+        assert!(world.take_changes().is_empty());
+
+        let packets = corrections(&mut world, &[IVec3::new(1, 5, 1), IVec3::new(1, 200, 1)]);
+        assert!(matches!(packets.as_slice(), [Packet::BlockChange { x: 1, y: 5, z: 1, block_type: 3, metadata: 0 }]));
+    }
+}
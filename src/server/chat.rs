@@ -0,0 +1,61 @@
+/// The longest chat message a b1.7.3 client accepts, longer ones get it disconnected
+pub const MAX_CHAT_LENGTH: usize = 119;
+
+const COLOUR_PREFIX: char = '§';
+
+/** Split a message into lines a client accepts, breaking between words where it can
+ *  Lines after the first carry on in the colour the previous line ended in,
+ *  and a colour code is never split from its prefix
+ */
+pub fn split_chat(message: &str) -> Vec<String> {
+    let mut lines = vec![];
+    let mut rest: Vec<char> = message.chars().collect();
+    let mut colour: Option<char> = None;
+    loop {
+        let prefix: String = colour.map(|code| format!("{COLOUR_PREFIX}{code}")).unwrap_or_default();
+        let room = MAX_CHAT_LENGTH - prefix.chars().count();
+        if rest.len() <= room {
+            lines.push(prefix + &rest.iter().collect::<String>());
+            return lines;
+        }
+        let (end, next) = match rest[..=room].iter().rposition(|&c| c == ' ').filter(|&space| space > 0) {
+            Some(space) => (space, space + 1),
+            None if rest[room - 1] == COLOUR_PREFIX => (room - 1, room - 1),
+            None => (room, room),
+        };
+        let line = &rest[..end];
+        colour = line.windows(2).rev().find(|pair| pair[0] == COLOUR_PREFIX).map(|pair| pair[1]).or(colour);
+        lines.push(prefix + &line.iter().collect::<String>());
+        rest.drain(..next);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{split_chat, MAX_CHAT_LENGTH};
+
+    #[test]
+    fn short_messages_are_kept() {
+        assert_eq!(split_chat("§7Hello"), ["§7Hello"]);
+        assert_eq!(split_chat(""), [""]);
+    }
+
+    #[test]
+    fn long_messages_break_between_words() {
+        let words: Vec<String> = (0..40).map(|i| format!("word{i}")).collect();
+        let message = format!("§7{}", words.join(" "));
+        let lines = split_chat(&message);
+        assert!(lines.len() > 1);
+        assert!(lines.iter().all(|line| line.chars().count() <= MAX_CHAT_LENGTH));
+        assert!(lines[1..].iter().all(|line| line.starts_with("§7word")));
+        let rejoined: Vec<&str> = lines.iter().map(|line| line.trim_start_matches("§7")).collect();
+        assert_eq!(rejoined.join(" "), words.join(" "));
+    }
+
+    #[test]
+    fn long_words_are_cut_outside_colour_codes() {
+        let message = format!("{}§c{}", "a".repeat(MAX_CHAT_LENGTH - 1), "b".repeat(10));
+        let lines = split_chat(&message);
+        assert_eq!(lines, ["a".repeat(MAX_CHAT_LENGTH - 1), format!("§c{}", "b".repeat(10))]);
+    }
+}
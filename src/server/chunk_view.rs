@@ -0,0 +1,281 @@
+use std::collections::VecDeque;
+
+use rustc_hash::FxHashSet as HashSet;
+
+use crate::packets::prot14::Packet;
+
+use super::world::ServerWorld;
+
+/// A chunk column position, x and z in chunk coordinates
+pub type ColumnPos = (i32, i32);
+
+/// Columns sent around players in every direction, a view distance of 10 is a 21x21 square
+pub const DEFAULT_VIEW_DISTANCE: i32 = 10;
+/// How many bytes of MapChunk data may be sent to each player in a tick
+pub const DEFAULT_CHUNK_BYTES_PER_TICK: usize = 32 * 1024;
+
+/// What changed about the columns a player should see after they moved
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ChunkViewChange {
+    /// Columns that came into view, now queued to be sent
+    pub entered: Vec<ColumnPos>,
+    /// Columns the client has that went out of view and should be unloaded
+    pub left: Vec<ColumnPos>,
+}
+
+/// The columns a player's client has, and those still on the way to it
+#[derive(Debug, Clone, Default)]
+pub struct PlayerChunkView {
+    center: Option<ColumnPos>,
+    loaded: HashSet<ColumnPos>,
+    queued: VecDeque<ColumnPos>,
+}
+
+impl PlayerChunkView {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The column the view was last computed around
+    pub fn center(&self) -> Option<ColumnPos> {
+        self.center
+    }
+
+    /// Columns that have been sent to the client
+    pub fn loaded_chunks(&self) -> &HashSet<ColumnPos> {
+        &self.loaded
+    }
+
+    pub fn is_loaded(&self, column: ColumnPos) -> bool {
+        self.loaded.contains(&column)
+    }
+
+    /// Columns in view that have not been sent yet, nearest first
+    pub fn queued_chunks(&self) -> impl Iterator<Item = &ColumnPos> {
+        self.queued.iter()
+    }
+}
+
+/** Decides which columns each player sees, and streams them under a bandwidth budget
+ *  Views are only recomputed when a player crosses into another column, queued columns are sent nearest first
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ViewDistanceManager {
+    view_distance: i32,
+    bytes_per_tick: usize,
+}
+
+impl Default for ViewDistanceManager {
+    fn default() -> Self {
+        Self::new(DEFAULT_VIEW_DISTANCE, DEFAULT_CHUNK_BYTES_PER_TICK)
+    }
+}
+
+impl ViewDistanceManager {
+    pub fn new(view_distance: i32, bytes_per_tick: usize) -> Self {
+        Self { view_distance: view_distance.max(0), bytes_per_tick }
+    }
+
+    pub fn view_distance(&self) -> i32 {
+        self.view_distance
+    }
+
+    pub fn bytes_per_tick(&self) -> usize {
+        self.bytes_per_tick
+    }
+
+    pub fn is_in_view(&self, center: ColumnPos, column: ColumnPos) -> bool {
+        (column.0 - center.0).abs() <= self.view_distance && (column.1 - center.1).abs() <= self.view_distance
+    }
+
+    /// Every column in view of `center`, nearest first
+    pub fn columns_in_view(&self, center: ColumnPos) -> Vec<ColumnPos> {
+        let distance = self.view_distance;
+        let mut columns: Vec<ColumnPos> = (-distance..=distance)
+            .flat_map(|x| (-distance..=distance).map(move |z| (center.0 + x, center.1 + z)))
+            .collect();
+        columns.sort_by_key(|&column| Self::distance_squared(center, column));
+        columns
+    }
+
+    fn distance_squared(a: ColumnPos, b: ColumnPos) -> i32 {
+        let (x, z) = (a.0 - b.0, a.1 - b.1);
+        x * x + z * z
+    }
+
+    /** Move the view to be around `center`, queueing the columns that came into view
+     *  Loaded columns that went out of view are forgotten and returned to be unloaded,
+     *  queued columns that went out of view are dropped without ever being sent
+     */
+    pub fn update(&self, view: &mut PlayerChunkView, center: ColumnPos) -> ChunkViewChange {
+        if view.center == Some(center) {
+            return ChunkViewChange::default();
+        }
+        view.center = Some(center);
+
+        let mut left: Vec<ColumnPos> = view.loaded.iter().copied().filter(|&column| !self.is_in_view(center, column)).collect();
+        left.sort();
+        for column in &left {
+            view.loaded.remove(column);
+        }
+        view.queued.retain(|&column| self.is_in_view(center, column));
+
+        let entered: Vec<ColumnPos> = self.columns_in_view(center).into_iter()
+            .filter(|column| !view.loaded.contains(column) && !view.queued.contains(column))
+            .collect();
+        view.queued.extend(entered.iter().copied());
+        view.queued.make_contiguous().sort_by_key(|&column| Self::distance_squared(center, column));
+
+        ChunkViewChange { entered, left }
+    }
+
+    /** Build the packets for queued columns, nearest first, until this tick's budget is used up
+     *  At least one column is always sent so a small budget can't stall streaming
+     */
+    pub fn stream(&self, view: &mut PlayerChunkView, world: &mut ServerWorld) -> Vec<Packet> {
+        let mut packets = vec![];
+        let mut bytes = 0;
+        while bytes < self.bytes_per_tick || packets.is_empty() {
+            let Some(column) = view.queued.pop_front() else { break; };
+            let column_packets = world.column_packets(column.0, column.1);
+            bytes += column_packets.iter().map(|packet| match packet {
+                Packet::MapChunk { compressed_data, .. } => compressed_data.len(),
+                _ => 0,
+            }).sum::<usize>();
+            packets.extend(column_packets);
+            view.loaded.insert(column);
+        }
+        packets
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ultraviolet::IVec3;
+
+    use super::{ColumnPos, PlayerChunkView, ViewDistanceManager};
+    use crate::packets::prot14::Packet;
+    use crate::server::generator::FlatGenerator;
+    use crate::server::world::ServerWorld;
+
+    fn flat_world() -> ServerWorld {
+        ServerWorld::new(Box::new(FlatGenerator::default()), 0, IVec3::new(0, 0, 0))
+    }
+
+    /// Stream everything that is queued, returning the columns sent
+    fn drain(manager: &ViewDistanceManager, view: &mut PlayerChunkView, world: &mut ServerWorld) -> Vec<ColumnPos> {
+        let mut sent = vec![];
+        loop {
+            let packets = manager.stream(view, world);
+            if packets.is_empty() {
+                return sent;
+            }
+            sent.extend(packets.iter().filter_map(|packet| match packet {
+                Packet::PreChunk { x, z, mode: true } => Some((*x, *z)),
+                _ => None,
+            }));
+        }
+    }
+
+    #[test]
+    fn walking_across_a_boundary() {
+        let manager = ViewDistanceManager::new(2, usize::MAX);
+        let mut world = flat_world();
+        let mut view = PlayerChunkView::new();
+
+        let change = manager.update(&mut view, (0, 0));
+        assert_eq!(change.entered.len(), 25);
+        assert_eq!(change.entered[0], (0, 0));
+        assert!(change.left.is_empty());
+        assert_eq!(drain(&manager, &mut view, &mut world).len(), 25);
+
+        // Staying in the same column changes nothing
+        assert_eq!(manager.update(&mut view, (0, 0)), Default::default());
+
+        let change = manager.update(&mut view, (1, 0));
+        let mut entered = change.entered.clone();
+        entered.sort();
+        assert_eq!(entered, [(3, -2), (3, -1), (3, 0), (3, 1), (3, 2)]);
+        assert_eq!(change.left, [(-2, -2), (-2, -1), (-2, 0), (-2, 1), (-2, 2)]);
+        drain(&manager, &mut view, &mut world);
+        assert_eq!(view.loaded_chunks().len(), 25);
+        assert!(view.loaded_chunks().iter().all(|&column| manager.is_in_view((1, 0), column)));
+    }
+
+    #[test]
+    fn teleporting_replaces_view() {
+        let manager = ViewDistanceManager::new(1, usize::MAX);
+        let mut world = flat_world();
+        let mut view = PlayerChunkView::new();
+        manager.update(&mut view, (0, 0));
+        drain(&manager, &mut view, &mut world);
+
+        let change = manager.update(&mut view, (100, -100));
+        assert_eq!(change.entered.len(), 9);
+        assert_eq!(change.left.len(), 9);
+        assert_eq!(drain(&manager, &mut view, &mut world)[0], (100, -100));
+    }
+
+    #[test]
+    fn budget_spreads_columns_over_ticks() {
+        let mut world = flat_world();
+        let column_size = match &world.column_packets(0, 0)[1] {
+            Packet::MapChunk { compressed_data, .. } => compressed_data.len(),
+            _ => unreachable!(),
+        };
+        let manager = ViewDistanceManager::new(2, column_size * 3);
+        let mut view = PlayerChunkView::new();
+        manager.update(&mut view, (0, 0));
+
+        let mut ticks = 0;
+        while view.queued_chunks().next().is_some() {
+            let columns = manager.stream(&mut view, &mut world).len() / 2;
+            assert!((1..=3).contains(&columns));
+            ticks += 1;
+        }
+        assert!(ticks >= 25 / 3);
+        assert_eq!(view.loaded_chunks().len(), 25);
+
+        // Even a budget smaller than a column sends one per tick
+        let manager = ViewDistanceManager::new(1, 1);
+        let mut view = PlayerChunkView::new();
+        manager.update(&mut view, (0, 0));
+        assert_eq!(manager.stream(&mut view, &mut world).len(), 2);
+    }
+
+    #[test]
+    fn queued_columns_left_behind_are_never_sent() {
+        let manager = ViewDistanceManager::new(1, 1);
+        let mut world = flat_world();
+        let mut view = PlayerChunkView::new();
+        manager.update(&mut view, (0, 0));
+        manager.stream(&mut view, &mut world);
+
+        // Only the center column was sent, it is the only one to unload
+        let change = manager.update(&mut view, (10, 0));
+        assert_eq!(change.left, [(0, 0)]);
+        let sent = drain(&manager, &mut view, &mut world);
+        assert_eq!(sent.len(), 9);
+        assert!(sent.iter().all(|&column| manager.is_in_view((10, 0), column)));
+    }
+
+    #[test]
+    fn wandering_path_keeps_view_consistent() {
+        let manager = ViewDistanceManager::new(3, 4096);
+        let mut world = flat_world();
+        let mut view = PlayerChunkView::new();
+        let path = [(0, 0), (0, 1), (1, 1), (1, 2), (0, 2), (-1, 2), (-1, 1), (5, 5), (5, 6), (4, 6)];
+        let mut client: std::collections::HashSet<ColumnPos> = Default::default();
+        for &center in path.iter().chain(path.iter().rev()) {
+            let change = manager.update(&mut view, center);
+            for column in &change.left {
+                assert!(client.remove(column), "Unloaded {:?} which was never sent", column);
+            }
+            for column in drain(&manager, &mut view, &mut world) {
+                assert!(client.insert(column), "Sent {:?} twice", column);
+            }
+            assert_eq!(client.len(), 49);
+            assert!(client.iter().all(|&column| manager.is_in_view(center, column)));
+        }
+    }
+}
@@ -0,0 +1,261 @@
+use rustc_hash::FxHashMap as HashMap;
+
+use crate::server::game_server::GameServer;
+
+/// What a command argument accepts, also what tab completion suggests for it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArgumentType {
+    /// A fixed word such as the `set` in `/time set`, selecting between a command's syntaxes
+    Literal(&'static str),
+    /// The name of an online player
+    Player,
+    Integer { min: i32, max: i32 },
+    /// A coordinate, either absolute or relative to the player with `~`
+    Coordinate,
+    /// A single word
+    Word,
+    /// Everything left on the line
+    Message,
+}
+
+impl ArgumentType {
+    /// Suggestions for a partially typed argument
+    pub fn suggestions(&self, server: &GameServer, prefix: &str) -> Vec<String> {
+        let starts_with = |candidate: &str| candidate.to_lowercase().starts_with(&prefix.to_lowercase());
+        match self {
+            Self::Literal(literal) if starts_with(literal) => vec![literal.to_string()],
+            Self::Player => server.players().players().iter()
+                .map(|player| player.player.username.clone())
+                .filter(|name| starts_with(name))
+                .collect(),
+            Self::Coordinate if prefix.is_empty() => vec![String::from("~")],
+            _ => vec![],
+        }
+    }
+}
+
+/// An argument of a command's syntax
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ArgumentSpec {
+    pub name: &'static str,
+    pub kind: ArgumentType,
+    /// Optional arguments may be left off of the end of the line
+    pub optional: bool,
+}
+
+impl ArgumentSpec {
+    pub fn literal(literal: &'static str) -> Self {
+        Self { name: literal, kind: ArgumentType::Literal(literal), optional: false }
+    }
+
+    pub fn player(name: &'static str) -> Self {
+        Self { name, kind: ArgumentType::Player, optional: false }
+    }
+
+    pub fn integer(name: &'static str, min: i32, max: i32) -> Self {
+        Self { name, kind: ArgumentType::Integer { min, max }, optional: false }
+    }
+
+    pub fn coordinate(name: &'static str) -> Self {
+        Self { name, kind: ArgumentType::Coordinate, optional: false }
+    }
+
+    pub fn word(name: &'static str) -> Self {
+        Self { name, kind: ArgumentType::Word, optional: false }
+    }
+
+    pub fn message(name: &'static str) -> Self {
+        Self { name, kind: ArgumentType::Message, optional: false }
+    }
+
+    pub fn optional(mut self) -> Self {
+        self.optional = true;
+        self
+    }
+
+    /// How the argument is shown in usage messages, `set`, `<player>` or `[amount]`
+    pub fn usage(&self) -> String {
+        match (self.kind, self.optional) {
+            (ArgumentType::Literal(literal), _) => literal.to_string(),
+            (_, true) => format!("[{}]", self.name),
+            (_, false) => format!("<{}>", self.name),
+        }
+    }
+
+    /// Parse a single token, Message arguments are handed the rest of the line
+    pub fn parse(&self, token: &str, server: &GameServer) -> Result<Argument, ArgumentError> {
+        match self.kind {
+            ArgumentType::Literal(literal) if token.eq_ignore_ascii_case(literal) => Ok(Argument::Literal),
+            ArgumentType::Literal(literal) => Err(ArgumentError::ExpectedLiteral(literal)),
+            ArgumentType::Player => server.players().find_by_name(token)
+                .map(|player| Argument::Player(player.player.username.clone()))
+                .ok_or_else(|| ArgumentError::PlayerNotFound(token.to_string())),
+            ArgumentType::Integer { min, max } => {
+                let value: i32 = token.parse().map_err(|_| ArgumentError::InvalidInteger(token.to_string()))?;
+                match (min..=max).contains(&value) {
+                    true => Ok(Argument::Integer(value)),
+                    false => Err(ArgumentError::OutOfRange { value, min, max }),
+                }
+            },
+            ArgumentType::Coordinate => Coordinate::parse(token).map(Argument::Coordinate),
+            ArgumentType::Word => Ok(Argument::Word(token.to_string())),
+            ArgumentType::Message => Ok(Argument::Message(token.to_string())),
+        }
+    }
+}
+
+/// A coordinate as typed, `~` makes it relative to where the player is
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Coordinate {
+    pub relative: bool,
+    pub value: f64,
+}
+
+impl Coordinate {
+    /// Parse `12`, `-3.5`, `~` or `~-2`
+    pub fn parse(token: &str) -> Result<Self, ArgumentError> {
+        let invalid = || ArgumentError::InvalidCoordinate(token.to_string());
+        match token.strip_prefix('~') {
+            Some("") => Ok(Self { relative: true, value: 0.0 }),
+            Some(offset) => offset.parse::<f64>().ok().filter(|value| value.is_finite()).map(|value| Self { relative: true, value }).ok_or_else(invalid),
+            None => token.parse::<f64>().ok().filter(|value| value.is_finite()).map(|value| Self { relative: false, value }).ok_or_else(invalid),
+        }
+    }
+
+    /// The coordinate in the world, relative coordinates are offsets from `base`
+    pub fn resolve(&self, base: f64) -> f64 {
+        match self.relative {
+            true => base + self.value,
+            false => self.value,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Argument {
+    Literal,
+    /// The online player's name as they logged in with it
+    Player(String),
+    Integer(i32),
+    Coordinate(Coordinate),
+    Word(String),
+    Message(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ArgumentError {
+    Missing(&'static str),
+    ExpectedLiteral(&'static str),
+    PlayerNotFound(String),
+    InvalidInteger(String),
+    OutOfRange { value: i32, min: i32, max: i32 },
+    InvalidCoordinate(String),
+    TooMany,
+}
+
+impl std::fmt::Display for ArgumentError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Missing(name) => write!(f, "Missing <{name}>"),
+            Self::ExpectedLiteral(literal) => write!(f, "Expected {literal}"),
+            Self::PlayerNotFound(name) => write!(f, "Can't find user {name}."),
+            Self::InvalidInteger(token) => write!(f, "{token} is not a number"),
+            Self::OutOfRange { value, min, max } => write!(f, "{value} must be between {min} and {max}"),
+            Self::InvalidCoordinate(token) => write!(f, "{token} is not a coordinate"),
+            Self::TooMany => write!(f, "Too many arguments"),
+        }
+    }
+}
+
+impl std::error::Error for ArgumentError {}
+
+/// The arguments a command was run with, by the name they were given in its syntax
+#[derive(Debug, Clone, Default)]
+pub struct ParsedArguments {
+    values: HashMap<&'static str, Argument>,
+}
+
+impl ParsedArguments {
+    /// Parse the tokens of a line against a syntax, a Message takes everything from its token on
+    pub fn parse(syntax: &[ArgumentSpec], tokens: &[&str], server: &GameServer) -> Result<Self, ArgumentError> {
+        let mut values = HashMap::default();
+        let mut index = 0;
+        for spec in syntax {
+            let Some(&token) = tokens.get(index) else {
+                if spec.optional {
+                    continue;
+                }
+                return Err(ArgumentError::Missing(spec.name));
+            };
+            let argument = match spec.kind {
+                ArgumentType::Message => {
+                    let rest = tokens[index..].join(" ");
+                    index = tokens.len();
+                    spec.parse(&rest, server)?
+                },
+                _ => {
+                    index += 1;
+                    spec.parse(token, server)?
+                },
+            };
+            values.insert(spec.name, argument);
+        }
+        if index < tokens.len() {
+            return Err(ArgumentError::TooMany);
+        }
+        Ok(Self { values })
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Argument> {
+        self.values.get(name)
+    }
+
+    pub fn player(&self, name: &str) -> Option<&str> {
+        match self.values.get(name) {
+            Some(Argument::Player(player)) => Some(player),
+            _ => None,
+        }
+    }
+
+    pub fn integer(&self, name: &str) -> Option<i32> {
+        match self.values.get(name) {
+            Some(Argument::Integer(value)) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn coordinate(&self, name: &str) -> Option<Coordinate> {
+        match self.values.get(name) {
+            Some(Argument::Coordinate(coordinate)) => Some(*coordinate),
+            _ => None,
+        }
+    }
+
+    pub fn word(&self, name: &str) -> Option<&str> {
+        match self.values.get(name) {
+            Some(Argument::Word(word)) | Some(Argument::Message(word)) => Some(word),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ArgumentError, Coordinate};
+
+    #[test]
+    fn parses_coordinates() {
+        assert_eq!(Coordinate::parse("12"), Ok(Coordinate { relative: false, value: 12.0 }));
+        assert_eq!(Coordinate::parse("-3.5"), Ok(Coordinate { relative: false, value: -3.5 }));
+        assert_eq!(Coordinate::parse("~"), Ok(Coordinate { relative: true, value: 0.0 }));
+        assert_eq!(Coordinate::parse("~-2"), Ok(Coordinate { relative: true, value: -2.0 }));
+        assert_eq!(Coordinate::parse("~x"), Err(ArgumentError::InvalidCoordinate(String::from("~x"))));
+        assert!(Coordinate::parse("NaN").is_err());
+        assert!(Coordinate::parse("~NaN").is_err());
+        assert!(Coordinate::parse("~inf").is_err());
+        assert!(Coordinate::parse("~-inf").is_err());
+
+        assert_eq!(Coordinate::parse("~5").unwrap().resolve(10.5), 15.5);
+        assert_eq!(Coordinate::parse("5").unwrap().resolve(10.5), 5.0);
+    }
+}
@@ -0,0 +1,266 @@
+use std::io;
+use std::net::IpAddr;
+
+use ultraviolet::DVec3;
+
+use super::arguments::{ArgumentSpec, ParsedArguments};
+use super::{Command, CommandContext, CommandError, CommandResult, PermissionLevel};
+use crate::server::access::AccessLists;
+use crate::server::inventory::{is_known_item, MAX_STACK_SIZE};
+
+/// Every command a server has out of the box
+pub fn commands() -> Vec<Command> {
+    vec![
+        Command::new("help", "Lists the commands you can use", PermissionLevel::Player)
+            .alias("?")
+            .syntax(vec![], help),
+        Command::new("list", "Lists the players that are online", PermissionLevel::Player)
+            .syntax(vec![], list),
+        Command::new("tp", "Teleports a player to another player or to coordinates", PermissionLevel::Operator)
+            .alias("teleport")
+            .syntax(vec![ArgumentSpec::player("player"), ArgumentSpec::player("target")], teleport_to_player)
+            .syntax(vec![ArgumentSpec::player("player"), ArgumentSpec::coordinate("x"), ArgumentSpec::coordinate("y"), ArgumentSpec::coordinate("z")], teleport_to_position)
+            .syntax(vec![ArgumentSpec::coordinate("x"), ArgumentSpec::coordinate("y"), ArgumentSpec::coordinate("z")], teleport_to_position),
+        Command::new("time", "Sets or advances the time of day", PermissionLevel::Operator)
+            .syntax(vec![ArgumentSpec::literal("set"), ArgumentSpec::integer("value", 0, i32::MAX)], time_set)
+            .syntax(vec![ArgumentSpec::literal("add"), ArgumentSpec::integer("value", 0, i32::MAX)], time_add),
+        Command::new("give", "Gives a player an item", PermissionLevel::Operator)
+            .syntax(vec![
+                ArgumentSpec::player("player"),
+                ArgumentSpec::integer("id", 1, i16::MAX as i32),
+                ArgumentSpec::integer("amount", 1, MAX_STACK_SIZE as i32).optional(),
+                ArgumentSpec::integer("damage", 0, i16::MAX as i32).optional(),
+            ], give),
+        Command::new("kick", "Disconnects a player", PermissionLevel::Operator)
+            .syntax(vec![ArgumentSpec::player("player"), ArgumentSpec::message("reason").optional()], kick),
+        Command::new("save-all", "Saves every changed part of the world", PermissionLevel::Operator)
+            .syntax(vec![], save_all),
+        Command::new("stop", "Saves the world and stops the server", PermissionLevel::Operator)
+            .syntax(vec![], stop),
+        Command::new("op", "Lets a player use operator commands", PermissionLevel::Operator)
+            .syntax(vec![ArgumentSpec::word("player")], op),
+        Command::new("deop", "Takes operator commands away from a player", PermissionLevel::Operator)
+            .syntax(vec![ArgumentSpec::word("player")], deop),
+        Command::new("ban", "Bans a player by name", PermissionLevel::Operator)
+            .syntax(vec![ArgumentSpec::word("player")], ban),
+        Command::new("pardon", "Lifts a player's ban", PermissionLevel::Operator)
+            .syntax(vec![ArgumentSpec::word("player")], pardon),
+        Command::new("ban-ip", "Bans an address, or the address an online player connected from", PermissionLevel::Operator)
+            .syntax(vec![ArgumentSpec::word("address")], ban_ip),
+        Command::new("pardon-ip", "Lifts an address's ban", PermissionLevel::Operator)
+            .syntax(vec![ArgumentSpec::word("address")], pardon_ip),
+        Command::new("whitelist", "Manages who may join while the whitelist is on", PermissionLevel::Operator)
+            .syntax(vec![ArgumentSpec::literal("add"), ArgumentSpec::word("player")], whitelist_add)
+            .syntax(vec![ArgumentSpec::literal("remove"), ArgumentSpec::word("player")], whitelist_remove)
+            .syntax(vec![ArgumentSpec::literal("list")], whitelist_list)
+            .syntax(vec![ArgumentSpec::literal("on")], |context, _| whitelist_toggle(context, true))
+            .syntax(vec![ArgumentSpec::literal("off")], |context, _| whitelist_toggle(context, false))
+            .syntax(vec![ArgumentSpec::literal("reload")], whitelist_reload),
+    ]
+}
+
+fn help(context: &mut CommandContext, _: &ParsedArguments) -> CommandResult {
+    let commands = context.server.commands().commands().iter()
+        .filter(|command| context.permission >= command.permission)
+        .flat_map(|command| command.usage().into_iter().map(move |usage| format!("§7{usage} - {}", command.description)))
+        .collect::<Vec<String>>();
+    for line in commands {
+        context.reply(line);
+    }
+    Ok(())
+}
+
+fn list(context: &mut CommandContext, _: &ParsedArguments) -> CommandResult {
+    let names: Vec<&str> = context.server.players().players().iter().map(|player| player.player.username.as_str()).collect();
+    let message = format!("§7Connected players: {}", names.join(", "));
+    context.reply(message);
+    Ok(())
+}
+
+/// The entity id of an online player named in the arguments
+fn player_argument(context: &CommandContext, arguments: &ParsedArguments, name: &str) -> Result<i32, CommandError> {
+    arguments.player(name)
+        .and_then(|username| context.server.players().find_by_name(username))
+        .map(|player| player.player.entity_id)
+        .ok_or_else(|| CommandError::Failed(format!("Can't find user {}.", arguments.player(name).unwrap_or_default())))
+}
+
+fn teleport(context: &mut CommandContext, entity_id: i32, position: DVec3, destination: &str) -> CommandResult {
+    let username = context.server.players().find_by_entity(entity_id).map(|player| player.player.username.clone()).unwrap_or_default();
+    context.server.teleport_player(entity_id, position);
+    context.reply(format!("§7Teleported {username} to {destination}"));
+    Ok(())
+}
+
+fn teleport_to_player(context: &mut CommandContext, arguments: &ParsedArguments) -> CommandResult {
+    let player = player_argument(context, arguments, "player")?;
+    let target = player_argument(context, arguments, "target")?;
+    let Some(target) = context.server.players().find_by_entity(target) else { return Err(CommandError::Failed(String::from("Target went offline"))); };
+    let (position, name) = (target.player.position, target.player.username.clone());
+    teleport(context, player, position, &name)
+}
+
+/// Teleports the named player, or whoever ran the command, relative coordinates are from the teleported player
+fn teleport_to_position(context: &mut CommandContext, arguments: &ParsedArguments) -> CommandResult {
+    let player = match arguments.player("player") {
+        Some(_) => player_argument(context, arguments, "player")?,
+        None => context.player_id()?,
+    };
+    let base = context.server.players().find_by_entity(player).map(|player| player.player.position).unwrap_or_else(DVec3::zero);
+    let coordinate = |name: &str, base: f64| arguments.coordinate(name).map_or(base, |coordinate| coordinate.resolve(base));
+    let position = DVec3::new(coordinate("x", base.x), coordinate("y", base.y), coordinate("z", base.z));
+    teleport(context, player, position, &format!("{:.2}, {:.2}, {:.2}", position.x, position.y, position.z))
+}
+
+fn time_set(context: &mut CommandContext, arguments: &ParsedArguments) -> CommandResult {
+    let time = arguments.integer("value").unwrap_or_default() as u64;
+    context.server.set_time(time);
+    context.reply(format!("§7Set the time to {time}"));
+    Ok(())
+}
+
+fn time_add(context: &mut CommandContext, arguments: &ParsedArguments) -> CommandResult {
+    let time = context.server.world().time() + arguments.integer("value").unwrap_or_default() as u64;
+    context.server.set_time(time);
+    context.reply(format!("§7Set the time to {time}"));
+    Ok(())
+}
+
+fn give(context: &mut CommandContext, arguments: &ParsedArguments) -> CommandResult {
+    let player = player_argument(context, arguments, "player")?;
+    let id = arguments.integer("id").unwrap_or_default() as i16;
+    if !is_known_item(id) {
+        return Err(CommandError::Failed(format!("There's no item with id {id}")));
+    }
+    let amount = arguments.integer("amount").unwrap_or(1) as u32;
+    let damage = arguments.integer("damage").unwrap_or(0) as i16;
+    let given = context.server.give_item(player, id, amount, damage);
+    let username = context.server.players().find_by_entity(player).map(|player| player.player.username.clone()).unwrap_or_default();
+    match given {
+        0 => Err(CommandError::Failed(format!("{username}'s inventory is full"))),
+        given => {
+            context.reply(format!("§7Giving {username} {given} of {id}"));
+            Ok(())
+        },
+    }
+}
+
+fn kick(context: &mut CommandContext, arguments: &ParsedArguments) -> CommandResult {
+    let player = player_argument(context, arguments, "player")?;
+    let reason = arguments.word("reason").unwrap_or("Kicked by admin").to_string();
+    let username = context.server.players().find_by_entity(player).map(|player| player.player.username.clone()).unwrap_or_default();
+    context.server.kick_player(player, &reason);
+    context.reply(format!("§7Kicked {username}"));
+    Ok(())
+}
+
+fn save_all(context: &mut CommandContext, _: &ParsedArguments) -> CommandResult {
+    context.reply("§7Forcing save..");
+    match context.server.world_mut().save_all() {
+        Ok(_) => {
+            context.reply("§7Save complete.");
+            Ok(())
+        },
+        Err(e) => Err(CommandError::Failed(format!("Failed to save the world: {e}"))),
+    }
+}
+
+fn stop(context: &mut CommandContext, _: &ParsedArguments) -> CommandResult {
+    context.reply("§7Stopping the server..");
+    context.server.stop();
+    Ok(())
+}
+
+/// Change the access lists and apply them to online players, failing with `unchanged` if nothing changed
+fn edit_access(context: &mut CommandContext, edit: impl FnOnce(&mut AccessLists) -> io::Result<bool>, unchanged: String) -> CommandResult {
+    let edited = edit(&mut context.server.access_lists().lock().unwrap());
+    match edited {
+        Ok(true) => {
+            context.server.apply_access_lists();
+            Ok(())
+        },
+        Ok(false) => Err(CommandError::Failed(unchanged)),
+        Err(e) => Err(CommandError::Failed(format!("Failed to save: {e}"))),
+    }
+}
+
+fn op(context: &mut CommandContext, arguments: &ParsedArguments) -> CommandResult {
+    let name = arguments.word("player").unwrap_or_default().to_string();
+    edit_access(context, |access| access.ops.add(&name), format!("{name} is already an operator"))?;
+    context.reply(format!("§7Opping {name}"));
+    Ok(())
+}
+
+fn deop(context: &mut CommandContext, arguments: &ParsedArguments) -> CommandResult {
+    let name = arguments.word("player").unwrap_or_default().to_string();
+    edit_access(context, |access| access.ops.remove(&name), format!("{name} is not an operator"))?;
+    context.reply(format!("§7De-opping {name}"));
+    Ok(())
+}
+
+fn ban(context: &mut CommandContext, arguments: &ParsedArguments) -> CommandResult {
+    let name = arguments.word("player").unwrap_or_default().to_string();
+    edit_access(context, |access| access.banned_players.add(&name), format!("{name} is already banned"))?;
+    context.reply(format!("§7Banning {name}"));
+    Ok(())
+}
+
+fn pardon(context: &mut CommandContext, arguments: &ParsedArguments) -> CommandResult {
+    let name = arguments.word("player").unwrap_or_default().to_string();
+    edit_access(context, |access| access.banned_players.remove(&name), format!("{name} is not banned"))?;
+    context.reply(format!("§7Pardoning {name}"));
+    Ok(())
+}
+
+fn ban_ip(context: &mut CommandContext, arguments: &ParsedArguments) -> CommandResult {
+    let target = arguments.word("address").unwrap_or_default();
+    let address = match target.parse::<IpAddr>() {
+        Ok(address) => address,
+        Err(_) => context.server.players().find_by_name(target)
+            .and_then(|player| player.player.address)
+            .ok_or_else(|| CommandError::Failed(format!("{target} is neither an address nor an online player")))?,
+    };
+    let address = address.to_string();
+    edit_access(context, |access| access.banned_ips.add(&address), format!("{address} is already banned"))?;
+    context.reply(format!("§7Banning ip {address}"));
+    Ok(())
+}
+
+fn pardon_ip(context: &mut CommandContext, arguments: &ParsedArguments) -> CommandResult {
+    let address = arguments.word("address").unwrap_or_default().to_string();
+    edit_access(context, |access| access.banned_ips.remove(&address), format!("{address} is not banned"))?;
+    context.reply(format!("§7Pardoning ip {address}"));
+    Ok(())
+}
+
+fn whitelist_add(context: &mut CommandContext, arguments: &ParsedArguments) -> CommandResult {
+    let name = arguments.word("player").unwrap_or_default().to_string();
+    edit_access(context, |access| access.whitelist.add(&name), format!("{name} is already white-listed"))?;
+    context.reply(format!("§7Added {name} to white-list"));
+    Ok(())
+}
+
+fn whitelist_remove(context: &mut CommandContext, arguments: &ParsedArguments) -> CommandResult {
+    let name = arguments.word("player").unwrap_or_default().to_string();
+    edit_access(context, |access| access.whitelist.remove(&name), format!("{name} is not white-listed"))?;
+    context.reply(format!("§7Removed {name} from white-list"));
+    Ok(())
+}
+
+fn whitelist_list(context: &mut CommandContext, _: &ParsedArguments) -> CommandResult {
+    let names = context.server.access_lists().lock().unwrap().whitelist.entries().collect::<Vec<&str>>().join(" ");
+    context.reply(format!("§7White-listed players: {names}"));
+    Ok(())
+}
+
+fn whitelist_toggle(context: &mut CommandContext, enabled: bool) -> CommandResult {
+    edit_access(context, |access| access.set_whitelist_enabled(enabled).map(|_| true), String::new())?;
+    context.reply(format!("§7Turned {} the white-list", if enabled { "on" } else { "off" }));
+    Ok(())
+}
+
+fn whitelist_reload(context: &mut CommandContext, _: &ParsedArguments) -> CommandResult {
+    edit_access(context, |access| access.whitelist.reload().map(|_| true), String::new())?;
+    context.reply("§7Reloaded white-list from file");
+    Ok(())
+}
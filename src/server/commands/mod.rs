@@ -0,0 +1,344 @@
+use ultraviolet::DVec3;
+
+use super::game_server::GameServer;
+
+pub mod arguments;
+pub mod builtin;
+
+use arguments::{ArgumentError, ArgumentSpec, ParsedArguments};
+
+/// Who may run a command, each level may run everything the levels below it can
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum PermissionLevel {
+    Player,
+    Operator,
+    /// The server console, which can run everything
+    Console,
+}
+
+/// Where a command came from
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CommandSource {
+    Console,
+    /// A player, by their entity id
+    Player(i32),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CommandError {
+    UnknownCommand(String),
+    NoPermission,
+    /// None of the command's syntaxes matched, holds the usage lines
+    Usage(Vec<String>),
+    Argument(ArgumentError),
+    /// The command only makes sense when run by a player
+    PlayerOnly,
+    Failed(String),
+}
+
+impl std::fmt::Display for CommandError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnknownCommand(_) => write!(f, "Unknown command. Type \"help\" for help."),
+            Self::NoPermission => write!(f, "You do not have permission to use this command."),
+            Self::Usage(usage) => write!(f, "Usage: {}", usage.join(" or ")),
+            Self::Argument(e) => write!(f, "{e}"),
+            Self::PlayerOnly => write!(f, "Only players can use this command."),
+            Self::Failed(message) => write!(f, "{message}"),
+        }
+    }
+}
+
+impl std::error::Error for CommandError {}
+
+impl From<ArgumentError> for CommandError {
+    fn from(e: ArgumentError) -> Self {
+        Self::Argument(e)
+    }
+}
+
+pub type CommandResult = Result<(), CommandError>;
+pub type CommandExecutor = fn(&mut CommandContext, &ParsedArguments) -> CommandResult;
+
+/// The server and who is running the command, with the replies to send back to them
+pub struct CommandContext<'a> {
+    pub server: &'a mut GameServer,
+    pub source: CommandSource,
+    pub permission: PermissionLevel,
+    replies: Vec<String>,
+}
+
+impl<'a> CommandContext<'a> {
+    pub fn new(server: &'a mut GameServer, source: CommandSource, permission: PermissionLevel) -> Self {
+        Self { server, source, permission, replies: vec![] }
+    }
+
+    /// Send a message back to whoever ran the command, § colour codes are kept as they are
+    pub fn reply(&mut self, message: impl Into<String>) {
+        self.replies.push(message.into());
+    }
+
+    pub fn replies(&self) -> &[String] {
+        &self.replies
+    }
+
+    /// The entity id of the player running the command
+    pub fn player_id(&self) -> Result<i32, CommandError> {
+        match self.source {
+            CommandSource::Player(entity_id) => Ok(entity_id),
+            CommandSource::Console => Err(CommandError::PlayerOnly),
+        }
+    }
+
+    /// Where the player running the command is, what `~` coordinates are relative to
+    pub fn position(&self) -> Option<DVec3> {
+        let entity_id = self.player_id().ok()?;
+        self.server.players().find_by_entity(entity_id).map(|player| player.player.position)
+    }
+}
+
+/// One way of calling a command, the arguments it takes and what runs when they parse
+#[derive(Clone)]
+pub struct CommandSyntax {
+    pub arguments: Vec<ArgumentSpec>,
+    executor: CommandExecutor,
+}
+
+impl CommandSyntax {
+    /// Whether a line with this many arguments could be this syntax
+    fn accepts_count(&self, count: usize) -> bool {
+        let required = self.arguments.iter().filter(|spec| !spec.optional).count();
+        let unbounded = self.arguments.iter().any(|spec| spec.kind == arguments::ArgumentType::Message);
+        count >= required && (unbounded || count <= self.arguments.len())
+    }
+}
+
+/** A command, with the syntaxes it can be called with
+ *  Syntaxes are tried in order and the first whose arguments parse runs
+ */
+#[derive(Clone)]
+pub struct Command {
+    pub name: &'static str,
+    pub aliases: Vec<&'static str>,
+    pub description: &'static str,
+    pub permission: PermissionLevel,
+    pub syntaxes: Vec<CommandSyntax>,
+}
+
+impl Command {
+    pub fn new(name: &'static str, description: &'static str, permission: PermissionLevel) -> Self {
+        Self { name, aliases: vec![], description, permission, syntaxes: vec![] }
+    }
+
+    pub fn alias(mut self, alias: &'static str) -> Self {
+        self.aliases.push(alias);
+        self
+    }
+
+    pub fn syntax(mut self, arguments: Vec<ArgumentSpec>, executor: CommandExecutor) -> Self {
+        self.syntaxes.push(CommandSyntax { arguments, executor });
+        self
+    }
+
+    pub fn is_called(&self, name: &str) -> bool {
+        self.name.eq_ignore_ascii_case(name) || self.aliases.iter().any(|alias| alias.eq_ignore_ascii_case(name))
+    }
+
+    /// A usage line for every syntax, like `/time set <value>`
+    pub fn usage(&self) -> Vec<String> {
+        self.syntaxes.iter().map(|syntax| {
+            let arguments: Vec<String> = syntax.arguments.iter().map(ArgumentSpec::usage).collect();
+            match arguments.is_empty() {
+                true => format!("/{}", self.name),
+                false => format!("/{} {}", self.name, arguments.join(" ")),
+            }
+        }).collect()
+    }
+}
+
+/// Finds, parses and runs commands typed in chat or the console
+#[derive(Clone, Default)]
+pub struct CommandDispatcher {
+    commands: Vec<Command>,
+}
+
+impl CommandDispatcher {
+    /// A dispatcher without any commands
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A dispatcher with every built in command
+    pub fn with_builtins() -> Self {
+        let mut dispatcher = Self::new();
+        for command in builtin::commands() {
+            dispatcher.register(command);
+        }
+        dispatcher
+    }
+
+    /// Add a command, replacing any with the same name
+    pub fn register(&mut self, command: Command) {
+        self.commands.retain(|existing| existing.name != command.name);
+        self.commands.push(command);
+    }
+
+    pub fn commands(&self) -> &[Command] {
+        &self.commands
+    }
+
+    pub fn find(&self, name: &str) -> Option<&Command> {
+        self.commands.iter().find(|command| command.is_called(name))
+    }
+
+    /// Run a command line, with or without its leading slash, replies are left in the context
+    pub fn execute(&self, context: &mut CommandContext, line: &str) -> CommandResult {
+        let line = line.trim().trim_start_matches('/');
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let Some((&name, arguments)) = tokens.split_first() else {
+            return Err(CommandError::UnknownCommand(String::new()));
+        };
+        let command = self.find(name).ok_or_else(|| CommandError::UnknownCommand(name.to_string()))?;
+        if context.permission < command.permission {
+            return Err(CommandError::NoPermission);
+        }
+
+        // Literals and the number of arguments pick the syntax, the first error past that is the most useful one to report
+        let mut error = None;
+        for syntax in &command.syntaxes {
+            match ParsedArguments::parse(&syntax.arguments, arguments, context.server) {
+                Ok(parsed) => { return (syntax.executor)(context, &parsed); },
+                Err(ArgumentError::ExpectedLiteral(_)) => {},
+                Err(e) if syntax.accepts_count(arguments.len()) => { error.get_or_insert(e); },
+                Err(_) => {},
+            }
+        }
+        Err(error.map(CommandError::Argument).unwrap_or_else(|| CommandError::Usage(command.usage())))
+    }
+
+    /// Run a command line, turning the outcome into chat messages for whoever ran it
+    pub fn dispatch(&self, server: &mut GameServer, source: CommandSource, permission: PermissionLevel, line: &str) -> Vec<String> {
+        let mut context = CommandContext::new(server, source, permission);
+        let result = self.execute(&mut context, line);
+        let mut replies = context.replies;
+        if let Err(e) = result {
+            replies.push(format!("§c{e}"));
+        }
+        replies
+    }
+
+    /** Completions for the last word of a partially typed line
+     *  The first word completes to command names, later words to whatever the argument at that position accepts
+     */
+    pub fn complete(&self, server: &GameServer, permission: PermissionLevel, line: &str) -> Vec<String> {
+        let line = line.trim_start().trim_start_matches('/');
+        let mut tokens: Vec<&str> = line.split_whitespace().collect();
+        if line.is_empty() || line.ends_with(char::is_whitespace) {
+            tokens.push("");
+        }
+        let usable = |command: &&Command| permission >= command.permission;
+        let Some((&name, arguments)) = tokens.split_first() else { return vec![]; };
+        if arguments.is_empty() {
+            let mut names: Vec<String> = self.commands.iter().filter(usable)
+                .filter(|command| command.name.starts_with(&name.to_lowercase()))
+                .map(|command| command.name.to_string())
+                .collect();
+            names.sort();
+            return names;
+        }
+
+        let Some(command) = self.find(name).filter(usable) else { return vec![]; };
+        let (typed, current) = arguments.split_at(arguments.len() - 1);
+        let mut completions = vec![];
+        for syntax in &command.syntaxes {
+            let literals_match = syntax.arguments.iter().zip(typed).all(|(spec, token)| match spec.kind {
+                arguments::ArgumentType::Literal(literal) => literal.eq_ignore_ascii_case(token),
+                _ => true,
+            });
+            let Some(spec) = syntax.arguments.get(typed.len()).filter(|_| literals_match) else { continue; };
+            for suggestion in spec.kind.suggestions(server, current[0]) {
+                if !completions.contains(&suggestion) {
+                    completions.push(suggestion);
+                }
+            }
+        }
+        completions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ultraviolet::IVec3;
+
+    use super::arguments::ArgumentSpec;
+    use super::{Command, CommandContext, CommandDispatcher, CommandError, CommandSource, PermissionLevel};
+    use crate::server::game_server::GameServer;
+    use crate::server::generator::FlatGenerator;
+    use crate::server::login::ServerLogin;
+    use crate::server::world::ServerWorld;
+
+    fn test_server() -> GameServer {
+        GameServer::new(ServerWorld::new(Box::new(FlatGenerator::default()), 0, IVec3::new(0, 7, 0)), ServerLogin::offline())
+    }
+
+    fn echo() -> Command {
+        Command::new("echo", "Repeats a number or a message", PermissionLevel::Player)
+            .syntax(vec![ArgumentSpec::literal("number"), ArgumentSpec::integer("value", 0, 10), ArgumentSpec::integer("times", 1, 3).optional()], |context, arguments| {
+                let times = arguments.integer("times").unwrap_or(1);
+                for _ in 0..times {
+                    let value = arguments.integer("value").unwrap();
+                    context.reply(format!("{value}"));
+                }
+                Ok(())
+            })
+            .syntax(vec![ArgumentSpec::literal("say"), ArgumentSpec::message("message")], |context, arguments| {
+                context.reply(arguments.word("message").unwrap().to_string());
+                Ok(())
+            })
+    }
+
+    fn run(dispatcher: &CommandDispatcher, server: &mut GameServer, permission: PermissionLevel, line: &str) -> Result<Vec<String>, CommandError> {
+        let mut context = CommandContext::new(server, CommandSource::Console, permission);
+        dispatcher.execute(&mut context, line).map(|_| context.replies().to_vec())
+    }
+
+    #[test]
+    fn picks_syntax_by_literal() {
+        let mut dispatcher = CommandDispatcher::new();
+        dispatcher.register(echo());
+        let mut server = test_server();
+
+        assert_eq!(run(&dispatcher, &mut server, PermissionLevel::Player, "/echo number 4 2"), Ok(vec![String::from("4"), String::from("4")]));
+        assert_eq!(run(&dispatcher, &mut server, PermissionLevel::Player, "ECHO say  hello   there"), Ok(vec![String::from("hello there")]));
+        assert!(matches!(run(&dispatcher, &mut server, PermissionLevel::Player, "/echo number 11"), Err(CommandError::Argument(_))));
+        assert_eq!(
+            run(&dispatcher, &mut server, PermissionLevel::Player, "/echo shout"),
+            Err(CommandError::Usage(vec![String::from("/echo number <value> [times]"), String::from("/echo say <message>")])),
+        );
+        assert_eq!(run(&dispatcher, &mut server, PermissionLevel::Player, "/nope"), Err(CommandError::UnknownCommand(String::from("nope"))));
+    }
+
+    #[test]
+    fn checks_permissions() {
+        let dispatcher = CommandDispatcher::with_builtins();
+        let mut server = test_server();
+        let replies = dispatcher.dispatch(&mut server, CommandSource::Console, PermissionLevel::Player, "/time set 100");
+        assert_eq!(replies, ["§cYou do not have permission to use this command."]);
+        assert_eq!(server.world().time(), 0);
+
+        let replies = dispatcher.dispatch(&mut server, CommandSource::Console, PermissionLevel::Operator, "/time set 100");
+        assert_eq!(replies, ["§7Set the time to 100"]);
+        assert_eq!(server.world().time(), 100);
+    }
+
+    #[test]
+    fn completes_names_and_arguments() {
+        let dispatcher = CommandDispatcher::with_builtins();
+        let server = test_server();
+        assert_eq!(dispatcher.complete(&server, PermissionLevel::Operator, "/ti"), ["time"]);
+        assert_eq!(dispatcher.complete(&server, PermissionLevel::Player, "/"), ["help", "list"]);
+        assert_eq!(dispatcher.complete(&server, PermissionLevel::Operator, "/time "), ["set", "add"]);
+        assert_eq!(dispatcher.complete(&server, PermissionLevel::Operator, "/time s"), ["set"]);
+        assert!(dispatcher.complete(&server, PermissionLevel::Player, "/time ").is_empty());
+    }
+}
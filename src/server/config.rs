@@ -0,0 +1,217 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use super::properties::{Properties, PropertiesError};
+
+pub const SERVER_PROPERTIES_FILE: &str = "server.properties";
+const PROPERTIES_HEADER: &str = "Minecraft server properties";
+const KNOWN_KEYS: [&str; 10] = [
+    "server-ip", "server-port", "motd", "max-players", "view-distance",
+    "online-mode", "level-name", "level-seed", "spawn-protection", "white-list",
+];
+
+/** The settings of a server, read from and written to a vanilla server.properties
+ *  Keys this does not know about are kept as they were, so tools and plugins can store their own settings there
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerConfig {
+    /// Address to listen on, empty for every interface
+    pub server_ip: String,
+    pub port: u16,
+    pub motd: String,
+    pub max_players: u32,
+    /// Columns sent around players in every direction
+    pub view_distance: i32,
+    pub online_mode: bool,
+    /// Directory of the level, relative to the server's directory
+    pub level_name: String,
+    /// A number, or any text which is hashed the way Java hashes strings, empty for a random seed
+    pub level_seed: String,
+    /// Blocks around spawn that only operators may change, 0 to let everyone build there
+    pub spawn_protection: u32,
+    pub white_list: bool,
+    properties: Properties,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            server_ip: String::new(),
+            port: 25565,
+            motd: String::from("A Minecraft Server"),
+            max_players: 20,
+            view_distance: 10,
+            online_mode: true,
+            level_name: String::from("world"),
+            level_seed: String::new(),
+            spawn_protection: 16,
+            white_list: false,
+            properties: Properties::new(),
+        }
+    }
+}
+
+impl ServerConfig {
+    /// Take every known key from `properties`, invalid values are logged and replaced by their default
+    pub fn from_properties(properties: Properties) -> Self {
+        let defaults = Self::default();
+        fn parse<T: FromStr>(properties: &Properties, key: &str, default: T) -> T {
+            match properties.get(key).map(|value| value.trim().parse()) {
+                Some(Ok(value)) => value,
+                Some(Err(_)) => {
+                    log::warn!("Invalid value for {key} in {SERVER_PROPERTIES_FILE}: {}", properties.get(key).unwrap_or_default());
+                    default
+                },
+                None => default,
+            }
+        }
+        let text = |key: &str, default: String| properties.get(key).map(str::to_string).unwrap_or(default);
+        Self {
+            server_ip: text("server-ip", defaults.server_ip),
+            port: parse(&properties, "server-port", defaults.port),
+            motd: text("motd", defaults.motd),
+            max_players: parse(&properties, "max-players", defaults.max_players),
+            view_distance: parse(&properties, "view-distance", defaults.view_distance),
+            online_mode: parse(&properties, "online-mode", defaults.online_mode),
+            level_name: text("level-name", defaults.level_name),
+            level_seed: text("level-seed", defaults.level_seed),
+            spawn_protection: parse(&properties, "spawn-protection", defaults.spawn_protection),
+            white_list: parse(&properties, "white-list", defaults.white_list),
+            properties,
+        }
+    }
+
+    /// Every key of the file this was read from, updated with the values set here
+    pub fn to_properties(&self) -> Properties {
+        let mut properties = self.properties.clone();
+        properties.set("server-ip", &self.server_ip);
+        properties.set("server-port", &self.port.to_string());
+        properties.set("motd", &self.motd);
+        properties.set("max-players", &self.max_players.to_string());
+        properties.set("view-distance", &self.view_distance.to_string());
+        properties.set("online-mode", &self.online_mode.to_string());
+        properties.set("level-name", &self.level_name);
+        properties.set("level-seed", &self.level_seed);
+        properties.set("spawn-protection", &self.spawn_protection.to_string());
+        properties.set("white-list", &self.white_list.to_string());
+        properties
+    }
+
+    /// Keys in the file that are not settings known here
+    pub fn other_properties(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.properties.iter().filter(|(key, _)| !KNOWN_KEYS.contains(key))
+    }
+
+    /** Read a server.properties, or write one with the defaults if there is none
+     *  Like vanilla, missing keys are added to the file with their default values
+     */
+    pub fn load(path: &Path) -> Result<Self, PropertiesError> {
+        if !path.exists() {
+            log::info!("Generating new properties file");
+            let config = Self::default();
+            config.save(path)?;
+            return Ok(config);
+        }
+        let properties = Properties::load(path)?;
+        let config = Self::from_properties(properties);
+        if KNOWN_KEYS.iter().any(|key| !config.properties.contains_key(key)) {
+            config.save(path)?;
+        }
+        Ok(config)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        self.to_properties().save(path, Some(PROPERTIES_HEADER))
+    }
+
+    pub fn level_directory(&self, server_directory: &Path) -> PathBuf {
+        server_directory.join(&self.level_name)
+    }
+
+    /// The world seed, a number is used as is, other text is hashed and an empty seed picks one at random
+    pub fn seed(&self) -> i64 {
+        let seed = self.level_seed.trim();
+        if seed.is_empty() {
+            use std::hash::{BuildHasher, Hasher};
+            let mut hasher = std::collections::hash_map::RandomState::new().build_hasher();
+            hasher.write_u128(std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap_or_default().as_nanos());
+            return hasher.finish() as i64;
+        }
+        seed.parse().unwrap_or_else(|_| java_string_hash(seed) as i64)
+    }
+}
+
+/// Java's String.hashCode, which vanilla uses to turn text seeds into numbers
+fn java_string_hash(text: &str) -> i32 {
+    text.encode_utf16().fold(0i32, |hash, unit| hash.wrapping_mul(31).wrapping_add(unit as i32))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{java_string_hash, ServerConfig};
+    use crate::server::properties::Properties;
+    use crate::util::test_directory::TestDirectory;
+
+    #[test]
+    fn reads_known_keys_and_keeps_others() {
+        let properties = Properties::parse(concat!(
+            "#Minecraft server properties\n",
+            "level-name=survival\n",
+            "server-port=25570\n",
+            "motd=\\u00A7aHello\n",
+            "online-mode=false\n",
+            "max-players=lots\n",
+            "spawn-monsters=true\n",
+            "level-seed=-42\n",
+        )).unwrap();
+        let config = ServerConfig::from_properties(properties);
+        assert_eq!(config.level_name, "survival");
+        assert_eq!(config.port, 25570);
+        assert_eq!(config.motd, "§aHello");
+        assert!(!config.online_mode);
+        assert_eq!(config.max_players, 20);
+        assert_eq!(config.view_distance, 10);
+        assert_eq!(config.seed(), -42);
+        assert_eq!(config.other_properties().collect::<Vec<_>>(), [("spawn-monsters", "true")]);
+
+        let mut config = config;
+        config.view_distance = 6;
+        let written = config.to_properties();
+        let keys: Vec<&str> = written.iter().map(|(key, _)| key).collect();
+        assert_eq!(&keys[..6], ["level-name", "server-port", "motd", "online-mode", "max-players", "spawn-monsters"]);
+        assert_eq!(written.get("spawn-monsters"), Some("true"));
+        assert_eq!(written.get("view-distance"), Some("6"));
+        assert_eq!(written.get("max-players"), Some("20"));
+        assert_eq!(ServerConfig::from_properties(written.clone()).to_properties(), written);
+    }
+
+    #[test]
+    fn generates_missing_file() {
+        let directory = TestDirectory::new("config");
+        let path = directory.join("server.properties");
+
+        let config = ServerConfig::load(&path).unwrap();
+        assert_eq!(config, ServerConfig::default());
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert!(contents.starts_with("#Minecraft server properties\n"));
+        assert!(contents.contains("online-mode=true\n"));
+
+        std::fs::write(&path, "# Edited by hand\nwhite-list=true\ncustom=kept\n").unwrap();
+        let config = ServerConfig::load(&path).unwrap();
+        assert!(config.white_list);
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert!(contents.contains("custom=kept\n") && contents.contains("level-name=world\n"));
+    }
+
+    #[test]
+    fn hashes_text_seeds_like_java() {
+        assert_eq!(java_string_hash(""), 0);
+        assert_eq!(java_string_hash("a"), 97);
+        assert_eq!(java_string_hash("ab"), 3105);
+        assert_eq!(java_string_hash("hello"), 99162322);
+        assert_eq!(java_string_hash("Hello World"), -862545276);
+        let config = ServerConfig { level_seed: String::from("ab"), ..Default::default() };
+        assert_eq!(config.seed(), 3105);
+    }
+}
@@ -0,0 +1,319 @@
+use legion::{Entity, IntoQuery};
+use rustc_hash::{FxHashMap as HashMap, FxHashSet as HashSet};
+use ultraviolet::{IVec3, Vec3};
+
+use crate::entities::{EntityNetworked, EntityTransform};
+use crate::minecraft::prot14::{to_angle_byte, to_fixed_point};
+use crate::packets::prot14::Packet;
+
+/// Ticks between absolute position resends, correcting any drift from rounding on the client
+pub const DEFAULT_ABSOLUTE_INTERVAL: u32 = 400;
+/// How far away, in blocks along x and z, players see other players, the most vanilla tracks them at
+pub const DEFAULT_TRACKING_RANGE: f32 = 512.0;
+
+/// A packet about an entity, to be sent to the client of the player with `viewer` as entity id
+#[derive(Debug, Clone)]
+pub struct TrackerUpdate {
+    pub viewer: i32,
+    pub packet: Packet,
+}
+
+/// What clients were last told about an entity
+#[derive(Debug, Clone)]
+struct TrackedEntity {
+    entity_id: i32,
+    name: String,
+    /// Fixed point position, as the client knows it
+    position: IVec3,
+    yaw: i8,
+    pitch: i8,
+    ticks_since_absolute: u32,
+    /// Players whose clients were sent this entity's spawn, by entity id
+    viewers: HashSet<i32>,
+}
+
+impl TrackedEntity {
+    fn new(networked: &EntityNetworked, position: IVec3, yaw: i8, pitch: i8) -> Self {
+        Self { entity_id: networked.entity_id, name: networked.name.clone(), position, yaw, pitch, ticks_since_absolute: 0, viewers: HashSet::default() }
+    }
+
+    fn spawn_packet(&self) -> Packet {
+        Packet::NamedEntitySpawn {
+            entity: self.entity_id,
+            name: self.name.clone(),
+            x: self.position.x,
+            y: self.position.y,
+            z: self.position.z,
+            rotation: self.yaw,
+            pitch: self.pitch,
+            held_item: 0,
+        }
+    }
+
+    /// The packet that brings clients up to date, None if nothing they can see changed
+    fn movement_packet(&mut self, position: IVec3, yaw: i8, pitch: i8, absolute_interval: u32) -> Option<Packet> {
+        let delta = position - self.position;
+        let moved = delta != IVec3::zero();
+        let turned = yaw != self.yaw || pitch != self.pitch;
+        let fits_byte = |value: i32| (i8::MIN as i32..=i8::MAX as i32).contains(&value);
+        self.ticks_since_absolute += 1;
+        let absolute = self.ticks_since_absolute >= absolute_interval || !(fits_byte(delta.x) && fits_byte(delta.y) && fits_byte(delta.z));
+
+        let entity = self.entity_id;
+        let packet = if absolute {
+            self.ticks_since_absolute = 0;
+            Packet::EntityTeleport { entity, x: position.x, y: position.y, z: position.z, yaw, pitch }
+        } else {
+            let (dx, dy, dz) = (delta.x as i8, delta.y as i8, delta.z as i8);
+            match (moved, turned) {
+                (true, true) => Packet::EntityLookMoveRelative { entity, dx, dy, dz, yaw, pitch },
+                (true, false) => Packet::EntityMoveRelative { entity, dx, dy, dz },
+                (false, true) => Packet::EntityLook { entity, yaw, pitch },
+                (false, false) => { return None; },
+            }
+        };
+        self.position = position;
+        (self.yaw, self.pitch) = (yaw, pitch);
+        Some(packet)
+    }
+}
+
+/** Keeps clients up to date on networked entities, from their EntityTransform
+ *  Every entity is only sent to the players within the tracking range of it, who are its viewers
+ *  Small movements go out as relative moves, larger ones and a periodic resend as teleports,
+ *  entities are spawned for players coming into range and destroyed for those leaving it or when they leave the world
+ */
+pub struct EntityTracker {
+    tracked: HashMap<Entity, TrackedEntity>,
+    absolute_interval: u32,
+    tracking_range: f32,
+}
+
+impl Default for EntityTracker {
+    fn default() -> Self {
+        Self::new(DEFAULT_ABSOLUTE_INTERVAL)
+    }
+}
+
+impl EntityTracker {
+    pub fn new(absolute_interval: u32) -> Self {
+        Self { tracked: HashMap::default(), absolute_interval: absolute_interval.max(1), tracking_range: DEFAULT_TRACKING_RANGE }
+    }
+
+    pub fn with_tracking_range(mut self, tracking_range: f32) -> Self {
+        self.tracking_range = tracking_range;
+        self
+    }
+
+    pub fn tracking_range(&self) -> f32 {
+        self.tracking_range
+    }
+
+    /// The fixed point position and angle bytes clients are sent for a transform
+    pub fn network_transform(transform: &EntityTransform) -> (IVec3, i8, i8) {
+        let position = IVec3::new(
+            to_fixed_point(transform.position.x as f64),
+            to_fixed_point(transform.position.y as f64),
+            to_fixed_point(transform.position.z as f64),
+        );
+        (position, to_angle_byte(transform.rotation.x), to_angle_byte(transform.rotation.y))
+    }
+
+    pub fn is_tracked(&self, entity: Entity) -> bool {
+        self.tracked.contains_key(&entity)
+    }
+
+    /// Whether the player with entity id `viewer` was sent `entity`
+    pub fn is_viewed_by(&self, entity: Entity, viewer: i32) -> bool {
+        self.tracked.get(&entity).is_some_and(|tracked| tracked.viewers.contains(&viewer))
+    }
+
+    fn in_range(&self, viewer: Vec3, position: Vec3) -> bool {
+        (viewer.x - position.x).abs() <= self.tracking_range && (viewer.z - position.z).abs() <= self.tracking_range
+    }
+
+    /** Compare the world against what clients were told, returning the packets that catch them up
+     *  `viewers` are the entity id and position of every player online, a player is never sent their own entity
+     */
+    pub fn tick(&mut self, world: &legion::World, viewers: &[(i32, Vec3)]) -> Vec<TrackerUpdate> {
+        let mut updates = vec![];
+        let mut seen = Vec::with_capacity(self.tracked.len());
+        let mut query = <(Entity, &EntityTransform, &EntityNetworked)>::query();
+        for (entity, transform, networked) in query.iter(world) {
+            seen.push(*entity);
+            let (position, yaw, pitch) = Self::network_transform(transform);
+            let in_range: HashSet<i32> = viewers.iter()
+                .filter(|(viewer, viewer_position)| *viewer != networked.entity_id && self.in_range(*viewer_position, transform.position))
+                .map(|(viewer, _)| *viewer)
+                .collect();
+            let tracked = match self.tracked.get_mut(entity) {
+                Some(tracked) => {
+                    // Viewers that stay in range are sent the movement, it is kept up to date even without any
+                    if let Some(packet) = tracked.movement_packet(position, yaw, pitch, self.absolute_interval) {
+                        for &viewer in tracked.viewers.intersection(&in_range) {
+                            updates.push(TrackerUpdate { viewer, packet: packet.clone() });
+                        }
+                    }
+                    tracked
+                },
+                None => self.tracked.entry(*entity).or_insert(TrackedEntity::new(networked, position, yaw, pitch)),
+            };
+            // Players that went offline have no client left to tell
+            for &viewer in tracked.viewers.difference(&in_range).filter(|viewer| viewers.iter().any(|(online, _)| online == *viewer)) {
+                updates.push(TrackerUpdate { viewer, packet: Packet::DestroyEntity { entity: tracked.entity_id } });
+            }
+            for &viewer in in_range.difference(&tracked.viewers) {
+                updates.push(TrackerUpdate { viewer, packet: tracked.spawn_packet() });
+            }
+            tracked.viewers = in_range;
+        }
+
+        let removed: Vec<Entity> = self.tracked.keys().copied().filter(|entity| !seen.contains(entity)).collect();
+        for entity in removed {
+            if let Some(tracked) = self.tracked.remove(&entity) {
+                for viewer in tracked.viewers.into_iter().filter(|viewer| viewers.iter().any(|(online, _)| online == viewer)) {
+                    updates.push(TrackerUpdate { viewer, packet: Packet::DestroyEntity { entity: tracked.entity_id } });
+                }
+            }
+        }
+        updates
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ultraviolet::Vec3;
+
+    use super::{EntityTracker, TrackerUpdate};
+    use crate::entities::{EntityNetworked, EntityTransform};
+    use crate::packets::prot14::Packet;
+
+    fn spawn(world: &mut legion::World, entity_id: i32, position: Vec3) -> legion::Entity {
+        world.push((EntityTransform { position, rotation: Vec3::zero() }, EntityNetworked { entity_id, name: format!("Player{entity_id}") }))
+    }
+
+    fn move_to(world: &mut legion::World, entity: legion::Entity, position: Vec3, rotation: Vec3) {
+        let mut entry = world.entry(entity).unwrap();
+        *entry.get_component_mut::<EntityTransform>().unwrap() = EntityTransform { position, rotation };
+    }
+
+    /// A single player with entity id 100 standing at the origin, every entity the tests spawn is within their range
+    const VIEWERS: [(i32, Vec3); 1] = [(100, Vec3::new(0.0, 64.0, 0.0))];
+
+    fn packets(updates: Vec<TrackerUpdate>) -> Vec<Packet> {
+        updates.into_iter().map(|update| update.packet).collect()
+    }
+
+    /// The packets each of `viewers` was sent, in the order of `viewers`
+    fn packets_for(updates: &[TrackerUpdate], viewers: &[i32]) -> Vec<Vec<Packet>> {
+        viewers.iter().map(|viewer| updates.iter().filter(|update| update.viewer == *viewer).map(|update| update.packet.clone()).collect()).collect()
+    }
+
+    #[test]
+    fn spawns_and_destroys_entities() {
+        let mut world = legion::World::default();
+        let mut tracker = EntityTracker::default();
+        let entity = spawn(&mut world, 7, Vec3::new(1.5, 64.0, -2.25));
+
+        assert!(matches!(packets(tracker.tick(&world, &VIEWERS)).as_slice(), [Packet::NamedEntitySpawn { entity: 7, name, x: 48, y: 2048, z: -72, rotation: 0, pitch: 0, held_item: 0 }] if name == "Player7"));
+        assert!(tracker.tick(&world, &VIEWERS).is_empty());
+        assert!(tracker.is_viewed_by(entity, 100));
+
+        world.remove(entity);
+        assert!(matches!(packets(tracker.tick(&world, &VIEWERS)).as_slice(), [Packet::DestroyEntity { entity: 7 }]));
+        assert!(!tracker.is_tracked(entity));
+    }
+
+    #[test]
+    fn small_moves_are_relative() {
+        let mut world = legion::World::default();
+        let mut tracker = EntityTracker::default();
+        let entity = spawn(&mut world, 1, Vec3::zero());
+        tracker.tick(&world, &VIEWERS);
+
+        move_to(&mut world, entity, Vec3::new(0.5, -0.25, 3.0), Vec3::zero());
+        assert!(matches!(packets(tracker.tick(&world, &VIEWERS)).as_slice(), [Packet::EntityMoveRelative { entity: 1, dx: 16, dy: -8, dz: 96 }]));
+
+        move_to(&mut world, entity, Vec3::new(0.5, -0.25, 3.0), Vec3::new(90.0, -45.0, 0.0));
+        assert!(matches!(packets(tracker.tick(&world, &VIEWERS)).as_slice(), [Packet::EntityLook { entity: 1, yaw: 64, pitch: -32 }]));
+
+        move_to(&mut world, entity, Vec3::new(1.0, -0.25, 3.0), Vec3::new(180.0, -45.0, 0.0));
+        assert!(matches!(packets(tracker.tick(&world, &VIEWERS)).as_slice(), [Packet::EntityLookMoveRelative { entity: 1, dx: 16, dy: 0, dz: 0, yaw: -128, pitch: -32 }]));
+    }
+
+    #[test]
+    fn large_moves_teleport() {
+        let mut world = legion::World::default();
+        let mut tracker = EntityTracker::default();
+        let entity = spawn(&mut world, 1, Vec3::zero());
+        tracker.tick(&world, &VIEWERS);
+
+        // Up to 127/32 blocks fits in a relative move, 4.5 blocks does not
+        move_to(&mut world, entity, Vec3::new(127.0 / 32.0, 0.0, 0.0), Vec3::zero());
+        assert!(matches!(packets(tracker.tick(&world, &VIEWERS)).as_slice(), [Packet::EntityMoveRelative { entity: 1, dx: 127, dy: 0, dz: 0 }]));
+        move_to(&mut world, entity, Vec3::new(127.0 / 32.0, 0.0, -4.5), Vec3::zero());
+        assert!(matches!(packets(tracker.tick(&world, &VIEWERS)).as_slice(), [Packet::EntityTeleport { entity: 1, x: 127, y: 0, z: -144, yaw: 0, pitch: 0 }]));
+        move_to(&mut world, entity, Vec3::new(100.0, 70.0, 0.0), Vec3::zero());
+        assert!(matches!(packets(tracker.tick(&world, &VIEWERS)).as_slice(), [Packet::EntityTeleport { entity: 1, x: 3200, y: 2240, z: 0, yaw: 0, pitch: 0 }]));
+    }
+
+    #[test]
+    fn resends_absolute_positions() {
+        let mut world = legion::World::default();
+        let mut tracker = EntityTracker::new(3);
+        let entity = spawn(&mut world, 1, Vec3::zero());
+        tracker.tick(&world, &VIEWERS);
+
+        let mut sent = vec![];
+        for step in 1..=6 {
+            move_to(&mut world, entity, Vec3::new(step as f32 * 0.25, 0.0, 0.0), Vec3::zero());
+            sent.extend(packets(tracker.tick(&world, &VIEWERS)));
+        }
+        let teleports = sent.iter().filter(|packet| matches!(packet, Packet::EntityTeleport { .. })).count();
+        assert_eq!(teleports, 2);
+        assert!(matches!(sent[2], Packet::EntityTeleport { x: 24, .. }));
+        assert!(matches!(sent[5], Packet::EntityTeleport { x: 48, .. }));
+    }
+
+    #[test]
+    fn players_see_each_other_in_range() {
+        let mut world = legion::World::default();
+        let mut tracker = EntityTracker::default().with_tracking_range(16.0);
+        let alice = spawn(&mut world, 1, Vec3::zero());
+        let bob = spawn(&mut world, 2, Vec3::new(40.0, 0.0, 0.0));
+        // Alice stands still, returns what she and Bob were sent once he walked to `position`
+        let walk_bob = |world: &mut legion::World, tracker: &mut EntityTracker, position: Vec3| {
+            move_to(world, bob, position, Vec3::zero());
+            let updates = tracker.tick(world, &[(1, Vec3::zero()), (2, position)]);
+            let [to_alice, to_bob] = <[Vec<Packet>; 2]>::try_from(packets_for(&updates, &[1, 2])).unwrap();
+            (to_alice, to_bob)
+        };
+
+        // Too far apart to see each other, and nobody is sent their own entity
+        let (to_alice, to_bob) = walk_bob(&mut world, &mut tracker, Vec3::new(40.0, 0.0, 0.0));
+        assert!(to_alice.is_empty() && to_bob.is_empty());
+
+        // Bob walks up to Alice, both are spawned for the other
+        let (to_alice, to_bob) = walk_bob(&mut world, &mut tracker, Vec3::new(15.0, 0.0, 0.0));
+        assert!(matches!(to_alice.as_slice(), [Packet::NamedEntitySpawn { entity: 2, x: 480, .. }]));
+        assert!(matches!(to_bob.as_slice(), [Packet::NamedEntitySpawn { entity: 1, x: 0, .. }]));
+        assert!(tracker.is_viewed_by(alice, 2) && tracker.is_viewed_by(bob, 1));
+
+        // Moves only go to whoever sees the one moving
+        let (to_alice, to_bob) = walk_bob(&mut world, &mut tracker, Vec3::new(14.0, 0.0, 0.0));
+        assert!(matches!(to_alice.as_slice(), [Packet::EntityMoveRelative { entity: 2, dx: -32, dy: 0, dz: 0 }]));
+        assert!(to_bob.is_empty());
+
+        // Bob walks off again, both are destroyed for the other and his moves are no longer sent
+        let (to_alice, to_bob) = walk_bob(&mut world, &mut tracker, Vec3::new(20.0, 0.0, 0.0));
+        assert!(matches!(to_alice.as_slice(), [Packet::DestroyEntity { entity: 2 }]));
+        assert!(matches!(to_bob.as_slice(), [Packet::DestroyEntity { entity: 1 }]));
+        let (to_alice, to_bob) = walk_bob(&mut world, &mut tracker, Vec3::new(21.0, 0.0, 0.0));
+        assert!(to_alice.is_empty() && to_bob.is_empty());
+        assert!(!tracker.is_viewed_by(alice, 2) && !tracker.is_viewed_by(bob, 1));
+
+        // Coming back into range spawns Bob where he is now
+        let (to_alice, _) = walk_bob(&mut world, &mut tracker, Vec3::new(2.0, 0.0, 3.0));
+        assert!(matches!(to_alice.as_slice(), [Packet::NamedEntitySpawn { entity: 2, x: 64, z: 96, .. }]));
+    }
+}
@@ -0,0 +1,805 @@
+use std::io;
+use std::net::TcpListener;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use ultraviolet::{DVec3, IVec3, Vec3};
+
+use crate::entities::{EntityNetworked, EntityTransform};
+use crate::network::auth::SessionVerifier;
+use crate::network::connection::TcpConnection;
+use crate::network::PacketConnection;
+use crate::packets::prot14::Packet;
+use crate::world::chunk::CHUNK_SECTION_AXIS_SIZE;
+
+use super::access::AccessLists;
+use super::block_actions::{self, BlockActionError, DIG_FINISH, DIG_START};
+use super::chat::split_chat;
+use super::chunk_view::{ViewDistanceManager, DEFAULT_CHUNK_BYTES_PER_TICK};
+use super::commands::{Command, CommandDispatcher, CommandSource, PermissionLevel};
+use super::config::ServerConfig;
+use super::entity_tracker::{EntityTracker, DEFAULT_TRACKING_RANGE};
+use super::generator::ChunkGenerator;
+use super::level_storage::LevelStorage;
+use super::login::{LoginError, ServerLogin};
+use super::player_handler::PlayerConnectionHandler;
+use super::server_player::{ServerPlayer, PLAYER_EYE_HEIGHT};
+use super::world::ServerWorld;
+
+pub const TICKS_PER_SECOND: u32 = 20;
+pub const TICK_DURATION: Duration = Duration::from_millis(1000 / TICKS_PER_SECOND as u64);
+/// Ticks between keep alives, clients time out after a while without packets
+const KEEP_ALIVE_INTERVAL: u64 = TICKS_PER_SECOND as u64;
+/// Ticks between checks for access list files edited by hand
+const ACCESS_RELOAD_INTERVAL: u64 = TICKS_PER_SECOND as u64 * 5;
+/// Ticks between saves of the changed parts of the world, so a crash loses at most this much
+const AUTOSAVE_INTERVAL: u64 = TICKS_PER_SECOND as u64 * 60;
+
+/// A client that finished logging in, waiting to be let into the world on the next tick
+pub type NewConnection = (ServerPlayer, Box<dyn PacketConnection + Send>);
+
+/** A b1.7.3 server, ticking its world at 20 TPS and relaying players to each other
+ *  Clients are logged in off of the tick thread and handed over through a channel,
+ *  so anything that can produce a logged in connection can add players
+ */
+pub struct GameServer {
+    login: Arc<ServerLogin>,
+    world: ServerWorld,
+    entities: legion::World,
+    tracker: EntityTracker,
+    players: PlayerConnectionHandler,
+    new_players: Receiver<NewConnection>,
+    new_player_sender: Sender<NewConnection>,
+    next_entity_id: i32,
+    ticks: u64,
+    view_distance: ViewDistanceManager,
+    commands: Arc<CommandDispatcher>,
+    console: Receiver<String>,
+    console_sender: Sender<String>,
+    /// Blocks around spawn only operators may change
+    spawn_protection: u32,
+    /// Set by the stop command, ends `run` after the current tick
+    stopping: bool,
+}
+
+impl GameServer {
+    pub fn new(world: ServerWorld, login: ServerLogin) -> Self {
+        let (new_player_sender, new_players) = mpsc::channel();
+        let (console_sender, console) = mpsc::channel();
+        Self {
+            login: Arc::new(login),
+            world,
+            entities: legion::World::default(),
+            tracker: EntityTracker::default(),
+            players: PlayerConnectionHandler::new(),
+            new_players,
+            new_player_sender,
+            next_entity_id: 1,
+            ticks: 0,
+            view_distance: ViewDistanceManager::default(),
+            commands: Arc::new(CommandDispatcher::with_builtins()),
+            console,
+            console_sender,
+            spawn_protection: 0,
+            stopping: false,
+        }
+    }
+
+    /** Set up a server the way `config` says, with its files in `directory`
+     *  The level is stored in the level-name directory and the access lists next to it,
+     *  players are checked with `verifier` when the config is in online mode
+     *  A level keeps the seed it was first generated with, level-seed only picks the seed of a new level
+     */
+    pub fn from_config(config: &ServerConfig, directory: &Path, generator: Box<dyn ChunkGenerator + Send>, verifier: Box<dyn SessionVerifier + Send + Sync>) -> io::Result<Self> {
+        let access = AccessLists::load(directory)?.with_whitelist(config.white_list);
+        let login = match config.online_mode {
+            true => ServerLogin::online(verifier),
+            false => ServerLogin::offline(),
+        }.with_access_lists(access).with_motd(&config.motd).with_max_players(config.max_players as usize);
+        let storage = LevelStorage::new(config.level_directory(directory));
+        let seed = match storage.load_seed()? {
+            Some(seed) => seed,
+            None => {
+                let seed = config.seed();
+                storage.save_seed(seed)?;
+                seed
+            },
+        };
+        let world = ServerWorld::new(generator, seed, IVec3::new(0, 0, 0)).with_storage(storage);
+        Ok(Self::new(world, login)
+            .with_view_distance(ViewDistanceManager::new(config.view_distance, DEFAULT_CHUNK_BYTES_PER_TICK))
+            .with_spawn_protection(config.spawn_protection))
+    }
+
+    /// Players are also only shown other players within the columns they can see
+    pub fn with_view_distance(mut self, view_distance: ViewDistanceManager) -> Self {
+        let tracking_range = (view_distance.view_distance() * CHUNK_SECTION_AXIS_SIZE as i32) as f32;
+        self.tracker = std::mem::take(&mut self.tracker).with_tracking_range(tracking_range.min(DEFAULT_TRACKING_RANGE));
+        self.view_distance = view_distance;
+        self
+    }
+
+    pub fn with_spawn_protection(mut self, radius: u32) -> Self {
+        self.spawn_protection = radius;
+        self
+    }
+
+    pub fn world(&self) -> &ServerWorld {
+        &self.world
+    }
+
+    pub fn world_mut(&mut self) -> &mut ServerWorld {
+        &mut self.world
+    }
+
+    pub fn entities(&self) -> &legion::World {
+        &self.entities
+    }
+
+    pub fn players(&self) -> &PlayerConnectionHandler {
+        &self.players
+    }
+
+    pub fn players_mut(&mut self) -> &mut PlayerConnectionHandler {
+        &mut self.players
+    }
+
+    /// Shared with the threads logging clients in
+    pub fn login(&self) -> &Arc<ServerLogin> {
+        &self.login
+    }
+
+    /// The ops, whitelist and bans, shared with the login threads
+    pub fn access_lists(&self) -> &Arc<Mutex<AccessLists>> {
+        self.login.access_lists()
+    }
+
+    pub fn ticks(&self) -> u64 {
+        self.ticks
+    }
+
+    pub fn commands(&self) -> &CommandDispatcher {
+        &self.commands
+    }
+
+    /// Add a command, replacing a built in one with the same name
+    pub fn register_command(&mut self, command: Command) {
+        Arc::make_mut(&mut self.commands).register(command);
+    }
+
+    /// Where to send command lines typed into the server console, they run on the next tick
+    pub fn console_sender(&self) -> Sender<String> {
+        self.console_sender.clone()
+    }
+
+    /// Where to send clients that logged in some other way than through `listen`
+    pub fn connection_sender(&self) -> Sender<NewConnection> {
+        self.new_player_sender.clone()
+    }
+
+    /** Accept clients from a listener until it fails, logging each one in on its own thread
+     *  Clients that log in join the world on the next tick
+     */
+    pub fn listen(&self, listener: TcpListener) -> JoinHandle<()> {
+        let login = self.login.clone();
+        let sender = self.new_player_sender.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(e) => { log::error!("Stopped accepting connections: {e}"); return; },
+                };
+                let login = login.clone();
+                let sender = sender.clone();
+                thread::spawn(move || {
+                    let address = stream.peer_addr().map(|address| address.to_string()).unwrap_or_default();
+                    let connection = match TcpConnection::from_stream(stream) {
+                        Ok(connection) => connection,
+                        Err(e) => { log::warn!("Failed to set up connection from {address}: {e}"); return; },
+                    };
+                    let ip = connection.peer_address().ok().map(|address| address.ip());
+                    match login.accept_from(&connection, ip) {
+                        Ok(player) => { let _ = sender.send((player, Box::new(connection))); },
+                        Err(LoginError::Pinged) => { log::debug!("{address} pinged the server"); },
+                        Err(e) => { log::info!("{address} failed to log in: {e}"); },
+                    }
+                });
+            }
+        })
+    }
+
+    /// Tick at 20 TPS until `running` is cleared or the server is stopped
+    pub fn run(&mut self, running: &AtomicBool) {
+        let mut next_tick = Instant::now();
+        while running.load(Ordering::Acquire) && !self.stopping {
+            self.tick();
+            next_tick += TICK_DURATION;
+            let now = Instant::now();
+            if next_tick > now {
+                thread::sleep(next_tick - now);
+            } else if now - next_tick > TICK_DURATION * TICKS_PER_SECOND {
+                log::warn!("Can't keep up! Skipping {} ticks", (now - next_tick).as_millis() / TICK_DURATION.as_millis());
+                next_tick = now;
+            }
+        }
+    }
+
+    pub fn tick(&mut self) {
+        while let Ok((player, connection)) = self.new_players.try_recv() {
+            self.join_player(player, connection);
+        }
+
+        let mut incoming = vec![];
+        for player in self.players.players() {
+            for packet in player.connection.get_packets() {
+                incoming.push((player.player.entity_id, packet));
+            }
+        }
+        for (entity_id, packet) in incoming {
+            self.handle_packet(entity_id, packet);
+        }
+        while let Ok(line) = self.console.try_recv() {
+            for reply in self.run_command(CommandSource::Console, &line) {
+                log::info!("{reply}");
+            }
+        }
+
+        for player in self.players.remove_disconnected() {
+            self.player_left(player);
+        }
+        self.send_block_changes();
+        self.stream_chunks();
+        self.track_entities();
+
+        self.world.tick();
+        self.ticks += 1;
+        if self.ticks % ACCESS_RELOAD_INTERVAL == 0 {
+            let reloaded = self.access_lists().lock().unwrap().reload_changed();
+            match reloaded {
+                Ok(true) => self.apply_access_lists(),
+                Ok(false) => {},
+                Err(e) => { log::warn!("Failed to reload access lists: {e}"); },
+            }
+        }
+        if self.ticks % KEEP_ALIVE_INTERVAL == 0 {
+            self.players.broadcast(Packet::KeepAlive);
+        }
+        if self.ticks % AUTOSAVE_INTERVAL == 0 {
+            match self.world.save_all() {
+                Ok(saved) => { log::debug!("Autosaved {saved} sections"); },
+                Err(e) => { log::warn!("Failed to autosave the world: {e}"); },
+            }
+        }
+        self.login.set_online_players(self.players.player_count());
+    }
+
+    /// End `run` after the current tick, the caller then shuts the server down
+    pub fn stop(&mut self) {
+        self.stopping = true;
+    }
+
+    pub fn is_stopping(&self) -> bool {
+        self.stopping
+    }
+
+    /// Disconnect everyone and save the world, for when the server stops
+    pub fn shutdown(&mut self) -> io::Result<usize> {
+        self.players.broadcast(Packet::DisconnectKick { reason: String::from("Server closed") });
+        let entity_ids: Vec<i32> = self.players.players().iter().map(|player| player.player.entity_id).collect();
+        for entity_id in entity_ids {
+            if let Some(player) = self.players.remove_player(entity_id) {
+                self.player_left(player);
+            }
+        }
+        self.world.save_all()
+    }
+
+    /// Send a chat message to every player, split into lines their clients accept
+    pub fn broadcast_chat(&self, message: &str) {
+        log::info!("{message}");
+        for line in split_chat(message) {
+            self.players.broadcast(Packet::Chat { chat_data: line });
+        }
+    }
+
+    /// Send a chat message to one player, split into lines their client accepts
+    pub fn send_chat(&self, entity_id: i32, message: &str) {
+        for line in split_chat(message) {
+            self.players.send_to(entity_id, Packet::Chat { chat_data: line });
+        }
+    }
+
+    /// What `source` may run, None for players that are not online
+    pub fn permission_level(&self, source: CommandSource) -> Option<PermissionLevel> {
+        match source {
+            CommandSource::Console => Some(PermissionLevel::Console),
+            CommandSource::Player(entity_id) => self.players.find_by_entity(entity_id).map(|player| player.player.permission),
+        }
+    }
+
+    /// Run a command line for `source`, returning the replies for them
+    pub fn run_command(&mut self, source: CommandSource, line: &str) -> Vec<String> {
+        let Some(permission) = self.permission_level(source) else { return vec![]; };
+        let commands = self.commands.clone();
+        commands.dispatch(self, source, permission, line)
+    }
+
+    pub fn set_time(&mut self, time: u64) {
+        self.world.set_time(time);
+        self.players.broadcast(Packet::TimeUpdate { time });
+    }
+
+    /// Move a player, their client is told right away and everyone else on the next tick
+    pub fn teleport_player(&mut self, entity_id: i32, position: DVec3) {
+        let Some(player) = self.players.players_mut().iter_mut().find(|player| player.player.entity_id == entity_id) else { return; };
+        player.player.position = position;
+        player.send_packet(Self::position_packet(&player.player));
+    }
+
+    /// Put items into a player's inventory, returning how many fit
+    pub fn give_item(&mut self, entity_id: i32, id: i16, amount: u32, damage: i16) -> u32 {
+        let Some(player) = self.players.players_mut().iter_mut().find(|player| player.player.entity_id == entity_id) else { return 0; };
+        let (changed, left) = player.player.inventory.add_item(id, amount, damage);
+        for slot in changed {
+            player.send_packet(player.player.inventory.slot_packet(slot));
+        }
+        amount - left
+    }
+
+    /** Bring online players in line with the access lists after they changed
+     *  Ops get operator commands and lose them once deopped, banned players are kicked
+     */
+    pub fn apply_access_lists(&mut self) {
+        let mut kicked: Vec<(i32, String)> = vec![];
+        {
+            let access = self.login.access_lists().lock().unwrap();
+            for player in self.players.players_mut() {
+                let player = &mut player.player;
+                // The host of an integrated server is always an operator and never kicked from their own game
+                if player.is_local() {
+                    continue;
+                }
+                player.permission = if access.is_op(&player.username) { PermissionLevel::Operator } else { PermissionLevel::Player };
+                if let Err(e) = access.check(&player.username, player.address) {
+                    kicked.push((player.entity_id, e.to_string()));
+                }
+            }
+        }
+        for (entity_id, reason) in kicked {
+            self.kick_player(entity_id, &reason);
+        }
+    }
+
+    /// Disconnect a player, showing them `reason`
+    pub fn kick_player(&mut self, entity_id: i32, reason: &str) {
+        let Some(player) = self.players.find_by_entity(entity_id) else { return; };
+        player.send_packet(Packet::DisconnectKick { reason: reason.to_string() });
+        log::info!("Kicking {}: {reason}", player.player.username);
+        if let Some(player) = self.players.remove_player(entity_id) {
+            self.player_left(player);
+        }
+    }
+
+    fn join_player(&mut self, mut player: ServerPlayer, connection: Box<dyn PacketConnection + Send>) {
+        let existing = self.players.find_by_name(&player.username).map(|existing| {
+            existing.send_packet(Packet::DisconnectKick { reason: String::from("You logged in from another location") });
+            existing.player.entity_id
+        });
+        if let Some(existing) = existing {
+            if let Some(existing) = self.players.remove_player(existing) {
+                self.player_left(existing);
+            }
+        }
+
+        // Players on this machine get in even when the server is full
+        if !player.is_local() && self.players.player_count() >= self.login.max_players() {
+            connection.send_packet(Packet::DisconnectKick { reason: LoginError::ServerFull.to_string() });
+            return;
+        }
+
+        player.entity_id = self.next_entity_id;
+        self.next_entity_id += 1;
+        if self.login.access_lists().lock().unwrap().is_op(&player.username) {
+            player.permission = PermissionLevel::Operator;
+        }
+        let spawn = self.world.spawn_position();
+        let spawn_y = self.world.surface_height(spawn.x, spawn.z).max(spawn.y);
+        player.position = DVec3::new(spawn.x as f64 + 0.5, spawn_y as f64, spawn.z as f64 + 0.5);
+
+        connection.send_packet(Packet::Login { protocol: player.entity_id, username: String::new(), seed: self.world.seed(), dimension: 0 });
+        connection.send_packet(Packet::SpawnPosition { x: spawn.x, y: spawn_y, z: spawn.z });
+        connection.send_packet(Packet::TimeUpdate { time: self.world.time() });
+        // The columns nearest to the player go out before the position, the rest over the next ticks
+        let center = player.chunk_position();
+        self.view_distance.update(&mut player.chunk_view, center);
+        for packet in self.view_distance.stream(&mut player.chunk_view, &mut self.world) {
+            connection.send_packet(packet);
+        }
+        connection.send_packet(Self::position_packet(&player));
+
+        // The new player and everyone near them are spawned for each other once the tracker picks up their entity
+        player.entity = Some(self.entities.push((Self::player_transform(&player), EntityNetworked { entity_id: player.entity_id, name: player.username.clone() })));
+
+        let message = format!("§e{} joined the game.", player.username);
+        self.players.add_player(player, connection);
+        self.broadcast_chat(&message);
+    }
+
+    /// Send the blocks that changed this tick to everyone that has their column, queued columns are read fresh when streamed
+    fn send_block_changes(&mut self) {
+        for change in self.world.take_changes() {
+            let packet = change.packet();
+            for player in self.players.players().iter().filter(|player| player.player.chunk_view.is_loaded(change.column())) {
+                player.send_packet(packet.clone());
+            }
+        }
+    }
+
+    /// Move every player's view along with them and send them their share of queued columns
+    fn stream_chunks(&mut self) {
+        for player in self.players.players_mut() {
+            let center = player.player.chunk_position();
+            let change = self.view_distance.update(&mut player.player.chunk_view, center);
+            for (x, z) in change.left {
+                player.send_packet(ServerWorld::unload_column_packet(x, z));
+            }
+            for packet in self.view_distance.stream(&mut player.player.chunk_view, &mut self.world) {
+                player.send_packet(packet);
+            }
+        }
+    }
+
+    /// Copy player positions into their entities and tell the players near them what moved
+    fn track_entities(&mut self) {
+        let mut viewers = Vec::with_capacity(self.players.player_count());
+        for player in self.players.players() {
+            let transform = Self::player_transform(&player.player);
+            viewers.push((player.player.entity_id, transform.position));
+            let Some(mut entry) = player.player.entity.and_then(|entity| self.entities.entry(entity)) else { continue; };
+            if let Ok(entity_transform) = entry.get_component_mut::<EntityTransform>() {
+                *entity_transform = transform;
+            }
+        }
+        for update in self.tracker.tick(&self.entities, &viewers) {
+            self.players.send_to(update.viewer, update.packet);
+        }
+    }
+
+    fn player_transform(player: &ServerPlayer) -> EntityTransform {
+        let position = Vec3::new(player.position.x as f32, player.position.y as f32, player.position.z as f32);
+        EntityTransform { position, rotation: Vec3::new(player.yaw, player.pitch, 0.0) }
+    }
+
+    /// The tracker destroys the player's entity for everyone else on the next tick
+    fn player_left(&mut self, player: ServerPlayer) {
+        if let Some(entity) = player.entity {
+            self.entities.remove(entity);
+        }
+        self.broadcast_chat(&format!("§e{} left the game.", player.username));
+    }
+
+    fn handle_packet(&mut self, entity_id: i32, packet: Packet) {
+        let Some(index) = self.players.players().iter().position(|player| player.player.entity_id == entity_id) else { return; };
+        match packet {
+            Packet::KeepAlive => {},
+            Packet::Chat { chat_data } if chat_data.starts_with('/') => {
+                log::info!("{} issued server command: {}", self.players.players()[index].player.username, chat_data);
+                for reply in self.run_command(CommandSource::Player(entity_id), &chat_data) {
+                    self.send_chat(entity_id, &reply);
+                }
+            },
+            Packet::Chat { chat_data } => {
+                let message = format!("<{}> {}", self.players.players()[index].player.username, chat_data);
+                self.broadcast_chat(&message);
+            },
+            Packet::PlayerChangeSlot { slot } => {
+                let player = &mut self.players.players_mut()[index].player;
+                if !player.inventory.select(slot) {
+                    log::debug!("Ignoring slot change from {} to {slot}", player.username);
+                }
+            },
+            Packet::PlayerOnGround { on_ground } => {
+                self.players.players_mut()[index].player.on_ground = on_ground;
+            },
+            Packet::PlayerPosition { x, y, z, on_ground, .. } => {
+                let player = &mut self.players.players_mut()[index].player;
+                player.position = DVec3::new(x, y, z);
+                player.on_ground = on_ground;
+            },
+            Packet::PlayerLook { yaw, pitch, on_ground } => {
+                let player = &mut self.players.players_mut()[index].player;
+                (player.yaw, player.pitch, player.on_ground) = (yaw, pitch, on_ground);
+            },
+            // Sent by the client with the y before the stance
+            Packet::PlayerPositionAndLook { x, y_c_stance_s, z, yaw, pitch, on_ground, .. } => {
+                let player = &mut self.players.players_mut()[index].player;
+                player.position = DVec3::new(x, y_c_stance_s, z);
+                (player.yaw, player.pitch, player.on_ground) = (yaw, pitch, on_ground);
+            },
+            Packet::PlayerDigging { status, x, y, z, .. } if status == DIG_START || status == DIG_FINISH => {
+                let position = IVec3::new(x, y as i32, z);
+                let protected = self.is_spawn_protected(index, position);
+                let player = &mut self.players.players_mut()[index].player;
+                let result = if protected { Err(BlockActionError::SpawnProtected) } else { block_actions::dig(&mut self.world, player, status, position) };
+                if let Err(e) = result {
+                    log::debug!("Rejected dig from {} at {:?}: {e}", player.username, position);
+                    self.send_corrections(index, &[position]);
+                }
+            },
+            Packet::PlayerUse { x, y, z, direction, item_data } => {
+                let position = IVec3::new(x, y as i32, z);
+                let protected = direction != -1 && self.is_spawn_protected(index, position);
+                let player = &mut self.players.players_mut()[index].player;
+                let result = if protected { Err(BlockActionError::SpawnProtected) } else { block_actions::place(&mut self.world, player, position, direction, &item_data) };
+                if let Err(e) = result {
+                    log::debug!("Rejected placement from {} at {:?}: {e}", player.username, position);
+                    let mut positions = vec![position];
+                    positions.extend(block_actions::face_offset(direction).map(|offset| position + offset));
+                    self.send_corrections(index, &positions);
+                    // The client already took the block out of its hand
+                    let player = &self.players.players()[index];
+                    player.send_packet(player.player.inventory.slot_packet(player.player.inventory.held_slot()));
+                }
+            },
+            Packet::DisconnectKick { .. } => {
+                if let Some(player) = self.players.remove_player(entity_id) {
+                    self.player_left(player);
+                }
+            },
+            packet => { log::debug!("Ignoring packet from {}: {:?}", self.players.players()[index].player.username, packet); },
+        }
+    }
+
+    fn is_spawn_protected(&self, index: usize, position: IVec3) -> bool {
+        self.players.players()[index].player.permission < PermissionLevel::Operator
+            && block_actions::is_spawn_protected(self.world.spawn_position(), position, self.spawn_protection)
+    }
+
+    /// Tell a player what is really at blocks they were refused changing
+    fn send_corrections(&mut self, index: usize, positions: &[IVec3]) {
+        for packet in block_actions::corrections(&mut self.world, positions) {
+            self.players.players()[index].send_packet(packet);
+        }
+    }
+
+    /// Where the server puts the player, sent with the stance before the y
+    fn position_packet(player: &ServerPlayer) -> Packet {
+        let (x, y, z) = (player.position.x, player.position.y, player.position.z);
+        Packet::PlayerPositionAndLook { x, y_c_stance_s: y + PLAYER_EYE_HEIGHT, stance_c_y_s: y, z, yaw: player.yaw, pitch: player.pitch, on_ground: player.on_ground }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+    use std::sync::atomic::AtomicBool;
+    use std::time::{Duration, Instant};
+
+    use ultraviolet::IVec3;
+
+    use super::GameServer;
+    use crate::server::chunk_view::ViewDistanceManager;
+    use crate::network::connection::TcpConnection;
+    use crate::network::PacketConnection;
+    use crate::server::chat::MAX_CHAT_LENGTH;
+    use crate::server::commands::arguments::ArgumentSpec;
+    use crate::server::commands::{Command, PermissionLevel};
+    use crate::packets::prot14::{ItemPacketData, Packet, PROTOCOL_VERSION};
+    use crate::network::auth::HttpSessionVerifier;
+    use crate::server::config::ServerConfig;
+    use crate::server::generator::FlatGenerator;
+    use crate::server::login::ServerLogin;
+    use crate::server::world::ServerWorld;
+    use crate::util::test_directory::TestDirectory;
+
+    fn test_server() -> (GameServer, std::net::SocketAddr) {
+        let world = ServerWorld::new(Box::new(FlatGenerator::default()), 42, IVec3::new(0, 0, 0));
+        let server = GameServer::new(world, ServerLogin::offline()).with_view_distance(ViewDistanceManager::new(1, usize::MAX));
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        server.listen(listener);
+        (server, address)
+    }
+
+    /// Tick the server until the client has received a packet matching `matches`, returning everything received
+    fn tick_until(server: &mut GameServer, client: &TcpConnection, received: &mut Vec<Packet>, matches: fn(&Packet) -> bool) {
+        let start = Instant::now();
+        while !received.iter().any(matches) {
+            assert!(start.elapsed() < Duration::from_secs(5), "Timed out, received {:?}", received);
+            server.tick();
+            received.extend(client.get_packets());
+            std::thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    fn join(server: &mut GameServer, address: std::net::SocketAddr, username: &str) -> (TcpConnection, Vec<Packet>) {
+        let client = TcpConnection::connect(address).unwrap();
+        client.send_packet(Packet::Handshake { handshake_data: username.to_string() });
+        client.send_packet(Packet::Login { protocol: PROTOCOL_VERSION, username: username.to_string(), seed: 0, dimension: 0 });
+        let mut received = vec![];
+        tick_until(server, &client, &mut received, |packet| matches!(packet, Packet::PlayerPositionAndLook { .. }));
+        (client, received)
+    }
+
+    #[test]
+    fn serves_world_to_joining_players() {
+        let (mut server, address) = test_server();
+        let (_client, received) = join(&mut server, address, "Dev");
+
+        assert!(matches!(received[0], Packet::Handshake { .. }));
+        assert!(matches!(received[1], Packet::Login { protocol: 1, seed: 42, .. }));
+        assert!(matches!(received[2], Packet::SpawnPosition { x: 0, y: 7, z: 0 }));
+        let columns = received.iter().filter(|packet| matches!(packet, Packet::MapChunk { .. })).count();
+        assert_eq!(columns, 9);
+        assert!(received.iter().any(|packet| matches!(packet, Packet::PlayerPositionAndLook { stance_c_y_s, .. } if *stance_c_y_s == 7.0)));
+        assert_eq!(server.players().player_count(), 1);
+    }
+
+    #[test]
+    fn relays_chat_and_movement() {
+        let (mut server, address) = test_server();
+        let (first, _) = join(&mut server, address, "Alice");
+        let (second, mut second_received) = join(&mut server, address, "Bob");
+        assert!(second_received.iter().any(|packet| matches!(packet, Packet::NamedEntitySpawn { entity: 1, name, .. } if name == "Alice")));
+
+        let mut first_received = vec![];
+        tick_until(&mut server, &first, &mut first_received, |packet| matches!(packet, Packet::NamedEntitySpawn { entity: 2, .. }));
+
+        first.send_packet(Packet::Chat { chat_data: String::from("Hello") });
+        first.send_packet(Packet::PlayerPosition { x: 2.5, y: 7.0, stance: 8.62, z: -1.0, on_ground: true });
+        tick_until(&mut server, &second, &mut second_received, |packet| matches!(packet, Packet::EntityMoveRelative { entity: 1, dx: 64, dy: 0, dz: -48 }));
+        assert!(second_received.iter().any(|packet| matches!(packet, Packet::Chat { chat_data } if chat_data == "<Alice> Hello")));
+
+        drop(first);
+        tick_until(&mut server, &second, &mut second_received, |packet| matches!(packet, Packet::DestroyEntity { entity: 1 }));
+        assert_eq!(server.players().player_count(), 1);
+    }
+
+    #[test]
+    fn broadcasts_and_corrects_block_changes() {
+        let (mut server, address) = test_server();
+        let (first, _) = join(&mut server, address, "Alice");
+        let (second, mut second_received) = join(&mut server, address, "Bob");
+
+        first.send_packet(Packet::PlayerDigging { status: 0, x: 1, y: 6, z: 0, face: 1 });
+        first.send_packet(Packet::PlayerDigging { status: 2, x: 1, y: 6, z: 0, face: 1 });
+        tick_until(&mut server, &second, &mut second_received, |packet| matches!(packet, Packet::BlockChange { x: 1, y: 6, z: 0, block_type: 0, metadata: 0 }));
+        assert_eq!(server.world_mut().get_block(1, 6, 0), Some(0));
+
+        let mut first_received = vec![];
+        assert_eq!(server.give_item(1, 35, 1, 4), 1);
+        first.send_packet(Packet::PlayerUse { x: 1, y: 5, z: 0, direction: 1, item_data: ItemPacketData { id: 35, amount: 1, damage: 4 } });
+        tick_until(&mut server, &first, &mut first_received, |packet| matches!(packet, Packet::BlockChange { x: 1, y: 6, z: 0, block_type: 35, metadata: 4 }));
+        // That was her only wool, the client is told her hand is empty
+        first.send_packet(Packet::PlayerUse { x: 1, y: 6, z: 0, direction: 1, item_data: ItemPacketData { id: 35, amount: 1, damage: 4 } });
+        tick_until(&mut server, &first, &mut first_received, |packet| matches!(packet, Packet::SetContainerSlot { window_id: 0, slot: 36, item_data } if item_data.id == -1));
+        assert_eq!(server.world_mut().get_block(1, 7, 0), Some(0));
+
+        // Too far away to reach, Alice is told the block is still there
+        first.send_packet(Packet::PlayerDigging { status: 0, x: 12, y: 6, z: 0, face: 1 });
+        tick_until(&mut server, &first, &mut first_received, |packet| matches!(packet, Packet::BlockChange { x: 12, y: 6, z: 0, block_type: 2, .. }));
+        first.send_packet(Packet::PlayerUse { x: 12, y: 6, z: 0, direction: 1, item_data: ItemPacketData { id: 4, amount: 1, damage: 0 } });
+        tick_until(&mut server, &first, &mut first_received, |packet| matches!(packet, Packet::BlockChange { x: 12, y: 7, z: 0, block_type: 0, .. }));
+        assert_eq!(server.world_mut().get_block(12, 6, 0), Some(2));
+        assert_eq!(server.world_mut().get_block(12, 7, 0), Some(0));
+
+        tick_until(&mut server, &second, &mut second_received, |packet| matches!(packet, Packet::BlockChange { block_type: 35, .. }));
+        assert!(!second_received.iter().any(|packet| matches!(packet, Packet::BlockChange { x: 12, .. })));
+    }
+
+    #[test]
+    fn runs_commands_from_chat_and_console() {
+        let (mut server, address) = test_server();
+        let (first, mut first_received) = join(&mut server, address, "Alice");
+        let (second, mut second_received) = join(&mut server, address, "Bob");
+
+        first.send_packet(Packet::Chat { chat_data: String::from("/list") });
+        tick_until(&mut server, &first, &mut first_received, |packet| matches!(packet, Packet::Chat { chat_data } if chat_data == "§7Connected players: Alice, Bob"));
+        first.send_packet(Packet::Chat { chat_data: String::from("/time set 1000") });
+        tick_until(&mut server, &first, &mut first_received, |packet| matches!(packet, Packet::Chat { chat_data } if chat_data == "§cYou do not have permission to use this command."));
+        assert!(!second_received.iter().any(|packet| matches!(packet, Packet::Chat { chat_data } if chat_data.contains("/list"))));
+
+        server.players_mut().players_mut().iter_mut().find(|player| player.player.username == "Alice").unwrap().player.permission = PermissionLevel::Operator;
+        first.send_packet(Packet::Chat { chat_data: String::from("/tp Bob ~ ~5 ~") });
+        tick_until(&mut server, &second, &mut second_received, |packet| matches!(packet, Packet::PlayerPositionAndLook { stance_c_y_s, .. } if *stance_c_y_s == 12.0));
+        first.send_packet(Packet::Chat { chat_data: String::from("/give Bob 4 10") });
+        tick_until(&mut server, &second, &mut second_received, |packet| matches!(packet, Packet::SetContainerSlot { window_id: 0, slot: 36, item_data } if item_data.id == 4 && item_data.amount == 10));
+        first.send_packet(Packet::Chat { chat_data: String::from("/give Bob 5000") });
+        tick_until(&mut server, &first, &mut first_received, |packet| matches!(packet, Packet::Chat { chat_data } if chat_data == "§cThere's no item with id 5000"));
+
+        server.console_sender().send(String::from("kick Bob Go away")).unwrap();
+        tick_until(&mut server, &second, &mut second_received, |packet| matches!(packet, Packet::DisconnectKick { reason } if reason == "Go away"));
+        assert_eq!(server.players().player_count(), 1);
+    }
+
+    #[test]
+    fn splits_long_replies_into_lines_clients_accept() {
+        const NAMES: [&str; 12] = ["alpha", "bravo", "charlie", "delta", "echo", "foxtrot", "golf", "hotel", "india", "juliett", "kilo", "lima"];
+        let (mut server, address) = test_server();
+        for name in NAMES {
+            server.register_command(Command::new(name, "Does something that takes a good while to explain, so the line listing it in the help goes on for longer than any client is willing to show in one message, ending here", PermissionLevel::Player)
+                .syntax(vec![ArgumentSpec::word("first"), ArgumentSpec::word("second").optional()], |_, _| Ok(())));
+        }
+        let (client, mut received) = join(&mut server, address, "Dev");
+
+        client.send_packet(Packet::Chat { chat_data: String::from("/help") });
+        tick_until(&mut server, &client, &mut received, |packet| matches!(packet, Packet::Chat { chat_data } if chat_data.starts_with("§7/lima")));
+        let lines: Vec<&String> = received.iter().filter_map(|packet| match packet { Packet::Chat { chat_data } => Some(chat_data), _ => None }).collect();
+        assert_eq!(lines.iter().filter(|line| line.ends_with("ending here")).count(), NAMES.len());
+        assert!(lines.iter().all(|line| line.chars().count() <= MAX_CHAT_LENGTH));
+    }
+
+    #[test]
+    fn stop_command_ends_the_run() {
+        let (mut server, _) = test_server();
+        server.console_sender().send(String::from("stop")).unwrap();
+        server.run(&AtomicBool::new(true));
+        assert!(server.is_stopping());
+    }
+
+    #[test]
+    fn ops_and_bans_apply_to_online_players() {
+        let (mut server, address) = test_server();
+        let (first, mut first_received) = join(&mut server, address, "Alice");
+        let (second, mut second_received) = join(&mut server, address, "Bob");
+
+        server.console_sender().send(String::from("op alice")).unwrap();
+        server.tick();
+        assert!(server.access_lists().lock().unwrap().is_op("Alice"));
+        first.send_packet(Packet::Chat { chat_data: String::from("/ban Bob") });
+        tick_until(&mut server, &second, &mut second_received, |packet| matches!(packet, Packet::DisconnectKick { reason } if reason == "You are banned from this server!"));
+        tick_until(&mut server, &first, &mut first_received, |packet| matches!(packet, Packet::Chat { chat_data } if chat_data == "§7Banning Bob"));
+
+        let banned = TcpConnection::connect(address).unwrap();
+        banned.send_packet(Packet::Handshake { handshake_data: String::from("Bob") });
+        banned.send_packet(Packet::Login { protocol: PROTOCOL_VERSION, username: String::from("Bob"), seed: 0, dimension: 0 });
+        let mut received = vec![];
+        tick_until(&mut server, &banned, &mut received, |packet| matches!(packet, Packet::DisconnectKick { .. }));
+        assert_eq!(server.players().player_count(), 1);
+
+        server.console_sender().send(String::from("deop Alice")).unwrap();
+        server.tick();
+        first.send_packet(Packet::Chat { chat_data: String::from("/pardon Bob") });
+        tick_until(&mut server, &first, &mut first_received, |packet| matches!(packet, Packet::Chat { chat_data } if chat_data == "§cYou do not have permission to use this command."));
+    }
+
+    #[test]
+    fn spawn_protection_only_lets_ops_build() {
+        let (server, address) = test_server();
+        let mut server = server.with_spawn_protection(4);
+        let (first, mut first_received) = join(&mut server, address, "Alice");
+
+        first.send_packet(Packet::PlayerDigging { status: 0, x: 1, y: 6, z: 0, face: 1 });
+        first.send_packet(Packet::PlayerDigging { status: 2, x: 1, y: 6, z: 0, face: 1 });
+        tick_until(&mut server, &first, &mut first_received, |packet| matches!(packet, Packet::BlockChange { x: 1, y: 6, z: 0, block_type: 2, .. }));
+        assert_eq!(server.world_mut().get_block(1, 6, 0), Some(2));
+
+        server.console_sender().send(String::from("op Alice")).unwrap();
+        server.tick();
+        first.send_packet(Packet::PlayerDigging { status: 0, x: 1, y: 6, z: 0, face: 1 });
+        first.send_packet(Packet::PlayerDigging { status: 2, x: 1, y: 6, z: 0, face: 1 });
+        tick_until(&mut server, &first, &mut first_received, |packet| matches!(packet, Packet::BlockChange { x: 1, y: 6, z: 0, block_type: 0, .. }));
+    }
+
+    #[test]
+    fn keeps_the_seed_of_a_level() {
+        let directory = TestDirectory::new("seed");
+        let start = |level_seed: &str| {
+            let mut config = ServerConfig::default();
+            config.level_seed = level_seed.to_string();
+            GameServer::from_config(&config, &directory, Box::new(FlatGenerator::default()), Box::new(HttpSessionVerifier::default())).unwrap().world.seed()
+        };
+        // A random seed is picked once, and kept by every later start
+        let seed = start("");
+        assert_eq!(start(""), seed);
+        assert_eq!(start("12"), seed);
+
+        std::fs::remove_dir_all(directory.join("world")).unwrap();
+        assert_eq!(start("12"), 12);
+        assert_eq!(start(""), 12);
+    }
+}
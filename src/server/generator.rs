@@ -0,0 +1,54 @@
+use ultraviolet::IVec3;
+
+use crate::world::chunk::{Chunk, TBlockData, CHUNK_SECTION_AXIS_SIZE};
+
+/// Fills in the sections of a server world that are not in storage yet
+pub trait ChunkGenerator {
+    /// Generate the section at `section_position`, in chunk coordinates with y being the section index
+    fn generate_section(&self, section_position: IVec3) -> Chunk;
+}
+
+/** A superflat world, one block per layer from the bottom of the world up
+ *  Blocks are protocol block data, the id in the lower byte and the metadata in the upper byte
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct FlatGenerator {
+    pub layers: Vec<TBlockData>,
+}
+
+impl FlatGenerator {
+    pub fn new(layers: Vec<TBlockData>) -> Self {
+        Self { layers }
+    }
+
+    /// The y of the first air block
+    pub fn surface_height(&self) -> i32 {
+        self.layers.len() as i32
+    }
+}
+
+impl Default for FlatGenerator {
+    /// Bedrock, three stone, two dirt and a grass layer
+    fn default() -> Self {
+        Self::new(vec![7, 1, 1, 1, 3, 3, 2])
+    }
+}
+
+impl ChunkGenerator for FlatGenerator {
+    fn generate_section(&self, section_position: IVec3) -> Chunk {
+        let mut chunk = Chunk::create_empty();
+        let base_y = section_position.y * CHUNK_SECTION_AXIS_SIZE as i32;
+        for y in 0..CHUNK_SECTION_AXIS_SIZE as u32 {
+            let block = match usize::try_from(base_y + y as i32).ok().and_then(|layer| self.layers.get(layer)) {
+                Some(&block) if block != 0 => block,
+                _ => continue,
+            };
+            for x in 0..CHUNK_SECTION_AXIS_SIZE as u32 {
+                for z in 0..CHUNK_SECTION_AXIS_SIZE as u32 {
+                    chunk.set_block_at_pos(x, y, z, block);
+                }
+            }
+        }
+        chunk
+    }
+}
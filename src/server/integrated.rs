@@ -0,0 +1,158 @@
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+use crate::network::auth::HttpSessionVerifier;
+use crate::network::local::LocalConnection;
+
+use super::commands::PermissionLevel;
+use super::config::ServerConfig;
+use super::game_server::{GameServer, NewConnection};
+use super::generator::ChunkGenerator;
+use super::login::ServerLogin;
+
+/** A server running in the same process as the client, for singleplayer
+ *  The world ticks on a thread of its own and the client joins over a LocalConnection,
+ *  going through the same login sequence a client connecting over the network would
+ */
+pub struct IntegratedServer {
+    login: Arc<ServerLogin>,
+    connections: Sender<NewConnection>,
+    running: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl IntegratedServer {
+    /// Tick `server` on its own thread until stopped, it is then shut down and its world saved
+    pub fn start(mut server: GameServer) -> Self {
+        let login = server.login().clone();
+        let connections = server.connection_sender();
+        let running = Arc::new(AtomicBool::new(true));
+        let server_running = running.clone();
+        let thread = thread::spawn(move || {
+            server.run(&server_running);
+            match server.shutdown() {
+                Ok(saved) => { log::info!("Saved {saved} sections"); },
+                Err(e) => { log::error!("Failed to save the world: {e}"); },
+            }
+        });
+        Self { login, connections, running, thread: Some(thread) }
+    }
+
+    /** Start a server on the world in `directory`, which is generated if it does not exist yet
+     *  Always in offline mode, there is no one to check the host's session with
+     */
+    pub fn open(config: &ServerConfig, directory: &Path, generator: Box<dyn ChunkGenerator + Send>) -> io::Result<Self> {
+        std::fs::create_dir_all(directory)?;
+        let mut config = config.clone();
+        config.online_mode = false;
+        let server = GameServer::from_config(&config, directory, generator, Box::new(HttpSessionVerifier::default()))?;
+        Ok(Self::start(server))
+    }
+
+    /** Connect the host, the returned end is used like a NetworkThread connected to a server
+     *  The host logs in like anyone else, but plays as an operator that is let in even when the server is full,
+     *  and whatever the bans and whitelist say
+     */
+    pub fn connect(&self) -> LocalConnection {
+        let (client, server) = LocalConnection::pair();
+        let login = self.login.clone();
+        let connections = self.connections.clone();
+        thread::spawn(move || {
+            match login.accept_host(&server) {
+                Ok(mut player) => {
+                    player.permission = PermissionLevel::Operator;
+                    let _ = connections.send((player, Box::new(server)));
+                },
+                Err(e) => { log::warn!("Failed to join the integrated server: {e}"); },
+            }
+        });
+        client
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.thread.as_ref().is_some_and(|thread| !thread.is_finished())
+    }
+
+    /// Stop ticking, waiting until the world is saved
+    pub fn stop(&mut self) {
+        self.running.store(false, Ordering::Release);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for IntegratedServer {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use ultraviolet::IVec3;
+
+    use super::IntegratedServer;
+    use crate::network::local::LocalConnection;
+    use crate::network::PacketConnection;
+    use crate::packets::prot14::{Packet, PROTOCOL_VERSION};
+    use crate::server::access::AccessLists;
+    use crate::server::game_server::GameServer;
+    use crate::server::generator::FlatGenerator;
+    use crate::server::login::ServerLogin;
+    use crate::server::world::ServerWorld;
+
+    fn receive_until(client: &LocalConnection, received: &mut Vec<Packet>, matches: fn(&Packet) -> bool) {
+        let start = Instant::now();
+        while !received.iter().any(matches) {
+            assert!(start.elapsed() < Duration::from_secs(5), "Timed out, received {:?}", received);
+            received.extend(client.get_packets());
+            std::thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn host_plays_over_local_connection() {
+        let world = ServerWorld::new(Box::new(FlatGenerator::default()), 7, IVec3::new(0, 0, 0));
+        let mut server = IntegratedServer::start(GameServer::new(world, ServerLogin::offline()));
+        let client = server.connect();
+        client.send_packet(Packet::Handshake { handshake_data: String::from("Dev") });
+        client.send_packet(Packet::Login { protocol: PROTOCOL_VERSION, username: String::from("Dev"), seed: 0, dimension: 0 });
+
+        let mut received = vec![];
+        receive_until(&client, &mut received, |packet| matches!(packet, Packet::PlayerPositionAndLook { .. }));
+        assert!(matches!(received[0], Packet::Handshake { ref handshake_data } if handshake_data == "-"));
+        assert!(matches!(received[1], Packet::Login { seed: 7, .. }));
+
+        // Only operators may set the time
+        client.send_packet(Packet::Chat { chat_data: String::from("/time set 1000") });
+        receive_until(&client, &mut received, |packet| matches!(packet, Packet::TimeUpdate { time: 1000 }));
+
+        assert!(server.is_running());
+        server.stop();
+        assert!(!server.is_running());
+        receive_until(&client, &mut received, |packet| matches!(packet, Packet::DisconnectKick { reason } if reason == "Server closed"));
+        assert!(client.is_closed());
+    }
+
+    #[test]
+    fn host_joins_a_full_server() {
+        let world = ServerWorld::new(Box::new(FlatGenerator::default()), 7, IVec3::new(0, 0, 0));
+        let login = ServerLogin::offline().with_max_players(0).with_access_lists(AccessLists::in_memory().with_whitelist(true));
+        login.access_lists().lock().unwrap().banned_players.add("Dev").unwrap();
+        let server = IntegratedServer::start(GameServer::new(world, login));
+        let client = server.connect();
+        client.send_packet(Packet::Handshake { handshake_data: String::from("Dev") });
+        client.send_packet(Packet::Login { protocol: PROTOCOL_VERSION, username: String::from("Dev"), seed: 0, dimension: 0 });
+
+        let mut received = vec![];
+        receive_until(&client, &mut received, |packet| matches!(packet, Packet::PlayerPositionAndLook { .. }));
+        assert!(!received.iter().any(|packet| matches!(packet, Packet::DisconnectKick { .. })));
+    }
+}
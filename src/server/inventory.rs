@@ -0,0 +1,160 @@
+use crate::packets::prot14::{ItemPacketData, Packet};
+
+/// The id of the window that is the player's own inventory
+pub const PLAYER_WINDOW: i8 = 0;
+/// Slots of the player window, crafting, armor, the main inventory and the hotbar
+pub const PLAYER_WINDOW_SLOTS: usize = 45;
+const MAIN_SLOTS: std::ops::Range<usize> = 9..36;
+const HOTBAR_SLOTS: std::ops::Range<usize> = 36..45;
+pub const MAX_STACK_SIZE: i8 = 64;
+/// Every b1.7.3 block has an item form, up to the trapdoor
+const BLOCK_ITEMS: std::ops::RangeInclusive<i16> = 1..=96;
+/// The b1.7.3 items, from the iron shovel to the shears
+const ITEMS: std::ops::RangeInclusive<i16> = 256..=359;
+/// The two music discs, numbered apart from the other items
+const RECORDS: std::ops::RangeInclusive<i16> = 2256..=2257;
+
+/** Whether a b1.7.3 client knows an item with this id, anything else crashes it when shown
+ *  The registry lives on the client and has no items, so the server keeps its own list of ids
+ */
+pub fn is_known_item(id: i16) -> bool {
+    BLOCK_ITEMS.contains(&id) || ITEMS.contains(&id) || RECORDS.contains(&id)
+}
+
+/** What the server has put into a player's inventory, and which hotbar slot they hold
+ *  Window clicks are not handled yet, so this only knows about items the server gave,
+ *  not where the player has since moved them
+ */
+#[derive(Debug, Clone)]
+pub struct PlayerInventory {
+    slots: Vec<Option<ItemPacketData>>,
+    /// The selected hotbar slot, 0 to 8
+    selected: usize,
+}
+
+impl Default for PlayerInventory {
+    fn default() -> Self {
+        Self { slots: vec![None; PLAYER_WINDOW_SLOTS], selected: 0 }
+    }
+}
+
+impl PlayerInventory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn slot(&self, slot: usize) -> Option<&ItemPacketData> {
+        self.slots.get(slot).and_then(Option::as_ref)
+    }
+
+    /** Add items, topping up matching stacks before filling empty slots, hotbar first
+     *  Returns the slots that changed and how many items did not fit
+     */
+    pub fn add_item(&mut self, id: i16, mut amount: u32, damage: i16) -> (Vec<usize>, u32) {
+        let mut changed = vec![];
+        let order: Vec<usize> = HOTBAR_SLOTS.chain(MAIN_SLOTS).collect();
+        for &slot in &order {
+            let Some(stack) = &mut self.slots[slot] else { continue; };
+            if amount == 0 || stack.id != id || stack.damage != damage || stack.amount >= MAX_STACK_SIZE {
+                continue;
+            }
+            let added = amount.min((MAX_STACK_SIZE - stack.amount) as u32);
+            stack.amount += added as i8;
+            amount -= added;
+            changed.push(slot);
+        }
+        for &slot in &order {
+            if amount == 0 {
+                break;
+            }
+            if self.slots[slot].is_none() {
+                let added = amount.min(MAX_STACK_SIZE as u32);
+                self.slots[slot] = Some(ItemPacketData { id, amount: added as i8, damage });
+                amount -= added;
+                changed.push(slot);
+            }
+        }
+        (changed, amount)
+    }
+
+    /// Select a hotbar slot as sent in PlayerChangeSlot, returning false for slots past the hotbar
+    pub fn select(&mut self, hotbar_slot: i16) -> bool {
+        match usize::try_from(hotbar_slot) {
+            Ok(hotbar_slot) if hotbar_slot < HOTBAR_SLOTS.len() => {
+                self.selected = hotbar_slot;
+                true
+            },
+            _ => false,
+        }
+    }
+
+    /// The slot of the player window holding the selected item
+    pub fn held_slot(&self) -> usize {
+        HOTBAR_SLOTS.start + self.selected
+    }
+
+    pub fn held(&self) -> Option<&ItemPacketData> {
+        self.slot(self.held_slot())
+    }
+
+    /// Use up one of the held item, emptying the slot after the last one
+    pub fn take_held(&mut self) -> Option<ItemPacketData> {
+        let slot = &mut self.slots[HOTBAR_SLOTS.start + self.selected];
+        let stack = slot.as_mut()?;
+        stack.amount -= 1;
+        let taken = ItemPacketData { id: stack.id, amount: 1, damage: stack.damage };
+        if stack.amount <= 0 {
+            *slot = None;
+        }
+        Some(taken)
+    }
+
+    /// The SetContainerSlot packet that shows the client what is in a slot
+    pub fn slot_packet(&self, slot: usize) -> Packet {
+        let item_data = self.slot(slot).cloned().unwrap_or(ItemPacketData { id: -1, amount: 0, damage: 0 });
+        Packet::SetContainerSlot { window_id: PLAYER_WINDOW, slot: slot as i16, item_data }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_known_item, PlayerInventory};
+
+    #[test]
+    fn fills_stacks_then_empty_slots() {
+        let mut inventory = PlayerInventory::new();
+        assert_eq!(inventory.add_item(1, 10, 0), (vec![36], 0));
+        assert_eq!(inventory.add_item(1, 60, 0), (vec![36, 37], 0));
+        assert_eq!(inventory.slot(36).unwrap().amount, 64);
+        assert_eq!(inventory.slot(37).unwrap().amount, 6);
+        // Other damage values are a different item
+        assert_eq!(inventory.add_item(1, 1, 2), (vec![38], 0));
+
+        let (changed, left) = inventory.add_item(4, 64 * 40, 0);
+        assert_eq!(changed.len(), 33);
+        assert_eq!(left, 64 * 7);
+    }
+
+    #[test]
+    fn takes_from_the_selected_slot() {
+        let mut inventory = PlayerInventory::new();
+        inventory.add_item(3, 2, 0);
+        inventory.add_item(4, 1, 0);
+        assert!(inventory.select(1));
+        assert!(!inventory.select(9));
+        assert_eq!(inventory.held_slot(), 37);
+        assert_eq!(inventory.take_held().map(|item| item.id), Some(4));
+        assert!(inventory.held().is_none());
+        assert!(inventory.take_held().is_none());
+
+        inventory.select(0);
+        assert_eq!(inventory.take_held().map(|item| item.id), Some(3));
+        assert_eq!(inventory.held().map(|item| item.amount), Some(1));
+    }
+
+    #[test]
+    fn knows_blocks_and_items() {
+        assert!(is_known_item(1) && is_known_item(96) && is_known_item(276) && is_known_item(2257));
+        assert!(!is_known_item(0) && !is_known_item(97) && !is_known_item(360) && !is_known_item(2258));
+    }
+}
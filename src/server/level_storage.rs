@@ -0,0 +1,91 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use ultraviolet::IVec3;
+
+use crate::world::chunk::{Chunk, CHUNK_SECTION_AXIS_SIZE};
+
+const SECTION_BLOCKS: usize = CHUNK_SECTION_AXIS_SIZE * CHUNK_SECTION_AXIS_SIZE * CHUNK_SECTION_AXIS_SIZE;
+
+/** Sections saved to a level directory, one file per section holding its protocol block data
+ *  Only sections that were changed get saved, everything else is generated again when needed
+ */
+#[derive(Debug, Clone)]
+pub struct LevelStorage {
+    directory: PathBuf,
+}
+
+impl LevelStorage {
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        Self { directory: directory.into() }
+    }
+
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    fn seed_path(&self) -> PathBuf {
+        self.directory.join("seed.txt")
+    }
+
+    /// The seed the level was generated with, None for a level that never had one saved
+    pub fn load_seed(&self) -> io::Result<Option<i64>> {
+        let text = match fs::read_to_string(self.seed_path()) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => { return Ok(None); },
+            Err(e) => { return Err(e); },
+        };
+        text.trim().parse().map(Some).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("Seed {:?}: {e}", text.trim())))
+    }
+
+    pub fn save_seed(&self, seed: i64) -> io::Result<()> {
+        fs::create_dir_all(&self.directory)?;
+        fs::write(self.seed_path(), format!("{seed}\n"))
+    }
+
+    fn section_path(&self, section_position: IVec3) -> PathBuf {
+        self.directory.join("sections").join(format!("s.{}.{}.{}.dat", section_position.x, section_position.y, section_position.z))
+    }
+
+    /// The saved section at a position, None if it was never saved
+    pub fn load_section(&self, section_position: IVec3) -> io::Result<Option<Chunk>> {
+        let bytes = match fs::read(self.section_path(section_position)) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => { return Ok(None); },
+            Err(e) => { return Err(e); },
+        };
+        if bytes.len() != SECTION_BLOCKS * 2 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Section {:?} is {} bytes", section_position, bytes.len())));
+        }
+        let mut chunk = Chunk::create_empty();
+        let mut blocks = bytes.chunks_exact(2).map(|data| u16::from_be_bytes([data[0], data[1]]));
+        for x in 0..CHUNK_SECTION_AXIS_SIZE as u32 {
+            for y in 0..CHUNK_SECTION_AXIS_SIZE as u32 {
+                for z in 0..CHUNK_SECTION_AXIS_SIZE as u32 {
+                    chunk.set_block_at_pos(x, y, z, blocks.next().unwrap_or(0));
+                }
+            }
+        }
+        Ok(Some(chunk))
+    }
+
+    pub fn save_section(&self, section_position: IVec3, chunk: &Chunk) -> io::Result<()> {
+        let mut bytes = Vec::with_capacity(SECTION_BLOCKS * 2);
+        for x in 0..CHUNK_SECTION_AXIS_SIZE as u32 {
+            for y in 0..CHUNK_SECTION_AXIS_SIZE as u32 {
+                for z in 0..CHUNK_SECTION_AXIS_SIZE as u32 {
+                    bytes.extend(chunk.get_block_at_pos(x, y, z).to_be_bytes());
+                }
+            }
+        }
+        let path = self.section_path(section_position);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // Written next to the section first so a crash never leaves half a section behind
+        let temporary = path.with_extension("tmp");
+        fs::write(&temporary, bytes)?;
+        fs::rename(temporary, path)
+    }
+}
@@ -0,0 +1,394 @@
+use std::fmt::Display;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::network::auth::{generate_server_id, AuthError, HttpSessionVerifier, SessionVerifier, OFFLINE_SERVER_HASH};
+use crate::network::PacketConnection;
+use crate::packets::prot14::{Packet, PROTOCOL_VERSION};
+
+use super::access::{AccessDenied, AccessLists};
+use super::server_player::ServerPlayer;
+
+/// How long a client gets to finish logging in, the 600 ticks b1.7.3 servers allow
+const LOGIN_TIMEOUT: Duration = Duration::from_secs(30);
+/// How often to check for packets while waiting on the client
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+/// Why a client could not log in, each is sent to the client as the kick reason
+#[derive(Debug)]
+pub enum LoginError {
+    TimedOut,
+    Disconnected,
+    UnexpectedPacket(String),
+    OutdatedClient(i32),
+    OutdatedServer(i32),
+    /// The session server did not confirm the player joined with our server id
+    NotVerified,
+    /// The session server could not be asked
+    Verifier(AuthError),
+    /// Banned or not on the whitelist
+    Denied(AccessDenied),
+    ServerFull,
+    /// The client only asked for the motd and player counts, which it was sent instead of a kick reason
+    Pinged,
+}
+
+impl std::error::Error for LoginError {}
+
+impl Display for LoginError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::TimedOut => write!(f, "Took too long to log in"),
+            Self::Disconnected => write!(f, "Disconnected while logging in"),
+            Self::UnexpectedPacket(packet) => write!(f, "Protocol error, unexpected packet: {packet}"),
+            Self::OutdatedClient(_) => write!(f, "Outdated client!"),
+            Self::OutdatedServer(_) => write!(f, "Outdated server!"),
+            Self::NotVerified => write!(f, "Failed to verify username!"),
+            Self::Verifier(e) => write!(f, "Failed to verify username! {e}"),
+            Self::Denied(e) => write!(f, "{e}"),
+            Self::ServerFull => write!(f, "The server is full!"),
+            Self::Pinged => write!(f, "Pinged the server"),
+        }
+    }
+}
+
+impl From<AuthError> for LoginError {
+    fn from(e: AuthError) -> Self {
+        Self::Verifier(e)
+    }
+}
+
+impl From<AccessDenied> for LoginError {
+    fn from(e: AccessDenied) -> Self {
+        Self::Denied(e)
+    }
+}
+
+/** The server side of the login sequence, shared by every connection a server accepts
+ *  In online mode every connection is sent a server id of its own in the handshake, which is checked with the verifier
+ *  once the client asks to log in, so a session check cannot be replayed for another connection
+ *  in offline mode a dash is sent and every username is taken as is
+ *  Either way the player is then checked against the server's bans and whitelist
+ *  Clients pinging the server from their server list are answered with the motd and player counts
+ */
+pub struct ServerLogin {
+    online_mode: bool,
+    verifier: Box<dyn SessionVerifier + Send + Sync>,
+    timeout: Duration,
+    access: Arc<Mutex<AccessLists>>,
+    motd: String,
+    max_players: usize,
+    /// Kept up to date by the game server, for pings and to turn players away once it is full
+    online_players: Arc<AtomicUsize>,
+}
+
+impl ServerLogin {
+    pub fn offline() -> Self {
+        Self {
+            online_mode: false,
+            verifier: Box::new(HttpSessionVerifier::default()),
+            timeout: LOGIN_TIMEOUT,
+            access: Arc::new(Mutex::new(AccessLists::in_memory())),
+            motd: String::from("A Minecraft Server"),
+            max_players: 20,
+            online_players: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Verify players with `verifier`, such as an HttpSessionVerifier pointed at the session server
+    pub fn online(verifier: Box<dyn SessionVerifier + Send + Sync>) -> Self {
+        Self { online_mode: true, verifier, ..Self::offline() }
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn with_access_lists(mut self, access: AccessLists) -> Self {
+        self.access = Arc::new(Mutex::new(access));
+        self
+    }
+
+    pub fn with_motd(mut self, motd: &str) -> Self {
+        self.motd = motd.to_string();
+        self
+    }
+
+    pub fn with_max_players(mut self, max_players: usize) -> Self {
+        self.max_players = max_players;
+        self
+    }
+
+    pub fn max_players(&self) -> usize {
+        self.max_players
+    }
+
+    pub fn set_online_players(&self, count: usize) {
+        self.online_players.store(count, Ordering::Relaxed);
+    }
+
+    /// What a server list ping is answered with, the motd and the online and max player counts split by §
+    pub fn status(&self) -> String {
+        format!("{}§{}§{}", self.motd, self.online_players.load(Ordering::Relaxed), self.max_players)
+    }
+
+    /// The bans, whitelist and ops checked when players log in, shared with the game server
+    pub fn access_lists(&self) -> &Arc<Mutex<AccessLists>> {
+        &self.access
+    }
+
+    pub fn is_online_mode(&self) -> bool {
+        self.online_mode
+    }
+
+    /// What is sent in the handshake reply, a new id for every connection in online mode
+    fn new_server_id(&self) -> String {
+        match self.online_mode {
+            true => generate_server_id(),
+            false => OFFLINE_SERVER_HASH.to_string(),
+        }
+    }
+
+    /** Run the login sequence with a freshly accepted client, up to receiving its Login packet
+     *  Replying with the server's Login is left to the caller, which knows the entity id and world
+     *  A client that fails to log in is kicked with the error as the reason
+     */
+    pub fn accept<C: PacketConnection + ?Sized>(&self, connection: &C) -> Result<ServerPlayer, LoginError> {
+        self.accept_from(connection, None)
+    }
+
+    /// Accept a client that connected from `address`, which IP bans are checked against
+    pub fn accept_from<C: PacketConnection + ?Sized>(&self, connection: &C, address: Option<IpAddr>) -> Result<ServerPlayer, LoginError> {
+        let result = self.run(connection).and_then(|mut player| {
+            self.access.lock().unwrap().check(&player.username, address)?;
+            if self.online_players.load(Ordering::Relaxed) >= self.max_players {
+                return Err(LoginError::ServerFull);
+            }
+            player.address = address;
+            Ok(player)
+        });
+        self.kick_on_error(connection, result)
+    }
+
+    /// Accept the host of an integrated server, who is let in whatever the bans, whitelist and player count say
+    pub fn accept_host<C: PacketConnection + ?Sized>(&self, connection: &C) -> Result<ServerPlayer, LoginError> {
+        let result = self.run(connection).map(|mut player| {
+            player.is_local = true;
+            player
+        });
+        self.kick_on_error(connection, result)
+    }
+
+    /// Tell a client why it was not let in, or answer its ping
+    fn kick_on_error<C: PacketConnection + ?Sized>(&self, connection: &C, result: Result<ServerPlayer, LoginError>) -> Result<ServerPlayer, LoginError> {
+        match &result {
+            Ok(_) | Err(LoginError::Disconnected) => {},
+            Err(LoginError::Pinged) => connection.send_packet(Packet::DisconnectKick { reason: self.status() }),
+            Err(e) => connection.send_packet(Packet::DisconnectKick { reason: e.to_string() }),
+        }
+        result
+    }
+
+    fn run<C: PacketConnection + ?Sized>(&self, connection: &C) -> Result<ServerPlayer, LoginError> {
+        let start = Instant::now();
+        // The username the client shook hands as, and the server id it was sent
+        let mut handshake = None;
+        loop {
+            if connection.is_closed() {
+                return Err(LoginError::Disconnected);
+            }
+            if start.elapsed() > self.timeout {
+                return Err(LoginError::TimedOut);
+            }
+            let packets = connection.get_packets();
+            if packets.is_empty() {
+                std::thread::sleep(POLL_INTERVAL);
+                continue;
+            }
+            for packet in packets {
+                match (&handshake, packet) {
+                    (None, Packet::ServerListPing) => { return Err(LoginError::Pinged); },
+                    (None, Packet::Handshake { handshake_data }) => {
+                        let server_id = self.new_server_id();
+                        connection.send_packet(Packet::Handshake { handshake_data: server_id.clone() });
+                        handshake = Some((handshake_data, server_id));
+                    },
+                    (Some((handshake_name, server_id)), Packet::Login { protocol, username: login_name, .. }) => {
+                        if protocol < PROTOCOL_VERSION {
+                            return Err(LoginError::OutdatedClient(protocol));
+                        }
+                        if protocol > PROTOCOL_VERSION {
+                            return Err(LoginError::OutdatedServer(protocol));
+                        }
+                        if &login_name != handshake_name {
+                            return Err(LoginError::UnexpectedPacket(format!("login as {login_name} after a handshake as {handshake_name}")));
+                        }
+                        return self.verify(login_name, server_id);
+                    },
+                    (_, Packet::DisconnectKick { .. }) => { return Err(LoginError::Disconnected); },
+                    (_, packet) => { return Err(LoginError::UnexpectedPacket(format!("{:?}", packet))); },
+                }
+            }
+        }
+    }
+
+    fn verify(&self, username: String, server_id: &str) -> Result<ServerPlayer, LoginError> {
+        if !self.online_mode {
+            return Ok(ServerPlayer::remote(username, false));
+        }
+        match self.verifier.verify(&username, server_id)? {
+            true => Ok(ServerPlayer::remote(username, true)),
+            false => Err(LoginError::NotVerified),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+    use std::time::{Duration, Instant};
+
+    use super::{LoginError, ServerLogin};
+    use crate::server::access::{AccessDenied, AccessLists};
+    use crate::network::auth::{AuthError, HttpSessionVerifier, SessionVerifier};
+    use crate::network::connection::TcpConnection;
+    use crate::network::mock_session::MockSessionServer;
+    use crate::network::PacketConnection;
+    use crate::packets::prot14::{Packet, PROTOCOL_VERSION};
+
+    /// Accepts everyone without asking anything
+    struct TrustingVerifier;
+
+    impl SessionVerifier for TrustingVerifier {
+        fn verify(&self, _username: &str, _server_id: &str) -> Result<bool, AuthError> {
+            Ok(true)
+        }
+    }
+
+    fn connected_pair() -> (TcpConnection, TcpConnection) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpConnection::connect(listener.local_addr().unwrap()).unwrap();
+        let server = TcpConnection::from_stream(listener.accept().unwrap().0).unwrap();
+        (server, client)
+    }
+
+    /// Wait for `count` packets, which may arrive together
+    fn receive(connection: &TcpConnection, count: usize) -> Vec<Packet> {
+        let start = Instant::now();
+        let mut packets = vec![];
+        while packets.len() < count {
+            packets.extend(connection.get_packets());
+            assert!(start.elapsed() < Duration::from_secs(5), "Timed out waiting for packets");
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        packets
+    }
+
+    fn log_in(client: &TcpConnection, protocol: i32) {
+        client.send_packet(Packet::Handshake { handshake_data: String::from("Dev") });
+        client.send_packet(Packet::Login { protocol, username: String::from("Dev"), seed: 0, dimension: 0 });
+    }
+
+    #[test]
+    fn offline_login_is_unverified() {
+        let (server, client) = connected_pair();
+        log_in(&client, PROTOCOL_VERSION);
+        let player = ServerLogin::offline().accept(&server).unwrap();
+        assert_eq!(player.username(), "Dev");
+        assert!(!player.is_verified());
+        assert!(player.is_remote());
+        assert!(matches!(receive(&client, 1).as_slice(), [Packet::Handshake { handshake_data }] if handshake_data == "-"));
+    }
+
+    #[test]
+    fn online_login_checks_session() {
+        let session = MockSessionServer::start(|_| String::from("YES"));
+        let login = ServerLogin::online(Box::new(HttpSessionVerifier::new(session.url("/game/checkserver.jsp"))));
+        let (server, client) = connected_pair();
+        log_in(&client, PROTOCOL_VERSION);
+        let player = login.accept(&server).unwrap();
+        assert!(player.is_verified());
+        let server_id = match receive(&client, 1).as_slice() {
+            [Packet::Handshake { handshake_data }] => handshake_data.clone(),
+            packets => panic!("Expected a handshake, received {:?}", packets),
+        };
+        assert_eq!(session.requests(), [format!("/game/checkserver.jsp?user=Dev&serverId={}", server_id)]);
+
+        // Every connection is checked against an id of its own
+        let (server, client) = connected_pair();
+        log_in(&client, PROTOCOL_VERSION);
+        login.accept(&server).unwrap();
+        let other_id = match receive(&client, 1).as_slice() {
+            [Packet::Handshake { handshake_data }] => handshake_data.clone(),
+            packets => panic!("Expected a handshake, received {:?}", packets),
+        };
+        assert_ne!(other_id, server_id);
+        assert_eq!(session.requests()[1], format!("/game/checkserver.jsp?user=Dev&serverId={}", other_id));
+
+        let (server, client) = connected_pair();
+        log_in(&client, PROTOCOL_VERSION);
+        assert!(ServerLogin::online(Box::new(TrustingVerifier)).accept(&server).unwrap().is_verified());
+    }
+
+    #[test]
+    fn unverified_player_is_kicked() {
+        let session = MockSessionServer::start(|_| String::from("NO"));
+        let login = ServerLogin::online(Box::new(HttpSessionVerifier::new(session.url("/game/checkserver.jsp"))));
+        let (server, client) = connected_pair();
+        log_in(&client, PROTOCOL_VERSION);
+        assert!(matches!(login.accept(&server), Err(LoginError::NotVerified)));
+        assert!(matches!(receive(&client, 2).as_slice(), [Packet::Handshake { .. }, Packet::DisconnectKick { reason }] if reason == "Failed to verify username!"));
+    }
+
+    #[test]
+    fn rejects_other_protocols() {
+        let (server, client) = connected_pair();
+        log_in(&client, PROTOCOL_VERSION - 1);
+        assert!(matches!(ServerLogin::offline().accept(&server), Err(LoginError::OutdatedClient(13))));
+
+        let (server, client) = connected_pair();
+        log_in(&client, PROTOCOL_VERSION + 1);
+        assert!(matches!(ServerLogin::offline().accept(&server), Err(LoginError::OutdatedServer(15))));
+
+        let (server, _client) = connected_pair();
+        let login = ServerLogin::offline().with_timeout(Duration::from_millis(50));
+        assert!(matches!(login.accept(&server), Err(LoginError::TimedOut)));
+    }
+
+    #[test]
+    fn banned_player_is_kicked() {
+        let mut access = AccessLists::in_memory();
+        access.banned_ips.add("127.0.0.1").unwrap();
+        let login = ServerLogin::offline().with_access_lists(access);
+        let (server, client) = connected_pair();
+        log_in(&client, PROTOCOL_VERSION);
+        let address = server.peer_address().ok().map(|address| address.ip());
+        assert!(matches!(login.accept_from(&server, address), Err(LoginError::Denied(AccessDenied::BannedIp))));
+        assert!(matches!(receive(&client, 2).as_slice(), [Packet::Handshake { .. }, Packet::DisconnectKick { reason }] if reason == "Your IP address is banned from this server!"));
+
+        login.access_lists().lock().unwrap().banned_ips.remove("127.0.0.1").unwrap();
+        let (server, client) = connected_pair();
+        log_in(&client, PROTOCOL_VERSION);
+        let player = login.accept_from(&server, address).unwrap();
+        assert_eq!(player.address, address);
+    }
+
+    #[test]
+    fn answers_pings_and_turns_away_when_full() {
+        let login = ServerLogin::offline().with_motd("Orange").with_max_players(2);
+        login.set_online_players(1);
+        let (server, client) = connected_pair();
+        client.send_packet(Packet::ServerListPing);
+        assert!(matches!(login.accept(&server), Err(LoginError::Pinged)));
+        assert!(matches!(receive(&client, 1).as_slice(), [Packet::DisconnectKick { reason }] if reason == "Orange§1§2"));
+
+        login.set_online_players(2);
+        let (server, client) = connected_pair();
+        log_in(&client, PROTOCOL_VERSION);
+        assert!(matches!(login.accept(&server), Err(LoginError::ServerFull)));
+        assert!(matches!(receive(&client, 2).as_slice(), [Packet::Handshake { .. }, Packet::DisconnectKick { reason }] if reason == "The server is full!"));
+    }
+}
@@ -1,2 +1,18 @@
+pub mod access;
+pub mod block_actions;
+pub mod chat;
+pub mod chunk_view;
+pub mod commands;
+pub mod config;
+pub mod entity_tracker;
+pub mod game_server;
+pub mod generator;
+#[cfg(feature = "integrated")]
+pub mod integrated;
+pub mod inventory;
+pub mod level_storage;
+pub mod login;
 pub mod player_handler;
+pub mod properties;
 pub mod server_player;
+pub mod world;
@@ -1,11 +1,26 @@
+use crate::network::PacketConnection;
+use crate::packets::prot14::Packet;
+
 use super::server_player::ServerPlayer;
 
+/// A logged in player together with the connection to their client
+pub struct PlayerConnection {
+    pub player: ServerPlayer,
+    pub connection: Box<dyn PacketConnection + Send>,
+}
+
+impl PlayerConnection {
+    pub fn send_packet(&self, packet: Packet) {
+        self.connection.send_packet(packet);
+    }
+}
+
 /**
  *  Represents the player connection handler
- *
+ *  Owns every player in the world and the connection to their client
  */
 pub struct PlayerConnectionHandler {
-    players: Vec<ServerPlayer>,
+    players: Vec<PlayerConnection>,
 }
 
 impl PlayerConnectionHandler {
@@ -16,7 +31,54 @@ impl PlayerConnectionHandler {
         }
     }
 
-    pub fn add_player(&mut self, player: ServerPlayer) {
-        self.players.push(player);
+    pub fn add_player(&mut self, player: ServerPlayer, connection: Box<dyn PacketConnection + Send>) {
+        self.players.push(PlayerConnection { player, connection });
+    }
+
+    pub fn players(&self) -> &[PlayerConnection] {
+        &self.players
+    }
+
+    pub fn players_mut(&mut self) -> &mut [PlayerConnection] {
+        &mut self.players
+    }
+
+    pub fn player_count(&self) -> usize {
+        self.players.len()
+    }
+
+    pub fn find_by_name(&self, username: &str) -> Option<&PlayerConnection> {
+        self.players.iter().find(|player| player.player.username.eq_ignore_ascii_case(username))
+    }
+
+    pub fn find_by_entity(&self, entity_id: i32) -> Option<&PlayerConnection> {
+        self.players.iter().find(|player| player.player.entity_id == entity_id)
+    }
+
+    pub fn broadcast(&self, packet: Packet) {
+        for player in &self.players {
+            player.send_packet(packet.clone());
+        }
+    }
+
+    /// Send a packet to the player with `entity_id` only, nothing is sent if they are not online
+    pub fn send_to(&self, entity_id: i32, packet: Packet) {
+        if let Some(player) = self.find_by_entity(entity_id) {
+            player.send_packet(packet);
+        }
+    }
+
+    /// Take out the player with `entity_id`, stopping their connection
+    pub fn remove_player(&mut self, entity_id: i32) -> Option<ServerPlayer> {
+        let index = self.players.iter().position(|player| player.player.entity_id == entity_id)?;
+        let mut removed = self.players.swap_remove(index);
+        removed.connection.stop();
+        Some(removed.player)
+    }
+
+    /// Take out every player whose connection closed
+    pub fn remove_disconnected(&mut self) -> Vec<ServerPlayer> {
+        let closed: Vec<i32> = self.players.iter().filter(|player| player.connection.is_closed()).map(|player| player.player.entity_id).collect();
+        closed.into_iter().filter_map(|entity_id| self.remove_player(entity_id)).collect()
     }
 }
@@ -0,0 +1,255 @@
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+#[derive(Debug)]
+pub enum PropertiesError {
+    Io(io::Error),
+    /// A `\u` escape that is not followed by four hex digits, on the given line
+    MalformedUnicodeEscape(usize),
+}
+
+impl std::error::Error for PropertiesError {}
+
+impl std::fmt::Display for PropertiesError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "{e}"),
+            Self::MalformedUnicodeEscape(line) => write!(f, "Malformed \\uxxxx encoding on line {line}"),
+        }
+    }
+}
+
+impl From<io::Error> for PropertiesError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+/** Key value pairs in the Java properties format, as used by server.properties
+ *  Keys keep the order they were read or added in, so rewriting a file only moves what changed
+ */
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Properties {
+    entries: Vec<(String, String)>,
+}
+
+impl Properties {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn load(path: &Path) -> Result<Self, PropertiesError> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn save(&self, path: &Path, comment: Option<&str>) -> io::Result<()> {
+        fs::write(path, self.to_string_with_comment(comment))
+    }
+
+    /** Parse the contents of a properties file
+     *  Handles `#` and `!` comments, `=`, `:` or whitespace separators, lines continued with a trailing backslash
+     *  and the escapes Java writes, a later duplicate key replaces the earlier one
+     */
+    pub fn parse(contents: &str) -> Result<Self, PropertiesError> {
+        let mut properties = Self::new();
+        let mut lines = contents.lines().enumerate();
+        while let Some((number, line)) = lines.next() {
+            let line = line.trim_start_matches([' ', '\t', '\x0C']);
+            if line.is_empty() || line.starts_with('#') || line.starts_with('!') {
+                continue;
+            }
+            // Join continued lines, dropping the backslash and the next line's leading whitespace
+            let mut logical = line.to_string();
+            while Self::ends_with_continuation(&logical) {
+                logical.pop();
+                match lines.next() {
+                    Some((_, next)) => logical.push_str(next.trim_start_matches([' ', '\t', '\x0C'])),
+                    None => break,
+                }
+            }
+            let (key, value) = Self::split_key_value(&logical);
+            properties.set(&Self::unescape(key, number + 1)?, &Self::unescape(value, number + 1)?);
+        }
+        Ok(properties)
+    }
+
+    /// An odd number of trailing backslashes continues the line
+    fn ends_with_continuation(line: &str) -> bool {
+        line.chars().rev().take_while(|&c| c == '\\').count() % 2 == 1
+    }
+
+    /// Split at the first unescaped separator, the still escaped key and value
+    fn split_key_value(line: &str) -> (&str, &str) {
+        let mut escaped = false;
+        let mut key_end = line.len();
+        for (index, c) in line.char_indices() {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if matches!(c, '=' | ':' | ' ' | '\t' | '\x0C') {
+                key_end = index;
+                break;
+            }
+        }
+        let rest = line[key_end..].trim_start_matches([' ', '\t', '\x0C']);
+        let rest = rest.strip_prefix(['=', ':']).unwrap_or(rest);
+        (&line[..key_end], rest.trim_start_matches([' ', '\t', '\x0C']))
+    }
+
+    /// Undo escapes, \u escapes are UTF-16 so characters outside of the basic plane come as a surrogate pair
+    fn unescape(escaped: &str, line: usize) -> Result<String, PropertiesError> {
+        let mut units: Vec<u16> = Vec::with_capacity(escaped.len());
+        let push = |units: &mut Vec<u16>, c: char| units.extend(c.encode_utf16(&mut [0u16; 2]).iter());
+        let mut chars = escaped.chars();
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                push(&mut units, c);
+                continue;
+            }
+            match chars.next() {
+                Some('t') => push(&mut units, '\t'),
+                Some('n') => push(&mut units, '\n'),
+                Some('r') => push(&mut units, '\r'),
+                Some('f') => push(&mut units, '\x0C'),
+                Some('u') => {
+                    let hex: String = chars.by_ref().take(4).collect();
+                    let unit = u16::from_str_radix(&hex, 16).ok().filter(|_| hex.len() == 4).ok_or(PropertiesError::MalformedUnicodeEscape(line))?;
+                    units.push(unit);
+                },
+                Some(other) => push(&mut units, other),
+                None => {},
+            }
+        }
+        Ok(String::from_utf16_lossy(&units))
+    }
+
+    fn escape(text: &str, is_key: bool) -> String {
+        let mut escaped = String::with_capacity(text.len());
+        for (index, c) in text.chars().enumerate() {
+            match c {
+                ' ' if is_key || index == 0 => escaped.push_str("\\ "),
+                '\\' => escaped.push_str("\\\\"),
+                '\t' => escaped.push_str("\\t"),
+                '\n' => escaped.push_str("\\n"),
+                '\r' => escaped.push_str("\\r"),
+                '\x0C' => escaped.push_str("\\f"),
+                '=' | ':' | '#' | '!' => {
+                    escaped.push('\\');
+                    escaped.push(c);
+                },
+                c if (' '..='~').contains(&c) => escaped.push(c),
+                c => {
+                    let mut units = [0u16; 2];
+                    for unit in c.encode_utf16(&mut units) {
+                        let _ = write!(escaped, "\\u{:04X}", unit);
+                    }
+                },
+            }
+        }
+        escaped
+    }
+
+    /// The file contents Java's Properties.store would write, with an optional comment at the top
+    pub fn to_string_with_comment(&self, comment: Option<&str>) -> String {
+        let mut contents = String::new();
+        if let Some(comment) = comment {
+            for line in comment.lines() {
+                let _ = writeln!(contents, "#{line}");
+            }
+        }
+        for (key, value) in &self.entries {
+            let _ = writeln!(contents, "{}={}", Self::escape(key, true), Self::escape(value, false));
+        }
+        contents
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entries.iter().find(|(existing, _)| existing == key).map(|(_, value)| value.as_str())
+    }
+
+    /// Set a value, keeping the key where it was if it already existed
+    pub fn set(&mut self, key: &str, value: &str) {
+        match self.entries.iter_mut().find(|(existing, _)| existing == key) {
+            Some((_, existing)) => *existing = value.to_string(),
+            None => self.entries.push((key.to_string(), value.to_string())),
+        }
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<String> {
+        let index = self.entries.iter().position(|(existing, _)| existing == key)?;
+        Some(self.entries.remove(index).1)
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.get(key).is_some()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries.iter().map(|(key, value)| (key.as_str(), value.as_str()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Properties, PropertiesError};
+
+    #[test]
+    fn parses_java_syntax() {
+        let properties = Properties::parse(concat!(
+            "#Minecraft server properties\n",
+            "! another comment\n",
+            "level-name=world\n",
+            "  server-port = 25565\n",
+            "motd:A \\u00A7cRed\\tServer\n",
+            "spaced key value\n",
+            "escaped\\=key=a\\:b\\\\\n",
+            "long=first \\\n",
+            "      second\n",
+            "empty\n",
+            "\n",
+            "level-name=other\n",
+        )).unwrap();
+        assert_eq!(properties.get("level-name"), Some("other"));
+        assert_eq!(properties.get("server-port"), Some("25565"));
+        assert_eq!(properties.get("motd"), Some("A §cRed\tServer"));
+        assert_eq!(properties.get("spaced"), Some("key value"));
+        assert_eq!(properties.get("escaped=key"), Some("a:b\\"));
+        assert_eq!(properties.get("long"), Some("first second"));
+        assert_eq!(properties.get("empty"), Some(""));
+        assert_eq!(properties.len(), 7);
+
+        assert_eq!(Properties::parse("emoji=\\uD83D\\uDE00").unwrap().get("emoji"), Some("\u{1F600}"));
+        assert!(matches!(Properties::parse("bad=\\u00G1"), Err(PropertiesError::MalformedUnicodeEscape(1))));
+    }
+
+    #[test]
+    fn writes_what_it_reads() {
+        let mut properties = Properties::new();
+        properties.set("motd", " §eWelcome: #1!");
+        properties.set("odd key", "tab\there\nand newline\\");
+        properties.set("level-name", "world");
+        let written = properties.to_string_with_comment(Some("Minecraft server properties"));
+        assert_eq!(written, concat!(
+            "#Minecraft server properties\n",
+            "motd=\\ \\u00A7eWelcome\\: \\#1\\!\n",
+            "odd\\ key=tab\\there\\nand newline\\\\\n",
+            "level-name=world\n",
+        ));
+        assert_eq!(Properties::parse(&written).unwrap(), properties);
+
+        properties.set("motd", "Changed");
+        properties.remove("odd key");
+        assert_eq!(properties.iter().collect::<Vec<_>>(), [("motd", "Changed"), ("level-name", "world")]);
+    }
+}
@@ -1,8 +1,41 @@
+use std::hash::{Hash, Hasher};
+use std::net::IpAddr;
+
+use ultraviolet::DVec3;
+
+use crate::util::pos::BlockPos;
+
+use super::chunk_view::PlayerChunkView;
+use super::commands::PermissionLevel;
+use super::inventory::PlayerInventory;
+
+/// Height of a player's eyes above their feet, the stance sent along with positions
+pub const PLAYER_EYE_HEIGHT: f64 = 1.62;
 
 pub struct ServerPlayer {
     pub is_local : bool,
     pub username : String,
     pub uuid: u64,
+    /// Whether the session server confirmed the player owns the username, only ever true on online mode servers
+    pub verified: bool,
+    /// Where the player connected from, None if they did not connect over the network
+    pub address: Option<IpAddr>,
+    /// Assigned by the server when the player joins the world
+    pub entity_id: i32,
+    /// Position of the player's feet
+    pub position: DVec3,
+    pub yaw: f32,
+    pub pitch: f32,
+    pub on_ground: bool,
+    /// The columns the player's client has and is waiting for
+    pub chunk_view: PlayerChunkView,
+    /// The player's entity in the server's world, once they joined it
+    pub entity: Option<legion::Entity>,
+    /// The block the player started breaking, if they are still on it
+    pub digging: Option<BlockPos>,
+    pub inventory: PlayerInventory,
+    /// Which commands the player may run
+    pub permission: PermissionLevel,
 }
 
 impl ServerPlayer {
@@ -12,6 +45,41 @@ impl ServerPlayer {
             is_local: true,
             username: "TestPlayer001".to_string(),
             uuid: 1247,
+            verified: false,
+            address: None,
+            entity_id: 0,
+            position: DVec3::zero(),
+            yaw: 0.0,
+            pitch: 0.0,
+            on_ground: true,
+            chunk_view: PlayerChunkView::new(),
+            entity: None,
+            digging: None,
+            inventory: PlayerInventory::new(),
+            permission: PermissionLevel::Operator,
+        }
+    }
+
+    /// A player that logged in over the network
+    pub fn remote(username: String, verified: bool) -> Self {
+        let mut hasher = rustc_hash::FxHasher::default();
+        username.hash(&mut hasher);
+        Self {
+            is_local: false,
+            username,
+            uuid: hasher.finish(),
+            verified,
+            address: None,
+            entity_id: 0,
+            position: DVec3::zero(),
+            yaw: 0.0,
+            pitch: 0.0,
+            on_ground: true,
+            chunk_view: PlayerChunkView::new(),
+            entity: None,
+            digging: None,
+            inventory: PlayerInventory::new(),
+            permission: PermissionLevel::Player,
         }
     }
 
@@ -30,4 +98,13 @@ impl ServerPlayer {
     pub fn is_remote(&self) -> bool {
         return !self.is_local;
     } 
+
+    pub fn is_verified(&self) -> bool {
+        self.verified
+    }
+
+    /// The chunk column the player is standing in
+    pub fn chunk_position(&self) -> (i32, i32) {
+        ((self.position.x.floor() as i32) >> 4, (self.position.z.floor() as i32) >> 4)
+    }
 }
@@ -0,0 +1,291 @@
+use std::io;
+
+use rustc_hash::FxHashSet as HashSet;
+use ultraviolet::IVec3;
+
+use crate::minecraft::prot14::map_chunk::{encode_map_chunk, MapChunkRegion, WORLD_HEIGHT};
+use crate::packets::prot14::Packet;
+use crate::util::pos::BlockPos;
+use crate::world::chunk::{Chunk, TBlockData, CHUNK_SECTION_AXIS_SIZE};
+use crate::world::{ChunkStorage, ChunkStoragePlanar, ChunkStorageTrait};
+
+use super::generator::ChunkGenerator;
+use super::level_storage::LevelStorage;
+
+/// Sections in a column of a b1.7.3 world
+pub const WORLD_SECTIONS: usize = WORLD_HEIGHT as usize / CHUNK_SECTION_AXIS_SIZE;
+
+/// A block that was set to something else, to be sent to every player that has its column
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BlockChangeEvent {
+    pub position: BlockPos,
+    pub old: TBlockData,
+    pub new: TBlockData,
+}
+
+impl BlockChangeEvent {
+    pub fn column(&self) -> (i32, i32) {
+        (self.position.x >> 4, self.position.z >> 4)
+    }
+
+    pub fn packet(&self) -> Packet {
+        ServerWorld::block_change_packet(self.position, self.new)
+    }
+}
+
+/** The world as the server sees it, sections that are not in storage are generated the first time they are needed
+ *  Unlike the client, chunks hold protocol block data (id | meta << 8) rather than block states,
+ *  so the server does not need a registry to stream them
+ */
+pub struct ServerWorld {
+    chunk_storage: ChunkStorage<Chunk>,
+    generator: Box<dyn ChunkGenerator + Send>,
+    seed: i64,
+    time: u64,
+    spawn_position: BlockPos,
+    changes: Vec<BlockChangeEvent>,
+    storage: Option<LevelStorage>,
+    /// Sections changed since they were last saved
+    unsaved: HashSet<IVec3>,
+}
+
+impl ServerWorld {
+    pub fn new(generator: Box<dyn ChunkGenerator + Send>, seed: i64, spawn_position: BlockPos) -> Self {
+        Self {
+            chunk_storage: ChunkStorage::Planar(ChunkStoragePlanar::new(WORLD_SECTIONS)),
+            generator,
+            seed,
+            time: 0,
+            spawn_position,
+            changes: vec![],
+            storage: None,
+            unsaved: HashSet::default(),
+        }
+    }
+
+    /// Load changed sections from and save them to `storage`, instead of only keeping them in memory
+    pub fn with_storage(mut self, storage: LevelStorage) -> Self {
+        self.storage = Some(storage);
+        self
+    }
+
+    pub fn storage(&self) -> Option<&LevelStorage> {
+        self.storage.as_ref()
+    }
+
+    pub fn seed(&self) -> i64 {
+        self.seed
+    }
+
+    pub fn time(&self) -> u64 {
+        self.time
+    }
+
+    pub fn set_time(&mut self, time: u64) {
+        self.time = time;
+    }
+
+    pub fn spawn_position(&self) -> BlockPos {
+        self.spawn_position
+    }
+
+    pub fn tick(&mut self) {
+        self.time += 1;
+    }
+
+    /// Put a section into storage, replacing what was generated or stored there
+    pub fn set_section(&mut self, section_position: IVec3, chunk: Chunk) -> bool {
+        let stored = self.chunk_storage.set_chunk(chunk, section_position).is_ok();
+        if stored {
+            self.unsaved.insert(section_position);
+        }
+        stored
+    }
+
+    /// A saved section if there is one, otherwise a freshly generated one
+    fn load_section(storage: &Option<LevelStorage>, generator: &(dyn ChunkGenerator + Send), section_position: IVec3) -> Chunk {
+        match storage.as_ref().map(|storage| storage.load_section(section_position)) {
+            Some(Ok(Some(chunk))) => chunk,
+            Some(Err(e)) => {
+                log::error!("Failed to load section {:?}, generating it again: {e}", section_position);
+                generator.generate_section(section_position)
+            },
+            _ => generator.generate_section(section_position),
+        }
+    }
+
+    /** Write every section changed since the last save to storage, returning how many were written
+     *  Without storage nothing is written, sections that fail to save are kept for the next try
+     */
+    pub fn save_all(&mut self) -> io::Result<usize> {
+        let Some(storage) = &self.storage else { return Ok(0); };
+        let mut saved = 0;
+        let mut result = Ok(());
+        let mut unsaved: Vec<IVec3> = self.unsaved.iter().copied().collect();
+        unsaved.sort_by_key(|position| (position.x, position.y, position.z));
+        for section_position in unsaved {
+            let Ok(chunk) = self.chunk_storage.get_chunk(section_position) else { continue; };
+            match storage.save_section(section_position, chunk) {
+                Ok(()) => {
+                    self.unsaved.remove(&section_position);
+                    saved += 1;
+                },
+                Err(e) => { result = Err(e); },
+            }
+        }
+        result.map(|_| saved)
+    }
+
+    /// Whether any section of the column is in storage
+    pub fn is_column_loaded(&self, chunk_x: i32, chunk_z: i32) -> bool {
+        (0..WORLD_SECTIONS as i32).any(|y| self.chunk_storage.get_chunk(IVec3::new(chunk_x, y, chunk_z)).is_ok())
+    }
+
+    /// Generate every section of the column that is not in storage yet
+    pub fn load_column(&mut self, chunk_x: i32, chunk_z: i32) {
+        for y in 0..WORLD_SECTIONS as i32 {
+            let section_position = IVec3::new(chunk_x, y, chunk_z);
+            let (storage, generator) = (&self.storage, self.generator.as_ref());
+            let _ = self.chunk_storage.get_or_create_chunk(section_position, || Self::load_section(storage, generator, section_position));
+        }
+    }
+
+    fn section(&mut self, x: i32, y: i32, z: i32) -> Option<&mut Chunk> {
+        if !(0..WORLD_HEIGHT).contains(&y) {
+            return None;
+        }
+        let section_position = IVec3::new(x >> 4, y >> 4, z >> 4);
+        let (storage, generator) = (&self.storage, self.generator.as_ref());
+        self.chunk_storage.get_or_create_chunk(section_position, || Self::load_section(storage, generator, section_position)).ok()
+    }
+
+    /// The protocol block data at a position, None outside of the world
+    pub fn get_block(&mut self, x: i32, y: i32, z: i32) -> Option<TBlockData> {
+        self.section(x, y, z).map(|chunk| chunk.get_block_at_pos((x & 15) as u32, (y & 15) as u32, (z & 15) as u32))
+    }
+
+    /// Set the protocol block data at a position, returns false outside of the world
+    pub fn set_block(&mut self, x: i32, y: i32, z: i32, data: TBlockData) -> bool {
+        let Some(chunk) = self.section(x, y, z) else { return false; };
+        let (local_x, local_y, local_z) = ((x & 15) as u32, (y & 15) as u32, (z & 15) as u32);
+        let old = chunk.get_block_at_pos(local_x, local_y, local_z);
+        if old != data {
+            chunk.set_block_at_pos(local_x, local_y, local_z, data);
+            chunk.set_dirty(true);
+            self.unsaved.insert(IVec3::new(x >> 4, y >> 4, z >> 4));
+            self.changes.push(BlockChangeEvent { position: IVec3::new(x, y, z), old, new: data });
+        }
+        true
+    }
+
+    /// Every block change since the last call, oldest first
+    pub fn take_changes(&mut self) -> Vec<BlockChangeEvent> {
+        std::mem::take(&mut self.changes)
+    }
+
+    /// The y of the first air block above the highest solid block of a column
+    pub fn surface_height(&mut self, x: i32, z: i32) -> i32 {
+        (0..WORLD_HEIGHT).rev().find(|&y| self.get_block(x, y, z).is_some_and(|block| block != 0)).map_or(0, |y| y + 1)
+    }
+
+    /** The PreChunk and MapChunk packets that send a whole column to a client
+     *  Light is not tracked yet, air gets full sky light and everything else none
+     */
+    pub fn column_packets(&mut self, chunk_x: i32, chunk_z: i32) -> Vec<Packet> {
+        self.load_column(chunk_x, chunk_z);
+        let region = MapChunkRegion { x: chunk_x << 4, y: 0, z: chunk_z << 4, size_x: 16, size_y: WORLD_HEIGHT as usize, size_z: 16 };
+        let compressed_data = encode_map_chunk(region, |position| {
+            let data = self.get_block(position.x, position.y, position.z).unwrap_or(0);
+            (data, 0, if data == 0 { 15 } else { 0 })
+        }).expect("A full chunk column is always inside of the world");
+        let (size_x, size_y, size_z) = region.packet_sizes();
+        vec![
+            Packet::PreChunk { x: chunk_x, z: chunk_z, mode: true },
+            Packet::MapChunk { x: region.x, y: region.y as i16, z: region.z, size_x, size_y, size_z, compressed_data },
+        ]
+    }
+
+    /// The BlockChange packet that sets a single block on a client
+    pub fn block_change_packet(position: BlockPos, data: TBlockData) -> Packet {
+        Packet::BlockChange { x: position.x, y: position.y as i8, z: position.z, block_type: (data & 0xFF) as u8 as i8, metadata: ((data >> 8) & 0xF) as i8 }
+    }
+
+    /// The PreChunk packet that tells a client to forget a column
+    pub fn unload_column_packet(chunk_x: i32, chunk_z: i32) -> Packet {
+        Packet::PreChunk { x: chunk_x, z: chunk_z, mode: false }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ultraviolet::IVec3;
+
+    use super::ServerWorld;
+    use crate::minecraft::prot14::map_chunk::{decode_map_chunk, MapChunkRegion};
+    use crate::packets::prot14::Packet;
+    use crate::server::generator::FlatGenerator;
+    use crate::server::level_storage::LevelStorage;
+    use crate::util::test_directory::TestDirectory;
+    use crate::world::chunk::Chunk;
+
+    fn flat_world() -> ServerWorld {
+        ServerWorld::new(Box::new(FlatGenerator::default()), 0, IVec3::new(0, 7, 0))
+    }
+
+    #[test]
+    fn generates_missing_sections() {
+        let mut world = flat_world();
+        assert!(!world.is_column_loaded(-1, 2));
+        assert_eq!(world.get_block(-5, 0, 40), Some(7));
+        assert_eq!(world.get_block(-5, 6, 40), Some(2));
+        assert_eq!(world.get_block(-5, 7, 40), Some(0));
+        assert_eq!(world.get_block(0, 128, 0), None);
+        assert!(world.is_column_loaded(-1, 2));
+        assert_eq!(world.surface_height(100, -100), 7);
+    }
+
+    #[test]
+    fn stored_sections_win_over_generator() {
+        let mut world = flat_world();
+        let mut section = Chunk::create_empty();
+        section.set_block_at_pos(1, 2, 3, 4 | (2 << 8));
+        assert!(world.set_section(IVec3::new(0, 0, 0), section));
+        assert_eq!(world.get_block(1, 2, 3), Some(4 | (2 << 8)));
+        assert_eq!(world.get_block(0, 0, 0), Some(0));
+        assert!(world.set_block(17, 7, 17, 20));
+        assert_eq!(world.get_block(17, 7, 17), Some(20));
+    }
+
+    #[test]
+    fn column_packets_round_trip() {
+        let mut world = flat_world();
+        world.set_block(-14, 9, 3, 5 | (1 << 8));
+        let packets = world.column_packets(-1, 0);
+        assert!(matches!(packets[0], Packet::PreChunk { x: -1, z: 0, mode: true }));
+        let Packet::MapChunk { x, y, z, size_x, size_y, size_z, compressed_data } = &packets[1] else { panic!("Expected a MapChunk") };
+        let sections = decode_map_chunk(MapChunkRegion::from_packet(*x, *y, *z, *size_x, *size_y, *size_z), compressed_data).unwrap();
+        let block = |x: u32, y: u32, z: u32| sections.iter()
+            .flat_map(|section| section.blocks.iter().map(move |block| (section.section_position, block)))
+            .find(|(section, block)| (block.x, block.y + section.y as u32 * 16, block.z) == (x, y, z))
+            .map(|(_, block)| (block.data, block.sky_light));
+        assert_eq!(block(2, 9, 3), Some((5 | (1 << 8), 0)));
+        assert_eq!(block(0, 6, 0), Some((2, 0)));
+        assert_eq!(block(0, 7, 0), Some((0, 15)));
+    }
+
+    #[test]
+    fn saves_changed_sections() {
+        let directory = TestDirectory::new("level");
+        let mut world = flat_world().with_storage(LevelStorage::new(directory.path()));
+        world.set_block(3, 20, -7, 1);
+        world.set_block(3, 6, -7, 0);
+        world.get_block(40, 6, 40);
+        assert_eq!(world.save_all().unwrap(), 2);
+        assert_eq!(world.save_all().unwrap(), 0);
+
+        let mut reloaded = flat_world().with_storage(LevelStorage::new(directory.path()));
+        assert_eq!(reloaded.get_block(3, 20, -7), Some(1));
+        assert_eq!(reloaded.get_block(3, 6, -7), Some(0));
+        assert_eq!(reloaded.get_block(4, 6, -7), Some(2));
+    }
+}
@@ -3,6 +3,8 @@ pub mod pos;
 pub mod workers;
 pub mod frustrum;
 pub mod nibble;
+#[cfg(test)]
+pub mod test_directory;
 
 // pub struct IteratorYZX(IVec3, IVec3, u32);
 //
@@ -97,6 +97,29 @@ impl<ChunkType> ChunkStorageTrait<ChunkType> for ChunkStorage<ChunkType> {
             Self::Cubic(cubic) => { cubic.get_nearby_chunks(position) },
         }
     }
+    fn positions(&self) -> Vec<IVec3> {
+        match self {
+            Self::Planar(planar) => { planar.positions() },
+            Self::PlanarLimited(planar_limited) => { planar_limited.positions() },
+            Self::Cubic(cubic) => { cubic.positions() },
+        }
+    }
+}
+
+impl ChunkStorage<chunk::Chunk> {
+    /**
+    Mark every section as changed, so everything built from them is built again
+    Returns the number of sections that were marked
+     */
+    pub fn mark_all_dirty(&self) -> usize {
+        let positions = self.positions();
+        for &position in &positions {
+            if let Ok(chunk) = self.get_chunk(position) {
+                chunk.set_dirty(true);
+            }
+        }
+        positions.len()
+    }
 }
 
 pub trait ChunkStorageTrait<ChunkType> {
@@ -129,6 +152,11 @@ pub trait ChunkStorageTrait<ChunkType> {
     Get the optional chunks surrounding the chunk at position, does not guarantee that all chunks exist
      */
     fn get_nearby_chunks(&self, position: IVec3) -> Vec<Option<&ChunkType>>;
+
+    /**
+    Get the positions of every chunk in the storage, in no particular order
+     */
+    fn positions(&self) -> Vec<IVec3>;
 }
 
 pub struct ChunkStoragePlanar<ChunkType> {
@@ -253,10 +281,23 @@ impl<ChunkType> ChunkStorageTrait<ChunkType> for ChunkStoragePlanar<ChunkType> {
                 self.get_chunk(position).ok()
             }).collect()
     }
+
+    fn positions(&self) -> Vec<IVec3> {
+        let mut positions = vec![];
+        for (&hash, &stack_index) in &self.stack_pos_to_index_map {
+            let (x, z): (i32, i32) = (bytemuck::cast(hash as u32), bytemuck::cast((hash >> 32) as u32));
+            for (y, chunk) in self.chunk_stacks[stack_index].chunks.iter().enumerate() {
+                if chunk.is_some() {
+                    positions.push(IVec3::new(x, y as i32, z));
+                }
+            }
+        }
+        positions
+    }
 }
 
 pub struct ChunkStoragePlanarLimited<ChunkType> {
-    chunks: Vec<ChunkType>,
+    chunks: Vec<(IVec3, ChunkType)>,
 }
 
 impl<ChunkType> ChunkStoragePlanarLimited<ChunkType> {
@@ -291,10 +332,14 @@ impl<ChunkType> ChunkStorageTrait<ChunkType> for ChunkStoragePlanarLimited<Chunk
     fn get_nearby_chunks(&self, position: IVec3) -> Vec<Option<&ChunkType>> {
         todo!()
     }
+
+    fn positions(&self) -> Vec<IVec3> {
+        self.chunks.iter().map(|(position, _)| *position).collect()
+    }
 }
 
 pub struct ChunkStorageCubic<ChunkType> {
-    chunks: Vec<ChunkType>,
+    chunks: Vec<(IVec3, ChunkType)>,
 }
 
 impl<ChunkType> ChunkStorageCubic<ChunkType> {
@@ -329,4 +374,40 @@ impl<ChunkType> ChunkStorageTrait<ChunkType> for ChunkStorageCubic<ChunkType> {
     fn get_nearby_chunks(&self, position: IVec3) -> Vec<Option<&ChunkType>> {
         todo!()
     }
+
+    fn positions(&self) -> Vec<IVec3> {
+        self.chunks.iter().map(|(position, _)| *position).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ultraviolet::IVec3;
+
+    use super::{ChunkStorage, ChunkStorageCubic, ChunkStoragePlanar, ChunkStoragePlanarLimited, ChunkStorageTrait};
+    use super::chunk::Chunk;
+
+    #[test]
+    fn marks_every_section_dirty() {
+        let mut storage = ChunkStorage::Planar(ChunkStoragePlanar::new(8));
+        let mut expected = vec![IVec3::new(0, 0, 0), IVec3::new(0, 7, 0), IVec3::new(-3, 2, 5), IVec3::new(i32::MIN, 1, -1)];
+        for &position in &expected {
+            storage.set_chunk(Chunk::create_empty(), position).unwrap();
+        }
+        let mut positions = storage.positions();
+        positions.sort_by_key(|position| (position.x, position.y, position.z));
+        expected.sort_by_key(|position| (position.x, position.y, position.z));
+        assert_eq!(positions, expected);
+
+        assert_eq!(storage.mark_all_dirty(), 4);
+        assert!(expected.iter().all(|&position| storage.get_chunk(position).unwrap().is_dirty()));
+    }
+
+    #[test]
+    fn marks_other_storages_dirty() {
+        for storage in [ChunkStorage::<Chunk>::PlanarLimited(ChunkStoragePlanarLimited::new()), ChunkStorage::Cubic(ChunkStorageCubic::new())] {
+            assert!(storage.positions().is_empty());
+            assert_eq!(storage.mark_all_dirty(), 0);
+        }
+    }
 }